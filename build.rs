@@ -0,0 +1,138 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Detects the version of the SQLite to link, and emits cfg `sqlite_has_*` for each API that
+//! the version provides, so that the wrappers of the newer APIs are compiled out instead of
+//! failing to link.
+//!
+//! The version is taken from environment variable `SQLITE3_VERSION_NUMBER` (e.g. "3022000") if
+//! set, or from `SQLITE_VERSION_NUMBER` in "sqlite3.h" under `SQLITE3_INCLUDE_DIR` or the
+//! standard include directories. If neither is found, all the cfgs are emitted.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Pairs of the cfg name and the SQLite version that introduced the API.
+///
+/// Keep them in sync with module `compat` .
+const APIS: &[(&str, u32)] = &[
+    ("sqlite_has_prepare_v3", 3_020_000),
+    ("sqlite_has_keywords", 3_024_000),
+    ("sqlite_has_hard_heap_limit", 3_031_000),
+    ("sqlite_has_txn_state", 3_034_000),
+    // sqlite3_deserialize() requires SQLITE_ENABLE_DESERIALIZE before 3.36.0.
+    ("sqlite_has_deserialize", 3_036_000),
+    ("sqlite_has_changes64", 3_037_000),
+    ("sqlite_has_error_offset", 3_038_000),
+];
+
+const INCLUDE_DIRS: &[&str] = &[
+    "/usr/include",
+    "/usr/local/include",
+    "/opt/homebrew/include",
+];
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=SQLITE3_VERSION_NUMBER");
+    println!("cargo:rerun-if-env-changed=SQLITE3_INCLUDE_DIR");
+
+    let version = version_from_env().or_else(version_from_header);
+    if version.is_none() {
+        println!("cargo:warning=SQLite version is unknown; assuming all the APIs are available");
+    }
+
+    for &(cfg, since) in APIS {
+        println!("cargo:rustc-check-cfg=cfg({})", cfg);
+        let available = match version {
+            None => true,
+            Some(v) => since <= v,
+        };
+        if available {
+            println!("cargo:rustc-cfg={}", cfg);
+        }
+    }
+}
+
+fn version_from_env() -> Option<u32> {
+    env::var("SQLITE3_VERSION_NUMBER").ok()?.trim().parse().ok()
+}
+
+fn version_from_header() -> Option<u32> {
+    let dirs = env::var_os("SQLITE3_INCLUDE_DIR")
+        .map(PathBuf::from)
+        .into_iter()
+        .chain(INCLUDE_DIRS.iter().map(PathBuf::from));
+
+    for dir in dirs {
+        let header = dir.join("sqlite3.h");
+        if let Some(version) = parse_header(&header) {
+            println!("cargo:rerun-if-changed={}", header.display());
+            return Some(version);
+        }
+    }
+    None
+}
+
+/// Returns the value of `#define SQLITE_VERSION_NUMBER` in `header` .
+fn parse_header(header: &Path) -> Option<u32> {
+    let text = fs::read_to_string(header).ok()?;
+    text.lines().find_map(|line| {
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (Some("#define"), Some("SQLITE_VERSION_NUMBER"), Some(n)) => n.parse().ok(),
+            _ => None,
+        }
+    })
+}
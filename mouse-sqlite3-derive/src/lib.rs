@@ -0,0 +1,144 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Procedural macros of crate `mouse-sqlite3` , which are re-exported by its feature "derive".
+
+mod sql;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields};
+
+/// Derives `mouse_sqlite3::BindParams` for a struct with named fields.
+///
+/// Each field is bound to the parameter of the same name (e.g. field `id` to ":id" ); the field
+/// type must implement `mouse_sqlite3::ToSql` .
+#[proc_macro_derive(BindParams)]
+pub fn derive_bind_params(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    bind_params(&input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn bind_params(input: &DeriveInput) -> Result<TokenStream2, syn::Error> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                let message = "BindParams can be derived only for structs with named fields";
+                return Err(syn::Error::new_spanned(&input.ident, message));
+            }
+        },
+        _ => {
+            let message = "BindParams can be derived only for structs";
+            return Err(syn::Error::new_spanned(&input.ident, message));
+        }
+    };
+
+    let idents: Vec<_> = fields.iter().filter_map(|f| f.ident.as_ref()).collect();
+    let names: Vec<String> = idents
+        .iter()
+        .map(|ident| {
+            let name = ident.to_string();
+            name.strip_prefix("r#").map(String::from).unwrap_or(name)
+        })
+        .collect();
+    let indices = 0..idents.len();
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::mouse_sqlite3::BindParams for #ident #ty_generics #where_clause {
+            fn param_names() -> &'static [&'static str] {
+                &[#(#names),*]
+            }
+
+            fn bind_params(
+                &self,
+                stmt: &mut ::mouse_sqlite3::Stmt,
+                indices: &[usize],
+            ) -> ::core::result::Result<(), ::mouse_sqlite3::Error> {
+                #(stmt.bind(indices[#indices], &self.#idents)?;)*
+                ::core::result::Result::Ok(())
+            }
+        }
+    })
+}
+
+/// Checks the SQL literal at build time, and expands to the literal (i.e. `&'static str` ) as it
+/// is.
+///
+/// The SQL is prepared against the schema specified by one of the followings, and the build
+/// fails with the message of SQLite (and the offset of the error token if available) if it does
+/// not compile. Only one statement is allowed.
+///
+/// - `sql!("SELECT ...", schema = "schema.sql")` ; the SQL file to build the schema on an empty
+///   in-memory database.
+/// - `sql!("SELECT ...", database = "reference.sqlite3")` ; the database file to open read-only.
+/// - Environment variable `MOUSE_SQLITE3_SCHEMA` or `MOUSE_SQLITE3_DATABASE` ; same to the
+///   above respectively.
+///
+/// The relative path is resolved from the directory of `Cargo.toml` of the crate being built.
+/// The SQL is not checked if none is specified.
+///
+/// Cargo rebuilds the caller when the schema file is changed, however, it does not track the
+/// environment variables nor the database file. Print "cargo:rerun-if-env-changed=..." and
+/// "cargo:rerun-if-changed=..." in the build script to do so.
+#[proc_macro]
+pub fn sql(input: TokenStream) -> TokenStream {
+    sql::expand(input.into())
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
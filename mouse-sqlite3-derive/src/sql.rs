@@ -0,0 +1,367 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Implementation of macro `sql!` , which prepares the SQL at build time to check it.
+
+use core::ptr::null_mut;
+use proc_macro2::TokenStream;
+use quote::quote;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::path::{Path, PathBuf};
+use syn::parse::{Parse, ParseStream};
+use syn::{Ident, LitStr, Token};
+
+/// Environment variable of the SQL file to build the schema from.
+const SCHEMA_ENV: &str = "MOUSE_SQLITE3_SCHEMA";
+/// Environment variable of the reference database file.
+const DATABASE_ENV: &str = "MOUSE_SQLITE3_DATABASE";
+
+/// Where the schema to check the SQL against comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// SQL file to execute on an empty in-memory database.
+    Schema(PathBuf),
+    /// Database file to open read-only.
+    Database(PathBuf),
+}
+
+impl Source {
+    fn path(&self) -> &Path {
+        match self {
+            Self::Schema(path) | Self::Database(path) => path,
+        }
+    }
+}
+
+/// Arguments of `sql!` ; i.e. the SQL literal optionally followed by `schema = "..."` or
+/// `database = "..."` .
+struct Input {
+    sql: LitStr,
+    source: Option<Source>,
+}
+
+impl Parse for Input {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let sql: LitStr = input.parse()?;
+        if input.is_empty() {
+            return Ok(Self { sql, source: None });
+        }
+
+        input.parse::<Token![,]>()?;
+        if input.is_empty() {
+            return Ok(Self { sql, source: None });
+        }
+        let key: Ident = input.parse()?;
+        input.parse::<Token![=]>()?;
+        let path: LitStr = input.parse()?;
+        input.parse::<Option<Token![,]>>()?;
+
+        let source = match key.to_string().as_str() {
+            "schema" => Source::Schema(resolve(&path.value())),
+            "database" => Source::Database(resolve(&path.value())),
+            _ => {
+                let message = "expected `schema` or `database`";
+                return Err(syn::Error::new_spanned(key, message));
+            }
+        };
+        Ok(Self {
+            sql,
+            source: Some(source),
+        })
+    }
+}
+
+/// Resolves `path` relative to the directory of the manifest of the crate being built.
+fn resolve(path: &str) -> PathBuf {
+    let path = Path::new(path);
+    match std::env::var_os("CARGO_MANIFEST_DIR") {
+        Some(dir) if path.is_relative() => Path::new(&dir).join(path),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Returns the source specified by the environment variables if any.
+fn source_from_env() -> Option<Source> {
+    if let Ok(path) = std::env::var(SCHEMA_ENV) {
+        return Some(Source::Schema(resolve(&path)));
+    }
+    std::env::var(DATABASE_ENV)
+        .ok()
+        .map(|path| Source::Database(resolve(&path)))
+}
+
+pub fn expand(input: TokenStream) -> syn::Result<TokenStream> {
+    let Input { sql, source } = syn::parse2(input)?;
+    let source = match source.or_else(source_from_env) {
+        Some(source) => source,
+        None => return Ok(quote! { #sql }),
+    };
+
+    check(&sql.value(), &source).map_err(|message| syn::Error::new(sql.span(), message))?;
+
+    // `include_bytes!` lets cargo rebuild the caller when the schema is changed.
+    let path = source.path().to_string_lossy().into_owned();
+    match source {
+        Source::Schema(_) => Ok(quote! {{
+            const _: &[u8] = include_bytes!(#path);
+            #sql
+        }}),
+        Source::Database(_) => Ok(quote! { #sql }),
+    }
+}
+
+/// Prepares `sql` against the schema of `source` , and returns the message on failure.
+pub fn check(sql: &str, source: &Source) -> Result<(), String> {
+    let sql = CString::new(sql).map_err(|_| "the SQL must not contain NUL".to_string())?;
+    let con = Db::open(source)?;
+
+    let mut stmt = core::ptr::null_mut();
+    let mut tail: *const c_char = core::ptr::null();
+    let code = unsafe { sqlite3_prepare_v2(con.0, sql.as_ptr(), -1, &mut stmt, &mut tail) };
+    if code != SQLITE_OK {
+        let message = con.errmsg();
+        return match con.error_offset() {
+            Some(offset) if 0 <= offset => Err(format!("{} (at offset {})", message, offset)),
+            _ => Err(message),
+        };
+    }
+    unsafe { sqlite3_finalize(stmt) };
+
+    if stmt.is_null() {
+        return Err("the SQL is empty".to_string());
+    }
+    let tail = unsafe { CStr::from_ptr(tail) }.to_string_lossy();
+    let tail = tail.trim_start_matches(|c: char| c.is_whitespace() || c == ';');
+    if !tail.is_empty() {
+        return Err(format!("only one statement is allowed: {}", tail));
+    }
+    Ok(())
+}
+
+/// Minimal wrapper of C `sqlite3 *` to check the SQL.
+struct Db(*mut sqlite3);
+
+impl Drop for Db {
+    fn drop(&mut self) {
+        unsafe { sqlite3_close(self.0) };
+    }
+}
+
+impl Db {
+    fn open(source: &Source) -> Result<Self, String> {
+        let (filename, flags) = match source {
+            Source::Schema(_) => (":memory:".to_string(), SQLITE_OPEN_READWRITE),
+            Source::Database(path) => (path.to_string_lossy().into_owned(), SQLITE_OPEN_READONLY),
+        };
+        let filename = CString::new(filename).map_err(|_| "the path contains NUL".to_string())?;
+
+        let mut raw = core::ptr::null_mut();
+        let code =
+            unsafe { sqlite3_open_v2(filename.as_ptr(), &mut raw, flags, core::ptr::null()) };
+        let con = Self(raw);
+        if code != SQLITE_OK {
+            let path = source.path().display();
+            return Err(format!("failed to open {}: {}", path, con.errmsg()));
+        }
+
+        // Not to regard a misspelled identifier in double quotes as a string literal. SQLite older
+        // than 3.29.0 does not know the options, and always accepts double-quoted strings.
+        if 3_029_000 <= unsafe { sqlite3_libversion_number() } {
+            for &op in [SQLITE_DBCONFIG_DQS_DML, SQLITE_DBCONFIG_DQS_DDL].iter() {
+                let code = unsafe { sqlite3_db_config(raw, op, 0 as c_int, null_mut::<c_int>()) };
+                if code != SQLITE_OK {
+                    let message =
+                        format!("failed to disable double-quoted strings: {}", con.errmsg());
+                    return Err(message);
+                }
+            }
+        }
+
+        if let Source::Schema(path) = source {
+            let schema = std::fs::read_to_string(path)
+                .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+            let schema = CString::new(schema).map_err(|_| "the schema contains NUL".to_string())?;
+            let code = unsafe {
+                sqlite3_exec(
+                    raw,
+                    schema.as_ptr(),
+                    None,
+                    core::ptr::null_mut(),
+                    core::ptr::null_mut(),
+                )
+            };
+            if code != SQLITE_OK {
+                return Err(format!(
+                    "the schema {} failed: {}",
+                    path.display(),
+                    con.errmsg()
+                ));
+            }
+        }
+        Ok(con)
+    }
+
+    /// Calls C function `sqlite3_error_offset` , or returns `None` if the SQLite is older than
+    /// 3.38.0.
+    fn error_offset(&self) -> Option<c_int> {
+        #[cfg(sqlite_has_error_offset)]
+        {
+            if 3_038_000 <= unsafe { sqlite3_libversion_number() } {
+                return Some(unsafe { sqlite3_error_offset(self.0) });
+            }
+        }
+        None
+    }
+
+    fn errmsg(&self) -> String {
+        if self.0.is_null() {
+            return "out of memory".to_string();
+        }
+        unsafe { CStr::from_ptr(sqlite3_errmsg(self.0)) }
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+const SQLITE_OK: c_int = 0;
+const SQLITE_OPEN_READONLY: c_int = 0x00000001;
+const SQLITE_OPEN_READWRITE: c_int = 0x00000002;
+const SQLITE_DBCONFIG_DQS_DML: c_int = 1013;
+const SQLITE_DBCONFIG_DQS_DDL: c_int = 1014;
+
+#[allow(non_camel_case_types)]
+enum sqlite3 {}
+#[allow(non_camel_case_types)]
+enum sqlite3_stmt {}
+
+type ExecCallback =
+    Option<unsafe extern "C" fn(*mut c_void, c_int, *mut *mut c_char, *mut *mut c_char) -> c_int>;
+
+#[link(name = "sqlite3")]
+extern "C" {
+    fn sqlite3_open_v2(
+        filename: *const c_char,
+        ppdb: *mut *mut sqlite3,
+        flags: c_int,
+        zvfs: *const c_char,
+    ) -> c_int;
+    fn sqlite3_close(db: *mut sqlite3) -> c_int;
+    fn sqlite3_db_config(db: *mut sqlite3, op: c_int, ...) -> c_int;
+    fn sqlite3_exec(
+        db: *mut sqlite3,
+        sql: *const c_char,
+        callback: ExecCallback,
+        arg: *mut c_void,
+        errmsg: *mut *mut c_char,
+    ) -> c_int;
+    fn sqlite3_prepare_v2(
+        db: *mut sqlite3,
+        zsql: *const c_char,
+        nbyte: c_int,
+        ppstmt: *mut *mut sqlite3_stmt,
+        pztail: *mut *const c_char,
+    ) -> c_int;
+    fn sqlite3_finalize(pstmt: *mut sqlite3_stmt) -> c_int;
+    fn sqlite3_errmsg(db: *mut sqlite3) -> *const c_char;
+    fn sqlite3_libversion_number() -> c_int;
+}
+
+#[cfg(sqlite_has_error_offset)]
+#[link(name = "sqlite3")]
+extern "C" {
+    fn sqlite3_error_offset(db: *mut sqlite3) -> c_int;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> Source {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../testdata/schema.sql");
+        Source::Schema(path)
+    }
+
+    #[test]
+    fn check_valid() {
+        let sql = r#"SELECT "code", "name" FROM "country" WHERE "population" > ?;"#;
+        assert_eq!(Ok(()), check(sql, &schema()));
+
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../testdata/reference.sqlite3");
+        assert_eq!(Ok(()), check(sql, &Source::Database(path)));
+    }
+
+    #[test]
+    fn check_invalid() {
+        let message = check("SELECT code, nmae FROM country", &schema()).unwrap_err();
+        assert!(message.contains("no such column: nmae"), "{}", message);
+        if cfg!(sqlite_has_error_offset) {
+            assert!(message.contains("(at offset 13)"), "{}", message);
+        } else {
+            assert!(!message.contains("(at offset"), "{}", message);
+        }
+
+        // A missing column in double quotes is not regarded as a string literal.
+        let message = check(r#"SELECT "nmae" FROM "country""#, &schema()).unwrap_err();
+        assert!(message.contains("no such column: nmae"), "{}", message);
+
+        let message = check("SELECT FROM", &schema()).unwrap_err();
+        assert!(message.contains("syntax error"), "{}", message);
+
+        let message = check("SELECT 1; SELECT 2", &schema()).unwrap_err();
+        assert!(message.starts_with("only one statement"), "{}", message);
+
+        assert!(check("", &schema()).is_err());
+        assert!(check("SELECT 1", &Source::Schema(PathBuf::from("no_such_file"))).is_err());
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{Error, Stmt, ToSql, ValueRef, SQLITE_MISMATCH};
+use core::fmt::Write;
+
+impl Stmt {
+    /// Binds `values` to the parameter `index` as one TEXT of JSON array, so that the statement
+    /// can take a list of any length with the built-in table-valued function [`json_each`] .
+    ///
+    /// For example, `SELECT * FROM "foo" WHERE "id" IN (SELECT value FROM json_each(?1))` takes
+    /// any number of ids with one prepared statement, and never exceeds
+    /// `SQLITE_LIMIT_VARIABLE_NUMBER` .
+    ///
+    /// Elements of INTEGER, REAL, TEXT, and NULL are supported; BLOB or non-finite REAL is an
+    /// error of `SQLITE_MISMATCH` .
+    ///
+    /// Calls method [`reset`] if the privious [`step`] returns `true` .
+    ///
+    /// Note that `index` starts at 1, not 0.
+    ///
+    /// [`reset`]: #method.reset
+    /// [`step`]: #method.step
+    /// [`json_each`]: https://www.sqlite.org/json1.html#jeach
+    pub fn bind_array<T>(&mut self, index: usize, values: &[T]) -> Result<(), Error>
+    where
+        T: ToSql,
+    {
+        let mut json = String::with_capacity(2 + values.len() * 4);
+        json.push('[');
+
+        for (i, val) in values.iter().enumerate() {
+            if i != 0 {
+                json.push(',');
+            }
+
+            let output = val.to_sql()?;
+            match output.as_value_ref() {
+                ValueRef::Null => json.push_str("null"),
+                ValueRef::Integer(n) => write!(json, "{}", n).unwrap(),
+                ValueRef::Real(f) if f.is_finite() => write!(json, "{:?}", f).unwrap(),
+                ValueRef::Text(s) => match core::str::from_utf8(s) {
+                    Ok(s) => push_json_string(&mut json, s),
+                    Err(e) => {
+                        let message = format!("array element {}: {}", i, e);
+                        return Err(Error::with_message(SQLITE_MISMATCH, message));
+                    }
+                },
+                ValueRef::Real(f) => {
+                    let message = format!("array element {}: {} is not supported", i, f);
+                    return Err(Error::with_message(SQLITE_MISMATCH, message));
+                }
+                ValueRef::Blob(_) => {
+                    let message = format!("array element {}: BLOB is not supported", i);
+                    return Err(Error::with_message(SQLITE_MISMATCH, message));
+                }
+            }
+        }
+
+        json.push(']');
+        self.bind_value_ref(index, ValueRef::Text(json.as_bytes()))
+    }
+}
+
+/// Appends `s` to `buf` as a JSON string literal.
+fn push_json_string(buf: &mut String, s: &str) {
+    buf.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(buf, "\\u{:04x}", c as u32).unwrap(),
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Connection, SQLITE_MISMATCH};
+
+    const SELECT: &str = r#"SELECT "name" FROM "foo"
+                            WHERE "id" IN (SELECT value FROM json_each(?1))
+                            ORDER BY "id""#;
+
+    fn prepared_count(con: &mut Connection) -> i64 {
+        let mut stmt = con.stmt_once("SELECT count(*) FROM sqlite_stmt").unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        // Except for this statement itself.
+        stmt.get::<i64>(0).unwrap() - 1
+    }
+
+    #[test]
+    fn bind_array() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(
+            r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "name" TEXT);
+               WITH RECURSIVE "n"("i") AS (SELECT 1 UNION ALL SELECT "i" + 1 FROM "n"
+                                           WHERE "i" < 10000)
+               INSERT INTO "foo" SELECT "i", 'name' || "i" FROM "n";"#,
+        )
+        .unwrap();
+        let before = prepared_count(&mut con);
+
+        let ids: Vec<i64> = vec![5, 1, 3, 20000, 3];
+        let stmt = con.stmt(SELECT).unwrap();
+        stmt.bind_array(1, &ids).unwrap();
+        let names: Vec<(String,)> = stmt.query_as().unwrap();
+        let expected = ["name1", "name3", "name5"];
+        assert_eq!(expected.len(), names.len());
+        assert!(names.iter().zip(&expected).all(|(n, e)| n.0 == *e));
+
+        let ids: Vec<i64> = (1..=5000).map(|i| i * 2).collect();
+        let stmt = con.stmt(SELECT).unwrap();
+        stmt.bind_array(1, &ids).unwrap();
+        let names: Vec<(String,)> = stmt.query_as().unwrap();
+        assert_eq!(5000, names.len());
+        assert_eq!("name10000", names[4999].0);
+
+        // Only one statement is added regardless of the length of the list.
+        assert_eq!(before + 1, prepared_count(&mut con));
+
+        // Text elements
+        let mut stmt = con
+            .stmt_once(r#"SELECT count(*) FROM json_each(?1) WHERE value IN ('a"\', 'b')"#)
+            .unwrap();
+        stmt.bind_array(1, &["a\"\\", "b", "c\n"]).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(2), stmt.get::<i64>(0));
+
+        // Errors
+        let e = stmt.bind_array(1, &[f64::NAN]).unwrap_err();
+        assert_eq!(SQLITE_MISMATCH, e.code());
+        let e = stmt.bind_array(1, &[&b"\x01"[..]]).unwrap_err();
+        assert_eq!(SQLITE_MISMATCH, e.code());
+    }
+}
@@ -0,0 +1,262 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{Connection, Error, Row, StepResult, Value};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread;
+use tokio::sync::oneshot;
+
+type Job = Box<dyn FnOnce(&mut Connection) + Send>;
+
+/// Asynchronous wrapper of [`Connection`] .
+///
+/// `AsyncConnection` owns a dedicated thread which owns the [`Connection`] , and runs the
+/// closures passed to [`call`] one by one in the order of the calls. The async runtime is never
+/// blocked by libsqlite3.
+///
+/// # Cancellation
+///
+/// Once [`call`] (or another async method) is called, the closure runs to completion even if the
+/// returned future is dropped; only the result is discarded. So cancellation never leaves the
+/// [`Connection`] in the middle of an operation. Note that a transaction that the closure leaves
+/// open is kept open.
+///
+/// The thread finishes after `AsyncConnection` is dropped and all the pending closures are done.
+///
+/// This struct is enabled by feature "async-tokio".
+///
+/// [`Connection`]: struct.Connection.html
+/// [`call`]: #method.call
+pub struct AsyncConnection {
+    sender: mpsc::Sender<Job>,
+}
+
+impl AsyncConnection {
+    /// Spawns the thread to own `con` and returns a new instance.
+    pub fn new(mut con: Connection) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        thread::spawn(move || {
+            for job in receiver {
+                job(&mut con);
+            }
+        });
+
+        Self { sender }
+    }
+
+    /// Runs `f` with the [`Connection`] on the dedicated thread and returns the result.
+    ///
+    /// If `f` panics, the panic is propagated to the caller; the thread and the [`Connection`]
+    /// remain available.
+    ///
+    /// [`Connection`]: struct.Connection.html
+    pub async fn call<F, T>(&self, f: F) -> T
+    where
+        F: 'static + FnOnce(&mut Connection) -> T + Send,
+        T: 'static + Send,
+    {
+        let (sender, receiver) = oneshot::channel();
+        let job: Job = Box::new(move |con| {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| f(con)));
+            // The receiver has been dropped if the future was cancelled.
+            let _ = sender.send(result);
+        });
+
+        // The thread never finishes while `self` is alive.
+        self.sender
+            .send(job)
+            .expect("the connection thread has finished");
+        match receiver.await.expect("the connection thread has finished") {
+            Ok(t) => t,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+
+    /// Asynchronous version of [`Connection::execute_batch`] .
+    ///
+    /// [`Connection::execute_batch`]: struct.Connection.html#method.execute_batch
+    pub async fn execute_batch<S>(&self, sql: S) -> Result<(), Error>
+    where
+        S: Into<String>,
+    {
+        let sql = sql.into();
+        self.call(move |con| con.execute_batch(&sql)).await
+    }
+
+    /// Prepares `sql` , binds `params` , and executes it until the end discarding the rows.
+    ///
+    /// Note that the index of `params` starts at 0 while the parameter index starts at 1; i.e.
+    /// `params[0]` is bound to `?1` .
+    pub async fn execute<S>(&self, sql: S, params: Vec<Value>) -> Result<(), Error>
+    where
+        S: Into<String>,
+    {
+        let sql = sql.into();
+        self.call(move |con| {
+            let mut stmt = con.stmt_once(&sql)?;
+            for (i, param) in params.iter().enumerate() {
+                stmt.bind(i + 1, param)?;
+            }
+            while let StepResult::Row = stmt.step2()? {}
+            Ok(())
+        })
+        .await
+    }
+
+    /// Prepares `sql` , binds `params` , and returns the rows converted by `f` .
+    ///
+    /// Note that the index of `params` starts at 0 while the parameter index starts at 1; i.e.
+    /// `params[0]` is bound to `?1` .
+    pub async fn query_map<S, F, T>(
+        &self,
+        sql: S,
+        params: Vec<Value>,
+        mut f: F,
+    ) -> Result<Vec<T>, Error>
+    where
+        S: Into<String>,
+        F: 'static + FnMut(&Row<'_>) -> Result<T, Error> + Send,
+        T: 'static + Send,
+    {
+        let sql = sql.into();
+        self.call(move |con| {
+            let mut stmt = con.stmt_once(&sql)?;
+            for (i, param) in params.iter().enumerate() {
+                stmt.bind(i + 1, param)?;
+            }
+
+            let mut ret = Vec::new();
+            while let StepResult::Row = stmt.step2()? {
+                ret.push(f(&stmt.row()?)?);
+            }
+            Ok(ret)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn serialized() {
+        let con = Arc::new(AsyncConnection::new(Connection::open_memory_db().unwrap()));
+        con.execute_batch(
+            r#"CREATE TABLE "counter" ("value" INTEGER);
+               INSERT INTO "counter" VALUES (0);
+               CREATE TABLE "log" ("task" INTEGER);"#,
+        )
+        .await
+        .unwrap();
+
+        let tasks: Vec<_> = (0..16)
+            .map(|i| {
+                let con = con.clone();
+                tokio::spawn(async move {
+                    con.execute(r#"INSERT INTO "log" VALUES (?1)"#, vec![Value::Integer(i)])
+                        .await
+                        .unwrap();
+
+                    // Read-modify-write is not interleaved with other tasks.
+                    con.call(|con| {
+                        let stmt = con.stmt(r#"SELECT "value" FROM "counter""#)?;
+                        stmt.step()?;
+                        let value = stmt.get::<i64>(0)?;
+                        std::thread::yield_now();
+
+                        let stmt = con.stmt(r#"UPDATE "counter" SET "value" = ?1"#)?;
+                        stmt.bind(1, &(value + 1))?;
+                        stmt.step().map(|_| ())
+                    })
+                    .await
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+
+        let rows = con
+            .query_map(r#"SELECT "value" FROM "counter""#, vec![], |row| {
+                row.get::<i64>(0)
+            })
+            .await
+            .unwrap();
+        assert_eq!(vec![16], rows);
+
+        let rows = con
+            .query_map(
+                r#"SELECT "task" FROM "log" WHERE "task" < ?1 ORDER BY "task""#,
+                vec![Value::Integer(3)],
+                |row| row.get::<i64>(0),
+            )
+            .await
+            .unwrap();
+        assert_eq!(vec![0, 1, 2], rows);
+    }
+
+    #[tokio::test]
+    async fn panic_in_call() {
+        let con = Arc::new(AsyncConnection::new(Connection::open_memory_db().unwrap()));
+
+        let cloned = con.clone();
+        let task = tokio::spawn(async move { cloned.call(|_| panic!("foo")).await });
+        assert!(task.await.is_err());
+
+        // The connection is still available.
+        assert_eq!(Ok(()), con.execute_batch("SELECT 1").await);
+    }
+}
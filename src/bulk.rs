@@ -0,0 +1,315 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{Connection, Error, StepResult, Stmt, ToSql, Value, SQLITE_MISUSE};
+use std::fmt;
+
+/// Error of [`Connection::bulk_insert`] .
+///
+/// [`Connection::bulk_insert`]: struct.Connection.html#method.bulk_insert
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BulkInsertError {
+    /// The number of the rows committed before the error occurred; i.e. the rows of the chunks
+    /// committed before the failed one.
+    pub committed: u64,
+    /// The error that stopped the insertion.
+    pub error: Error,
+}
+
+impl fmt::Display for BulkInsertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} rows were committed: {}", self.committed, self.error)
+    }
+}
+
+impl std::error::Error for BulkInsertError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<BulkInsertError> for Error {
+    #[inline]
+    fn from(e: BulkInsertError) -> Self {
+        e.error
+    }
+}
+
+/// Types that bind all the parameters of a statement at once.
+///
+/// `BindRow` is implemented for tuples up to 12 elements of [`ToSql`] types, `[&dyn ToSql]` ,
+/// and `[Value]` (and `Vec<Value>` .) The first element is bound to the parameter 1, the second
+/// one to the parameter 2, and so on.
+///
+/// [`ToSql`]: trait.ToSql.html
+pub trait BindRow {
+    /// Binds `self` to the parameters of `stmt` from the parameter 1.
+    fn bind_row(&self, stmt: &mut Stmt) -> Result<(), Error>;
+}
+
+impl<T> BindRow for &T
+where
+    T: ?Sized + BindRow,
+{
+    #[inline]
+    fn bind_row(&self, stmt: &mut Stmt) -> Result<(), Error> {
+        (**self).bind_row(stmt)
+    }
+}
+
+impl BindRow for [&dyn ToSql] {
+    #[inline]
+    fn bind_row(&self, stmt: &mut Stmt) -> Result<(), Error> {
+        for (i, val) in self.iter().enumerate() {
+            stmt.bind(i + 1, *val)?;
+        }
+        Ok(())
+    }
+}
+
+impl BindRow for [Value] {
+    #[inline]
+    fn bind_row(&self, stmt: &mut Stmt) -> Result<(), Error> {
+        for (i, val) in self.iter().enumerate() {
+            stmt.bind(i + 1, val)?;
+        }
+        Ok(())
+    }
+}
+
+impl BindRow for Vec<Value> {
+    #[inline]
+    fn bind_row(&self, stmt: &mut Stmt) -> Result<(), Error> {
+        self.as_slice().bind_row(stmt)
+    }
+}
+
+macro_rules! impl_bind_row_for_tuple {
+    ($($t:ident $i:tt),+) => {
+        impl<$($t),+> BindRow for ($($t,)+)
+        where
+            $($t: ToSql,)+
+        {
+            #[inline]
+            fn bind_row(&self, stmt: &mut Stmt) -> Result<(), Error> {
+                $(stmt.bind($i + 1, &self.$i)?;)+
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_bind_row_for_tuple!(A 0);
+impl_bind_row_for_tuple!(A 0, B 1);
+impl_bind_row_for_tuple!(A 0, B 1, C 2);
+impl_bind_row_for_tuple!(A 0, B 1, C 2, D 3);
+impl_bind_row_for_tuple!(A 0, B 1, C 2, D 3, E 4);
+impl_bind_row_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5);
+impl_bind_row_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+impl_bind_row_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+impl_bind_row_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8);
+impl_bind_row_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9);
+impl_bind_row_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10);
+impl_bind_row_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11);
+
+impl Connection {
+    /// Executes `sql` through the statement cache once for each of `rows` , committing a
+    /// transaction every `chunk` rows, and returns the number of the rows executed.
+    ///
+    /// Executing many "INSERT" statements in a transaction is much faster than executing each of
+    /// them in its own transaction (i.e. in autocommit mode.)
+    ///
+    /// If an error occurs, only the current chunk is rolled back; the chunks committed before
+    /// are kept. [`BulkInsertError::committed`] tells the number of the rows committed.
+    ///
+    /// The cached statement is reset and the parameters are cleared after each row.
+    ///
+    /// Returns `SQLITE_MISUSE` if `chunk` is 0 or if a transaction is already open.
+    ///
+    /// [`BulkInsertError::committed`]: struct.BulkInsertError.html#structfield.committed
+    pub fn bulk_insert<I>(
+        &mut self,
+        sql: &'static str,
+        rows: I,
+        chunk: usize,
+    ) -> Result<u64, BulkInsertError>
+    where
+        I: IntoIterator,
+        I::Item: BindRow,
+    {
+        let committed_error = |error: Error, committed: u64| BulkInsertError { committed, error };
+
+        if chunk == 0 {
+            let e = Error::with_message(SQLITE_MISUSE, "chunk must not be 0");
+            return Err(committed_error(e, 0));
+        }
+        self.check_no_transaction("bulk_insert")
+            .map_err(|e| committed_error(e, 0))?;
+
+        let mut rows = rows.into_iter().peekable();
+        let mut committed = 0;
+        while rows.peek().is_some() {
+            let mut txn = self
+                .transaction()
+                .map_err(|e| committed_error(e, committed))?;
+            let mut n = 0;
+            for row in rows.by_ref().take(chunk) {
+                // The transaction is rolled back on drop.
+                txn.execute_row(sql, &row)
+                    .map_err(|e| committed_error(e, committed))?;
+                n += 1;
+            }
+            txn.commit().map_err(|e| committed_error(e, committed))?;
+            committed += n;
+        }
+
+        Ok(committed)
+    }
+
+    /// Binds `row` to the cached statement of `sql` and executes it.
+    fn execute_row<R>(&mut self, sql: &'static str, row: &R) -> Result<(), Error>
+    where
+        R: ?Sized + BindRow,
+    {
+        let stmt = self.stmt(sql)?;
+        let ret = stmt
+            .clear_bindings()
+            .and_then(|_| row.bind_row(stmt))
+            .and_then(|_| loop {
+                if let StepResult::Done { .. } = stmt.step2()? {
+                    break Ok(());
+                }
+            });
+        let cleared = stmt.clear();
+        ret?;
+        cleared
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INSERT: &str = r#"INSERT INTO "foo" ("id", "name") VALUES (?1, ?2)"#;
+
+    fn setup() -> Connection {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "name" TEXT)"#)
+            .unwrap();
+        con
+    }
+
+    fn count(con: &mut Connection) -> i64 {
+        con.query_one::<(i64,)>(r#"SELECT COUNT(*) FROM "foo""#, &[])
+            .unwrap()
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn bulk_insert() {
+        let mut con = setup();
+        let rows = (0..10_000).map(|i| (i, format!("name {}", i)));
+        assert_eq!(Ok(10_000), con.bulk_insert(INSERT, rows, 1_000));
+        assert_eq!(10_000, count(&mut con));
+        assert!(con.is_autocommit());
+
+        let name = con
+            .query_one::<(String,)>(r#"SELECT "name" FROM "foo" WHERE "id" = 9999"#, &[])
+            .unwrap();
+        assert_eq!(Some(("name 9999".to_string(),)), name);
+
+        // Other row types
+        let rows: Vec<Vec<Value>> = vec![vec![Value::Integer(10_000), Value::Null]];
+        assert_eq!(Ok(1), con.bulk_insert(INSERT, &rows, 1));
+        let name = "foo".to_string();
+        let rows: Vec<[&dyn ToSql; 2]> = vec![[&10_001, &name]];
+        let rows = rows.iter().map(|r| &r[..]);
+        assert_eq!(Ok(1), con.bulk_insert(INSERT, rows, 1));
+        assert_eq!(10_002, count(&mut con));
+    }
+
+    #[test]
+    fn bulk_insert_error() {
+        let mut con = setup();
+        // The id of row 7_500 conflicts with row 0.
+        let rows = (0..10_000).map(|i| {
+            if i == 7_500 {
+                (0, "conflict")
+            } else {
+                (i, "ok")
+            }
+        });
+        let e = con.bulk_insert(INSERT, rows, 1_000).unwrap_err();
+        assert_eq!(7_000, e.committed);
+        assert!(e.to_string().contains("constraint failed"), "{}", e);
+        assert!(e.to_string().starts_with("7000 rows were committed: "));
+        assert_eq!(7_000, count(&mut con));
+        assert!(con.is_autocommit());
+
+        // The statement cache is still usable.
+        assert_eq!(Ok(1), con.bulk_insert(INSERT, vec![(7_000, "ok")], 1));
+    }
+
+    #[test]
+    fn bulk_insert_misuse() {
+        let mut con = setup();
+        let e = con.bulk_insert(INSERT, vec![(1, "a")], 0).unwrap_err();
+        assert_eq!(0, e.committed);
+        assert_eq!(SQLITE_MISUSE, e.error.code());
+
+        let mut txn = con.transaction().unwrap();
+        let e = txn.bulk_insert(INSERT, vec![(1, "a")], 1).unwrap_err();
+        assert_eq!(SQLITE_MISUSE, Error::from(e).code());
+    }
+}
@@ -0,0 +1,251 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{sqlite3, Connection, Error};
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+
+extern "C" {
+    fn sqlite3_wal_checkpoint_v2(
+        db: *mut sqlite3,
+        zdb: *const c_char,
+        emode: c_int,
+        pnlog: *mut c_int,
+        pnckpt: *mut c_int,
+    ) -> c_int;
+    fn sqlite3_log(ierrcode: c_int, zformat: *const c_char, ...);
+}
+
+const SQLITE_CHECKPOINT_TRUNCATE: c_int = 3;
+
+/// What [`Connection`] does before closing the database, which
+/// [`Connection::set_close_behavior`] sets.
+///
+/// [`Connection`]: struct.Connection.html
+/// [`Connection::set_close_behavior`]: struct.Connection.html#method.set_close_behavior
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CloseBehavior {
+    /// Just closes the database.
+    Default,
+    /// Runs [`sqlite3_wal_checkpoint_v2`] with `SQLITE_CHECKPOINT_TRUNCATE` for all the attached
+    /// databases, which truncates the WAL file to 0 bytes. It does nothing unless the database
+    /// is in WAL mode.
+    ///
+    /// [`sqlite3_wal_checkpoint_v2`]: https://www.sqlite.org/c3ref/wal_checkpoint_v2.html
+    CheckpointTruncate,
+    /// Executes [`PRAGMA optimize`] , and then does the same as `CheckpointTruncate` .
+    ///
+    /// [`PRAGMA optimize`]: https://www.sqlite.org/pragma.html#pragma_optimize
+    OptimizeAndCheckpoint,
+}
+
+impl Default for CloseBehavior {
+    #[inline]
+    fn default() -> Self {
+        Self::Default
+    }
+}
+
+impl Connection {
+    /// Sets what `self` does before closing the database, on [`close`] or on drop.
+    ///
+    /// Errors are returned from [`close`] . On drop, they are logged through
+    /// [`sqlite3_log`] (i.e. the callback that [`config_log`] set) and ignored.
+    ///
+    /// The default is [`CloseBehavior::Default`] . [`try_clone`] does not inherit this setting.
+    ///
+    /// [`close`]: #method.close
+    /// [`try_clone`]: #method.try_clone
+    /// [`sqlite3_log`]: https://www.sqlite.org/c3ref/log.html
+    /// [`config_log`]: fn.config_log.html
+    /// [`CloseBehavior::Default`]: enum.CloseBehavior.html#variant.Default
+    #[inline]
+    pub fn set_close_behavior(&mut self, behavior: CloseBehavior) {
+        self.close_behavior = behavior;
+    }
+
+    /// Returns the behavior that [`set_close_behavior`] set.
+    ///
+    /// [`set_close_behavior`]: #method.set_close_behavior
+    #[inline]
+    pub fn close_behavior(&self) -> CloseBehavior {
+        self.close_behavior
+    }
+
+    /// Does what [`close_behavior`] specifies.
+    ///
+    /// The statement cache must be cleared in advance; a cached statement could hold a read
+    /// transaction, which makes the checkpoint fail with `SQLITE_BUSY` .
+    ///
+    /// [`close_behavior`]: #method.close_behavior
+    pub(crate) fn run_close_behavior(&mut self) -> Result<(), Error> {
+        if self.close_behavior == CloseBehavior::OptimizeAndCheckpoint {
+            // "PRAGMA optimize" may run "ANALYZE" outside of any transaction.
+            self.require_explicit_transactions(false);
+            self.optimize()?;
+        }
+
+        match self.close_behavior {
+            CloseBehavior::Default => Ok(()),
+            _ => {
+                // NULL means all the attached databases.
+                let code = unsafe {
+                    sqlite3_wal_checkpoint_v2(
+                        self.raw(),
+                        core::ptr::null(),
+                        SQLITE_CHECKPOINT_TRUNCATE,
+                        core::ptr::null_mut(),
+                        core::ptr::null_mut(),
+                    )
+                };
+                match Error::new(code) {
+                    e if e == Error::OK => Ok(()),
+                    e => Err(e),
+                }
+            }
+        }
+    }
+}
+
+/// Logs `e` , which occurred while a [`Connection`] was being dropped.
+///
+/// [`Connection`]: struct.Connection.html
+pub(crate) fn log_drop_error(e: &Error) {
+    #[cfg(feature = "tracing")]
+    crate::trace::drop_error(e);
+
+    let message = format!("failed to close the connection: {}", e);
+    if let Ok(message) = CString::new(message) {
+        const FORMAT: &[u8] = b"%s\0";
+        unsafe { sqlite3_log(e.code(), FORMAT.as_ptr() as *const c_char, message.as_ptr()) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::TryFrom;
+    use std::fs;
+    use std::os::raw::c_void;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    extern "C" {
+        fn sqlite3_file_control(
+            db: *mut sqlite3,
+            zdbname: *const c_char,
+            op: c_int,
+            arg: *mut c_void,
+        ) -> c_int;
+    }
+
+    const SQLITE_FCNTL_PERSIST_WAL: c_int = 10;
+
+    /// Opens `path` with the WAL file kept on close, as the last connection otherwise deletes it.
+    fn write(path: &Path, behavior: CloseBehavior) -> Connection {
+        let mut con = Connection::try_from(path).unwrap();
+        con.set_close_behavior(behavior);
+
+        let mut persist: c_int = 1;
+        let arg = &mut persist as *mut c_int as *mut c_void;
+        let code = unsafe {
+            sqlite3_file_control(con.raw(), core::ptr::null(), SQLITE_FCNTL_PERSIST_WAL, arg)
+        };
+        assert_eq!(crate::SQLITE_OK, code);
+
+        con.execute_batch(
+            r#"PRAGMA journal_mode = WAL;
+               CREATE TABLE IF NOT EXISTS "foo" ("value" BLOB);
+               INSERT INTO "foo" VALUES (randomblob(65536))"#,
+        )
+        .unwrap();
+        con
+    }
+
+    #[test]
+    fn checkpoint_truncate() {
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("test_sqlite");
+        let wal = tmp.path().join("test_sqlite-wal");
+        let wal_len = || fs::metadata(&wal).map(|m| m.len()).unwrap_or(0);
+
+        drop(write(&path, CloseBehavior::Default));
+        assert!(0 < wal_len());
+
+        let con = write(&path, CloseBehavior::CheckpointTruncate);
+        assert_eq!(CloseBehavior::CheckpointTruncate, con.close_behavior());
+        drop(con);
+        assert_eq!(0, wal_len());
+
+        let mut con = write(&path, CloseBehavior::OptimizeAndCheckpoint);
+        con.require_explicit_transactions(true);
+        assert_eq!(Ok(()), con.close());
+        assert_eq!(0, wal_len());
+    }
+
+    #[test]
+    fn close_busy() {
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("test_sqlite");
+
+        // The reader prevents the checkpoint from truncating the WAL file.
+        let mut reader = Connection::try_from(path.as_path()).unwrap();
+        let con = write(&path, CloseBehavior::CheckpointTruncate);
+        reader
+            .execute_batch(r#"BEGIN; SELECT * FROM "foo""#)
+            .unwrap();
+
+        let e = con.close().unwrap_err();
+        assert_eq!(crate::SQLITE_BUSY, e.code());
+    }
+}
@@ -0,0 +1,283 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{sqlite3, sqlite3_stmt, Connection, Error, Stmt, SQLITE_MISUSE};
+use core::convert::TryFrom;
+use core::ptr::null;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+
+#[link(name = "sqlite3")]
+extern "C" {
+    fn sqlite3_column_database_name(pstmt: *mut sqlite3_stmt, n: c_int) -> *const c_char;
+    fn sqlite3_column_table_name(pstmt: *mut sqlite3_stmt, n: c_int) -> *const c_char;
+    fn sqlite3_column_origin_name(pstmt: *mut sqlite3_stmt, n: c_int) -> *const c_char;
+    fn sqlite3_table_column_metadata(
+        db: *mut sqlite3,
+        zdbname: *const c_char,
+        ztablename: *const c_char,
+        zcolumnname: *const c_char,
+        pzdatatype: *mut *const c_char,
+        pzcollseq: *mut *const c_char,
+        pnotnull: *mut c_int,
+        pprimarykey: *mut c_int,
+        pautoinc: *mut c_int,
+    ) -> c_int;
+}
+
+/// The origin of a result column, which [`Stmt::column_origin`] returns.
+///
+/// [`Stmt::column_origin`]: struct.Stmt.html#method.column_origin
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ColumnOrigin<'a> {
+    /// The name of the database; e.g. "main" , "temp" , or the name of the attached database.
+    pub database: &'a str,
+    /// The name of the table.
+    pub table: &'a str,
+    /// The name of the column in the table.
+    pub column: &'a str,
+}
+
+/// The declaration of a table column, which [`Connection::column_metadata`] returns.
+///
+/// [`Connection::column_metadata`]: struct.Connection.html#method.column_metadata
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ColumnMetadata {
+    /// The declared type, or `None` if the type is not declared.
+    pub declared_type: Option<String>,
+    /// The name of the default collation sequence; e.g. "BINARY" .
+    pub collation: String,
+    /// Whether the column has NOT NULL constraint.
+    pub not_null: bool,
+    /// Whether the column is a part of the PRIMARY KEY.
+    pub primary_key: bool,
+    /// Whether the column is AUTOINCREMENT.
+    pub autoincrement: bool,
+}
+
+/// Converts `ptr` into `&str` , or returns `None` if `ptr` is null or not a valid UTF-8.
+unsafe fn to_str<'a>(ptr: *const c_char) -> Option<&'a str> {
+    if ptr.is_null() {
+        None
+    } else {
+        CStr::from_ptr(ptr).to_str().ok()
+    }
+}
+
+impl Stmt {
+    /// Wrapper of C function [`sqlite3_column_database_name`] , [`sqlite3_column_table_name`] ,
+    /// and [`sqlite3_column_origin_name`] .
+    ///
+    /// Returns the table column that the result column `index` comes from, or `None` if the
+    /// result column is an expression or a subquery rather than a table column, or if `index` is
+    /// out of range.
+    ///
+    /// This method is enabled by feature "column-metadata" , which requires libsqlite3 built with
+    /// option `SQLITE_ENABLE_COLUMN_METADATA` .
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// [`sqlite3_column_database_name`]: https://www.sqlite.org/c3ref/column_database_name.html
+    /// [`sqlite3_column_table_name`]: https://www.sqlite.org/c3ref/column_database_name.html
+    /// [`sqlite3_column_origin_name`]: https://www.sqlite.org/c3ref/column_database_name.html
+    pub fn column_origin(&self, index: usize) -> Option<ColumnOrigin<'_>> {
+        let index = c_int::try_from(index).ok()?;
+        // The returned texts are owned by the statement.
+        unsafe {
+            Some(ColumnOrigin {
+                database: to_str(sqlite3_column_database_name(self.raw(), index))?,
+                table: to_str(sqlite3_column_table_name(self.raw(), index))?,
+                column: to_str(sqlite3_column_origin_name(self.raw(), index))?,
+            })
+        }
+    }
+}
+
+impl Connection {
+    /// Wrapper of C function [`sqlite3_table_column_metadata`] .
+    ///
+    /// Returns the declaration of column `column` of table `table` in database `db` . If `db` is
+    /// `None` , all the databases are searched in the same order as SQL does.
+    ///
+    /// This method is enabled by feature "column-metadata" , which requires libsqlite3 built with
+    /// option `SQLITE_ENABLE_COLUMN_METADATA` .
+    ///
+    /// Returns `SQLITE_ERROR` if the table or the column does not exist, or `SQLITE_MISUSE` if
+    /// an argument contains NUL.
+    ///
+    /// [`sqlite3_table_column_metadata`]: https://www.sqlite.org/c3ref/table_column_metadata.html
+    pub fn column_metadata(
+        &mut self,
+        db: Option<&str>,
+        table: &str,
+        column: &str,
+    ) -> Result<ColumnMetadata, Error> {
+        let to_cstring = |s: &str| CString::new(s).map_err(|_| Error::new(SQLITE_MISUSE));
+        let db = db.map(to_cstring).transpose()?;
+        let table = to_cstring(table)?;
+        let column = to_cstring(column)?;
+
+        let mut declared_type = null();
+        let mut collation = null();
+        let mut not_null = 0;
+        let mut primary_key = 0;
+        let mut autoincrement = 0;
+        let code = unsafe {
+            sqlite3_table_column_metadata(
+                self.raw(),
+                db.as_ref().map_or(null(), |db| db.as_ptr()),
+                table.as_ptr(),
+                column.as_ptr(),
+                &mut declared_type,
+                &mut collation,
+                &mut not_null,
+                &mut primary_key,
+                &mut autoincrement,
+            )
+        };
+
+        match Error::new(code) {
+            // The returned texts are valid only until the next call of this function.
+            e if e == Error::OK => unsafe {
+                Ok(ColumnMetadata {
+                    declared_type: to_str(declared_type).map(ToString::to_string),
+                    collation: to_str(collation).unwrap_or("BINARY").to_string(),
+                    not_null: not_null != 0,
+                    primary_key: primary_key != 0,
+                    autoincrement: autoincrement != 0,
+                })
+            },
+            e => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SQLITE_ERROR;
+
+    #[test]
+    fn column_origin() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(
+            r#"CREATE TABLE "users" ("id" INTEGER PRIMARY KEY, "name" TEXT);
+               CREATE TEMP TABLE "posts" ("id" INTEGER PRIMARY KEY, "user_id" INTEGER);"#,
+        )
+        .unwrap();
+
+        let stmt = con
+            .stmt_once(
+                r#"SELECT "u"."name" AS "author", "p"."id", length("u"."name"),
+                          (SELECT 1)
+                   FROM "users" AS "u" JOIN "posts" AS "p" ON "u"."id" = "p"."user_id""#,
+            )
+            .unwrap();
+        let expected = ColumnOrigin {
+            database: "main",
+            table: "users",
+            column: "name",
+        };
+        assert_eq!(Some(expected), stmt.column_origin(0));
+        let expected = ColumnOrigin {
+            database: "temp",
+            table: "posts",
+            column: "id",
+        };
+        assert_eq!(Some(expected), stmt.column_origin(1));
+        assert_eq!(None, stmt.column_origin(2));
+        assert_eq!(None, stmt.column_origin(3));
+        assert_eq!(None, stmt.column_origin(4));
+    }
+
+    #[test]
+    fn column_metadata() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(
+            r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY AUTOINCREMENT,
+                                   "name" TEXT NOT NULL COLLATE NOCASE, "any")"#,
+        )
+        .unwrap();
+
+        let expected = ColumnMetadata {
+            declared_type: Some("INTEGER".to_string()),
+            collation: "BINARY".to_string(),
+            not_null: false,
+            primary_key: true,
+            autoincrement: true,
+        };
+        assert_eq!(Ok(expected), con.column_metadata(None, "foo", "id"));
+
+        let expected = ColumnMetadata {
+            declared_type: Some("TEXT".to_string()),
+            collation: "NOCASE".to_string(),
+            not_null: true,
+            primary_key: false,
+            autoincrement: false,
+        };
+        assert_eq!(
+            Ok(expected),
+            con.column_metadata(Some("main"), "foo", "name")
+        );
+
+        let metadata = con.column_metadata(None, "foo", "any").unwrap();
+        assert_eq!(None, metadata.declared_type);
+
+        let e = con.column_metadata(None, "foo", "bar").unwrap_err();
+        assert_eq!(SQLITE_ERROR, e.code());
+        let e = con.column_metadata(Some("temp"), "foo", "id").unwrap_err();
+        assert_eq!(SQLITE_ERROR, e.code());
+        let e = con.column_metadata(None, "foo\0", "id").unwrap_err();
+        assert_eq!(SQLITE_MISUSE, e.code());
+    }
+}
@@ -0,0 +1,354 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Wrappers of the C functions that older SQLite lacks.
+//!
+//! Each wrapper is compiled out by the cfg that build.rs emits if the SQLite to link is older
+//! than the API, so that the crate still links. Even if compiled in, the runtime SQLite could be
+//! older than the header (e.g. the shared library was replaced), so the wrapper checks
+//! `sqlite3_libversion_number()` before calling the function.
+
+use crate::{sqlite3, sqlite3_libversion_number, sqlite3_stmt, Error, PrepareFlags};
+use std::os::raw::{c_char, c_int, c_uint};
+
+/// Whether the crate was built against SQLite providing `sqlite3_prepare_v3()` (3.20.0 or
+/// later.)
+///
+/// Otherwise, `sqlite3_prepare_v2()` is used instead, which ignores [`PrepareFlags`] except for
+/// [`PrepareFlags::NO_VTAB`] ; the preparation fails with [`Error::UNSUPPORTED`] if it is set.
+///
+/// [`PrepareFlags`]: struct.PrepareFlags.html
+/// [`PrepareFlags::NO_VTAB`]: struct.PrepareFlags.html#associatedconstant.NO_VTAB
+/// [`Error::UNSUPPORTED`]: struct.Error.html#associatedconstant.UNSUPPORTED
+pub const HAS_PREPARE_V3: bool = cfg!(sqlite_has_prepare_v3);
+/// Whether the crate was built against SQLite providing `sqlite3_keyword_check()` (3.24.0 or
+/// later.)
+///
+/// Otherwise, [`keyword_check`] always returns `false` and [`keywords`] returns an empty `Vec` .
+///
+/// [`keyword_check`]: fn.keyword_check.html
+/// [`keywords`]: fn.keywords.html
+pub const HAS_KEYWORDS: bool = cfg!(sqlite_has_keywords);
+/// Whether the crate was built against SQLite providing `sqlite3_hard_heap_limit64()` (3.31.0
+/// or later.)
+///
+/// Otherwise, [`hard_heap_limit`] returns [`Error::UNSUPPORTED`] .
+///
+/// [`hard_heap_limit`]: fn.hard_heap_limit.html
+/// [`Error::UNSUPPORTED`]: struct.Error.html#associatedconstant.UNSUPPORTED
+pub const HAS_HARD_HEAP_LIMIT: bool = cfg!(sqlite_has_hard_heap_limit);
+/// Whether the crate was built against SQLite providing `sqlite3_txn_state()` (3.34.0 or
+/// later.)
+///
+/// Otherwise, [`Connection::txn_state`] returns [`Error::UNSUPPORTED`] .
+///
+/// [`Connection::txn_state`]: struct.Connection.html#method.txn_state
+/// [`Error::UNSUPPORTED`]: struct.Error.html#associatedconstant.UNSUPPORTED
+pub const HAS_TXN_STATE: bool = cfg!(sqlite_has_txn_state);
+/// Whether the crate was built against SQLite providing `sqlite3_deserialize()` by default
+/// (3.36.0 or later.)
+///
+/// Otherwise, [`Connection::open_serialized`] returns [`Error::UNSUPPORTED`] .
+///
+/// [`Connection::open_serialized`]: struct.Connection.html#method.open_serialized
+/// [`Error::UNSUPPORTED`]: struct.Error.html#associatedconstant.UNSUPPORTED
+pub const HAS_DESERIALIZE: bool = cfg!(sqlite_has_deserialize);
+/// Whether the crate was built against SQLite providing `sqlite3_changes64()` (3.37.0 or
+/// later.)
+///
+/// Otherwise, `sqlite3_changes()` is used instead, which counts the changed rows in `int` .
+pub const HAS_CHANGES64: bool = cfg!(sqlite_has_changes64);
+/// Whether the crate was built against SQLite providing `sqlite3_error_offset()` (3.38.0 or
+/// later.)
+///
+/// Otherwise, [`Connection::error_offset`] always returns `None` .
+///
+/// [`Connection::error_offset`]: struct.Connection.html#method.error_offset
+pub const HAS_ERROR_OFFSET: bool = cfg!(sqlite_has_error_offset);
+
+// The versions that introduced the APIs; keep them in sync with build.rs.
+#[cfg(sqlite_has_prepare_v3)]
+const PREPARE_V3_VERSION: c_int = 3_020_000;
+#[cfg(sqlite_has_keywords)]
+const KEYWORDS_VERSION: c_int = 3_024_000;
+#[cfg(sqlite_has_hard_heap_limit)]
+const HARD_HEAP_LIMIT_VERSION: c_int = 3_031_000;
+#[cfg(sqlite_has_txn_state)]
+const TXN_STATE_VERSION: c_int = 3_034_000;
+#[cfg(sqlite_has_deserialize)]
+const DESERIALIZE_VERSION: c_int = 3_036_000;
+#[cfg(sqlite_has_changes64)]
+const CHANGES64_VERSION: c_int = 3_037_000;
+#[cfg(sqlite_has_error_offset)]
+const ERROR_OFFSET_VERSION: c_int = 3_038_000;
+
+/// Returns `true` if the runtime SQLite is `since` or later.
+#[inline]
+fn runtime_has(since: c_int) -> bool {
+    since <= unsafe { sqlite3_libversion_number() }
+}
+
+/// Calls C function `sqlite3_prepare_v3` , or `sqlite3_prepare_v2` if unavailable.
+#[inline]
+pub(crate) unsafe fn prepare(
+    pdb: *mut sqlite3,
+    zsql: *const c_char,
+    nbyte: c_int,
+    flags: PrepareFlags,
+    ppstmt: *mut *mut sqlite3_stmt,
+    pztail: *mut *const c_char,
+) -> c_int {
+    #[cfg(sqlite_has_prepare_v3)]
+    {
+        if runtime_has(PREPARE_V3_VERSION) {
+            return sqlite3_prepare_v3(pdb, zsql, nbyte, flags.bits(), ppstmt, pztail);
+        }
+    }
+
+    if flags.contains(PrepareFlags::NO_VTAB) {
+        return Error::UNSUPPORTED.code();
+    }
+    sqlite3_prepare_v2(pdb, zsql, nbyte, ppstmt, pztail)
+}
+
+/// Calls C function `sqlite3_changes64` , or `sqlite3_changes` if unavailable.
+#[inline]
+pub(crate) unsafe fn changes64(pdb: *mut sqlite3) -> i64 {
+    #[cfg(sqlite_has_changes64)]
+    {
+        if runtime_has(CHANGES64_VERSION) {
+            return sqlite3_changes64(pdb);
+        }
+    }
+    i64::from(sqlite3_changes(pdb))
+}
+
+/// Returns `true` if `sqlite3_keyword_check` and the related functions are available.
+#[inline]
+pub(crate) fn has_keywords() -> bool {
+    #[cfg(sqlite_has_keywords)]
+    {
+        runtime_has(KEYWORDS_VERSION)
+    }
+    #[cfg(not(sqlite_has_keywords))]
+    {
+        false
+    }
+}
+
+/// Calls C function `sqlite3_keyword_count` , or returns 0 if unavailable.
+#[inline]
+pub(crate) unsafe fn keyword_count() -> c_int {
+    #[cfg(sqlite_has_keywords)]
+    {
+        if runtime_has(KEYWORDS_VERSION) {
+            return sqlite3_keyword_count();
+        }
+    }
+    0
+}
+
+/// Calls C function `sqlite3_keyword_name` , or returns [`Error::UNSUPPORTED`] if unavailable.
+///
+/// [`Error::UNSUPPORTED`]: struct.Error.html#associatedconstant.UNSUPPORTED
+#[inline]
+pub(crate) unsafe fn keyword_name(
+    i: c_int,
+    pzname: *mut *const c_char,
+    pnname: *mut c_int,
+) -> c_int {
+    #[cfg(sqlite_has_keywords)]
+    {
+        if runtime_has(KEYWORDS_VERSION) {
+            return sqlite3_keyword_name(i, pzname, pnname);
+        }
+    }
+    let _ = (i, pzname, pnname);
+    Error::UNSUPPORTED.code()
+}
+
+/// Calls C function `sqlite3_keyword_check` , or returns 0 if unavailable.
+#[inline]
+pub(crate) unsafe fn keyword_check(zname: *const c_char, nname: c_int) -> c_int {
+    #[cfg(sqlite_has_keywords)]
+    {
+        if runtime_has(KEYWORDS_VERSION) {
+            return sqlite3_keyword_check(zname, nname);
+        }
+    }
+    let _ = (zname, nname);
+    0
+}
+
+/// Calls C function `sqlite3_hard_heap_limit64` .
+#[inline]
+pub(crate) fn hard_heap_limit64(n: i64) -> Result<i64, Error> {
+    #[cfg(sqlite_has_hard_heap_limit)]
+    {
+        if runtime_has(HARD_HEAP_LIMIT_VERSION) {
+            return Ok(unsafe { sqlite3_hard_heap_limit64(n) });
+        }
+    }
+    let _ = n;
+    Err(Error::UNSUPPORTED)
+}
+
+/// Calls C function `sqlite3_txn_state` .
+#[inline]
+pub(crate) unsafe fn txn_state(pdb: *mut sqlite3, zschema: *const c_char) -> Result<c_int, Error> {
+    #[cfg(sqlite_has_txn_state)]
+    {
+        if runtime_has(TXN_STATE_VERSION) {
+            return Ok(sqlite3_txn_state(pdb, zschema));
+        }
+    }
+    let _ = (pdb, zschema);
+    Err(Error::UNSUPPORTED)
+}
+
+/// Calls C function `sqlite3_deserialize` , or returns [`Error::UNSUPPORTED`] if unavailable.
+///
+/// [`Error::UNSUPPORTED`]: struct.Error.html#associatedconstant.UNSUPPORTED
+#[inline]
+pub(crate) unsafe fn deserialize(
+    pdb: *mut sqlite3,
+    zschema: *const c_char,
+    pdata: *mut u8,
+    szdb: i64,
+    szbuf: i64,
+    mflags: c_uint,
+) -> c_int {
+    #[cfg(sqlite_has_deserialize)]
+    {
+        if runtime_has(DESERIALIZE_VERSION) {
+            return sqlite3_deserialize(pdb, zschema, pdata, szdb, szbuf, mflags);
+        }
+    }
+    let _ = (pdb, zschema, pdata, szdb, szbuf, mflags);
+    Error::UNSUPPORTED.code()
+}
+
+/// Calls C function `sqlite3_error_offset` , or returns `None` if unavailable.
+#[inline]
+pub(crate) unsafe fn error_offset(pdb: *mut sqlite3) -> Option<c_int> {
+    #[cfg(sqlite_has_error_offset)]
+    {
+        if runtime_has(ERROR_OFFSET_VERSION) {
+            return Some(sqlite3_error_offset(pdb));
+        }
+    }
+    let _ = pdb;
+    None
+}
+
+extern "C" {
+    fn sqlite3_prepare_v2(
+        pdb: *mut sqlite3,
+        zsql: *const c_char,
+        nbyte: c_int,
+        ppstmt: *mut *mut sqlite3_stmt,
+        pztail: *mut *const c_char,
+    ) -> c_int;
+    fn sqlite3_changes(pdb: *mut sqlite3) -> c_int;
+}
+
+#[cfg(sqlite_has_prepare_v3)]
+extern "C" {
+    fn sqlite3_prepare_v3(
+        pdb: *mut sqlite3,
+        zsql: *const c_char,
+        nbyte: c_int,
+        prep_flags: c_uint,
+        ppstmt: *mut *mut sqlite3_stmt,
+        pztail: *mut *const c_char,
+    ) -> c_int;
+}
+
+#[cfg(sqlite_has_keywords)]
+extern "C" {
+    fn sqlite3_keyword_count() -> c_int;
+    fn sqlite3_keyword_name(i: c_int, pzname: *mut *const c_char, pnname: *mut c_int) -> c_int;
+    fn sqlite3_keyword_check(zname: *const c_char, nname: c_int) -> c_int;
+}
+
+#[cfg(sqlite_has_hard_heap_limit)]
+extern "C" {
+    fn sqlite3_hard_heap_limit64(n: i64) -> i64;
+}
+
+#[cfg(sqlite_has_txn_state)]
+extern "C" {
+    fn sqlite3_txn_state(pdb: *mut sqlite3, zschema: *const c_char) -> c_int;
+}
+
+#[cfg(sqlite_has_deserialize)]
+extern "C" {
+    fn sqlite3_deserialize(
+        pdb: *mut sqlite3,
+        zschema: *const c_char,
+        pdata: *mut u8,
+        szdb: i64,
+        szbuf: i64,
+        mflags: c_uint,
+    ) -> c_int;
+}
+
+#[cfg(sqlite_has_changes64)]
+extern "C" {
+    fn sqlite3_changes64(pdb: *mut sqlite3) -> i64;
+}
+
+#[cfg(sqlite_has_error_offset)]
+extern "C" {
+    fn sqlite3_error_offset(db: *mut sqlite3) -> c_int;
+}
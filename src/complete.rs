@@ -0,0 +1,173 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{sqlite3_complete, Error, SQLITE_MISUSE, SQLITE_NOMEM, SQLITE_OK, SQLITE_TOOBIG};
+use core::convert::TryFrom;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+
+/// Wrapper of C function [`sqlite3_complete`] .
+///
+/// Returns whether `sql` ends with one or more complete SQL statements or not; i.e. the last
+/// statement is terminated by a semicolon that is not in a string literal, an identifier, nor a
+/// comment. Note that the syntax is not checked.
+///
+/// Returns `SQLITE_MISUSE` if `sql` includes NUL character, or `SQLITE_NOMEM` if libsqlite3
+/// failed to allocate memory.
+///
+/// [`sqlite3_complete`]: https://www.sqlite.org/c3ref/complete.html
+pub fn is_complete(sql: &str) -> Result<bool, Error> {
+    let sql = CString::new(sql).map_err(|_| Error::new(SQLITE_MISUSE))?;
+    match unsafe { sqlite3_complete(sql.as_ptr()) } {
+        0 => Ok(false),
+        SQLITE_NOMEM => Err(Error::new(SQLITE_NOMEM)),
+        _ => Ok(true),
+    }
+}
+
+/// Wrapper of C function [`sqlite3_keyword_check`] .
+///
+/// Returns whether `word` is an SQL keyword or not. (Case insensitive.)
+///
+/// Always returns `false` unless SQLite provides [`sqlite3_keyword_check`] ; see
+/// [`HAS_KEYWORDS`] .
+///
+/// [`HAS_KEYWORDS`]: constant.HAS_KEYWORDS.html
+/// [`sqlite3_keyword_check`]: https://www.sqlite.org/c3ref/keyword_check.html
+pub fn keyword_check(word: &str) -> bool {
+    match c_int::try_from(word.len()) {
+        Ok(len) => unsafe {
+            crate::compat::keyword_check(word.as_ptr() as *const c_char, len) != 0
+        },
+        Err(_) => false,
+    }
+}
+
+/// Returns all the SQL keywords that the runtime SQLite recognizes in upper case, calling C
+/// functions [`sqlite3_keyword_count`] and [`sqlite3_keyword_name`] .
+///
+/// Returns an empty `Vec` unless SQLite provides them; see [`HAS_KEYWORDS`] .
+///
+/// [`HAS_KEYWORDS`]: constant.HAS_KEYWORDS.html
+/// [`sqlite3_keyword_count`]: https://www.sqlite.org/c3ref/keyword_check.html
+/// [`sqlite3_keyword_name`]: https://www.sqlite.org/c3ref/keyword_check.html
+pub fn keywords() -> Vec<&'static str> {
+    let count = unsafe { crate::compat::keyword_count() };
+    (0..count).filter_map(|i| keyword_name(i).ok()).collect()
+}
+
+/// Returns the `i` th keyword. (The keyword is not NUL terminated.)
+fn keyword_name(i: c_int) -> Result<&'static str, Error> {
+    let mut ptr: *const c_char = core::ptr::null();
+    let mut len: c_int = 0;
+    match unsafe { crate::compat::keyword_name(i, &mut ptr, &mut len) } {
+        SQLITE_OK if !ptr.is_null() => {
+            let len = usize::try_from(len).map_err(|_| Error::new(SQLITE_TOOBIG))?;
+            let bytes = unsafe { core::slice::from_raw_parts(ptr as *const u8, len) };
+            core::str::from_utf8(bytes).map_err(|_| Error::new(SQLITE_MISUSE))
+        }
+        code => Err(Error::new(code)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_complete() {
+        assert_eq!(Ok(false), super::is_complete(""));
+        assert_eq!(Ok(false), super::is_complete("SELECT 1"));
+        assert_eq!(Ok(true), super::is_complete("SELECT 1;"));
+        assert_eq!(Ok(true), super::is_complete("SELECT 1; -- comment\n"));
+
+        // Multi-line statements
+        assert_eq!(Ok(false), super::is_complete("SELECT 1;\nSELECT\n  2"));
+        assert_eq!(Ok(true), super::is_complete("SELECT 1;\nSELECT\n  2;\n"));
+
+        // Semicolons in a string literal or an identifier
+        assert_eq!(Ok(false), super::is_complete("SELECT 'a;b"));
+        assert_eq!(Ok(false), super::is_complete("SELECT 'a;b;'"));
+        assert_eq!(Ok(true), super::is_complete("SELECT 'a;b';"));
+        assert_eq!(Ok(false), super::is_complete(r#"SELECT "a;"#));
+
+        // Unterminated block comment
+        assert_eq!(Ok(false), super::is_complete("SELECT 1; /* comment;"));
+        assert_eq!(Ok(false), super::is_complete("SELECT 1 /* ; */"));
+        assert_eq!(Ok(true), super::is_complete("SELECT 1 /* ; */;"));
+
+        // NUL character
+        let e = super::is_complete("SELECT 1;\0").unwrap_err();
+        assert_eq!(SQLITE_MISUSE, e.code());
+    }
+
+    #[test]
+    fn keywords() {
+        if !crate::HAS_KEYWORDS {
+            assert!(!keyword_check("SELECT"));
+            assert!(super::keywords().is_empty());
+            return;
+        }
+
+        assert!(keyword_check("SELECT"));
+        assert!(keyword_check("select"));
+        assert!(!keyword_check("foo"));
+        assert!(!keyword_check(""));
+
+        let keywords = super::keywords();
+        assert!(100 < keywords.len());
+        assert!(keywords.contains(&"SELECT"));
+        assert!(keywords.contains(&"WHERE"));
+        assert!(keywords.iter().all(|k| keyword_check(k)));
+    }
+}
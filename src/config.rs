@@ -0,0 +1,249 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    sqlite3_config, sqlite3_initialize, sqlite3_shutdown, Error, SQLITE_CONFIG_LOG,
+    SQLITE_CONFIG_LOOKASIDE, SQLITE_CONFIG_MEMSTATUS, SQLITE_CONFIG_PAGECACHE, SQLITE_MISUSE,
+    SQLITE_TOOBIG,
+};
+use core::convert::TryFrom;
+use core::panic::AssertUnwindSafe;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_void};
+
+/// Wrapper of C function [`sqlite3_config`] with option [`SQLITE_CONFIG_LOG`] .
+///
+/// Sets `callback` as the error log callback of libsqlite3, or unsets it if `callback` is
+/// `None` . libsqlite3 passes the (extended) result code and the message to `callback` ; e.g.
+/// the warning of "automatic index" or the detail of `SQLITE_MISUSE` .
+///
+/// `callback` can be called from any thread at the same time. It should be quick and must not
+/// call any libsqlite3 API. If `callback` panics, the panic is caught and discarded.
+///
+/// This function must be called before libsqlite3 is initialized; i.e. before any connection is
+/// opened or [`initialize`] is called, or after [`shutdown`] . Otherwise, returns
+/// `SQLITE_MISUSE` .
+///
+/// [`sqlite3_config`]: https://www.sqlite.org/c3ref/config.html
+/// [`SQLITE_CONFIG_LOG`]: https://www.sqlite.org/c3ref/c_config_covering_index_scan.html
+/// [`initialize`]: fn.initialize.html
+/// [`shutdown`]: fn.shutdown.html
+pub fn config_log(callback: Option<fn(code: i32, msg: &str)>) -> Result<(), Error> {
+    type Trampoline = unsafe extern "C" fn(*mut c_void, c_int, *const c_char);
+
+    let (trampoline, arg) = match callback {
+        None => (None, core::ptr::null_mut()),
+        Some(f) => (Some(log_trampoline as Trampoline), f as *mut c_void),
+    };
+
+    let code = unsafe { sqlite3_config(SQLITE_CONFIG_LOG, trampoline, arg) };
+    config_result(code)
+}
+
+/// Converts the return value of `sqlite3_config()` into `Result` .
+///
+/// `sqlite3_config()` returns `SQLITE_MISUSE` if libsqlite3 is already initialized.
+fn config_result(code: c_int) -> Result<(), Error> {
+    match Error::new(code) {
+        e if e == Error::OK => Ok(()),
+        e if e.code() == SQLITE_MISUSE => {
+            let message = "libsqlite3 is already initialized";
+            Err(Error::with_message(SQLITE_MISUSE, message))
+        }
+        e => Err(e),
+    }
+}
+
+/// Converts `n` into `c_int` , or returns `SQLITE_TOOBIG` .
+fn to_c_int(n: usize) -> Result<c_int, Error> {
+    c_int::try_from(n).map_err(|_| Error::new(SQLITE_TOOBIG))
+}
+
+/// Wrapper of C function [`sqlite3_config`] with option [`SQLITE_CONFIG_MEMSTATUS`] .
+///
+/// Enables or disables the collection of the memory allocation statistics (e.g.
+/// [`soft_heap_limit`] depends on it.) It is enabled by default unless libsqlite3 is built with
+/// `SQLITE_DEFAULT_MEMSTATUS=0` ; disabling it saves a global mutex for each allocation.
+///
+/// This function must be called before libsqlite3 is initialized as [`config_log`] .
+/// Otherwise, returns `SQLITE_MISUSE` .
+///
+/// [`sqlite3_config`]: https://www.sqlite.org/c3ref/config.html
+/// [`SQLITE_CONFIG_MEMSTATUS`]: https://www.sqlite.org/c3ref/c_config_covering_index_scan.html
+/// [`soft_heap_limit`]: fn.soft_heap_limit.html
+/// [`config_log`]: fn.config_log.html
+pub fn config_memstatus(enabled: bool) -> Result<(), Error> {
+    let code = unsafe { sqlite3_config(SQLITE_CONFIG_MEMSTATUS, enabled as c_int) };
+    config_result(code)
+}
+
+/// Wrapper of C function [`sqlite3_config`] with option [`SQLITE_CONFIG_PAGECACHE`] .
+///
+/// Lets libsqlite3 use `buffer` for the page cache of all the connections, which consists of
+/// `n_pages` pages of `page_size` bytes. `page_size` must be a little larger than the database
+/// page size to hold the page header; see [`SQLITE_CONFIG_PAGECACHE`] for details. libsqlite3
+/// allocates the page cache from the heap when `buffer` is exhausted.
+///
+/// libsqlite3 keeps using `buffer` even after [`shutdown`] , so `buffer` must be `'static` ;
+/// e.g. `Box::leak` of a `Vec` .
+///
+/// This function must be called before libsqlite3 is initialized as [`config_log`] .
+/// Otherwise, returns `SQLITE_MISUSE` .
+///
+/// Returns `SQLITE_MISUSE` if `buffer` is shorter than `page_size * n_pages` or if `buffer` is
+/// not aligned to 8 bytes, or `SQLITE_TOOBIG` if `page_size` or `n_pages` exceeds `c_int` .
+///
+/// [`sqlite3_config`]: https://www.sqlite.org/c3ref/config.html
+/// [`SQLITE_CONFIG_PAGECACHE`]: https://www.sqlite.org/c3ref/c_config_covering_index_scan.html
+/// [`shutdown`]: fn.shutdown.html
+/// [`config_log`]: fn.config_log.html
+pub fn config_pagecache(
+    buffer: &'static mut [u8],
+    page_size: usize,
+    n_pages: usize,
+) -> Result<(), Error> {
+    match page_size.checked_mul(n_pages) {
+        Some(len) if len <= buffer.len() => (),
+        _ => {
+            let message = format!(
+                "buffer of {} bytes is shorter than {} pages of {} bytes",
+                buffer.len(),
+                n_pages,
+                page_size
+            );
+            return Err(Error::with_message(SQLITE_MISUSE, message));
+        }
+    }
+    if buffer.as_ptr().align_offset(8) != 0 {
+        let message = "buffer is not aligned to 8 bytes";
+        return Err(Error::with_message(SQLITE_MISUSE, message));
+    }
+
+    let page_size = to_c_int(page_size)?;
+    let n_pages = to_c_int(n_pages)?;
+    let ptr = buffer.as_mut_ptr() as *mut c_void;
+    let code = unsafe { sqlite3_config(SQLITE_CONFIG_PAGECACHE, ptr, page_size, n_pages) };
+    config_result(code)
+}
+
+/// Wrapper of C function [`sqlite3_config`] with option [`SQLITE_CONFIG_LOOKASIDE`] .
+///
+/// Sets the default size of the lookaside memory allocator of each connection; `slots` slots
+/// of `slot_size` bytes. The lookaside allocator is disabled if either is 0.
+///
+/// This function must be called before libsqlite3 is initialized as [`config_log`] .
+/// Otherwise, returns `SQLITE_MISUSE` .
+///
+/// Returns `SQLITE_TOOBIG` if `slot_size` or `slots` exceeds `c_int` .
+///
+/// [`sqlite3_config`]: https://www.sqlite.org/c3ref/config.html
+/// [`SQLITE_CONFIG_LOOKASIDE`]: https://www.sqlite.org/c3ref/c_config_covering_index_scan.html
+/// [`config_log`]: fn.config_log.html
+pub fn config_lookaside(slot_size: usize, slots: usize) -> Result<(), Error> {
+    let slot_size = to_c_int(slot_size)?;
+    let slots = to_c_int(slots)?;
+    let code = unsafe { sqlite3_config(SQLITE_CONFIG_LOOKASIDE, slot_size, slots) };
+    config_result(code)
+}
+
+/// Passes the log to the callback that [`config_log`] set.
+///
+/// `arg` is the callback function.
+///
+/// [`config_log`]: fn.config_log.html
+unsafe extern "C" fn log_trampoline(arg: *mut c_void, code: c_int, msg: *const c_char) {
+    let callback: fn(i32, &str) = core::mem::transmute(arg);
+    let msg = if msg.is_null() {
+        Default::default()
+    } else {
+        CStr::from_ptr(msg).to_string_lossy()
+    };
+
+    // Unwinding across the FFI boundary is not allowed.
+    let _ = std::panic::catch_unwind(AssertUnwindSafe(|| callback(code, &msg)));
+}
+
+/// Wrapper of C function [`sqlite3_initialize`] .
+///
+/// libsqlite3 is initialized automatically when a connection is opened, so it is rarely
+/// necessary to call this function. It does nothing if libsqlite3 is already initialized.
+///
+/// [`sqlite3_initialize`]: https://www.sqlite.org/c3ref/initialize.html
+#[inline]
+pub fn initialize() -> Result<(), Error> {
+    match Error::new(unsafe { sqlite3_initialize() }) {
+        e if e == Error::OK => Ok(()),
+        e => Err(e),
+    }
+}
+
+/// Wrapper of C function [`sqlite3_shutdown`] .
+///
+/// Releases all the resources that [`initialize`] allocated. The global configurations (e.g.
+/// [`config_log`] ) can be changed after this function.
+///
+/// # Safety
+///
+/// All the database connections must be closed before this function is called, and no
+/// connection may be opened in another thread during the call.
+///
+/// [`sqlite3_shutdown`]: https://www.sqlite.org/c3ref/initialize.html
+/// [`initialize`]: fn.initialize.html
+/// [`config_log`]: fn.config_log.html
+#[inline]
+pub unsafe fn shutdown() -> Result<(), Error> {
+    match Error::new(sqlite3_shutdown()) {
+        e if e == Error::OK => Ok(()),
+        e => Err(e),
+    }
+}
@@ -51,120 +51,680 @@
 // ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
 // POSSIBILITY OF SUCH DAMAGE.
 
+use crate::interrupt::InterruptState;
+use crate::stmt_cache::{CacheKey, Sql, StmtCache};
 use crate::{
-    sqlite3, sqlite3_close, sqlite3_open_v2, sqlite3_prepare_v2, sqlite3_stmt, Error, Stmt,
-    SQLITE_OPEN_CREATE, SQLITE_OPEN_MEMORY, SQLITE_OPEN_NOMUTEX, SQLITE_OPEN_READWRITE,
-    SQLITE_TOOBIG,
+    sqlite3, sqlite3_close, sqlite3_db_filename, sqlite3_db_handle, sqlite3_db_readonly,
+    sqlite3_limit, sqlite3_open_v2, sqlite3_stmt, Error, FromRow, PrepareFlags, StepResult, Stmt,
+    StmtCacheStats, StmtStats, ToSql, SQLITE_DESERIALIZE_READONLY, SQLITE_LIMIT_VARIABLE_NUMBER,
+    SQLITE_MISUSE, SQLITE_OPEN_CREATE, SQLITE_OPEN_FULLMUTEX, SQLITE_OPEN_MEMORY,
+    SQLITE_OPEN_READWRITE, SQLITE_OPEN_URI, SQLITE_TOOBIG,
 };
 use core::convert::TryFrom;
-use core::hash::{Hash, Hasher};
+use core::fmt;
 use core::ptr::NonNull;
-use std::collections::hash_map::{Entry, HashMap};
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
 use std::os::raw::{c_char, c_int};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-/// New type of `&'static str` , which is compared by the address.
-#[derive(Debug, Clone, Copy)]
-struct Sql(*const u8);
-
-impl PartialEq for Sql {
-    #[inline]
-    fn eq(&self, other: &Self) -> bool {
-        core::ptr::eq(self.0, other.0)
-    }
-}
-
-impl Eq for Sql {}
-
-impl Hash for Sql {
-    #[inline]
-    fn hash<H>(&self, hasher: &mut H)
-    where
-        H: Hasher,
-    {
-        (self.0 as usize).hash(hasher)
-    }
-}
+/// Closure to initialize each new connection, which [`OpenOptions::init`] registers and
+/// [`Connection::try_clone`] replays.
+///
+/// [`OpenOptions::init`]: struct.OpenOptions.html#method.init
+/// [`Connection::try_clone`]: struct.Connection.html#method.try_clone
+pub(crate) type Init = dyn Fn(&mut Connection) -> Result<(), Error> + Send + Sync;
 
 /// Wrapper of C [`sqlite3 *`] with cache of [`Stmt`] .
 ///
-/// Use `TryFrom` implementation to build an instance, or method [`open_memory_db`] .
+/// Use `TryFrom` implementation to build an instance, method [`open_memory_db`] , or
+/// [`OpenOptions`] .
+///
+/// # Threading model
+///
+/// `Connection` is `Send` but not `Sync` ; it can be moved to another thread, but it is used by
+/// only one thread at a time. A [`Stmt`] that [`stmt_once`] returns does not borrow the
+/// connection, though, so the statement and the connection can be used in different threads at
+/// the same time. That is why the database is always opened with flag `SQLITE_OPEN_FULLMUTEX`
+/// (the "serialized" mode of SQLite.)
 ///
 /// [`open_memory_db`]: #method.open_memory_db
+/// [`stmt_once`]: #method.stmt_once
+/// [`OpenOptions`]: struct.OpenOptions.html
 /// [`sqlite3 *`]: https://www.sqlite.org/c3ref/sqlite3.html
 /// [`Stmt`]: struct.Stmt.html
 pub struct Connection {
     raw: *mut sqlite3,
-    stmts: HashMap<Sql, Stmt>,
+    filename: CString,
+    flags: c_int,
+    inits: Vec<Arc<Init>>,
+    pub(crate) functions: Vec<crate::FunctionInfo>,
+    interrupt: Arc<InterruptState>,
+    pub(crate) stmts: StmtCache,
+    pub(crate) stats_enabled: bool,
+    explicit_txn: bool,
+    pub(crate) close_behavior: crate::CloseBehavior,
+    pub(crate) auto_optimize: Option<Arc<crate::optimize::AutoOptimize>>,
+    pub(crate) stmt_timeout: Option<Box<crate::stmt_timeout::StmtTimeout>>,
+    #[cfg(feature = "preupdate-hook")]
+    pub(crate) preupdate_hook: Option<Box<crate::preupdate_hook::Hook>>,
 }
 
+// Safety:
+// `open_v2` always opens the database in the "serialized" mode, in which libsqlite3 serializes
+// the calls on a database connection and its prepared statements with the mutex of the
+// connection. Moving `Connection` to another thread is safe even while a `Stmt` returned by
+// `stmt_once` is used in another thread. The same argument makes `Stmt` `Send` .
 unsafe impl Send for Connection {}
 
 impl Drop for Connection {
     #[inline]
     fn drop(&mut self) {
-        self.stmts.clear(); // All the Stmt instances must be finalized before close.
-        unsafe { sqlite3_close(self.raw) };
+        if let Err(e) = self.close_impl() {
+            crate::close_behavior::log_drop_error(&e);
+        }
     }
 }
 
+impl fmt::Debug for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Connection")
+            .field("filename", &self.db_filename("main"))
+            .field("cached_stmts", &self.stmts.len())
+            .finish()
+    }
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).as_ref())
+}
+
 impl TryFrom<&Path> for Connection {
     type Error = Box<dyn std::error::Error>;
 
     #[inline]
     fn try_from(filename: &Path) -> Result<Self, Self::Error> {
         let filename = CString::new(filename.to_string_lossy().as_bytes()).map_err(Box::new)?;
-        let mut raw: *mut sqlite3 = core::ptr::null_mut();
-        const FLAGS: c_int = SQLITE_OPEN_READWRITE | SQLITE_OPEN_CREATE | SQLITE_OPEN_NOMUTEX;
+        const FLAGS: c_int = SQLITE_OPEN_READWRITE | SQLITE_OPEN_CREATE | SQLITE_OPEN_FULLMUTEX;
         const ZVFS: *const c_char = core::ptr::null();
 
-        let code = unsafe { sqlite3_open_v2(filename.as_ptr(), &mut raw, FLAGS, ZVFS) };
+        Self::open_v2(&filename, FLAGS, ZVFS).map_err(|e| Box::new(e).into())
+    }
+}
+
+impl Connection {
+    /// Closes the database after doing what [`close_behavior`] specifies, and returns the error
+    /// that drop would ignore.
+    ///
+    /// The database is closed even if [`close_behavior`] fails.
+    ///
+    /// [`close_behavior`]: #method.close_behavior
+    #[inline]
+    pub fn close(mut self) -> Result<(), Error> {
+        self.close_impl()
+    }
+
+    /// Closes the database and sets NULL to `self.raw` unless it is already closed.
+    fn close_impl(&mut self) -> Result<(), Error> {
+        if self.raw.is_null() {
+            return Ok(());
+        }
+
+        self.stmts.clear(); // All the Stmt instances must be finalized before close.
+        let ret = self.run_close_behavior();
+
+        #[cfg(feature = "unlock-notify")]
+        crate::unlock_notify::unregister(self.raw);
+        self.interrupt.close();
+        if self.stmt_timeout.is_some() {
+            crate::stmt_timeout::forget(self.raw);
+        }
+        let code = unsafe { sqlite3_close(self.raw) };
+        self.raw = core::ptr::null_mut();
+
+        ret?;
         match Error::new(code) {
-            Error::OK => Ok(Self {
+            e if e == Error::OK => Ok(()),
+            e => Err(e),
+        }
+    }
+
+    /// Returns the raw pointer of `sqlite3` .
+    #[inline]
+    pub(crate) fn raw(&self) -> *mut sqlite3 {
+        self.raw
+    }
+
+    #[inline]
+    pub(crate) fn interrupt_state(&self) -> &Arc<InterruptState> {
+        &self.interrupt
+    }
+
+    /// Returns the raw pointer of C [`sqlite3`] to call the C functions that this crate does not
+    /// wrap.
+    ///
+    /// The ownership is not transferred; the pointer is valid while `self` is alive.
+    ///
+    /// # Safety
+    ///
+    /// The caller must not close the database connection; `self` does it on drop. The caller must
+    /// not finalize nor step the statements that `self` caches. Changing the connection settings
+    /// (e.g. the busy handler) is tolerated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse_sqlite3::{sqlite3, Connection};
+    /// use std::os::raw::{c_char, c_int};
+    ///
+    /// extern "C" {
+    ///     fn sqlite3_db_readonly(db: *mut sqlite3, zDbName: *const c_char) -> c_int;
+    /// }
+    ///
+    /// let con = Connection::open_memory_db().unwrap();
+    /// let readonly = unsafe { sqlite3_db_readonly(con.as_ptr(), b"main\0".as_ptr() as _) };
+    /// assert_eq!(0, readonly);
+    /// ```
+    ///
+    /// [`sqlite3`]: https://www.sqlite.org/c3ref/sqlite3.html
+    #[inline]
+    pub unsafe fn as_ptr(&self) -> *mut sqlite3 {
+        self.raw
+    }
+
+    /// Wrapper of C function [`sqlite3_db_handle`] .
+    ///
+    /// Returns the raw pointer of C [`sqlite3`] that `stmt` belongs to. The ownership is not
+    /// transferred. See [`as_ptr`] for the safety.
+    ///
+    /// # Safety
+    ///
+    /// The same as [`as_ptr`] .
+    ///
+    /// [`sqlite3_db_handle`]: https://www.sqlite.org/c3ref/db_handle.html
+    /// [`sqlite3`]: https://www.sqlite.org/c3ref/sqlite3.html
+    /// [`as_ptr`]: #method.as_ptr
+    #[inline]
+    pub unsafe fn db_handle_of(stmt: &Stmt) -> *mut sqlite3 {
+        sqlite3_db_handle(stmt.raw())
+    }
+
+    /// Calls C function [`sqlite3_open_v2`] and returns a new instance.
+    ///
+    /// [`sqlite3_open_v2`]: https://www.sqlite.org/c3ref/open.html
+    pub(crate) fn open_v2(
+        filename: &CStr,
+        flags: c_int,
+        zvfs: *const c_char,
+    ) -> Result<Self, Error> {
+        let mut raw: *mut sqlite3 = core::ptr::null_mut();
+        // `Connection` is `Send` only in the "serialized" mode.
+        debug_assert_eq!(SQLITE_OPEN_FULLMUTEX, flags & SQLITE_OPEN_FULLMUTEX);
+
+        let code = unsafe { sqlite3_open_v2(filename.as_ptr(), &mut raw, flags, zvfs) };
+        match Error::new(code) {
+            e if e == Error::OK => Ok(Self {
                 raw,
+                filename: filename.to_owned(),
+                flags,
+                inits: Vec::new(),
+                functions: Vec::new(),
+                interrupt: InterruptState::new(raw),
                 stmts: Default::default(),
+                stats_enabled: false,
+                explicit_txn: false,
+                close_behavior: Default::default(),
+                auto_optimize: None,
+                stmt_timeout: None,
+                #[cfg(feature = "preupdate-hook")]
+                preupdate_hook: None,
             }),
-            e => Err(Box::new(e)),
+            e => {
+                let e = e.with_system_errno(raw);
+                // sqlite3_open_v2() allocates the handle even if it failed (unless out of memory.)
+                unsafe { sqlite3_close(raw) };
+                Err(e)
+            }
         }
     }
-}
 
-impl Connection {
     /// Opens in-memory database and returns a new instance.
     #[inline]
     pub fn open_memory_db() -> Result<Self, Error> {
-        let filename: *const c_char = "memory_db".as_ptr() as *const c_char;
-        let mut raw: *mut sqlite3 = core::ptr::null_mut();
-        const FLAGS: c_int = SQLITE_OPEN_MEMORY | SQLITE_OPEN_READWRITE | SQLITE_OPEN_NOMUTEX;
+        let filename = CStr::from_bytes_with_nul(b"memory_db\0").unwrap();
+        const FLAGS: c_int = SQLITE_OPEN_MEMORY | SQLITE_OPEN_READWRITE | SQLITE_OPEN_FULLMUTEX;
         const ZVFS: *const c_char = core::ptr::null();
 
-        let code = unsafe { sqlite3_open_v2(filename, &mut raw, FLAGS, ZVFS) };
+        Self::open_v2(filename, FLAGS, ZVFS)
+    }
+
+    /// Opens an in-memory database of the serialized image `data` in read only mode, and returns
+    /// a new instance.
+    ///
+    /// This is built on C function [`sqlite3_deserialize`] with flag
+    /// `SQLITE_DESERIALIZE_READONLY` , so `data` is neither copied nor written; any write to the
+    /// database fails with `SQLITE_READONLY` .
+    ///
+    /// `data` is typically embedded in the binary with `include_bytes!` .
+    ///
+    /// [`sqlite3_deserialize`]: https://www.sqlite.org/c3ref/deserialize.html
+    pub fn open_serialized(data: &'static [u8]) -> Result<Self, Error> {
+        let con = Self::open_memory_db()?;
+
+        let schema = CStr::from_bytes_with_nul(b"main\0").unwrap();
+        // libsqlite3 never writes into the buffer because of SQLITE_DESERIALIZE_READONLY.
+        let ptr = data.as_ptr() as *mut u8;
+        let len = i64::try_from(data.len()).map_err(|_| Error::new(SQLITE_TOOBIG))?;
+        let flags = SQLITE_DESERIALIZE_READONLY;
+
+        let code =
+            unsafe { crate::compat::deserialize(con.raw, schema.as_ptr(), ptr, len, len, flags) };
         match Error::new(code) {
-            Error::OK => Ok(Self {
-                raw,
-                stmts: Default::default(),
-            }),
+            e if e == Error::OK => Ok(con),
             e => Err(e),
         }
     }
 
+    /// Calls `init` with `self` , and registers it to be called on the connections that
+    /// [`try_clone`] opens after that.
+    ///
+    /// `init` typically executes PRAGMA statements or registers SQL functions. To initialize all
+    /// the connections in one place, use [`OpenOptions::init`] instead.
+    ///
+    /// `init` is not registered if it returns an error.
+    ///
+    /// [`try_clone`]: #method.try_clone
+    /// [`OpenOptions::init`]: struct.OpenOptions.html#method.init
+    pub fn add_init<F>(&mut self, init: F) -> Result<(), Error>
+    where
+        F: 'static + Fn(&mut Connection) -> Result<(), Error> + Send + Sync,
+    {
+        init(self)?;
+        self.inits.push(Arc::new(init));
+        Ok(())
+    }
+
+    /// Opens another connection to the same database with the same flags, and calls the closures
+    /// registered by [`add_init`] on the new connection in the registered order.
+    ///
+    /// The statement cache, the hooks, and the settings not done in the closures are not copied.
+    ///
+    /// An in-memory or a temporary database is private to the connection, so cloning it fails
+    /// with `SQLITE_MISUSE` . To share an in-memory database, open it by [`OpenOptions`] with the
+    /// option [`uri`] and a shared cache URI like "file:foo?mode=memory&cache=shared" ; then this
+    /// method opens the same URI.
+    ///
+    /// [`add_init`]: #method.add_init
+    /// [`OpenOptions`]: struct.OpenOptions.html
+    /// [`uri`]: struct.OpenOptions.html#method.uri
+    pub fn try_clone(&self) -> Result<Self, Error> {
+        // sqlite3_db_filename() returns an empty string for an in-memory or a temporary database
+        // however it is opened, e.g. ":memory:", "file::memory:", or "file:foo?mode=memory".
+        let is_private = self.db_filename("main").is_none() && !self.is_shared_cache_uri();
+        if is_private {
+            let message = "in-memory database cannot be cloned without a shared cache URI";
+            return Err(Error::with_message(SQLITE_MISUSE, message));
+        }
+
+        let mut con = Self::open_v2(&self.filename, self.flags, core::ptr::null())?;
+        con.run_inits(&self.inits)?;
+        Ok(con)
+    }
+
+    /// Returns whether `self` is opened with a URI filename with query parameter "cache=shared".
+    fn is_shared_cache_uri(&self) -> bool {
+        if self.flags & SQLITE_OPEN_URI == 0 {
+            return false;
+        }
+        let filename = self.filename.to_bytes();
+        if !filename.starts_with(b"file:") {
+            return false;
+        }
+        match filename.iter().position(|&b| b == b'?') {
+            None => false,
+            Some(i) => filename[i + 1..]
+                .split(|&b| b == b'&')
+                .any(|param| param == b"cache=shared"),
+        }
+    }
+
+    /// Calls `inits` with `self` in order, and registers them as [`add_init`] does.
+    ///
+    /// [`add_init`]: #method.add_init
+    pub(crate) fn run_inits(&mut self, inits: &[Arc<Init>]) -> Result<(), Error> {
+        for init in inits {
+            init(self)?;
+            self.inits.push(init.clone());
+        }
+        Ok(())
+    }
+
+    /// Wrapper of C function [`sqlite3_db_filename`] .
+    ///
+    /// Returns the absolute path of the database file of `schema` (i.e. "main", "temp", or the
+    /// name of an attached database.)
+    ///
+    /// Returns `None` if `schema` is not the name of any schema, or if the database is in-memory
+    /// or temporary.
+    ///
+    /// [`sqlite3_db_filename`]: https://www.sqlite.org/c3ref/db_filename.html
+    pub fn db_filename(&self, schema: &str) -> Option<PathBuf> {
+        let schema = CString::new(schema).ok()?;
+        let ptr = unsafe { sqlite3_db_filename(self.raw, schema.as_ptr()) };
+        if ptr.is_null() {
+            return None;
+        }
+
+        let filename = unsafe { CStr::from_ptr(ptr) };
+        match filename.to_bytes() {
+            [] => None,
+            bytes => Some(path_from_bytes(bytes)),
+        }
+    }
+
+    /// Wrapper of C function [`sqlite3_db_readonly`] .
+    ///
+    /// Returns whether the database of `schema` is read-only or not.
+    ///
+    /// Returns `SQLITE_MISUSE` if `schema` is not the name of any schema.
+    ///
+    /// [`sqlite3_db_readonly`]: https://www.sqlite.org/c3ref/db_readonly.html
+    pub fn is_readonly(&self, schema: &str) -> Result<bool, Error> {
+        let schema = CString::new(schema).map_err(|_| Error::new(SQLITE_MISUSE))?;
+        match unsafe { sqlite3_db_readonly(self.raw, schema.as_ptr()) } {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(Error::new(SQLITE_MISUSE)),
+        }
+    }
+
     /// Creates and caches [`Stmt`] if not cached and provides a reference to the cached instance.
     ///
+    /// If the instance is cached, calls [`Stmt::reset`] before returning it, but does not call
+    /// [`Stmt::clear_bindings`] not to bind all the parameters again for each call. I.e. the
+    /// parameters bound by the previous caller are kept, and a parameter that the caller does not
+    /// bind is not always NULL. [`Stmt::bind_all`] and the methods built on it (e.g.
+    /// [`query_one`] ) clear the bindings first.
+    ///
+    /// The cache is unbounded by default; see [`set_stmt_cache_capacity`] .
+    ///
     /// [`Stmt`]: struct.Stmt.html
+    /// [`Stmt::reset`]: struct.Stmt.html#method.reset
+    /// [`Stmt::clear_bindings`]: struct.Stmt.html#method.clear_bindings
+    /// [`Stmt::bind_all`]: struct.Stmt.html#method.bind_all
+    /// [`query_one`]: #method.query_one
+    /// [`set_stmt_cache_capacity`]: #method.set_stmt_cache_capacity
     #[inline]
     pub fn stmt(&mut self, sql: &'static str) -> Result<&mut Stmt, Error> {
-        match self.stmts.entry(Sql(sql.as_ptr())) {
-            Entry::Occupied(o) => {
-                let stmt = o.into_mut();
-                stmt.clear();
-                Ok(stmt)
+        let key = CacheKey {
+            sql: Sql(sql),
+            arity: None,
+        };
+        let raw = self.raw;
+        let stats_enabled = self.stats_enabled;
+        let explicit_txn = self.explicit_txn;
+        let auto_optimize = &self.auto_optimize;
+        let (stmt, hit) = self.stmts.get_or_insert_with(key, || {
+            let mut stmt = Self::build_stmt(raw, sql, PrepareFlags::PERSISTENT)?;
+            stmt.enable_stats(stats_enabled);
+            stmt.require_explicit_transaction(explicit_txn);
+            stmt.set_auto_optimize(auto_optimize.clone());
+            Ok(stmt)
+        })?;
+        if hit {
+            stmt.reset();
+            #[cfg(feature = "tracing")]
+            crate::trace::cache_hit(sql);
+        }
+        Ok(stmt)
+    }
+
+    /// Same to [`stmt`] except for replacing the marker "{placeholders}" in `template` with `n`
+    /// anonymous parameters separated by comma (i.e. [`sql::placeholders`] .)
+    ///
+    /// The statement is cached for each pair of `template` and `n` , so a query taking a list of
+    /// the same length reuses the prepared statement.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse_sqlite3::Connection;
+    ///
+    /// let mut con = Connection::open_memory_db().unwrap();
+    /// con.execute_batch(r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY)"#).unwrap();
+    ///
+    /// const SQL: &str = r#"SELECT count(*) FROM "foo" WHERE "id" IN ({placeholders})"#;
+    /// let stmt = con.stmt_with_arity(SQL, 3).unwrap();
+    /// stmt.bind_all(&[&1, &2, &3]).unwrap();
+    /// assert_eq!(Ok(true), stmt.step());
+    /// ```
+    ///
+    /// Returns `SQLITE_TOOBIG` if `n` exceeds `SQLITE_LIMIT_VARIABLE_NUMBER` , or
+    /// `SQLITE_MISUSE` if `template` does not include the marker.
+    ///
+    /// [`stmt`]: #method.stmt
+    /// [`sql::placeholders`]: sql/fn.placeholders.html
+    pub fn stmt_with_arity(
+        &mut self,
+        template: &'static str,
+        n: usize,
+    ) -> Result<&mut Stmt, Error> {
+        let key = CacheKey {
+            sql: Sql(template),
+            arity: Some(n),
+        };
+        let raw = self.raw;
+        let stats_enabled = self.stats_enabled;
+        let explicit_txn = self.explicit_txn;
+        let auto_optimize = &self.auto_optimize;
+        let (stmt, hit) = self.stmts.get_or_insert_with(key, || {
+            let limit = unsafe { sqlite3_limit(raw, SQLITE_LIMIT_VARIABLE_NUMBER, -1) };
+            if limit < 0 || (limit as usize) < n {
+                let message = format!(
+                    "{} parameters exceed SQLITE_LIMIT_VARIABLE_NUMBER ({})",
+                    n, limit
+                );
+                return Err(Error::with_message(SQLITE_TOOBIG, message));
             }
-            Entry::Vacant(v) => {
-                let stmt = Self::build_stmt(self.raw, sql)?;
-                Ok(v.insert(stmt))
+            if !template.contains(crate::sql::PLACEHOLDERS) {
+                let message = format!(
+                    "{:?} does not include {}",
+                    template,
+                    crate::sql::PLACEHOLDERS
+                );
+                return Err(Error::with_message(SQLITE_MISUSE, message));
             }
+
+            let sql = template.replace(crate::sql::PLACEHOLDERS, &crate::sql::placeholders(n));
+            let mut stmt = Self::build_stmt(raw, &sql, PrepareFlags::PERSISTENT)?;
+            stmt.enable_stats(stats_enabled);
+            stmt.require_explicit_transaction(explicit_txn);
+            stmt.set_auto_optimize(auto_optimize.clone());
+            Ok(stmt)
+        })?;
+        if hit {
+            stmt.reset();
+            #[cfg(feature = "tracing")]
+            crate::trace::cache_hit(template);
+        }
+        Ok(stmt)
+    }
+
+    /// Starts collecting the execution metrics of the statements that [`stmt`] caches if
+    /// `enabled` is `true` , or stops collecting and discards them if `false` .
+    ///
+    /// The metrics are disabled by default. The statements that [`stmt_once`] returns are not
+    /// tracked.
+    ///
+    /// [`stmt`]: #method.stmt
+    /// [`stmt_once`]: #method.stmt_once
+    pub fn enable_stats(&mut self, enabled: bool) {
+        self.stats_enabled = enabled;
+        for stmt in self.stmts.values_mut() {
+            stmt.enable_stats(enabled);
+        }
+        self.enable_auto_optimize_stats(enabled);
+    }
+
+    /// Makes the statements of `self` fail with [`Error::AUTOCOMMIT_WRITE`] when they are about
+    /// to write the database out of any transaction (i.e. in autocommit mode) if `required` is
+    /// `true` . (The default is `false` .)
+    ///
+    /// This guards against writes that accidentally run out of the intended transaction. The
+    /// statements are checked by [`Stmt::is_readonly`] before executed, so reading statements
+    /// and transaction control statements such as "BEGIN" are not affected, while "CREATE
+    /// TABLE" or "PRAGMA" changing the database are.
+    ///
+    /// The setting applies to the statements cached or created by `self` from now on and the
+    /// cached ones, but not to the ones that [`stmt_once`] has already returned.
+    ///
+    /// [`Error::AUTOCOMMIT_WRITE`]: struct.Error.html#associatedconstant.AUTOCOMMIT_WRITE
+    /// [`Stmt::is_readonly`]: struct.Stmt.html#method.is_readonly
+    /// [`stmt_once`]: #method.stmt_once
+    pub fn require_explicit_transactions(&mut self, required: bool) {
+        self.explicit_txn = required;
+        for stmt in self.stmts.values_mut() {
+            stmt.require_explicit_transaction(required);
+        }
+    }
+
+    /// Returns whether [`require_explicit_transactions`] is enabled.
+    ///
+    /// [`require_explicit_transactions`]: #method.require_explicit_transactions
+    #[inline]
+    pub fn requires_explicit_transactions(&self) -> bool {
+        self.explicit_txn
+    }
+
+    /// Returns the execution metrics of the cached statements executed at least once since
+    /// [`enable_stats`] or [`reset_stats`] was called.
+    ///
+    /// Returns an empty `Vec` if the metrics are disabled.
+    ///
+    /// [`enable_stats`]: #method.enable_stats
+    /// [`reset_stats`]: #method.reset_stats
+    pub fn stats(&self) -> Vec<StmtStats> {
+        let mut stats: Vec<StmtStats> = self.stmts.values().filter_map(Stmt::stats).collect();
+        stats.extend(self.auto_optimize_stats());
+        stats
+    }
+
+    /// Discards the execution metrics collected so far.
+    pub fn reset_stats(&mut self) {
+        for stmt in self.stmts.values_mut() {
+            stmt.enable_stats(false);
+            stmt.enable_stats(self.stats_enabled);
         }
+        self.enable_auto_optimize_stats(false);
+        self.enable_auto_optimize_stats(self.stats_enabled);
+    }
+
+    /// Sets the max number of the statements that [`stmt`] and [`stmt_with_arity`] cache, or
+    /// `None` for unbounded. (The default is `None` .)
+    ///
+    /// If the cache is full, the least recently used statement that is not pinned is finalized
+    /// to cache a new one. See [`pin_stmt`] .
+    ///
+    /// Returns `SQLITE_MISUSE` if `capacity` is less than the number of the pinned statements.
+    ///
+    /// [`stmt`]: #method.stmt
+    /// [`stmt_with_arity`]: #method.stmt_with_arity
+    /// [`pin_stmt`]: #method.pin_stmt
+    #[inline]
+    pub fn set_stmt_cache_capacity(&mut self, capacity: Option<usize>) -> Result<(), Error> {
+        self.stmts.set_capacity(capacity)
+    }
+
+    /// Sets the max bytes of the heap memory that the statements cached by [`stmt`] and
+    /// [`stmt_with_arity`] use, or `None` for unbounded. (The default is `None` .)
+    ///
+    /// The memory of each statement is the value of [`StmtStatus::MemUsed`] . Whenever a new
+    /// statement is cached, the least recently used statements that are not pinned are finalized
+    /// until the total fits `bytes` . The new statement itself is kept even if it alone exceeds
+    /// `bytes` , as are the pinned statements. See also [`set_stmt_cache_capacity`] .
+    ///
+    /// [`stmt`]: #method.stmt
+    /// [`stmt_with_arity`]: #method.stmt_with_arity
+    /// [`StmtStatus::MemUsed`]: enum.StmtStatus.html#variant.MemUsed
+    /// [`set_stmt_cache_capacity`]: #method.set_stmt_cache_capacity
+    #[inline]
+    pub fn set_stmt_cache_budget_bytes(&mut self, bytes: Option<usize>) {
+        self.stmts.set_budget_bytes(bytes)
+    }
+
+    /// Prepares and caches the statement of `sql` as [`stmt`] does unless cached, and pins it not
+    /// to be evicted from the cache until [`unpin_stmt`] is called.
+    ///
+    /// A pinned statement is cached even if the cache is full; a new statement is cached at the
+    /// cost of the other statements that are not pinned.
+    ///
+    /// Returns `SQLITE_MISUSE` if all the capacity of the cache is already pinned.
+    ///
+    /// [`stmt`]: #method.stmt
+    /// [`unpin_stmt`]: #method.unpin_stmt
+    pub fn pin_stmt(&mut self, sql: &'static str) -> Result<(), Error> {
+        let key = CacheKey {
+            sql: Sql(sql),
+            arity: None,
+        };
+        self.stmts.check_pin(&key)?;
+        self.stmt(sql)?;
+        self.stmts.set_pinned(&key, true);
+        Ok(())
+    }
+
+    /// Lets the statement pinned by [`pin_stmt`] be evicted from the cache again.
+    ///
+    /// Returns `false` if the statement of `sql` is not cached.
+    ///
+    /// [`pin_stmt`]: #method.pin_stmt
+    #[inline]
+    pub fn unpin_stmt(&mut self, sql: &'static str) -> bool {
+        let key = CacheKey {
+            sql: Sql(sql),
+            arity: None,
+        };
+        self.stmts.set_pinned(&key, false)
+    }
+
+    /// Returns the statistics of the statement cache.
+    #[inline]
+    pub fn stmt_cache_stats(&self) -> StmtCacheStats {
+        self.stmts.stats()
+    }
+
+    /// Returns the SQL of the statements pinned by [`pin_stmt`] .
+    ///
+    /// [`pin_stmt`]: #method.pin_stmt
+    #[inline]
+    pub(crate) fn pinned_sqls(&self) -> Vec<&'static str> {
+        self.stmts.pinned_sqls()
+    }
+
+    /// Returns the number of the statements that [`stmt`] and [`stmt_with_arity`] have cached.
+    ///
+    /// [`stmt`]: #method.stmt
+    /// [`stmt_with_arity`]: #method.stmt_with_arity
+    #[inline]
+    pub(crate) fn cached_stmt_count(&self) -> usize {
+        self.stmts.len()
+    }
+
+    /// Finalizes all the statements that [`stmt`] and [`stmt_with_arity`] have cached, including
+    /// the pinned ones.
+    ///
+    /// [`stmt`]: #method.stmt
+    /// [`stmt_with_arity`]: #method.stmt_with_arity
+    #[inline]
+    pub fn clear_stmt_cache(&mut self) {
+        self.stmts.clear();
     }
 
     /// Creates [`Stmt`] instance.
@@ -172,21 +732,152 @@ impl Connection {
     /// [`Stmt`]: struct.Stmt.html
     #[inline]
     pub fn stmt_once(&mut self, sql: &str) -> Result<Stmt, Error> {
-        Self::build_stmt(self.raw, sql)
+        self.stmt_once_with_flags(sql, PrepareFlags::EMPTY)
     }
 
+    /// Creates [`Stmt`] instance passing `flags` to C function [`sqlite3_prepare_v3`] .
+    ///
+    /// [`Stmt`]: struct.Stmt.html
+    /// [`sqlite3_prepare_v3`]: https://www.sqlite.org/c3ref/prepare.html
     #[inline]
-    fn build_stmt(raw: *mut sqlite3, sql: &str) -> Result<Stmt, Error> {
+    pub fn stmt_once_with_flags(&mut self, sql: &str, flags: PrepareFlags) -> Result<Stmt, Error> {
+        let mut stmt = Self::build_stmt(self.raw, sql, flags)?;
+        stmt.require_explicit_transaction(self.explicit_txn);
+        stmt.set_auto_optimize(self.auto_optimize.clone());
+        Ok(stmt)
+    }
+
+    /// Executes `sql` with `params` through the statement cache, and returns the number of the
+    /// rows modified, inserted, or deleted.
+    ///
+    /// This method is the same as calling [`stmt`] and [`Stmt::bind_and_execute`] ; the cached
+    /// statement is reset and the parameters are cleared before returning, even if an error
+    /// occurs.
+    ///
+    /// Note that the index of `params` starts at 0 while the parameter index starts at 1; i.e.
+    /// `params[0]` is bound to `?1` .
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse_sqlite3::Connection;
+    ///
+    /// let mut con = Connection::open_memory_db().unwrap();
+    /// con.execute_batch(r#"CREATE TABLE "foo" ("id" INTEGER, "name" TEXT)"#).unwrap();
+    ///
+    /// let changes = con.execute(r#"INSERT INTO "foo" VALUES (?1, ?2)"#, &[&1, &"bar"]);
+    /// assert_eq!(Ok(1), changes);
+    /// ```
+    ///
+    /// [`stmt`]: #method.stmt
+    /// [`Stmt::bind_and_execute`]: struct.Stmt.html#method.bind_and_execute
+    pub fn execute(&mut self, sql: &'static str, params: &[&dyn ToSql]) -> Result<usize, Error> {
+        self.stmt(sql)?.bind_and_execute(params)
+    }
+
+    /// Executes `sql` with `params` through the statement cache, and returns the first row
+    /// converted into `T` , or `None` if no row is returned.
+    ///
+    /// The rest rows are discarded. The cached statement is reset and the parameters are cleared
+    /// before returning, even if an error occurs.
+    ///
+    /// Note that the index of `params` starts at 0 while the parameter index starts at 1; i.e.
+    /// `params[0]` is bound to `?1` .
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse_sqlite3::Connection;
+    ///
+    /// let mut con = Connection::open_memory_db().unwrap();
+    /// con.execute_batch(r#"CREATE TABLE "foo" ("id" INTEGER, "name" TEXT)"#).unwrap();
+    /// con.execute_batch(r#"INSERT INTO "foo" VALUES (1, 'bar')"#).unwrap();
+    ///
+    /// let row = con.query_one(r#"SELECT "name" FROM "foo" WHERE "id" = ?1"#, &[&1]);
+    /// assert_eq!(Ok(Some((String::from("bar"),))), row);
+    /// ```
+    pub fn query_one<T>(
+        &mut self,
+        sql: &'static str,
+        params: &[&dyn ToSql],
+    ) -> Result<Option<T>, Error>
+    where
+        T: FromRow,
+    {
+        let stmt = self.stmt(sql)?;
+        let ret = stmt.bind_all(params).and_then(|_| match stmt.step2()? {
+            StepResult::Row => T::from_row(&stmt.row()?).map(Some),
+            StepResult::Done { .. } => Ok(None),
+        });
+        let cleared = stmt.clear();
+        let row = ret?;
+        cleared.map(|_| row)
+    }
+
+    /// Executes `sql` , which may consist of zero or more SQL statements separated by ';' .
+    ///
+    /// The statements are compiled and executed one by one, and the rows that the statements
+    /// return are discarded.
+    /// If a statement fails, returns the error immediately; the following statements are not
+    /// executed.
+    pub fn execute_batch(&mut self, sql: &str) -> Result<(), Error> {
+        let mut rest = sql;
+        while !rest.is_empty() {
+            let (stmt, tail) = Self::prepare(self.raw, rest, PrepareFlags::EMPTY)?;
+            if let Some(mut stmt) = stmt {
+                stmt.require_explicit_transaction(self.explicit_txn);
+                stmt.set_auto_optimize(self.auto_optimize.clone());
+                while let StepResult::Row = stmt.step2()? {}
+            }
+            rest = tail;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn build_stmt(raw: *mut sqlite3, sql: &str, flags: PrepareFlags) -> Result<Stmt, Error> {
+        #[cfg(feature = "tracing")]
+        let start = std::time::Instant::now();
+
+        let ret = match Self::prepare(raw, sql, flags) {
+            Ok((Some(stmt), _)) => Ok(stmt),
+            Ok((None, _)) => Err(Error::with_message(SQLITE_MISUSE, "no SQL statement")),
+            Err(e) => Err(e),
+        };
+
+        #[cfg(feature = "tracing")]
+        crate::trace::prepare(sql, start, &ret);
+        ret
+    }
+
+    /// Compiles the first SQL statement in `sql` and returns it with the rest of `sql` .
+    ///
+    /// The returned `Stmt` is `None` if `sql` is empty or only a comment.
+    pub(crate) fn prepare(
+        raw: *mut sqlite3,
+        sql: &str,
+        flags: PrepareFlags,
+    ) -> Result<(Option<Stmt>, &str), Error> {
+        #[cfg(test)]
+        tests::LAST_PREPARE_FLAGS.with(|f| f.set(flags));
+
         let zsql = sql.as_ptr() as *const c_char;
         let nbytes = c_int::try_from(sql.len()).map_err(|_| Error::new(SQLITE_TOOBIG))?;
         let mut raw_stmt: *mut sqlite3_stmt = core::ptr::null_mut();
         let mut pztail: *const c_char = core::ptr::null();
 
-        let code = unsafe { sqlite3_prepare_v2(raw, zsql, nbytes, &mut raw_stmt, &mut pztail) };
+        let code =
+            unsafe { crate::compat::prepare(raw, zsql, nbytes, flags, &mut raw_stmt, &mut pztail) };
         match Error::new(code) {
-            Error::OK => {
-                let ptr = NonNull::new(raw_stmt).unwrap();
-                Ok(crate::stmt_from_raw(ptr))
+            e if e == Error::OK => {
+                let consumed = if pztail.is_null() {
+                    sql.len()
+                } else {
+                    pztail as usize - zsql as usize
+                };
+                let tail = sql.get(consumed..).unwrap_or("");
+                let stmt = NonNull::new(raw_stmt).map(|raw| crate::stmt_from_raw(raw, flags));
+                Ok((stmt, tail))
             }
             e => Err(e),
         }
@@ -196,9 +887,48 @@ impl Connection {
 #[cfg(test)]
 mod tests {
     use super::Connection;
+    use crate::{
+        sqlite3_limit, PrepareFlags, ToSql, SQLITE_LIMIT_VARIABLE_NUMBER, SQLITE_MISUSE,
+        SQLITE_TOOBIG,
+    };
+    use core::cell::Cell;
     use core::convert::TryFrom;
+    use std::path::PathBuf;
     use tempfile::tempdir;
 
+    thread_local! {
+        /// Flags passed to `sqlite3_prepare_v3()` last time in this thread.
+        pub static LAST_PREPARE_FLAGS: Cell<PrepareFlags> =
+            const { Cell::new(PrepareFlags::EMPTY) };
+    }
+
+    #[test]
+    fn prepare_flags() {
+        let last_flags = || LAST_PREPARE_FLAGS.with(|f| f.get());
+
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("value" INTEGER)"#)
+            .unwrap();
+
+        let stmt = con.stmt(r#"INSERT INTO "foo" VALUES (1)"#).unwrap();
+        assert_eq!(PrepareFlags::PERSISTENT, last_flags());
+        assert_eq!(Ok(false), stmt.step());
+
+        let mut stmt = con.stmt_once(r#"SELECT count(*) FROM "foo""#).unwrap();
+        assert_eq!(PrepareFlags::EMPTY, last_flags());
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(1), stmt.get::<i64>(0));
+
+        // "json_each" is a virtual table.
+        const VTAB: &str = "SELECT * FROM json_each('[1]')";
+        let flags = PrepareFlags::NO_VTAB | PrepareFlags::PERSISTENT;
+        assert!(flags.contains(PrepareFlags::NO_VTAB));
+        assert!(!flags.contains(PrepareFlags::NORMALIZE));
+        assert!(con.stmt_once_with_flags(VTAB, flags).is_err());
+        assert_eq!(flags, last_flags());
+        assert!(con.stmt_once_with_flags(VTAB, PrepareFlags::EMPTY).is_ok());
+    }
+
     #[test]
     fn memory_db() {
         let mut con = Connection::open_memory_db().unwrap();
@@ -234,6 +964,49 @@ mod tests {
         assert_eq!(Ok(false), stmt.step());
     }
 
+    #[test]
+    fn insert_select_text() {
+        let mut con = Connection::open_memory_db().unwrap();
+
+        {
+            let sql = r#"CREATE TABLE "foo" ("_id" INTEGER PRIMARY KEY, "value" TEXT)"#;
+            let mut stmt = con.stmt_once(sql).unwrap();
+            assert_eq!(Ok(false), stmt.step());
+        }
+
+        {
+            let stmt = con
+                .stmt(r#"INSERT INTO "foo" ("value") VALUES (?1)"#)
+                .unwrap();
+
+            assert!(stmt.bind_text(1, "foo").is_ok());
+            assert_eq!(Ok(false), stmt.step());
+
+            assert!(stmt.bind_text(1, "").is_ok());
+            assert_eq!(Ok(false), stmt.step());
+
+            assert!(stmt.bind_null(1).is_ok());
+            assert_eq!(Ok(false), stmt.step());
+        }
+
+        {
+            let stmt = con
+                .stmt(r#"SELECT "value" FROM "foo" ORDER BY "_id""#)
+                .unwrap();
+
+            assert_eq!(Ok(true), stmt.step());
+            assert_eq!(Some("foo"), stmt.column_text(0));
+
+            assert_eq!(Ok(true), stmt.step());
+            assert_eq!(Some(""), stmt.column_text(0));
+
+            assert_eq!(Ok(true), stmt.step());
+            assert_eq!(None, stmt.column_text(0));
+
+            assert_eq!(Ok(false), stmt.step());
+        }
+    }
+
     #[test]
     fn insert_select() {
         let tmp = tempdir().unwrap();
@@ -250,16 +1023,16 @@ mod tests {
             assert_eq!(Ok(false), stmt.step());
         }
 
-        const SELECT: &'static str = r#"SELECT "_id", "value" from "foo" ORDER BY "_id""#;
-        const INSERT: &'static str = r#"INSERT INTO "foo" ("value") VALUES (?1)"#;
+        const SELECT: &str = r#"SELECT "_id", "value" from "foo" ORDER BY "_id""#;
+        const INSERT: &str = r#"INSERT INTO "foo" ("value") VALUES (?1)"#;
 
         {
             let stmt = con.stmt(SELECT).unwrap();
             assert_eq!(Ok(false), stmt.step());
         }
 
-        const FIRST_VALUE: &'static [u8] = &[1, 2, 3];
-        const SECOND_VALUE: &'static [u8] = &[4, 5];
+        const FIRST_VALUE: &[u8] = &[1, 2, 3];
+        const SECOND_VALUE: &[u8] = &[4, 5];
 
         {
             let stmt = con.stmt(INSERT).unwrap();
@@ -290,4 +1063,475 @@ mod tests {
             assert_eq!(None, stmt.column_blob(1));
         }
     }
+
+    #[test]
+    fn execute_batch() {
+        let mut con = Connection::open_memory_db().unwrap();
+
+        let sql = r#"
+            CREATE TABLE "foo" ("_id" INTEGER PRIMARY KEY, "value" TEXT);
+            -- Comment between statements.
+            INSERT INTO "foo" ("value") VALUES ('a;b');
+            INSERT INTO "foo" ("value") VALUES ('c');
+            SELECT * FROM "foo";
+            /* Trailing comment */
+        "#;
+        assert_eq!(Ok(()), con.execute_batch(sql));
+        assert_eq!(Ok(()), con.execute_batch(""));
+
+        let mut stmt = con.stmt_once(r#"SELECT COUNT(*) FROM "foo""#).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Some(2), stmt.column_int(0));
+
+        // The statements after the failed one are not executed.
+        let sql = r#"
+            INSERT INTO "foo" ("value") VALUES ('d');
+            INSERT INTO "no_such_table" ("value") VALUES ('e');
+            INSERT INTO "foo" ("value") VALUES ('f');
+        "#;
+        assert!(con.execute_batch(sql).is_err());
+
+        let mut stmt = con.stmt_once(r#"SELECT COUNT(*) FROM "foo""#).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Some(3), stmt.column_int(0));
+    }
+
+    #[test]
+    fn db_filename() {
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("test_sqlite");
+        let con = Connection::try_from(path.as_ref()).unwrap();
+
+        let expected = path.canonicalize().unwrap();
+        let actual = con.db_filename("main").unwrap().canonicalize().unwrap();
+        assert_eq!(expected, actual);
+        assert_eq!(None, con.db_filename("temp"));
+        assert_eq!(None, con.db_filename("no_such_schema"));
+        assert_eq!(Ok(false), con.is_readonly("main"));
+        assert!(con.is_readonly("no_such_schema").is_err());
+
+        let con = Connection::open_memory_db().unwrap();
+        assert_eq!(None, con.db_filename("main"));
+        assert_eq!(Ok(false), con.is_readonly("main"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn db_filename_non_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let tmp = tempdir().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        let path = dir.join(OsStr::from_bytes(b"non_utf8_\xff"));
+
+        let mut con = Connection::try_from(dir.join("main").as_ref()).unwrap();
+        con.execute(r#"ATTACH ? AS "other""#, &[&path]).unwrap();
+        con.execute_batch(r#"CREATE TABLE "other"."foo" ("value" INTEGER)"#)
+            .unwrap();
+        assert!(path.exists());
+        assert_eq!(Some(path), con.db_filename("other"));
+    }
+
+    #[test]
+    fn send() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("value" INTEGER)"#)
+            .unwrap();
+        con.stmt(r#"INSERT INTO "foo" VALUES (1)"#)
+            .unwrap()
+            .step()
+            .unwrap();
+
+        let mut con = std::thread::spawn(move || {
+            con.stmt(r#"INSERT INTO "foo" VALUES (1)"#)
+                .unwrap()
+                .step()
+                .unwrap();
+            let mut stmt = con.stmt_once(r#"SELECT sum("value") FROM "foo""#).unwrap();
+            assert_eq!(Ok(true), stmt.step());
+            assert_eq!(Ok(2), stmt.get::<i64>(0));
+            drop(stmt);
+            con
+        })
+        .join()
+        .unwrap();
+
+        let mut stmt = con.stmt_once(r#"SELECT count(*) FROM "foo""#).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(2), stmt.get::<i64>(0));
+
+        // `stmt` is used in this thread while `con` is used in another one.
+        extern "C" {
+            fn sqlite3_db_mutex(db: *mut crate::sqlite3) -> *mut std::os::raw::c_void;
+        }
+        assert!(!unsafe { sqlite3_db_mutex(con.raw()) }.is_null());
+
+        stmt.reset();
+        let handle = std::thread::spawn(move || {
+            for _ in 0..100 {
+                con.execute_batch(r#"INSERT INTO "foo" VALUES (1)"#)?;
+            }
+            Ok::<_, crate::Error>(con)
+        });
+        for _ in 0..100 {
+            assert_eq!(Ok(true), stmt.step());
+            stmt.reset();
+        }
+        assert!(handle.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn open_serialized() {
+        // https://www.sqlite.org/rescode.html#readonly
+        const SQLITE_READONLY: i32 = 8;
+        const DATA: &[u8] = include_bytes!("../testdata/reference.sqlite3");
+
+        if !crate::HAS_DESERIALIZE {
+            let e = Connection::open_serialized(DATA).unwrap_err();
+            assert_eq!(crate::Error::UNSUPPORTED, e);
+            return;
+        }
+
+        let mut con = Connection::open_serialized(DATA).unwrap();
+
+        let rows: Vec<(String, i64)> = con
+            .stmt_once(r#"SELECT "code", "population" FROM "country" ORDER BY "population""#)
+            .unwrap()
+            .query_as()
+            .unwrap();
+        let codes: Vec<&str> = rows.iter().map(|(c, _)| c.as_str()).collect();
+        assert_eq!(vec!["FR", "JP", "BR"], codes);
+
+        let e = con
+            .execute_batch(r#"INSERT INTO "country" VALUES ('DE', 'Germany', 0)"#)
+            .unwrap_err();
+        assert_eq!(SQLITE_READONLY, e.code());
+        let e = con
+            .execute_batch(r#"UPDATE "country" SET "population" = 0"#)
+            .unwrap_err();
+        assert_eq!(SQLITE_READONLY, e.code());
+        let e = con.execute_batch(r#"DELETE FROM "country""#).unwrap_err();
+        assert_eq!(SQLITE_READONLY, e.code());
+        let e = con
+            .execute_batch(r#"CREATE TABLE "foo" ("value" INTEGER)"#)
+            .unwrap_err();
+        assert_eq!(SQLITE_READONLY, e.code());
+
+        // Nothing is changed.
+        let count = con.query_one(r#"SELECT COUNT(*) FROM "country""#, &[]);
+        assert_eq!(Ok(Some((rows.len() as i64,))), count);
+
+        // The database is not backed by any file; sqlite3_deserialize() names it a dummy
+        // relative path, which must not be created by the failed writes.
+        let mut names = vec![PathBuf::from("memory_db")];
+        names.extend(con.db_filename("main"));
+        for name in names {
+            for suffix in &["", "-journal", "-wal"] {
+                let mut path = name.clone().into_os_string();
+                path.push(suffix);
+                assert!(!PathBuf::from(&path).exists(), "{:?}", path);
+            }
+        }
+
+        // Not a database
+        assert!(Connection::open_serialized(b"not a database")
+            .and_then(|mut con| con.execute_batch(r#"SELECT * FROM "country""#))
+            .is_err());
+    }
+
+    #[test]
+    fn stmt_after_schema_change() {
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("test_sqlite");
+
+        let mut con = Connection::try_from(path.as_path()).unwrap();
+        con.execute_batch(
+            r#"CREATE TABLE "foo" ("a" INTEGER, "b" INTEGER);
+               INSERT INTO "foo" VALUES (1, 2)"#,
+        )
+        .unwrap();
+
+        const SQL: &str = r#"SELECT * FROM "foo""#;
+        {
+            let stmt = con.stmt(SQL).unwrap();
+            assert_eq!(Ok(true), stmt.step());
+            assert_eq!(2, stmt.column_count());
+            assert_eq!(Ok(2), stmt.get::<i64>(1));
+            assert_eq!(Ok(false), stmt.step());
+        }
+
+        // Change the schema from another connection.
+        {
+            let mut other = Connection::try_from(path.as_path()).unwrap();
+            other
+                .execute_batch(r#"ALTER TABLE "foo" ADD COLUMN "c" TEXT DEFAULT 'bar'"#)
+                .unwrap();
+        }
+
+        let stmt = con.stmt(SQL).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(3, stmt.column_count());
+        assert_eq!(Ok(1), stmt.get::<i64>(0));
+        assert_eq!(Ok("bar"), stmt.get::<&str>(2));
+        assert_eq!(Ok(false), stmt.step());
+    }
+
+    #[test]
+    fn as_ptr() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let raw = unsafe { con.as_ptr() };
+        assert!(!raw.is_null());
+
+        let stmt = con.stmt_once("SELECT 1").unwrap();
+        assert_eq!(raw, unsafe { Connection::db_handle_of(&stmt) });
+        assert!(!unsafe { stmt.as_ptr() }.is_null());
+    }
+
+    #[test]
+    fn debug() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.stmt("SELECT 1").unwrap();
+        let s = format!("{:?}", con);
+        assert!(s.contains("filename: None"));
+        assert!(s.contains("cached_stmts: 1"));
+
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("test_sqlite");
+        let con = Connection::try_from(path.as_path()).unwrap();
+        assert!(format!("{:?}", con).contains("test_sqlite"));
+    }
+
+    #[test]
+    fn stmt_without_statement() {
+        let mut con = Connection::open_memory_db().unwrap();
+        assert!(con.stmt_once("").is_err());
+        assert!(con.stmt_once("-- comment").is_err());
+        assert!(con.stmt(" ").is_err());
+
+        // The cached statement keeps the bindings.
+        con.stmt("SELECT ?1").unwrap().bind_int(1, 5).unwrap();
+        let stmt = con.stmt("SELECT ?1").unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(5), stmt.get::<i64>(0));
+
+        // `query_one` does not see the stale bindings.
+        con.stmt("SELECT ?1, ?2").unwrap().bind_int(2, 7).unwrap();
+        let row = con.query_one::<(i64, Option<i64>)>("SELECT ?1, ?2", &[&1]);
+        assert_eq!(Ok(Some((1, None))), row);
+        let stmt = con.stmt("SELECT ?1, ?2").unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(None), stmt.get::<Option<i64>>(1));
+    }
+
+    #[test]
+    fn execute() {
+        const INSERT: &str = r#"INSERT INTO "foo" VALUES (?1, ?2)"#;
+        const SELECT: &str = r#"SELECT "id", "name" FROM "foo" WHERE "id" >= ?1 ORDER BY "id""#;
+
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "name" TEXT)"#)
+            .unwrap();
+
+        assert_eq!(Ok(1), con.execute(INSERT, &[&1, &"bar"]));
+        assert_eq!(Ok(1), con.execute(INSERT, &[&2, &"baz"]));
+        // Constraint violation
+        assert!(con.execute(INSERT, &[&2, &"qux"]).is_err());
+        // The parameters of the previous call are not left.
+        assert_eq!(Ok(1), con.execute(INSERT, &[&3]));
+
+        let row: Result<Option<(i64, String)>, _> = con.query_one(SELECT, &[&2]);
+        assert_eq!(Ok(Some((2, String::from("baz")))), row);
+        let row: Result<Option<(i64, Option<String>)>, _> = con.query_one(SELECT, &[&3]);
+        assert_eq!(Ok(Some((3, None))), row);
+        let row: Result<Option<(i64, String)>, _> = con.query_one(SELECT, &[&4]);
+        assert_eq!(Ok(None), row);
+        // Conversion error
+        assert!(con.query_one::<(i64,)>(SELECT, &[&1]).is_err());
+        assert_eq!(
+            Ok(Some((1,))),
+            con.query_one::<(i64,)>(r#"SELECT "id" FROM "foo""#, &[])
+        );
+    }
+
+    #[test]
+    fn stats() {
+        const SELECT: &str = r#"SELECT "value" FROM "foo""#;
+        const INSERT: &str = r#"INSERT INTO "foo" VALUES (?1)"#;
+
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("value" INTEGER UNIQUE)"#)
+            .unwrap();
+        con.execute(INSERT, &[&1]).unwrap();
+        con.execute(INSERT, &[&2]).unwrap();
+
+        // Disabled
+        assert!(con.stats().is_empty());
+
+        con.enable_stats(true);
+        let mut total = Default::default();
+        for i in 1..=3 {
+            let stmt = con.stmt(SELECT).unwrap();
+            while stmt.step().unwrap() {}
+
+            let stats = con.stats();
+            assert_eq!(1, stats.len());
+            assert_eq!(SELECT, stats[0].sql);
+            assert_eq!(i, stats[0].count);
+            assert_eq!(2 * i, stats[0].rows);
+            assert!(total <= stats[0].total_time);
+            assert!(stats[0].max_time <= stats[0].total_time);
+            total = stats[0].total_time;
+        }
+
+        assert!(con.execute(INSERT, &[&1]).is_err());
+        let stats = con.stats();
+        let insert = stats.iter().find(|s| s.sql == INSERT).unwrap();
+        assert_eq!(1, insert.count);
+        assert!(insert.last_error.is_some());
+
+        con.reset_stats();
+        assert!(con.stats().is_empty());
+        con.execute(INSERT, &[&3]).unwrap();
+        assert_eq!(1, con.stats().len());
+
+        con.enable_stats(false);
+        con.execute(INSERT, &[&4]).unwrap();
+        assert!(con.stats().is_empty());
+    }
+
+    #[test]
+    fn stats_last_step() {
+        // The only step does all the work and returns DONE.
+        const SQL: &str = "SELECT 1 WHERE sleep_ms(20) IS NULL";
+        let mut con = Connection::open_memory_db().unwrap();
+        con.create_scalar_function("sleep_ms", 1, false, |args| {
+            if let crate::ValueRef::Integer(ms) = args[0] {
+                std::thread::sleep(std::time::Duration::from_millis(ms as u64));
+            }
+            Ok(crate::Value::Integer(0))
+        })
+        .unwrap();
+        con.enable_stats(true);
+
+        assert_eq!(Ok(None), con.query_one::<(i64,)>(SQL, &[]));
+        let stats = con.stats();
+        assert_eq!(1, stats.len());
+        assert_eq!(1, stats[0].count);
+        assert_eq!(0, stats[0].rows);
+        assert!(std::time::Duration::from_millis(20) <= stats[0].total_time);
+        assert_eq!(stats[0].total_time, stats[0].max_time);
+    }
+
+    #[test]
+    fn try_clone() {
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("test_sqlite");
+
+        let mut con = Connection::try_from(path.as_path()).unwrap();
+        con.execute_batch(
+            r#"PRAGMA journal_mode = WAL;
+               CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY)"#,
+        )
+        .unwrap();
+        con.add_init(|con| con.execute_batch(r#"CREATE TEMP TABLE "bar" ("id" INTEGER)"#))
+            .unwrap();
+
+        let mut clone = con.try_clone().unwrap();
+        assert_eq!(con.db_filename("main"), clone.db_filename("main"));
+        assert!(clone.execute_batch(r#"SELECT * FROM temp."bar""#).is_ok());
+
+        // Reading in the clone does not block the writer in WAL mode.
+        clone.execute_batch("BEGIN").unwrap();
+        const COUNT: &str = r#"SELECT count(*) FROM "foo""#;
+        assert_eq!(Ok(Some((0_i64,))), clone.query_one(COUNT, &[]));
+
+        con.execute_batch(r#"INSERT INTO "foo" VALUES (1), (2)"#)
+            .unwrap();
+        assert_eq!(Ok(Some((0_i64,))), clone.query_one(COUNT, &[]));
+        clone.execute_batch("COMMIT").unwrap();
+        assert_eq!(Ok(Some((2_i64,))), clone.query_one(COUNT, &[]));
+
+        // Clone of the clone.
+        let mut clone = clone.try_clone().unwrap();
+        assert!(clone.execute_batch(r#"SELECT * FROM temp."bar""#).is_ok());
+
+        // An error of the init is not registered.
+        assert!(con
+            .add_init(|con| con.execute_batch("INVALID SQL"))
+            .is_err());
+        assert!(con.try_clone().is_ok());
+    }
+
+    #[test]
+    fn try_clone_memory_db() {
+        let con = Connection::open_memory_db().unwrap();
+        let e = con.try_clone().unwrap_err();
+        assert_eq!(SQLITE_MISUSE, e.code());
+
+        let uris = [
+            "file::memory:",
+            "file:try_clone_memory_db?mode=memory",
+            "file:",
+        ];
+        for uri in uris.iter() {
+            let con = crate::OpenOptions::new().uri(true).open(uri).unwrap();
+            let e = con.try_clone().unwrap_err();
+            assert_eq!(SQLITE_MISUSE, e.code(), "{}", uri);
+        }
+
+        let uri = "file:try_clone_memory_db?mode=memory&cache=shared";
+        let mut con = crate::OpenOptions::new().uri(true).open(uri).unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("id" INTEGER)"#)
+            .unwrap();
+        let mut clone = con.try_clone().unwrap();
+        assert!(clone.execute_batch(r#"SELECT * FROM "foo""#).is_ok());
+    }
+
+    #[test]
+    fn stmt_with_arity() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(
+            r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY);
+               INSERT INTO "foo" VALUES (1), (2), (3), (4), (5)"#,
+        )
+        .unwrap();
+        con.enable_stats(true);
+
+        const SQL: &str = r#"SELECT count(*) FROM "foo" WHERE "id" IN ({placeholders})"#;
+        for (params, expected) in &[([&1, &2, &3], 3), ([&3, &4, &9], 2)] {
+            let stmt = con.stmt_with_arity(SQL, 3).unwrap();
+            let params: Vec<&dyn ToSql> = params.iter().map(|&p| p as &dyn ToSql).collect();
+            stmt.bind_all(&params).unwrap();
+            assert_eq!(Ok(true), stmt.step());
+            assert_eq!(Some(*expected), stmt.column_int(0));
+            assert_eq!(Ok(false), stmt.step());
+        }
+
+        // The statement was prepared only once.
+        let stats = con.stats();
+        assert_eq!(1, stats.len());
+        assert_eq!(2, stats[0].count);
+        assert!(stats[0].sql.ends_with("IN (?,?,?)"));
+
+        // Another arity is another statement.
+        let stmt = con.stmt_with_arity(SQL, 1).unwrap();
+        stmt.bind_all(&[&5]).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Some(1), stmt.column_int(0));
+        assert_eq!(2, con.stats().len());
+
+        // Without the marker.
+        let e = con
+            .stmt_with_arity(r#"SELECT * FROM "foo""#, 1)
+            .unwrap_err();
+        assert_eq!(SQLITE_MISUSE, e.code());
+
+        // Exceeding the limit.
+        unsafe { sqlite3_limit(con.raw, SQLITE_LIMIT_VARIABLE_NUMBER, 999) };
+        let e = con.stmt_with_arity(SQL, 1000).unwrap_err();
+        assert_eq!(SQLITE_TOOBIG, e.code());
+        assert!(e.message().unwrap().contains("999"));
+        assert!(con.stmt_with_arity(SQL, 999).is_ok());
+    }
 }
@@ -0,0 +1,288 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{BlobEncoding, Error, Stmt, ValueRef};
+use std::borrow::Cow;
+use std::io::Write;
+
+/// Options of [`Stmt::write_csv`] .
+///
+/// [`Stmt::write_csv`]: struct.Stmt.html#method.write_csv
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CsvOptions {
+    /// Field delimiter. The default is `,` .
+    pub delimiter: char,
+    /// Whether to write the column names as the first record. The default is `true` .
+    pub header: bool,
+    /// Representation of NULL. The default is an empty string.
+    pub null: String,
+    /// Encoding of BLOB, or `None` to write BLOB as an empty field. The default is
+    /// [`BlobEncoding::Hex`] .
+    ///
+    /// [`BlobEncoding::Hex`]: enum.BlobEncoding.html#variant.Hex
+    pub blob: Option<BlobEncoding>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            header: true,
+            null: String::new(),
+            blob: Some(BlobEncoding::Hex),
+        }
+    }
+}
+
+impl CsvOptions {
+    /// Returns `field` quoted if necessary.
+    ///
+    /// The field is quoted if it includes the delimiter, a double quote, CR, or LF, or if it is
+    /// same to the representation of NULL.
+    fn quote<'a>(&self, field: &'a str) -> Cow<'a, str> {
+        let needs_quote =
+            field == self.null || field.contains(&[self.delimiter, '"', '\r', '\n'][..]);
+        if needs_quote {
+            Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+        } else {
+            Cow::Borrowed(field)
+        }
+    }
+}
+
+impl Stmt {
+    /// Executes the statement from the beginning and writes the rows into `out` as CSV, and
+    /// returns the number of the rows written (excluding the header.)
+    ///
+    /// The fields are quoted as RFC 4180, and each record ends with CRLF. TEXT equal to
+    /// [`CsvOptions::null`] is quoted to be distinguished from NULL. Invalid UTF-8 sequences in
+    /// TEXT are replaced with `U+FFFD` .
+    ///
+    /// The rows are written one by one as they are stepped; `out` is not buffered, so wrap it in
+    /// `std::io::BufWriter` if it is slow to write.
+    ///
+    /// Returns `SQLITE_IOERR` if failed to write into `out` .
+    ///
+    /// If the statement is in the middle of the iteration, it is reset at first.
+    /// The bound parameters are kept.
+    ///
+    /// [`CsvOptions::null`]: struct.CsvOptions.html#structfield.null
+    pub fn write_csv<W>(&mut self, mut out: W, opts: CsvOptions) -> Result<u64, Error>
+    where
+        W: Write,
+    {
+        self.reset();
+
+        let mut delimiter = [0; 4];
+        let delimiter = opts.delimiter.encode_utf8(&mut delimiter).as_bytes();
+
+        if opts.header {
+            for i in 0..self.column_count() {
+                if 0 < i {
+                    out.write_all(delimiter).map_err(|e| Error::from_io(&e))?;
+                }
+                let name = opts.quote(self.column_name(i).unwrap_or_default());
+                out.write_all(name.as_bytes())
+                    .map_err(|e| Error::from_io(&e))?;
+            }
+            out.write_all(b"\r\n").map_err(|e| Error::from_io(&e))?;
+        }
+
+        let mut rows = 0;
+        while self.step()? {
+            for i in 0..self.column_count() {
+                if 0 < i {
+                    out.write_all(delimiter).map_err(|e| Error::from_io(&e))?;
+                }
+
+                let field = match self.column_value_ref(i)? {
+                    ValueRef::Null => Cow::Borrowed(opts.null.as_str()),
+                    ValueRef::Integer(n) => Cow::Owned(n.to_string()),
+                    ValueRef::Real(f) => Cow::Owned(f.to_string()),
+                    ValueRef::Text(s) => match String::from_utf8_lossy(s) {
+                        Cow::Borrowed(s) => opts.quote(s),
+                        Cow::Owned(s) => Cow::Owned(opts.quote(&s).into_owned()),
+                    },
+                    ValueRef::Blob(b) => match opts.blob {
+                        Some(encoding) => Cow::Owned(encoding.encode(b)),
+                        None => Cow::Borrowed(""),
+                    },
+                };
+                out.write_all(field.as_bytes())
+                    .map_err(|e| Error::from_io(&e))?;
+            }
+            out.write_all(b"\r\n").map_err(|e| Error::from_io(&e))?;
+            rows += 1;
+        }
+
+        out.flush().map_err(|e| Error::from_io(&e))?;
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Connection, SQLITE_IOERR};
+    use std::io;
+
+    #[test]
+    fn write_csv() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let sql = r#"SELECT 1 AS "id", 'say "hi"' AS "text", NULL AS "a,b", x'0102' AS "data"
+                     UNION ALL SELECT 2, 'line 1' || char(10) || 'line 2', '', x''
+                     UNION ALL SELECT 3, 'plain', 1.5, NULL"#;
+        let mut stmt = con.stmt_once(sql).unwrap();
+
+        let mut out = Vec::new();
+        assert_eq!(Ok(3), stmt.write_csv(&mut out, CsvOptions::default()));
+        let expected = "id,text,\"a,b\",data\r\n\
+                        1,\"say \"\"hi\"\"\",,0102\r\n\
+                        2,\"line 1\nline 2\",\"\",\r\n\
+                        3,plain,1.5,\r\n";
+        assert_eq!(expected, String::from_utf8(out.clone()).unwrap());
+
+        // Round trip
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .from_reader(out.as_slice());
+        let headers = reader.headers().unwrap().clone();
+        assert_eq!(
+            vec!["id", "text", "a,b", "data"],
+            headers.iter().collect::<Vec<_>>()
+        );
+        let records: Vec<_> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(3, records.len());
+        assert_eq!("say \"hi\"", &records[0][1]);
+        assert_eq!("line 1\nline 2", &records[1][1]);
+        assert_eq!("0102", &records[0][3]);
+
+        // Options
+        let opts = CsvOptions {
+            delimiter: '\t',
+            header: false,
+            null: "NULL".to_string(),
+            blob: Some(BlobEncoding::Base64),
+        };
+        let mut out = Vec::new();
+        assert_eq!(Ok(3), stmt.write_csv(&mut out, opts));
+        let expected = "1\t\"say \"\"hi\"\"\"\tNULL\tAQI=\r\n\
+                        2\t\"line 1\nline 2\"\t\t\r\n\
+                        3\tplain\t1.5\tNULL\r\n";
+        assert_eq!(expected, String::from_utf8(out).unwrap());
+
+        let opts = CsvOptions {
+            blob: None,
+            ..CsvOptions::default()
+        };
+        let mut out = Vec::new();
+        assert_eq!(Ok(3), stmt.write_csv(&mut out, opts));
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .starts_with("id,text,\"a,b\",data\r\n1,"));
+    }
+
+    /// Counts the bytes written and checks that each write is small.
+    struct BoundedWriter {
+        written: u64,
+        max_write: usize,
+    }
+
+    impl io::Write for BoundedWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            assert!(buf.len() <= self.max_write);
+            self.written += buf.len() as u64;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_csv_streams() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let sql = r#"WITH RECURSIVE "s"("i") AS (SELECT 1 UNION ALL SELECT "i" + 1 FROM "s")
+                     SELECT "i", 'row ' || "i" FROM "s" LIMIT 100000"#;
+        let mut stmt = con.stmt_once(sql).unwrap();
+
+        let mut out = BoundedWriter {
+            written: 0,
+            max_write: 64,
+        };
+        assert_eq!(Ok(100_000), stmt.write_csv(&mut out, CsvOptions::default()));
+        assert!(100_000 * 10 < out.written);
+    }
+
+    #[test]
+    fn write_csv_io_error() {
+        struct Broken;
+
+        impl io::Write for Broken {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::other("broken"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut con = Connection::open_memory_db().unwrap();
+        let mut stmt = con.stmt_once("SELECT 1").unwrap();
+        let e = stmt.write_csv(Broken, CsvOptions::default()).unwrap_err();
+        assert_eq!(SQLITE_IOERR, e.code());
+        assert_eq!(Some("broken"), e.message());
+    }
+}
@@ -0,0 +1,290 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{Error, FromSql, FromSqlError, ToSql, ToSqlOutput, Value, ValueRef};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat, Utc};
+
+/// Integer greater than or equals to this (in absolute value) is regarded as unix time in
+/// milliseconds, or in seconds otherwise. (It is 5138-11-16 in seconds, and 1973-03-03 in
+/// milliseconds.)
+const MILLIS_THRESHOLD: i64 = 100_000_000_000;
+
+/// Julian day number of 1970-01-01T00:00:00Z.
+const UNIX_EPOCH_JULIAN_DAY: f64 = 2_440_587.5;
+
+/// Formats of TEXT accepted as `NaiveDateTime` .
+const NAIVE_DATETIME_FORMATS: &[&str] = &[
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M",
+];
+
+/// Newtype of `DateTime<Utc>` , which is stored as INTEGER of unix time in seconds.
+///
+/// Use this type to bind `DateTime<Utc>` as an integer, or to read INTEGER as seconds regardless
+/// of the magnitude.
+///
+/// This type is enabled by feature "chrono".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EpochSeconds(pub DateTime<Utc>);
+
+/// Newtype of `DateTime<Utc>` , which is stored as INTEGER of unix time in milliseconds.
+///
+/// Use this type to bind `DateTime<Utc>` as an integer, or to read INTEGER as milliseconds
+/// regardless of the magnitude.
+///
+/// This type is enabled by feature "chrono".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EpochMillis(pub DateTime<Utc>);
+
+fn invalid_value<T>(kind: &str, value: T) -> FromSqlError
+where
+    T: core::fmt::Debug,
+{
+    FromSqlError::Other(format!("invalid {}: {:?}", kind, value).into())
+}
+
+fn from_epoch_seconds(i: i64) -> Result<DateTime<Utc>, FromSqlError> {
+    DateTime::from_timestamp(i, 0).ok_or(FromSqlError::OutOfRange(i))
+}
+
+fn from_epoch_millis(i: i64) -> Result<DateTime<Utc>, FromSqlError> {
+    DateTime::from_timestamp_millis(i).ok_or(FromSqlError::OutOfRange(i))
+}
+
+fn from_julian_day(f: f64) -> Result<DateTime<Utc>, FromSqlError> {
+    let millis = ((f - UNIX_EPOCH_JULIAN_DAY) * 86_400_000.0).round();
+    if millis.is_finite() && (i64::MIN as f64) < millis && millis < (i64::MAX as f64) {
+        if let Some(dt) = DateTime::from_timestamp_millis(millis as i64) {
+            return Ok(dt);
+        }
+    }
+    Err(invalid_value("julian day", f))
+}
+
+fn to_str(s: &[u8]) -> Result<&str, FromSqlError> {
+    core::str::from_utf8(s).map_err(FromSqlError::Utf8)
+}
+
+fn parse_naive_datetime(s: &str) -> Option<NaiveDateTime> {
+    NAIVE_DATETIME_FORMATS
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(s, f).ok())
+}
+
+impl ToSql for DateTime<Utc> {
+    /// Binds as RFC 3339 TEXT like "2021-01-02T03:04:05.678Z" .
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        let s = self.to_rfc3339_opts(SecondsFormat::AutoSi, true);
+        Ok(ToSqlOutput::Owned(Value::Text(s)))
+    }
+}
+
+impl FromSql<'_> for DateTime<Utc> {
+    /// Accepts TEXT of RFC 3339 (or without the offset, which is regarded as UTC), INTEGER of unix
+    /// time in seconds or milliseconds (detected by the magnitude), and REAL of Julian day.
+    fn column_result(value: ValueRef<'_>) -> Result<Self, FromSqlError> {
+        match value {
+            ValueRef::Text(s) => {
+                let s = to_str(s)?;
+                if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+                    Ok(dt.with_timezone(&Utc))
+                } else {
+                    parse_naive_datetime(s)
+                        .map(|dt| dt.and_utc())
+                        .ok_or_else(|| invalid_value("datetime", s))
+                }
+            }
+            ValueRef::Integer(i) if i.abs() < MILLIS_THRESHOLD => from_epoch_seconds(i),
+            ValueRef::Integer(i) => from_epoch_millis(i),
+            ValueRef::Real(f) => from_julian_day(f),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl ToSql for NaiveDateTime {
+    /// Binds as TEXT like "2021-01-02T03:04:05.678" .
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        let s = self.format("%Y-%m-%dT%H:%M:%S%.f").to_string();
+        Ok(ToSqlOutput::Owned(Value::Text(s)))
+    }
+}
+
+impl FromSql<'_> for NaiveDateTime {
+    /// Accepts TEXT like "2021-01-02T03:04:05.678" or "2021-01-02 03:04:05" (or RFC 3339, which is
+    /// converted into UTC), INTEGER of unix time in seconds or milliseconds (detected by the
+    /// magnitude), and REAL of Julian day.
+    fn column_result(value: ValueRef<'_>) -> Result<Self, FromSqlError> {
+        if let ValueRef::Text(s) = value {
+            if let Some(dt) = parse_naive_datetime(to_str(s)?) {
+                return Ok(dt);
+            }
+        }
+        DateTime::<Utc>::column_result(value).map(|dt| dt.naive_utc())
+    }
+}
+
+impl ToSql for NaiveDate {
+    /// Binds as TEXT like "2021-01-02" .
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        let s = self.format("%Y-%m-%d").to_string();
+        Ok(ToSqlOutput::Owned(Value::Text(s)))
+    }
+}
+
+impl FromSql<'_> for NaiveDate {
+    /// Accepts TEXT like "2021-01-02" , and any value that `NaiveDateTime` accepts.
+    fn column_result(value: ValueRef<'_>) -> Result<Self, FromSqlError> {
+        if let ValueRef::Text(s) = value {
+            if let Ok(d) = NaiveDate::parse_from_str(to_str(s)?, "%Y-%m-%d") {
+                return Ok(d);
+            }
+        }
+        NaiveDateTime::column_result(value).map(|dt| dt.date())
+    }
+}
+
+impl ToSql for EpochSeconds {
+    /// Binds as INTEGER of unix time in seconds. (The fraction is truncated.)
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        Ok(ToSqlOutput::Borrowed(ValueRef::Integer(self.0.timestamp())))
+    }
+}
+
+impl FromSql<'_> for EpochSeconds {
+    /// Accepts INTEGER of unix time in seconds.
+    #[inline]
+    fn column_result(value: ValueRef<'_>) -> Result<Self, FromSqlError> {
+        match value {
+            ValueRef::Integer(i) => from_epoch_seconds(i).map(Self),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl ToSql for EpochMillis {
+    /// Binds as INTEGER of unix time in milliseconds. (The fraction is truncated.)
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        Ok(ToSqlOutput::Borrowed(ValueRef::Integer(
+            self.0.timestamp_millis(),
+        )))
+    }
+}
+
+impl FromSql<'_> for EpochMillis {
+    /// Accepts INTEGER of unix time in milliseconds.
+    #[inline]
+    fn column_result(value: ValueRef<'_>) -> Result<Self, FromSqlError> {
+        match value {
+            ValueRef::Integer(i) => from_epoch_millis(i).map(Self),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Connection;
+
+    fn round_trip<T>(con: &mut Connection, val: &T) -> T
+    where
+        T: ToSql + for<'a> FromSql<'a>,
+    {
+        let mut stmt = con.stmt_once("SELECT ?1").unwrap();
+        stmt.bind(1, val).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        stmt.get(0).unwrap()
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let dt = DateTime::from_timestamp_millis(1_612_345_678_901).unwrap();
+
+        assert_eq!(dt, round_trip(&mut con, &dt));
+        assert_eq!(dt.naive_utc(), round_trip(&mut con, &dt.naive_utc()));
+        assert_eq!(dt.date_naive(), round_trip(&mut con, &dt.date_naive()));
+        assert_eq!(EpochMillis(dt), round_trip(&mut con, &EpochMillis(dt)));
+
+        let secs = DateTime::from_timestamp(dt.timestamp(), 0).unwrap();
+        assert_eq!(EpochSeconds(secs), round_trip(&mut con, &EpochSeconds(dt)));
+    }
+
+    #[test]
+    fn legacy_formats() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let expected = DateTime::from_timestamp(1_612_345_678, 0).unwrap();
+
+        let sql = "SELECT 1612345678, 1612345678000, julianday(1612345678, 'unixepoch'), \
+                   datetime(1612345678, 'unixepoch'), '2021-02-03T09:47:58+00:00', 'foo'";
+        let mut stmt = con.stmt_once(sql).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+
+        for i in 0..5 {
+            assert_eq!(Ok(expected), stmt.get::<DateTime<Utc>>(i));
+        }
+        assert_eq!(Ok(expected.naive_utc()), stmt.get::<NaiveDateTime>(3));
+        assert_eq!(Ok(expected.date_naive()), stmt.get::<NaiveDate>(0));
+
+        let e = stmt.get::<DateTime<Utc>>(5).unwrap_err();
+        assert!(e.message().unwrap().contains("\"foo\""));
+    }
+}
@@ -0,0 +1,601 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{Connection, Error, SQLITE_ERROR, SQLITE_MISMATCH, SQLITE_RANGE, SQLITE_TOOBIG};
+use core::convert::TryFrom;
+use core::fmt;
+use core::time::Duration;
+
+/// Value of `PRAGMA journal_mode` .
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JournalMode {
+    /// "DELETE"
+    Delete,
+    /// "TRUNCATE"
+    Truncate,
+    /// "PERSIST"
+    Persist,
+    /// "MEMORY"
+    Memory,
+    /// "WAL"
+    Wal,
+    /// "OFF"
+    Off,
+}
+
+impl JournalMode {
+    /// Returns the name used in the SQL.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Delete => "DELETE",
+            Self::Truncate => "TRUNCATE",
+            Self::Persist => "PERSIST",
+            Self::Memory => "MEMORY",
+            Self::Wal => "WAL",
+            Self::Off => "OFF",
+        }
+    }
+
+    /// Parses the value that `PRAGMA journal_mode` returns. (Case insensitive.)
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        [
+            Self::Delete,
+            Self::Truncate,
+            Self::Persist,
+            Self::Memory,
+            Self::Wal,
+            Self::Off,
+        ]
+        .iter()
+        .copied()
+        .find(|mode| mode.as_str().eq_ignore_ascii_case(s))
+    }
+}
+
+impl fmt::Display for JournalMode {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Value of `PRAGMA synchronous` .
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Synchronous {
+    /// "OFF" (0)
+    Off = 0,
+    /// "NORMAL" (1)
+    Normal = 1,
+    /// "FULL" (2)
+    Full = 2,
+    /// "EXTRA" (3)
+    Extra = 3,
+}
+
+impl Synchronous {
+    /// Converts the value that `PRAGMA synchronous` returns.
+    pub(crate) fn from_int(i: i64) -> Option<Self> {
+        match i {
+            0 => Some(Self::Off),
+            1 => Some(Self::Normal),
+            2 => Some(Self::Full),
+            3 => Some(Self::Extra),
+            _ => None,
+        }
+    }
+}
+
+/// Value of `PRAGMA temp_store` .
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TempStore {
+    /// "DEFAULT" (0); follows the compile-time option `SQLITE_TEMP_STORE` .
+    Default = 0,
+    /// "FILE" (1)
+    File = 1,
+    /// "MEMORY" (2)
+    Memory = 2,
+}
+
+impl TempStore {
+    /// Converts the value that `PRAGMA temp_store` returns.
+    fn from_int(i: i64) -> Option<Self> {
+        match i {
+            0 => Some(Self::Default),
+            1 => Some(Self::File),
+            2 => Some(Self::Memory),
+            _ => None,
+        }
+    }
+}
+
+/// Typed settings of the database connection to be applied by [`Connection::configure`] .
+///
+/// Each field corresponds to a pragma; `None` leaves the current value as it is.
+///
+/// [`Connection::configure`]: struct.Connection.html#method.configure
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct DbSettings {
+    /// `PRAGMA journal_mode`
+    pub journal_mode: Option<JournalMode>,
+    /// `PRAGMA synchronous`
+    pub synchronous: Option<Synchronous>,
+    /// `PRAGMA cache_size` in KiB. (Passed to libsqlite3 as a negative number.)
+    pub cache_size_kib: Option<i64>,
+    /// `PRAGMA mmap_size` in bytes.
+    pub mmap_size: Option<u64>,
+    /// `PRAGMA temp_store`
+    pub temp_store: Option<TempStore>,
+    /// `PRAGMA busy_timeout` (milliseconds precision.)
+    pub busy_timeout: Option<Duration>,
+    /// `PRAGMA foreign_keys`
+    pub foreign_keys: Option<bool>,
+}
+
+impl DbSettings {
+    /// Returns the settings commonly used for a file database in production.
+    ///
+    /// - `journal_mode` : WAL
+    /// - `synchronous` : NORMAL (safe in WAL mode)
+    /// - `temp_store` : MEMORY
+    /// - `busy_timeout` : 5 seconds
+    /// - `foreign_keys` : ON
+    ///
+    /// The others are `None` .
+    pub const fn wal_defaults() -> Self {
+        Self {
+            journal_mode: Some(JournalMode::Wal),
+            synchronous: Some(Synchronous::Normal),
+            cache_size_kib: None,
+            mmap_size: None,
+            temp_store: Some(TempStore::Memory),
+            busy_timeout: Some(Duration::from_secs(5)),
+            foreign_keys: Some(true),
+        }
+    }
+}
+
+impl Connection {
+    /// Applies `cfg` to the "main" database and returns the effective values of all the
+    /// settings read back after that.
+    ///
+    /// The settings are applied in the following order: `busy_timeout` (so that the others wait
+    /// for the lock), `journal_mode` , `synchronous` , `cache_size_kib` , `mmap_size` ,
+    /// `temp_store` , and `foreign_keys` .
+    ///
+    /// Returns `SQLITE_TOOBIG` if `busy_timeout` or `mmap_size` is too large, or `SQLITE_RANGE` if
+    /// `cache_size_kib` is negative. These are validated before any setting is applied, so
+    /// nothing is changed then.
+    ///
+    /// Returns `SQLITE_ERROR` if the journal mode could not be changed; e.g. an in-memory
+    /// database does not support WAL. If applying a setting fails like this, the settings
+    /// applied before it are kept; call this method with `DbSettings::default()` to read the
+    /// current values.
+    ///
+    /// Note that `foreign_keys` is silently ignored in a transaction.
+    pub fn configure(&mut self, cfg: &DbSettings) -> Result<DbSettings, Error> {
+        let busy_timeout = match cfg.busy_timeout {
+            None => None,
+            Some(timeout) => {
+                let ms = i32::try_from(timeout.as_millis())
+                    .map_err(|_| Error::with_message(SQLITE_TOOBIG, "busy_timeout is too large"))?;
+                Some(ms)
+            }
+        };
+        let cache_size = match cfg.cache_size_kib {
+            None => None,
+            Some(kib) if kib < 0 => {
+                let message = format!("cache_size_kib must not be negative: {}", kib);
+                return Err(Error::with_message(SQLITE_RANGE, message));
+            }
+            Some(kib) => Some(-kib),
+        };
+        let mmap_size = match cfg.mmap_size {
+            None => None,
+            Some(size) => {
+                let size = i64::try_from(size)
+                    .map_err(|_| Error::with_message(SQLITE_TOOBIG, "mmap_size is too large"))?;
+                Some(size)
+            }
+        };
+
+        if let Some(ms) = busy_timeout {
+            self.pragma_update(None, "busy_timeout", ms)?;
+        }
+        if let Some(mode) = cfg.journal_mode {
+            self.set_journal_mode(mode)?;
+        }
+        if let Some(synchronous) = cfg.synchronous {
+            self.set_synchronous(synchronous)?;
+        }
+        if let Some(size) = cache_size {
+            self.pragma_update(None, "cache_size", size)?;
+        }
+        if let Some(size) = mmap_size {
+            self.pragma_update(None, "mmap_size", size)?;
+        }
+        if let Some(temp_store) = cfg.temp_store {
+            self.pragma_update(None, "temp_store", temp_store as i32)?;
+        }
+        if let Some(foreign_keys) = cfg.foreign_keys {
+            self.pragma_update(None, "foreign_keys", foreign_keys as i32)?;
+        }
+
+        self.effective_settings()
+    }
+
+    /// Executes `PRAGMA mmap_size` for the "main" database and returns the effective value read
+    /// back.
+    ///
+    /// The effective value can be smaller than `bytes` because libsqlite3 caps it by the
+    /// compile-time option `SQLITE_MAX_MMAP_SIZE` ; it is always 0 for an in-memory database.
+    ///
+    /// Returns `SQLITE_TOOBIG` if `bytes` exceeds `i64` .
+    pub fn set_mmap_size(&mut self, bytes: u64) -> Result<u64, Error> {
+        let bytes = i64::try_from(bytes).map_err(|_| Error::new(SQLITE_TOOBIG))?;
+        self.pragma_update(None, "mmap_size", bytes)?;
+        Ok(self.pragma_int_or_zero("mmap_size")?.max(0) as u64)
+    }
+
+    /// Executes `PRAGMA page_size` for the "main" database, and makes sure that the page size is
+    /// changed.
+    ///
+    /// The page size of a database can be changed only before the database is created (i.e.
+    /// before the first table is created), or by [`vacuum`] after this method unless the journal
+    /// mode is WAL.
+    ///
+    /// Returns `SQLITE_RANGE` if `bytes` is not a power of two between 512 and 65536, or
+    /// `SQLITE_ERROR` if the page size is not changed because the database already has data.
+    ///
+    /// [`vacuum`]: #method.vacuum
+    pub fn set_page_size(&mut self, bytes: u32) -> Result<(), Error> {
+        if !bytes.is_power_of_two() || !(512..=65536).contains(&bytes) {
+            let message = format!(
+                "page_size must be a power of two between 512 and 65536: {}",
+                bytes
+            );
+            return Err(Error::with_message(SQLITE_RANGE, message));
+        }
+
+        self.pragma_update(None, "page_size", bytes)?;
+        let effective = self.pragma_int_or_zero("page_size")?;
+        if effective == i64::from(bytes) {
+            Ok(())
+        } else {
+            let message = format!(
+                "page_size is {} instead of {}; VACUUM is required to change it",
+                effective, bytes
+            );
+            Err(Error::with_message(SQLITE_ERROR, message))
+        }
+    }
+
+    /// Reads all the settings of [`DbSettings`] of the "main" database.
+    ///
+    /// [`DbSettings`]: struct.DbSettings.html
+    fn effective_settings(&mut self) -> Result<DbSettings, Error> {
+        let journal_mode = self.journal_mode()?;
+        let synchronous = self.synchronous()?;
+
+        // A positive value is the number of pages, and a negative value is in KiB.
+        let cache_size = self.pragma_int_or_zero("cache_size")?;
+        let cache_size_kib = if cache_size < 0 {
+            -cache_size
+        } else {
+            let page_size = self.pragma_int_or_zero("page_size")?;
+            let bytes = cache_size.checked_mul(page_size).ok_or_else(|| {
+                let message = format!("cache_size is too large: {} pages", cache_size);
+                Error::with_message(SQLITE_TOOBIG, message)
+            })?;
+            bytes / 1024
+        };
+
+        let mmap_size = self.pragma_int_or_zero("mmap_size")?.max(0) as u64;
+
+        let temp_store = self.pragma_int_or_zero("temp_store")?;
+        let temp_store = TempStore::from_int(temp_store).ok_or_else(|| {
+            let message = format!("unknown temp_store: {}", temp_store);
+            Error::with_message(SQLITE_MISMATCH, message)
+        })?;
+
+        let busy_timeout = self.pragma_int_or_zero("busy_timeout")?.max(0) as u64;
+        let foreign_keys = self.pragma_int_or_zero("foreign_keys")? != 0;
+
+        Ok(DbSettings {
+            journal_mode: Some(journal_mode),
+            synchronous: Some(synchronous),
+            cache_size_kib: Some(cache_size_kib),
+            mmap_size: Some(mmap_size),
+            temp_store: Some(temp_store),
+            busy_timeout: Some(Duration::from_millis(busy_timeout)),
+            foreign_keys: Some(foreign_keys),
+        })
+    }
+
+    /// Returns the current `PRAGMA journal_mode` of the "main" database.
+    ///
+    /// Returns `SQLITE_MISMATCH` if libsqlite3 returns an unknown mode.
+    pub fn journal_mode(&mut self) -> Result<JournalMode, Error> {
+        let mode = self.pragma_text(None, "journal_mode")?.unwrap_or_default();
+        JournalMode::parse(&mode).ok_or_else(|| {
+            let message = format!("unknown journal_mode: {}", mode);
+            Error::with_message(SQLITE_MISMATCH, message)
+        })
+    }
+
+    /// Executes `PRAGMA journal_mode` for the "main" database, and returns the effective mode
+    /// read back, which is always `mode` .
+    ///
+    /// libsqlite3 does not fail even if it cannot change the journal mode, for example, an
+    /// in-memory database does not support WAL and a database in WAL mode cannot leave it in a
+    /// transaction. This method makes it an error.
+    ///
+    /// Returns `SQLITE_ERROR` if the effective mode is not `mode` .
+    pub fn set_journal_mode(&mut self, mode: JournalMode) -> Result<JournalMode, Error> {
+        self.pragma_update(None, "journal_mode", mode)?;
+        let effective = self.journal_mode()?;
+        if effective == mode {
+            Ok(effective)
+        } else {
+            let message = format!("journal_mode is {} instead of {}", effective, mode);
+            Err(Error::with_message(SQLITE_ERROR, message))
+        }
+    }
+
+    /// Returns the current `PRAGMA synchronous` of the "main" database.
+    ///
+    /// Returns `SQLITE_MISMATCH` if libsqlite3 returns an unknown value.
+    pub fn synchronous(&mut self) -> Result<Synchronous, Error> {
+        let synchronous = self.pragma_int_or_zero("synchronous")?;
+        Synchronous::from_int(synchronous).ok_or_else(|| {
+            let message = format!("unknown synchronous: {}", synchronous);
+            Error::with_message(SQLITE_MISMATCH, message)
+        })
+    }
+
+    /// Executes `PRAGMA synchronous` for the "main" database, and returns the effective value
+    /// read back, which is always `synchronous` .
+    ///
+    /// Returns `SQLITE_ERROR` if the effective value is not `synchronous` .
+    pub fn set_synchronous(&mut self, synchronous: Synchronous) -> Result<Synchronous, Error> {
+        self.pragma_update(None, "synchronous", synchronous as i32)?;
+        let effective = self.synchronous()?;
+        if effective == synchronous {
+            Ok(effective)
+        } else {
+            let message = format!(
+                "synchronous is {:?} instead of {:?}",
+                effective, synchronous
+            );
+            Err(Error::with_message(SQLITE_ERROR, message))
+        }
+    }
+
+    #[inline]
+    fn pragma_int_or_zero(&mut self, name: &str) -> Result<i64, Error> {
+        self.pragma_int(None, name).map(|v| v.unwrap_or(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn wal_defaults() {
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("test_sqlite");
+        let mut con = Connection::try_from(path.as_path()).unwrap();
+
+        let cfg = DbSettings {
+            cache_size_kib: Some(4096),
+            ..DbSettings::wal_defaults()
+        };
+        let report = con.configure(&cfg).unwrap();
+        assert_eq!(Some(JournalMode::Wal), report.journal_mode);
+        assert_eq!(Some(Synchronous::Normal), report.synchronous);
+        assert_eq!(Some(4096), report.cache_size_kib);
+        assert_eq!(Some(TempStore::Memory), report.temp_store);
+        assert_eq!(Some(Duration::from_secs(5)), report.busy_timeout);
+        assert_eq!(Some(true), report.foreign_keys);
+
+        // Read the pragmas back.
+        assert_eq!(
+            Ok(Some("wal".into())),
+            con.pragma_text(None, "journal_mode")
+        );
+        assert_eq!(Ok(Some(1)), con.pragma_int(None, "synchronous"));
+        assert_eq!(Ok(Some(-4096)), con.pragma_int(None, "cache_size"));
+        assert_eq!(Ok(Some(2)), con.pragma_int(None, "temp_store"));
+        assert_eq!(Ok(Some(5000)), con.pragma_int(None, "busy_timeout"));
+        assert_eq!(Ok(Some(1)), con.pragma_int(None, "foreign_keys"));
+
+        // Nothing is changed.
+        assert_eq!(Ok(report), con.configure(&DbSettings::default()));
+    }
+
+    #[test]
+    fn journal_mode_mismatch() {
+        // In-memory database does not support WAL.
+        let mut con = Connection::open_memory_db().unwrap();
+        let e = con.configure(&DbSettings::wal_defaults()).unwrap_err();
+        assert_eq!(SQLITE_ERROR, e.code());
+
+        let cfg = DbSettings {
+            journal_mode: Some(JournalMode::Off),
+            ..DbSettings::default()
+        };
+        let report = con.configure(&cfg).unwrap();
+        assert_eq!(Some(JournalMode::Off), report.journal_mode);
+    }
+
+    #[test]
+    fn configure_invalid() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let before = con.configure(&DbSettings::default()).unwrap();
+
+        // Nothing is applied if any value is out of range.
+        let cases = [
+            (
+                DbSettings {
+                    mmap_size: Some(u64::MAX),
+                    ..DbSettings::default()
+                },
+                SQLITE_TOOBIG,
+            ),
+            (
+                DbSettings {
+                    busy_timeout: Some(Duration::from_secs(u64::MAX)),
+                    ..DbSettings::default()
+                },
+                SQLITE_TOOBIG,
+            ),
+            (
+                DbSettings {
+                    cache_size_kib: Some(i64::MIN),
+                    ..DbSettings::default()
+                },
+                SQLITE_RANGE,
+            ),
+        ];
+        for (cfg, code) in cases.iter() {
+            let cfg = DbSettings {
+                journal_mode: Some(JournalMode::Off),
+                foreign_keys: Some(true),
+                ..*cfg
+            };
+            let e = con.configure(&cfg).unwrap_err();
+            assert_eq!(*code, e.code());
+            assert_eq!(Ok(before), con.configure(&DbSettings::default()));
+        }
+    }
+
+    #[test]
+    fn page_size() {
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("test_sqlite");
+        let mut con = Connection::try_from(path.as_path()).unwrap();
+
+        for &bytes in &[0, 256, 1000, 131072] {
+            let e = con.set_page_size(bytes).unwrap_err();
+            assert_eq!(SQLITE_RANGE, e.code());
+        }
+
+        // Fresh database
+        con.set_page_size(8192).unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("value" INTEGER)"#)
+            .unwrap();
+        assert_eq!(Ok(Some(8192)), con.pragma_int(None, "page_size"));
+
+        // Populated database
+        let e = con.set_page_size(16384).unwrap_err();
+        assert_eq!(SQLITE_ERROR, e.code());
+        assert!(e.message().unwrap().contains("VACUUM"));
+        assert_eq!(Ok(Some(8192)), con.pragma_int(None, "page_size"));
+
+        con.vacuum().unwrap();
+        assert_eq!(Ok(Some(16384)), con.pragma_int(None, "page_size"));
+        assert_eq!(Ok(()), con.set_page_size(16384));
+    }
+
+    #[test]
+    fn mmap_size() {
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("test_sqlite");
+        let mut con = Connection::try_from(path.as_path()).unwrap();
+
+        assert_eq!(Ok(1 << 20), con.set_mmap_size(1 << 20));
+        assert_eq!(Ok(Some(1 << 20)), con.pragma_int(None, "mmap_size"));
+        assert_eq!(Ok(0), con.set_mmap_size(0));
+
+        // Capped by SQLITE_MAX_MMAP_SIZE.
+        let effective = con.set_mmap_size(1 << 60).unwrap();
+        assert!(0 < effective && effective <= 1 << 60);
+
+        let e = con.set_mmap_size(u64::MAX).unwrap_err();
+        assert_eq!(SQLITE_TOOBIG, e.code());
+    }
+
+    #[test]
+    fn journal_mode() {
+        assert_eq!(Some(JournalMode::Wal), JournalMode::parse("wal"));
+        assert_eq!(Some(JournalMode::Delete), JournalMode::parse("Delete"));
+        assert_eq!(None, JournalMode::parse("foo"));
+
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("test_sqlite");
+        let mut con = Connection::try_from(path.as_path()).unwrap();
+        assert_eq!(Ok(JournalMode::Delete), con.journal_mode());
+        assert_eq!(Ok(JournalMode::Wal), con.set_journal_mode(JournalMode::Wal));
+        assert_eq!(Ok(JournalMode::Wal), con.journal_mode());
+
+        let mut con = Connection::open_memory_db().unwrap();
+        let e = con.set_journal_mode(JournalMode::Wal).unwrap_err();
+        assert_eq!(SQLITE_ERROR, e.code());
+        assert_eq!(Some("journal_mode is MEMORY instead of WAL"), e.message());
+    }
+
+    #[test]
+    fn synchronous() {
+        let mut con = Connection::open_memory_db().unwrap();
+        for &s in &[
+            Synchronous::Off,
+            Synchronous::Normal,
+            Synchronous::Full,
+            Synchronous::Extra,
+        ] {
+            assert_eq!(Ok(s), con.set_synchronous(s));
+            assert_eq!(Ok(s), con.synchronous());
+        }
+    }
+}
@@ -0,0 +1,167 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{sqlite3_db_status, Connection, Error};
+use std::os::raw::c_int;
+
+/// Counter of [`Connection::db_status`] .
+///
+/// See [`Status Parameters for database connections`] for details.
+///
+/// [`Connection::db_status`]: struct.Connection.html#method.db_status
+/// [`Status Parameters for database connections`]:
+/// https://www.sqlite.org/c3ref/c_dbstatus_options.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DbStatus {
+    /// "SQLITE_DBSTATUS_LOOKASIDE_USED"; the number of the lookaside memory slots in use.
+    LookasideUsed,
+    /// "SQLITE_DBSTATUS_CACHE_USED"; the bytes of the heap memory used by the pager caches.
+    CacheUsed,
+    /// "SQLITE_DBSTATUS_SCHEMA_USED"; the bytes of the heap memory used to store the schemas.
+    SchemaUsed,
+    /// "SQLITE_DBSTATUS_STMT_USED"; the bytes of the heap memory used by the prepared
+    /// statements.
+    StmtUsed,
+    /// "SQLITE_DBSTATUS_LOOKASIDE_HIT"; the number of the malloc calls satisfied by the lookaside
+    /// memory. (Only the highwater is meaningful.)
+    LookasideHit,
+    /// "SQLITE_DBSTATUS_LOOKASIDE_MISS_SIZE"; the number of the malloc calls that could not be
+    /// satisfied by the lookaside memory because of the size. (Only the highwater is meaningful.)
+    LookasideMissSize,
+    /// "SQLITE_DBSTATUS_LOOKASIDE_MISS_FULL"; the number of the malloc calls that could not be
+    /// satisfied by the lookaside memory because it was full. (Only the highwater is
+    /// meaningful.)
+    LookasideMissFull,
+    /// "SQLITE_DBSTATUS_CACHE_HIT"; the number of the pager cache hits.
+    CacheHit,
+    /// "SQLITE_DBSTATUS_CACHE_MISS"; the number of the pager cache misses.
+    CacheMiss,
+    /// "SQLITE_DBSTATUS_CACHE_WRITE"; the number of the dirty pages written to the disk.
+    CacheWrite,
+    /// "SQLITE_DBSTATUS_DEFERRED_FKS"; 1 if there are outstanding deferred foreign key
+    /// constraints, or 0 if not.
+    DeferredFks,
+    /// "SQLITE_DBSTATUS_CACHE_USED_SHARED"; same to [`CacheUsed`] except that the shared
+    /// caches are divided among the connections.
+    ///
+    /// [`CacheUsed`]: #variant.CacheUsed
+    CacheUsedShared,
+    /// "SQLITE_DBSTATUS_CACHE_SPILL"; the number of the dirty pages written to the disk in the
+    /// middle of a transaction.
+    CacheSpill,
+}
+
+impl DbStatus {
+    const fn as_c_int(self) -> c_int {
+        match self {
+            Self::LookasideUsed => 0,
+            Self::CacheUsed => 1,
+            Self::SchemaUsed => 2,
+            Self::StmtUsed => 3,
+            Self::LookasideHit => 4,
+            Self::LookasideMissSize => 5,
+            Self::LookasideMissFull => 6,
+            Self::CacheHit => 7,
+            Self::CacheMiss => 8,
+            Self::CacheWrite => 9,
+            Self::DeferredFks => 10,
+            Self::CacheUsedShared => 11,
+            Self::CacheSpill => 12,
+        }
+    }
+}
+
+impl Connection {
+    /// Wrapper of C function [`sqlite3_db_status`] .
+    ///
+    /// Returns the current value and the highwater of `counter` , and resets the highwater if
+    /// `reset` is `true` .
+    ///
+    /// [`sqlite3_db_status`]: https://www.sqlite.org/c3ref/db_status.html
+    pub fn db_status(&self, counter: DbStatus, reset: bool) -> Result<(i32, i32), Error> {
+        let mut current: c_int = 0;
+        let mut highwater: c_int = 0;
+        let code = unsafe {
+            sqlite3_db_status(
+                self.raw(),
+                counter.as_c_int(),
+                &mut current,
+                &mut highwater,
+                reset as c_int,
+            )
+        };
+        match Error::new(code) {
+            e if e == Error::OK => Ok((current, highwater)),
+            e => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn db_status() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let (schema_used, _) = con.db_status(DbStatus::SchemaUsed, false).unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("value" INTEGER)"#)
+            .unwrap();
+        let (current, _) = con.db_status(DbStatus::SchemaUsed, false).unwrap();
+        assert!(schema_used < current);
+
+        let (cache_used, _) = con.db_status(DbStatus::CacheUsed, false).unwrap();
+        assert!(0 < cache_used);
+        assert_eq!(Ok((0, 0)), con.db_status(DbStatus::DeferredFks, false));
+    }
+}
@@ -0,0 +1,179 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{Connection, Error, Feature, SQLITE_ERROR};
+
+/// Disk usage of a table or an index, which [`Connection::table_sizes`] returns.
+///
+/// [`Connection::table_sizes`]: struct.Connection.html#method.table_sizes
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TableSize {
+    /// The name of the table or the index.
+    pub name: String,
+    /// The number of the pages used.
+    pub pages: u64,
+    /// The total bytes of the pages used.
+    pub bytes: u64,
+    /// The bytes of the stored data (i.e. the keys and the values.)
+    pub payload: u64,
+    /// The bytes of the unused space in the pages.
+    pub unused: u64,
+}
+
+const TABLE_SIZES: &str = r#"SELECT "name", count(*), sum("pgsize"), sum("payload"), sum("unused")
+FROM "dbstat" GROUP BY "name" ORDER BY "name""#;
+
+impl Connection {
+    /// Returns the disk usage of each table and index of the main database aggregating
+    /// [`DBSTAT virtual table`] .
+    ///
+    /// The internal tables like "sqlite_schema" are included. The result is sorted by the name.
+    ///
+    /// Returns `SQLITE_ERROR` unless the runtime SQLite was compiled with
+    /// `SQLITE_ENABLE_DBSTAT_VTAB` . (See [`Feature::Dbstat`] .)
+    ///
+    /// [`DBSTAT virtual table`]: https://www.sqlite.org/dbstat.html
+    /// [`Feature::Dbstat`]: enum.Feature.html#variant.Dbstat
+    pub fn table_sizes(&mut self) -> Result<Vec<TableSize>, Error> {
+        if !self.supports(Feature::Dbstat) {
+            let message = "the runtime SQLite was not compiled with SQLITE_ENABLE_DBSTAT_VTAB";
+            return Err(Error::with_message(SQLITE_ERROR, message));
+        }
+
+        let mut stmt = self.stmt_once(TABLE_SIZES)?;
+        let mut ret = Vec::new();
+        while stmt.step()? {
+            ret.push(TableSize {
+                name: stmt.get(0)?,
+                pages: stmt.get::<i64>(1)? as u64,
+                bytes: stmt.get::<i64>(2)? as u64,
+                payload: stmt.get::<i64>(3)? as u64,
+                unused: stmt.get::<i64>(4)? as u64,
+            });
+        }
+        Ok(ret)
+    }
+
+    /// Returns the value of `PRAGMA page_count` of `schema` ("main" if `None` ); i.e. the
+    /// number of the pages in the database file.
+    #[inline]
+    pub fn page_count(&mut self, schema: Option<&str>) -> Result<u64, Error> {
+        self.pragma_int(schema, "page_count")
+            .map(|v| v.unwrap_or(0) as u64)
+    }
+
+    /// Returns the value of `PRAGMA freelist_count` of `schema` ("main" if `None` ); i.e. the
+    /// number of the unused pages in the database file.
+    #[inline]
+    pub fn freelist_count(&mut self, schema: Option<&str>) -> Result<u64, Error> {
+        self.pragma_int(schema, "freelist_count")
+            .map(|v| v.unwrap_or(0) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_sizes() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(
+            r#"CREATE TABLE "empty" ("value" TEXT);
+               CREATE TABLE "foo" ("value" TEXT);
+               WITH RECURSIVE "s"("i") AS (SELECT 1 UNION ALL SELECT "i" + 1 FROM "s" LIMIT 4096)
+               INSERT INTO "foo" SELECT hex(randomblob(16)) FROM "s""#,
+        )
+        .unwrap();
+
+        if !con.supports(Feature::Dbstat) {
+            let e = con.table_sizes().unwrap_err();
+            assert_eq!(SQLITE_ERROR, e.code());
+            return;
+        }
+
+        let sizes = con.table_sizes().unwrap();
+        let names: Vec<&str> = sizes.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(vec!["empty", "foo", "sqlite_schema"], names);
+
+        let (empty, foo) = (&sizes[0], &sizes[1]);
+        assert_eq!(1, empty.pages);
+        assert_eq!(0, empty.payload);
+        assert!(empty.pages < foo.pages);
+        assert!(empty.bytes < foo.bytes);
+        assert!(4096 * 32 <= foo.payload);
+        assert!(foo.payload + foo.unused <= foo.bytes);
+
+        let page_count = con.page_count(None).unwrap();
+        let total: u64 = sizes.iter().map(|s| s.pages).sum();
+        assert_eq!(page_count, total + con.freelist_count(None).unwrap());
+    }
+
+    #[test]
+    fn freelist_count() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(
+            r#"CREATE TABLE "foo" ("value" BLOB);
+               WITH RECURSIVE "s"("i") AS (SELECT 1 UNION ALL SELECT "i" + 1 FROM "s" LIMIT 64)
+               INSERT INTO "foo" SELECT randomblob(4096) FROM "s""#,
+        )
+        .unwrap();
+        let page_count = con.page_count(None).unwrap();
+        assert!(64 < page_count);
+        assert_eq!(Ok(0), con.freelist_count(Some("main")));
+
+        con.execute_batch(r#"DELETE FROM "foo""#).unwrap();
+        assert_eq!(Ok(page_count), con.page_count(None));
+        assert!(64 <= con.freelist_count(None).unwrap());
+    }
+}
@@ -0,0 +1,477 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{Error, Row, Stmt, ValueRef, SQLITE_MISMATCH};
+use serde::de::value::SeqDeserializer;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, Visitor};
+use serde::forward_to_deserialize_any;
+use std::fmt::Display;
+
+impl de::Error for Error {
+    #[inline]
+    fn custom<T>(msg: T) -> Self
+    where
+        T: Display,
+    {
+        Error::with_message(SQLITE_MISMATCH, msg.to_string())
+    }
+}
+
+/// Adds the column name to the message of `e` .
+fn with_column(e: Error, column: &str) -> Error {
+    let message = match e.message() {
+        None => format!("column `{}`", column),
+        Some(m) => format!("column `{}`: {}", column, m),
+    };
+    Error::with_message(e.code(), message)
+}
+
+/// `serde::Deserializer` over a row.
+///
+/// A struct is deserialized mapping the column names to the field names. A tuple or a sequence is
+/// deserialized from the columns in order.
+///
+/// Deserializing a struct fails if any column name is not a field of the struct. A field without
+/// the corresponding column is an error unless the field is an `Option` .
+///
+/// `#[serde(flatten)]` is supported; serde deserializes such a struct as a map, buffering the
+/// columns that are not the fields of the outer struct for the flattened ones. Then, unknown
+/// columns are ignored instead of being an error, and the flattened fields do not get the
+/// conversions of this deserializer; e.g. a BLOB is not deserialized into a flattened
+/// `Vec<u8>` , nor an INTEGER into a flattened `bool` .
+pub struct RowDeserializer<'a> {
+    row: Row<'a>,
+}
+
+impl<'a> RowDeserializer<'a> {
+    /// Creates a new instance.
+    #[inline]
+    pub fn new(row: Row<'a>) -> Self {
+        Self { row }
+    }
+
+    fn column_name(&self, index: usize) -> &'a str {
+        self.row.column_name(index).unwrap_or_default()
+    }
+}
+
+impl<'de> de::Deserializer<'de> for RowDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(RowMapAccess { de: self, index: 0 })
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        for i in 0..self.row.column_count() {
+            let column = self.column_name(i);
+            if !fields.contains(&column) {
+                let message = format!("column `{}` is not a field of the struct", column);
+                return Err(Error::with_message(SQLITE_MISMATCH, message));
+            }
+        }
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        let row = self.row;
+        let values = (0..row.column_count()).map(move |i| {
+            let column = row.column_name(i).unwrap_or_default();
+            ValueDeserializer {
+                value: row.get_ref(i).unwrap_or(ValueRef::Null),
+                column,
+            }
+        });
+        visitor.visit_seq(SeqDeserializer::new(values))
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct enum identifier ignored_any
+    }
+}
+
+struct RowMapAccess<'a> {
+    de: RowDeserializer<'a>,
+    index: usize,
+}
+
+impl<'de> MapAccess<'de> for RowMapAccess<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        if self.de.row.column_count() <= self.index {
+            return Ok(None);
+        }
+
+        let column = self.de.column_name(self.index);
+        seed.deserialize(column.into_deserializer()).map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let index = self.index;
+        self.index += 1;
+
+        let column = self.de.column_name(index);
+        let value = self.de.row.get_ref(index)?;
+        seed.deserialize(ValueDeserializer { value, column })
+            .map_err(|e| with_column(e, column))
+    }
+}
+
+/// `serde::Deserializer` over a column value.
+struct ValueDeserializer<'a> {
+    value: ValueRef<'a>,
+    column: &'a str,
+}
+
+impl<'de> IntoDeserializer<'de, Error> for ValueDeserializer<'de> {
+    type Deserializer = Self;
+
+    #[inline]
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.value {
+            ValueRef::Null => visitor.visit_unit(),
+            ValueRef::Integer(i) => visitor.visit_i64(i),
+            ValueRef::Real(f) => visitor.visit_f64(f),
+            ValueRef::Text(s) => match core::str::from_utf8(s) {
+                Ok(s) => visitor.visit_borrowed_str(s),
+                Err(e) => Err(with_column(de::Error::custom(e), self.column)),
+            },
+            ValueRef::Blob(b) => visitor.visit_borrowed_bytes(b),
+        }
+    }
+
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.value {
+            ValueRef::Integer(0) => visitor.visit_bool(false),
+            ValueRef::Integer(1) => visitor.visit_bool(true),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.value {
+            ValueRef::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.value {
+            // `Vec<u8>` is deserialized as a sequence.
+            ValueRef::Blob(b) => visitor.visit_seq(SeqDeserializer::new(b.iter().copied())),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        unit unit_struct tuple tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl Stmt {
+    /// Executes the statement from the beginning and returns all the rows deserialized into `T` .
+    ///
+    /// See [`RowDeserializer`] for how a row is deserialized.
+    ///
+    /// If the statement is in the middle of the iteration, it is reset at first.
+    /// The bound parameters are kept.
+    ///
+    /// This method is enabled by feature "serde".
+    ///
+    /// [`RowDeserializer`]: struct.RowDeserializer.html
+    pub fn query_de<T>(&mut self) -> Result<Vec<T>, Error>
+    where
+        T: DeserializeOwned,
+    {
+        self.reset();
+
+        let mut ret = Vec::new();
+        while self.step()? {
+            let row = self.row()?;
+            match T::deserialize(RowDeserializer::new(row)) {
+                Ok(t) => ret.push(t),
+                Err(e) => {
+                    self.reset();
+                    return Err(e);
+                }
+            }
+        }
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Connection;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct UserId(i64);
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Foo {
+        id: UserId,
+        name: String,
+        tags: Option<Vec<u8>>,
+        #[serde(rename = "score")]
+        points: f64,
+        missing: Option<i64>,
+    }
+
+    fn setup() -> Connection {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(
+            r#"CREATE TABLE "foo" ("id" INTEGER, "name" TEXT, "tags" BLOB, "score" REAL);
+               INSERT INTO "foo" VALUES (1, 'a', x'0102', 1.5), (2, 'b', NULL, 2);"#,
+        )
+        .unwrap();
+        con
+    }
+
+    #[test]
+    fn query_de() {
+        let mut con = setup();
+
+        let sql = r#"SELECT "id", "name", "tags", "score" FROM "foo" ORDER BY "id""#;
+        let rows: Vec<Foo> = con.stmt_once(sql).unwrap().query_de().unwrap();
+        let expected = vec![
+            Foo {
+                id: UserId(1),
+                name: "a".to_string(),
+                tags: Some(vec![1, 2]),
+                points: 1.5,
+                missing: None,
+            },
+            Foo {
+                id: UserId(2),
+                name: "b".to_string(),
+                tags: None,
+                points: 2.0,
+                missing: None,
+            },
+        ];
+        assert_eq!(expected, rows);
+
+        let sql = r#"SELECT "id", "name" FROM "foo" ORDER BY "id""#;
+        let rows: Vec<(i64, String)> = con.stmt_once(sql).unwrap().query_de().unwrap();
+        assert_eq!(vec![(1, "a".to_string()), (2, "b".to_string())], rows);
+    }
+
+    #[test]
+    fn errors() {
+        let mut con = setup();
+
+        // Unknown column
+        let sql = r#"SELECT "id", "name", "tags", "score", 1 AS "extra" FROM "foo""#;
+        let e = con.stmt_once(sql).unwrap().query_de::<Foo>().unwrap_err();
+        assert!(e.message().unwrap().contains("`extra`"));
+
+        // Missing column
+        let sql = r#"SELECT "id", "tags", "score" FROM "foo""#;
+        let e = con.stmt_once(sql).unwrap().query_de::<Foo>().unwrap_err();
+        assert!(e.message().unwrap().contains("name"));
+
+        // Type mismatch
+        let sql = r#"SELECT "name" AS "id", "name", "tags", "score" FROM "foo""#;
+        let e = con.stmt_once(sql).unwrap().query_de::<Foo>().unwrap_err();
+        assert!(e.message().unwrap().contains("column `id`"));
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Score {
+        score: f64,
+        tags: Option<Vec<u8>>,
+        missing: Option<i64>,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Flatten {
+        id: i64,
+        #[serde(flatten)]
+        score: Score,
+        flag: bool,
+    }
+
+    #[test]
+    fn flatten() {
+        let mut con = setup();
+
+        let sql = r#"SELECT "id", "score", NULL AS "tags", "id" = 1 AS "flag" FROM "foo"
+                     ORDER BY "id""#;
+        let rows: Vec<Flatten> = con.stmt_once(sql).unwrap().query_de().unwrap();
+        let expected = vec![
+            Flatten {
+                id: 1,
+                score: Score {
+                    score: 1.5,
+                    tags: None,
+                    missing: None,
+                },
+                flag: true,
+            },
+            Flatten {
+                id: 2,
+                score: Score {
+                    score: 2.0,
+                    tags: None,
+                    missing: None,
+                },
+                flag: false,
+            },
+        ];
+        assert_eq!(expected, rows);
+
+        // Unknown columns are not rejected.
+        let sql = r#"SELECT "id", "name", "score", 0 AS "flag" FROM "foo" ORDER BY "id""#;
+        let rows: Vec<Flatten> = con.stmt_once(sql).unwrap().query_de().unwrap();
+        assert_eq!(2, rows.len());
+
+        // Missing column
+        let sql = r#"SELECT "id", 0 AS "flag" FROM "foo""#;
+        let e = con
+            .stmt_once(sql)
+            .unwrap()
+            .query_de::<Flatten>()
+            .unwrap_err();
+        assert!(e.message().unwrap().contains("score"));
+
+        // BLOB is not deserialized into a flattened `Vec<u8>` .
+        let sql = r#"SELECT "id", "score", "tags", 0 AS "flag" FROM "foo" WHERE "id" = 1"#;
+        let e = con
+            .stmt_once(sql)
+            .unwrap()
+            .query_de::<Flatten>()
+            .unwrap_err();
+        assert_eq!(crate::SQLITE_MISMATCH, e.code());
+    }
+}
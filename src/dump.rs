@@ -0,0 +1,372 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::quote::quote_identifier;
+use crate::{Connection, Error, ValueRef, SQLITE_MISUSE};
+use core::fmt::Write as _;
+use std::io::Write;
+
+/// Options of [`Connection::dump`] .
+///
+/// [`Connection::dump`]: struct.Connection.html#method.dump
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DumpOptions {
+    /// Names of the tables to dump, or `None` to dump all the tables. The default is `None` .
+    ///
+    /// The indices and triggers follow the table they belong to. The views are regarded as
+    /// tables.
+    pub tables: Option<Vec<String>>,
+    /// Names of the tables not to dump. The default is empty.
+    pub exclude_tables: Vec<String>,
+    /// Whether to wrap the script in "BEGIN TRANSACTION" and "COMMIT". The default is `true` .
+    pub transaction: bool,
+}
+
+impl Default for DumpOptions {
+    fn default() -> Self {
+        Self {
+            tables: None,
+            exclude_tables: Vec::new(),
+            transaction: true,
+        }
+    }
+}
+
+impl DumpOptions {
+    fn includes(&self, table: &str) -> bool {
+        let included = match &self.tables {
+            None => true,
+            Some(tables) => tables.iter().any(|t| t == table),
+        };
+        included && !self.exclude_tables.iter().any(|t| t == table)
+    }
+}
+
+/// Row of "sqlite_master".
+struct SchemaObject {
+    type_: String,
+    name: String,
+    tbl_name: String,
+    sql: String,
+}
+
+impl Connection {
+    /// Writes the schema and the contents of the main database into `out` as an SQL script like
+    /// ".dump" command of the sqlite3 shell.
+    ///
+    /// The script creates the tables, inserts the rows, and then creates the indices, the views,
+    /// and the triggers in this order, so that [`execute_batch`] can import it into an empty
+    /// database. It starts with `PRAGMA foreign_keys = OFF` to insert the rows regardless of the
+    /// foreign key constraints. The generated columns are not inserted but calculated again.
+    ///
+    /// The whole database is read in a transaction to get a consistent snapshot; a transaction
+    /// is started and finished in this method unless one is already open.
+    ///
+    /// Returns `SQLITE_MISUSE` if a dumped table is a virtual table, which is not supported, or
+    /// `SQLITE_IOERR` if failed to write into `out` .
+    ///
+    /// [`execute_batch`]: #method.execute_batch
+    pub fn dump<W>(&mut self, out: W, opts: DumpOptions) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        if !self.is_autocommit() {
+            return self.dump_impl(out, &opts);
+        }
+
+        self.execute_batch("BEGIN")?;
+        let ret = self.dump_impl(out, &opts);
+        // The transaction only reads, so COMMIT just finishes it.
+        let end = self.execute_batch("COMMIT");
+        ret.and(end)
+    }
+
+    fn dump_impl<W>(&mut self, mut out: W, opts: &DumpOptions) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        let objects = self.schema_objects(opts)?;
+
+        let mut script = String::from("PRAGMA foreign_keys = OFF;\n");
+        if opts.transaction {
+            script.push_str("BEGIN TRANSACTION;\n");
+        }
+        out.write_all(script.as_bytes())
+            .map_err(|e| Error::from_io(&e))?;
+
+        for object in objects.iter().filter(|o| o.type_ == "table") {
+            if object
+                .sql
+                .to_ascii_uppercase()
+                .starts_with("CREATE VIRTUAL TABLE")
+            {
+                let message = format!("virtual table is not supported: {}", object.name);
+                return Err(Error::with_message(SQLITE_MISUSE, message));
+            }
+            writeln!(out, "{};", object.sql).map_err(|e| Error::from_io(&e))?;
+            self.dump_rows(&mut out, &object.name)?;
+        }
+
+        if self.table_exists("sqlite_sequence")? {
+            self.dump_sequence(&mut out, &objects)?;
+        }
+
+        for object in objects.iter().filter(|o| o.type_ != "table") {
+            writeln!(out, "{};", object.sql).map_err(|e| Error::from_io(&e))?;
+        }
+
+        if opts.transaction {
+            out.write_all(b"COMMIT;\n")
+                .map_err(|e| Error::from_io(&e))?;
+        }
+        out.flush().map_err(|e| Error::from_io(&e))
+    }
+
+    /// Returns the objects in "sqlite_master" to dump in the order to create.
+    fn schema_objects(&mut self, opts: &DumpOptions) -> Result<Vec<SchemaObject>, Error> {
+        const SQL: &str = r#"SELECT "type", "name", "tbl_name", "sql" FROM "sqlite_master"
+                             WHERE "sql" NOT NULL AND "name" NOT LIKE 'sqlite\_%' ESCAPE '\'
+                             ORDER BY CASE "type" WHEN 'table' THEN 0 WHEN 'index' THEN 1
+                                                  WHEN 'view' THEN 2 ELSE 3 END, "rowid""#;
+
+        let mut stmt = self.stmt_once(SQL)?;
+        let mut ret = Vec::new();
+        while stmt.step()? {
+            let tbl_name = stmt.column_text(2).unwrap_or_default().to_string();
+            if !opts.includes(&tbl_name) {
+                continue;
+            }
+            ret.push(SchemaObject {
+                type_: stmt.column_text(0).unwrap_or_default().to_string(),
+                name: stmt.column_text(1).unwrap_or_default().to_string(),
+                tbl_name,
+                sql: stmt.column_text(3).unwrap_or_default().to_string(),
+            });
+        }
+        Ok(ret)
+    }
+
+    /// Writes "INSERT" statements of all the rows in `table` .
+    fn dump_rows<W>(&mut self, out: &mut W, table: &str) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        // Skip the generated columns. ("hidden" is 2 or 3.)
+        let sql = format!("PRAGMA table_xinfo({})", quote_identifier(table)?);
+        let mut stmt = self.stmt_once(&sql)?;
+        let mut columns = Vec::new();
+        while stmt.step()? {
+            if stmt.column_int(6).unwrap_or(0) == 0 {
+                columns.push(quote_identifier(stmt.column_text(1).unwrap_or_default())?);
+            }
+        }
+        let columns = columns.join(", ");
+
+        let table = quote_identifier(table)?;
+        let sql = format!("SELECT {} FROM {}", columns, table);
+        let mut stmt = self.stmt_once(&sql)?;
+        let mut line = String::new();
+        while stmt.step()? {
+            line.clear();
+            write!(line, "INSERT INTO {} ({}) VALUES (", table, columns).unwrap();
+            for i in 0..stmt.column_count() {
+                if 0 < i {
+                    line.push_str(", ");
+                }
+                stmt.column_value_ref(i)?
+                    .write_sql_literal(&mut line)
+                    .unwrap();
+            }
+            line.push_str(");\n");
+            out.write_all(line.as_bytes())
+                .map_err(|e| Error::from_io(&e))?;
+        }
+        Ok(())
+    }
+
+    /// Writes the statements to restore "sqlite_sequence" of the dumped tables.
+    fn dump_sequence<W>(&mut self, out: &mut W, objects: &[SchemaObject]) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        const SQL: &str = r#"SELECT "name", "seq" FROM "sqlite_sequence""#;
+
+        let mut stmt = self.stmt_once(SQL)?;
+        let mut line = String::new();
+        while stmt.step()? {
+            let name = stmt.column_value_ref(0)?;
+            let dumped = objects
+                .iter()
+                .any(|o| o.type_ == "table" && ValueRef::Text(o.tbl_name.as_bytes()) == name);
+            if !dumped {
+                continue;
+            }
+
+            line.clear();
+            line.push_str(r#"DELETE FROM "sqlite_sequence" WHERE "name" = "#);
+            name.write_sql_literal(&mut line).unwrap();
+            line.push_str(";\n");
+            line.push_str(r#"INSERT INTO "sqlite_sequence" ("name", "seq") VALUES ("#);
+            name.write_sql_literal(&mut line).unwrap();
+            line.push_str(", ");
+            stmt.column_value_ref(1)?
+                .write_sql_literal(&mut line)
+                .unwrap();
+            line.push_str(");\n");
+            out.write_all(line.as_bytes())
+                .map_err(|e| Error::from_io(&e))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Value;
+    use std::collections::HashMap;
+
+    fn setup() -> Connection {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(
+            r#"CREATE TABLE "parent" ("id" INTEGER PRIMARY KEY AUTOINCREMENT, "name" TEXT);
+               CREATE TABLE "it's ""child""" (
+                   "id" INTEGER PRIMARY KEY,
+                   "parent_id" INTEGER REFERENCES "parent" ("id"),
+                   "text" TEXT,
+                   "data" BLOB,
+                   "real" REAL,
+                   "twice" REAL GENERATED ALWAYS AS ("real" * 2)
+               );
+               CREATE INDEX "child_text" ON "it's ""child""" ("text");
+               CREATE VIEW "names" AS SELECT "name" FROM "parent";
+               CREATE TRIGGER "parent_delete" AFTER DELETE ON "parent" BEGIN
+                   DELETE FROM "it's ""child""" WHERE "parent_id" = OLD."id";
+               END;
+               INSERT INTO "parent" ("name") VALUES ('a'), ('b'), ('c');
+               DELETE FROM "parent" WHERE "name" = 'c';
+               INSERT INTO "it's ""child""" ("parent_id", "text", "data", "real") VALUES
+                   (1, 'say ''hi''', x'00ff', 0.1),
+                   (1, 'line 1' || char(10) || 'line 2;', x'', 1e300),
+                   (2, NULL, NULL, -2.5),
+                   (2, CAST(x'ff' AS TEXT), randomblob(100), 9e999);"#,
+        )
+        .unwrap();
+        con
+    }
+
+    fn contents(con: &mut Connection) -> HashMap<String, Vec<HashMap<String, Value>>> {
+        let tables = [r#""parent""#, r#""it's ""child""""#, r#""sqlite_sequence""#];
+        let mut ret = HashMap::new();
+        for &table in tables.iter() {
+            let sql = format!("SELECT * FROM {} ORDER BY 1", table);
+            let rows = match con.stmt_once(&sql) {
+                Ok(mut stmt) => stmt.rows_as_maps().unwrap(),
+                Err(_) => continue,
+            };
+            ret.insert(table.to_string(), rows);
+        }
+        let mut stmt = con
+            .stmt_once(r#"SELECT * FROM "sqlite_master" ORDER BY "name""#)
+            .unwrap();
+        ret.insert("sqlite_master".to_string(), stmt.rows_as_maps().unwrap());
+        ret
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut a = setup();
+        let mut script = Vec::new();
+        a.dump(&mut script, DumpOptions::default()).unwrap();
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.starts_with("PRAGMA foreign_keys = OFF;\nBEGIN TRANSACTION;\n"));
+        assert!(script.ends_with("COMMIT;\n"));
+        assert!(a.is_autocommit());
+
+        let mut b = Connection::open_memory_db().unwrap();
+        b.execute_batch(&script).unwrap();
+        assert_eq!(contents(&mut a), contents(&mut b));
+    }
+
+    #[test]
+    fn options() {
+        let mut a = setup();
+        let opts = DumpOptions {
+            exclude_tables: vec!["it's \"child\"".to_string()],
+            transaction: false,
+            ..DumpOptions::default()
+        };
+        let mut script = Vec::new();
+        a.dump(&mut script, opts).unwrap();
+        let script = String::from_utf8(script).unwrap();
+        assert!(!script.contains("BEGIN TRANSACTION"));
+        assert!(!script.contains(r#"CREATE TABLE "it's"#));
+        assert!(!script.contains(r#"INSERT INTO "it's"#));
+        assert!(script.contains(r#"CREATE VIEW "names""#));
+
+        let mut b = Connection::open_memory_db().unwrap();
+        b.execute_batch(&script).unwrap();
+        assert_eq!(Ok(true), b.table_exists("parent"));
+        assert_eq!(Ok(false), b.table_exists("it's \"child\""));
+
+        let opts = DumpOptions {
+            tables: Some(vec!["names".to_string()]),
+            ..DumpOptions::default()
+        };
+        let mut script = Vec::new();
+        a.dump(&mut script, opts).unwrap();
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains(r#"CREATE VIEW "names""#));
+        assert!(!script.contains("CREATE TABLE"));
+    }
+}
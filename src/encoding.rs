@@ -0,0 +1,200 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::ValueRef;
+use core::fmt;
+
+/// Encoding of BLOB values in the text output such as [`Stmt::write_csv`] .
+///
+/// [`Stmt::write_csv`]: struct.Stmt.html#method.write_csv
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlobEncoding {
+    /// Standard base64 with padding (RFC 4648.)
+    Base64,
+    /// Lowercase hexadecimal digits.
+    Hex,
+}
+
+impl BlobEncoding {
+    pub(crate) fn encode(self, bytes: &[u8]) -> String {
+        let mut ret = String::new();
+        // Writing into String never fails.
+        match self {
+            Self::Base64 => {
+                ret.reserve(bytes.len().div_ceil(3) * 4);
+                write_base64(&mut ret, bytes).unwrap();
+            }
+            Self::Hex => {
+                ret.reserve(bytes.len() * 2);
+                write_hex(&mut ret, bytes).unwrap();
+            }
+        }
+        ret
+    }
+}
+
+/// Adapter to display TEXT or BLOB in lowercase hexadecimal digits, which
+/// [`ValueRef::display_hex`] returns.
+///
+/// The other types are displayed as SQL literals, i.e. "NULL" , "1" , "1.5" and so on.
+///
+/// [`ValueRef::display_hex`]: enum.ValueRef.html#method.display_hex
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplayHex<'a>(pub(crate) ValueRef<'a>);
+
+impl fmt::Display for DisplayHex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            ValueRef::Text(bytes) | ValueRef::Blob(bytes) => write_hex(f, bytes),
+            v => v.write_sql_literal(f),
+        }
+    }
+}
+
+/// Adapter to display TEXT or BLOB in standard base64 with padding (RFC 4648,) which
+/// [`ValueRef::display_base64`] returns.
+///
+/// The other types are displayed as SQL literals, i.e. "NULL" , "1" , "1.5" and so on.
+///
+/// [`ValueRef::display_base64`]: enum.ValueRef.html#method.display_base64
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplayBase64<'a>(pub(crate) ValueRef<'a>);
+
+impl fmt::Display for DisplayBase64<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            ValueRef::Text(bytes) | ValueRef::Blob(bytes) => write_base64(f, bytes),
+            v => v.write_sql_literal(f),
+        }
+    }
+}
+
+fn write_base64<W>(out: &mut W, bytes: &[u8]) -> fmt::Result
+where
+    W: ?Sized + fmt::Write,
+{
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize;
+        let mut buf = [b'='; 4];
+        for (i, c) in buf.iter_mut().enumerate().take(chunk.len() + 1) {
+            *c = TABLE[(n >> (18 - 6 * i)) & 0x3f];
+        }
+        // `buf` consists of ASCII characters.
+        out.write_str(core::str::from_utf8(&buf).unwrap())?;
+    }
+    Ok(())
+}
+
+/// Writes `bytes` into `out` in lowercase hexadecimal digits.
+pub(crate) fn write_hex<W>(out: &mut W, bytes: &[u8]) -> fmt::Result
+where
+    W: ?Sized + fmt::Write,
+{
+    const TABLE: &[u8; 16] = b"0123456789abcdef";
+
+    // Encode through a buffer on the stack not to call `write_str` for each byte.
+    let mut buf = [0; 128];
+    for chunk in bytes.chunks(buf.len() / 2) {
+        for (i, &b) in chunk.iter().enumerate() {
+            buf[2 * i] = TABLE[(b >> 4) as usize];
+            buf[2 * i + 1] = TABLE[(b & 0x0f) as usize];
+        }
+        // `buf` consists of ASCII characters.
+        out.write_str(core::str::from_utf8(&buf[..2 * chunk.len()]).unwrap())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode() {
+        let cases: &[(&[u8], &str, &str)] = &[
+            (b"", "", ""),
+            (b"f", "Zg==", "66"),
+            (b"fo", "Zm8=", "666f"),
+            (b"foo", "Zm9v", "666f6f"),
+            (b"foob", "Zm9vYg==", "666f6f62"),
+            (b"\xff\x00\x10", "/wAQ", "ff0010"),
+        ];
+        for &(bytes, base64, hex) in cases {
+            assert_eq!(base64, BlobEncoding::Base64.encode(bytes));
+            assert_eq!(hex, BlobEncoding::Hex.encode(bytes));
+            assert_eq!(base64, ValueRef::Blob(bytes).display_base64().to_string());
+            assert_eq!(hex, ValueRef::Text(bytes).display_hex().to_string());
+        }
+
+        // Longer than the buffer of `write_hex` .
+        let bytes: Vec<u8> = (0..=255).collect();
+        let hex = ValueRef::Blob(&bytes).display_hex().to_string();
+        assert_eq!(512, hex.len());
+        assert!(hex.starts_with("000102"));
+        assert!(hex.ends_with("fdfeff"));
+    }
+
+    #[test]
+    fn display_others() {
+        assert_eq!("NULL", ValueRef::Null.display_hex().to_string());
+        assert_eq!("-3", ValueRef::Integer(-3).display_base64().to_string());
+        assert_eq!("1.5", ValueRef::Real(1.5).display_hex().to_string());
+    }
+}
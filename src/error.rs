@@ -51,43 +51,430 @@
 // ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
 // POSSIBILITY OF SUCH DAMAGE.
 
-use crate::{SQLITE_DONE, SQLITE_OK, SQLITE_ROW};
+use crate::{
+    sqlite3, Connection, SQLITE_AUTH, SQLITE_BUSY, SQLITE_CANTOPEN, SQLITE_DONE, SQLITE_ERROR,
+    SQLITE_FULL, SQLITE_INTERRUPT, SQLITE_IOERR, SQLITE_LOCKED, SQLITE_MISUSE, SQLITE_NOTFOUND,
+    SQLITE_OK, SQLITE_PERM, SQLITE_READONLY, SQLITE_ROW,
+};
 use std::ffi::CStr;
 use std::fmt;
+use std::io;
 use std::os::raw::{c_char, c_int};
 
+/// Error code of [`Error::TIMEOUT`] , which libsqlite3 does not use.
+///
+/// [`Error::TIMEOUT`]: struct.Error.html#associatedconstant.TIMEOUT
+const SQLITE_INTERRUPT_TIMEOUT: c_int = SQLITE_INTERRUPT | (0xff << 8);
+
+/// Error code of [`Error::AUTOCOMMIT_WRITE`] , which libsqlite3 does not use.
+///
+/// [`Error::AUTOCOMMIT_WRITE`]: struct.Error.html#associatedconstant.AUTOCOMMIT_WRITE
+const SQLITE_MISUSE_AUTOCOMMIT: c_int = SQLITE_MISUSE | (0xff << 8);
+
+/// Error code of [`Error::UNSUPPORTED`] , which libsqlite3 does not use.
+///
+/// [`Error::UNSUPPORTED`]: struct.Error.html#associatedconstant.UNSUPPORTED
+const SQLITE_ERROR_UNSUPPORTED: c_int = SQLITE_ERROR | (0xff << 8);
+
 /// `Error` is a wrapper of libsqlite3 error code.
-#[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+///
+/// `Error` can also hold a message to describe the detail of the error. The message and the OS
+/// error number are additional information; `==` , `Ord` , and `Hash` compare only the error
+/// code as they did before `Error` held them.
+#[derive(Debug, Clone)]
 pub struct Error {
     code: c_int,
+    message: Option<Box<str>>,
+    /// Value of C function `sqlite3_system_errno` on `SQLITE_CANTOPEN` or `SQLITE_IOERR` .
+    errno: Option<c_int>,
 }
 
 impl Error {
     /// Wrapper of C "SQLITE_OK".
-    pub const OK: Error = Error { code: SQLITE_OK };
+    pub const OK: Error = Error::new(SQLITE_OK);
     /// Wrapper of C "SQLITE_ROW".
-    pub const ROW: Error = Error { code: SQLITE_ROW };
+    pub const ROW: Error = Error::new(SQLITE_ROW);
     /// Wrapper of C "SQLITE_DONE".
-    pub const DONE: Error = Error { code: SQLITE_DONE };
+    pub const DONE: Error = Error::new(SQLITE_DONE);
+    /// The statement ran longer than [`Connection::set_statement_timeout`] .
+    ///
+    /// This is not a libsqlite3 error code but an extended code of "SQLITE_INTERRUPT" that this
+    /// crate defines; i.e. `code() & 0xff` is "SQLITE_INTERRUPT".
+    ///
+    /// [`Connection::set_statement_timeout`]: struct.Connection.html#method.set_statement_timeout
+    pub const TIMEOUT: Error = Error::new(SQLITE_INTERRUPT_TIMEOUT);
+    /// A statement tried to write the database in autocommit mode while
+    /// [`Connection::require_explicit_transactions`] is enabled.
+    ///
+    /// This is not a libsqlite3 error code but an extended code of "SQLITE_MISUSE" that this
+    /// crate defines; i.e. `code() & 0xff` is "SQLITE_MISUSE".
+    ///
+    /// [`Connection::require_explicit_transactions`]:
+    /// struct.Connection.html#method.require_explicit_transactions
+    pub const AUTOCOMMIT_WRITE: Error = Error::new(SQLITE_MISUSE_AUTOCOMMIT);
+    /// The SQLite that the crate was built against or runs with is too old to provide the API.
+    ///
+    /// This is not a libsqlite3 error code but an extended code of "SQLITE_ERROR" that this crate
+    /// defines; i.e. `code() & 0xff` is "SQLITE_ERROR". See [`HAS_TXN_STATE`] and the other
+    /// `HAS_*` constants.
+    ///
+    /// [`HAS_TXN_STATE`]: constant.HAS_TXN_STATE.html
+    pub const UNSUPPORTED: Error = Error::new(SQLITE_ERROR_UNSUPPORTED);
 
     /// Creates a new instance.
     pub const fn new(code: c_int) -> Self {
-        Self { code }
+        Self {
+            code,
+            message: None,
+            errno: None,
+        }
+    }
+
+    /// Creates a new instance with the message describing the detail.
+    pub fn with_message<S>(code: c_int, message: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            code,
+            message: Some(message.into().into_boxed_str()),
+            errno: None,
+        }
+    }
+
+    /// Creates a new instance of `SQLITE_MISUSE` with the message naming the `expected` state
+    /// and the `actual` state; e.g. "misuse: expected Row, but the statement is Done".
+    pub fn misuse<E, A>(expected: E, actual: A) -> Self
+    where
+        E: fmt::Display,
+        A: fmt::Display,
+    {
+        let message = format!(
+            "misuse: expected {}, but the statement is {}",
+            expected, actual
+        );
+        Self::with_message(SQLITE_MISUSE, message)
+    }
+
+    /// Creates a new instance of `SQLITE_IOERR` from `e` , which was returned from Rust I/O.
+    pub(crate) fn from_io(e: &std::io::Error) -> Self {
+        Self::with_message(SQLITE_IOERR, e.to_string())
+    }
+
+    /// Attaches the OS error number of `db` if the primary result code is `SQLITE_CANTOPEN` or
+    /// `SQLITE_IOERR` . Call this method before any other libsqlite3 API call on `db` .
+    pub(crate) fn with_system_errno(mut self, db: *mut sqlite3) -> Self {
+        if !db.is_null() && matches!(self.code & 0xff, SQLITE_CANTOPEN | SQLITE_IOERR) {
+            self.errno = match unsafe { sqlite3_system_errno(db) } {
+                0 => None,
+                errno => Some(errno),
+            };
+        }
+        self
+    }
+
+    /// Returns the libsqlite3 error code.
+    pub const fn code(&self) -> c_int {
+        self.code
+    }
+
+    /// Returns the message describing the detail if any.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// Returns the OS error that caused `SQLITE_CANTOPEN` or `SQLITE_IOERR` if known.
+    ///
+    /// The error number is taken by C function [`sqlite3_system_errno`] when opening a
+    /// database or [`Stmt::step`] fails. Returns `None` for the other errors.
+    ///
+    /// [`sqlite3_system_errno`]: https://www.sqlite.org/c3ref/system_errno.html
+    /// [`Stmt::step`]: struct.Stmt.html#method.step
+    pub fn os_error(&self) -> Option<io::Error> {
+        self.errno.map(io::Error::from_raw_os_error)
+    }
+
+    /// Returns `std::io::ErrorKind` corresponding to the primary result code, which
+    /// `From<Error>` implementation of `std::io::Error` uses.
+    ///
+    /// - `SQLITE_BUSY` and `SQLITE_LOCKED` are `WouldBlock` .
+    /// - `SQLITE_NOTFOUND` and `SQLITE_CANTOPEN` are `NotFound` .
+    /// - `SQLITE_READONLY` , `SQLITE_AUTH` , and `SQLITE_PERM` are `PermissionDenied` .
+    /// - `SQLITE_FULL` is `StorageFull` .
+    /// - The others are `Other` .
+    pub fn io_kind(&self) -> io::ErrorKind {
+        if self.code == SQLITE_INTERRUPT_TIMEOUT {
+            return io::ErrorKind::TimedOut;
+        }
+
+        match self.code & 0xff {
+            SQLITE_BUSY | SQLITE_LOCKED => io::ErrorKind::WouldBlock,
+            SQLITE_NOTFOUND | SQLITE_CANTOPEN => io::ErrorKind::NotFound,
+            SQLITE_READONLY | SQLITE_AUTH | SQLITE_PERM => io::ErrorKind::PermissionDenied,
+            SQLITE_FULL => io::ErrorKind::StorageFull,
+            _ => io::ErrorKind::Other,
+        }
+    }
+}
+
+impl PartialEq for Error {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.code == other.code
+    }
+}
+
+impl Eq for Error {}
+
+impl PartialOrd for Error {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Error {
+    #[inline]
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.code.cmp(&other.code)
+    }
+}
+
+impl std::hash::Hash for Error {
+    #[inline]
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.code.hash(state);
     }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        unsafe {
-            let c_msg = sqlite3_errstr(self.code);
-            let msg = CStr::from_ptr(c_msg);
-            f.write_str(msg.to_string_lossy().as_ref())
+        match self.code {
+            SQLITE_INTERRUPT_TIMEOUT => f.write_str("statement timed out")?,
+            SQLITE_MISUSE_AUTOCOMMIT => f.write_str("write statement out of any transaction")?,
+            SQLITE_ERROR_UNSUPPORTED => f.write_str("not supported by this version of SQLite")?,
+            code => {
+                let msg = unsafe { CStr::from_ptr(sqlite3_errstr(code)) };
+                f.write_str(msg.to_string_lossy().as_ref())?;
+            }
+        }
+
+        match self.message.as_ref() {
+            None => Ok(()),
+            Some(message) => write!(f, ": {}", message),
         }
     }
 }
 
 impl std::error::Error for Error {}
 
+impl From<Error> for io::Error {
+    /// Creates `std::io::Error` of [`Error::io_kind`] wrapping `e` .
+    ///
+    /// `e` is recovered by `get_ref` or `into_inner` and `downcast` .
+    ///
+    /// [`Error::io_kind`]: struct.Error.html#method.io_kind
+    fn from(e: Error) -> Self {
+        io::Error::new(e.io_kind(), e)
+    }
+}
+
+impl Connection {
+    /// Wrapper of C function [`sqlite3_extended_errcode`] and [`sqlite3_errmsg`] .
+    ///
+    /// Returns the extended error code and the English message of the error of the last libsqlite3
+    /// API call on `self` , or `None` if the call succeeded. The message is often more specific
+    /// than [`Error`] that the method returned; e.g. "no such table: foo".
+    ///
+    /// Note that any later API call on `self` , including the ones that this crate makes
+    /// internally (e.g. [`Drop`] of [`Stmt`] ), overwrites the error. Call this method right
+    /// after the method failed.
+    ///
+    /// [`sqlite3_extended_errcode`]: https://www.sqlite.org/c3ref/errcode.html
+    /// [`sqlite3_errmsg`]: https://www.sqlite.org/c3ref/errcode.html
+    /// [`Error`]: struct.Error.html
+    /// [`Drop`]: https://doc.rust-lang.org/std/ops/trait.Drop.html
+    /// [`Stmt`]: struct.Stmt.html
+    pub fn last_error(&self) -> Option<(Error, String)> {
+        let raw = self.raw();
+        match unsafe { sqlite3_errcode(raw) } {
+            SQLITE_OK | SQLITE_ROW | SQLITE_DONE => None,
+            _ => {
+                let code = unsafe { sqlite3_extended_errcode(raw) };
+                let message = unsafe { CStr::from_ptr(sqlite3_errmsg(raw)) };
+                Some((Error::new(code), message.to_string_lossy().into_owned()))
+            }
+        }
+    }
+
+    /// Wrapper of C function [`sqlite3_system_errno`] .
+    ///
+    /// Returns the OS error number of the most recent I/O failure on `self` , or `None` if no
+    /// I/O error has occurred.
+    ///
+    /// [`Error::os_error`] is usually more convenient; it holds the number at the time of the
+    /// error.
+    ///
+    /// [`sqlite3_system_errno`]: https://www.sqlite.org/c3ref/system_errno.html
+    /// [`Error::os_error`]: struct.Error.html#method.os_error
+    #[inline]
+    pub fn system_errno(&self) -> Option<i32> {
+        match unsafe { sqlite3_system_errno(self.raw()) } {
+            0 => None,
+            errno => Some(errno),
+        }
+    }
+
+    /// Wrapper of C function [`sqlite3_error_offset`] .
+    ///
+    /// Returns the byte offset of the token in the SQL text that caused the error of the last
+    /// libsqlite3 API call on `self` (typically a syntax error in preparing a statement), or
+    /// `None` if the error is not related to any token or the call succeeded.
+    ///
+    /// Returns `None` if SQLite is older than 3.38.0, which lacks [`sqlite3_error_offset`] ; see
+    /// [`HAS_ERROR_OFFSET`] .
+    ///
+    /// The same caveat as [`last_error`] applies.
+    ///
+    /// [`sqlite3_error_offset`]: https://www.sqlite.org/c3ref/errcode.html
+    /// [`last_error`]: #method.last_error
+    /// [`HAS_ERROR_OFFSET`]: constant.HAS_ERROR_OFFSET.html
+    pub fn error_offset(&self) -> Option<usize> {
+        match unsafe { crate::compat::error_offset(self.raw())? } {
+            offset if offset < 0 => None,
+            offset => Some(offset as usize),
+        }
+    }
+}
+
 extern "C" {
     fn sqlite3_errstr(code: c_int) -> *const c_char;
+    fn sqlite3_errcode(db: *mut sqlite3) -> c_int;
+    fn sqlite3_extended_errcode(db: *mut sqlite3) -> c_int;
+    fn sqlite3_errmsg(db: *mut sqlite3) -> *const c_char;
+    fn sqlite3_system_errno(db: *mut sqlite3) -> c_int;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SQLITE_MISUSE;
+
+    #[test]
+    fn display() {
+        let e = Error::new(SQLITE_MISUSE);
+        assert_eq!("bad parameter or other API misuse", e.to_string());
+        assert_eq!(None, e.message());
+
+        let e = Error::with_message(SQLITE_MISUSE, "foo");
+        assert_eq!(SQLITE_MISUSE, e.code());
+        assert_eq!(Some("foo"), e.message());
+        assert_eq!("bad parameter or other API misuse: foo", e.to_string());
+    }
+
+    #[test]
+    fn eq() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        let hash = |e: &Error| {
+            let mut hasher = DefaultHasher::new();
+            e.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let e = Error::new(SQLITE_MISUSE);
+        let f = Error::with_message(SQLITE_MISUSE, "foo");
+        assert_eq!(e, f);
+        assert_eq!(hash(&e), hash(&f));
+        assert_eq!(std::cmp::Ordering::Equal, e.cmp(&f));
+        assert_ne!(Error::new(SQLITE_BUSY), f);
+    }
+
+    #[test]
+    fn io_error() {
+        let cases = [
+            (SQLITE_BUSY, io::ErrorKind::WouldBlock),
+            (SQLITE_LOCKED | (1 << 8), io::ErrorKind::WouldBlock),
+            (SQLITE_CANTOPEN, io::ErrorKind::NotFound),
+            (SQLITE_READONLY, io::ErrorKind::PermissionDenied),
+            (SQLITE_AUTH, io::ErrorKind::PermissionDenied),
+            (SQLITE_FULL, io::ErrorKind::StorageFull),
+            (SQLITE_MISUSE, io::ErrorKind::Other),
+        ];
+        for &(code, kind) in cases.iter() {
+            assert_eq!(kind, Error::new(code).io_kind());
+
+            let e = io::Error::from(Error::with_message(code, "foo"));
+            assert_eq!(kind, e.kind());
+            let inner = e.get_ref().unwrap().downcast_ref::<Error>().unwrap();
+            assert_eq!(code, inner.code());
+            assert_eq!(Some("foo"), inner.message());
+        }
+    }
+
+    #[test]
+    fn last_error() {
+        let mut con = Connection::open_memory_db().unwrap();
+        assert_eq!(None, con.last_error());
+        assert_eq!(None, con.error_offset());
+
+        const SQL: &str = r#"SELECT * FROM "foo" WHERE "id" = = 1"#;
+        assert!(con.stmt_once(SQL).is_err());
+        let (e, message) = con.last_error().unwrap();
+        assert_eq!(crate::SQLITE_ERROR, e.code());
+        assert!(message.contains("syntax error"), "{}", message);
+        if crate::HAS_ERROR_OFFSET {
+            assert_eq!(Some(SQL.find("= 1").unwrap()), con.error_offset());
+        } else {
+            assert_eq!(None, con.error_offset());
+        }
+
+        assert!(con.stmt_once(r#"SELECT * FROM "foo""#).is_err());
+        let (_, message) = con.last_error().unwrap();
+        assert!(message.contains("no such table: foo"), "{}", message);
+
+        // Succeeded.
+        con.execute_batch("SELECT 1").unwrap();
+        assert_eq!(None, con.last_error());
+    }
+
+    #[test]
+    fn os_error() {
+        use crate::OpenOptions;
+
+        assert!(Error::new(SQLITE_CANTOPEN).os_error().is_none());
+        let con = Connection::open_memory_db().unwrap();
+        assert_eq!(None, con.system_errno());
+
+        // A regular file in the middle of the path.
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join("file");
+        std::fs::write(&file, b"").unwrap();
+        let options = OpenOptions::default();
+        let e = options.open(file.join("test_sqlite")).unwrap_err();
+        assert_eq!(SQLITE_CANTOPEN, e.code() & 0xff);
+        assert!(e.os_error().is_some());
+
+        // A directory of mode 000. (The owner can access it if the process is privileged.)
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let dir = tmp.path().join("dir");
+            std::fs::create_dir(&dir).unwrap();
+            std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o000)).unwrap();
+            let privileged = std::fs::read_dir(&dir).is_ok();
+
+            match options.open(dir.join("test_sqlite")) {
+                Ok(_) => assert!(privileged),
+                Err(e) => {
+                    assert_eq!(SQLITE_CANTOPEN, e.code() & 0xff);
+                    let os_error = e.os_error().unwrap();
+                    assert_eq!(io::ErrorKind::PermissionDenied, os_error.kind());
+                }
+            }
+            std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+    }
 }
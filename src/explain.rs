@@ -0,0 +1,196 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{Connection, Error};
+use core::fmt;
+
+/// An instruction of the bytecode program, which corresponds to a row of `EXPLAIN` .
+///
+/// [`Connection::explain`] returns the whole program. See [`The SQLite Bytecode Engine`] for the
+/// meaning of the operands of each opcode; they are subject to change across SQLite versions.
+///
+/// `Display` writes the instruction in the same columns as the sqlite3 command line shell does.
+/// Write [`HEADER`] first to print a program as a table.
+///
+/// ```
+/// use mouse_sqlite3::{Connection, VdbeOp};
+///
+/// let mut con = Connection::open_memory_db().unwrap();
+/// let program = con.explain("SELECT 1").unwrap();
+///
+/// let mut table = String::from(VdbeOp::HEADER);
+/// for op in &program {
+///     table += &format!("{}\n", op);
+/// }
+/// assert!(table.contains("Halt"));
+/// ```
+///
+/// [`Connection::explain`]: struct.Connection.html#method.explain
+/// [`The SQLite Bytecode Engine`]: https://www.sqlite.org/opcode.html
+/// [`HEADER`]: #associatedconstant.HEADER
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct VdbeOp {
+    /// The address of the instruction.
+    pub addr: i64,
+    /// The name of the opcode, for example, "OpenRead" .
+    pub opcode: String,
+    /// Operand P1.
+    pub p1: i64,
+    /// Operand P2, which is often the address to jump to.
+    pub p2: i64,
+    /// Operand P3.
+    pub p3: i64,
+    /// Operand P4 in text, or `None` if the instruction does not use it.
+    pub p4: Option<String>,
+    /// Operand P5, which is usually flags.
+    pub p5: i64,
+    /// The description of the instruction, which is `None` unless libsqlite3 is built with
+    /// `SQLITE_ENABLE_EXPLAIN_COMMENTS` .
+    pub comment: Option<String>,
+}
+
+impl VdbeOp {
+    /// The column names and the separator line, which lines up with `Display` of `VdbeOp` .
+    pub const HEADER: &'static str = "\
+addr  opcode         p1    p2    p3    p4             p5  comment
+----  -------------  ----  ----  ----  -------------  --  -------------
+";
+}
+
+impl fmt::Display for VdbeOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let line = format!(
+            "{:<4}  {:<13}  {:<4}  {:<4}  {:<4}  {:<13}  {:<2}  {}",
+            self.addr,
+            self.opcode,
+            self.p1,
+            self.p2,
+            self.p3,
+            self.p4.as_deref().unwrap_or(""),
+            self.p5,
+            self.comment.as_deref().unwrap_or(""),
+        );
+        f.write_str(line.trim_end())
+    }
+}
+
+impl Connection {
+    /// Executes `EXPLAIN` for `sql` and returns the bytecode program that libsqlite3 compiled
+    /// `sql` into.
+    ///
+    /// Only the first statement in `sql` is explained, and it is not executed. See
+    /// [`explain_query_plan`] for the higher level plan.
+    ///
+    /// [`explain_query_plan`]: #method.explain_query_plan
+    pub fn explain(&mut self, sql: &str) -> Result<Vec<VdbeOp>, Error> {
+        let sql = format!("EXPLAIN {}", sql);
+        let mut stmt = self.stmt_once(&sql)?;
+
+        let mut program = Vec::new();
+        while stmt.step()? {
+            program.push(VdbeOp {
+                addr: stmt.get(0)?,
+                opcode: stmt.get(1)?,
+                p1: stmt.get(2)?,
+                p2: stmt.get(3)?,
+                p3: stmt.get(4)?,
+                p4: stmt.column_text_coerce(5)?.map(str::to_string),
+                p5: stmt.get(6)?,
+                comment: stmt.column_text_coerce(7)?.map(str::to_string),
+            });
+        }
+        Ok(program)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explain() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "name" TEXT)"#)
+            .unwrap();
+
+        let program = con
+            .explain(r#"SELECT "name" FROM "foo" WHERE "id" = 1"#)
+            .unwrap();
+        assert_eq!("Init", program[0].opcode);
+        assert!(program.iter().any(|op| op.opcode == "Halt"));
+        assert!(program.iter().any(|op| op.opcode == "OpenRead"));
+        assert!(!program.iter().any(|op| op.opcode == "OpenEphemeral"));
+        for (i, op) in program.iter().enumerate() {
+            assert_eq!(i as i64, op.addr);
+            let line = op.to_string();
+            assert!(line.starts_with(&format!("{:<4}  {:<13}", i, op.opcode)));
+        }
+
+        // Not executed.
+        con.explain(r#"DELETE FROM "foo""#).unwrap();
+        con.execute_batch(r#"INSERT INTO "foo" VALUES (1, 'a')"#)
+            .unwrap();
+        con.explain(r#"DELETE FROM "foo""#).unwrap();
+        assert_eq!(
+            Ok(Some((1,))),
+            con.query_one::<(i64,)>(r#"SELECT count(*) FROM "foo""#, &[])
+        );
+
+        let program = con
+            .explain(r#"SELECT "name" FROM "foo" UNION SELECT 'b'"#)
+            .unwrap();
+        assert!(program.iter().any(|op| op.opcode == "OpenEphemeral"));
+
+        assert!(con.explain("SELECT * FROM no_such_table").is_err());
+    }
+}
@@ -0,0 +1,374 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    sqlite3, sqlite3_context, sqlite3_value, value_from_raw, value_set_result, Connection, Error,
+    Value, ValueRef, SQLITE_MISUSE,
+};
+use core::convert::TryFrom;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_void};
+use std::panic::AssertUnwindSafe;
+
+const SQLITE_UTF8: c_int = 1;
+const SQLITE_DETERMINISTIC: c_int = 0x000000800;
+
+type XFunc = unsafe extern "C" fn(*mut sqlite3_context, c_int, *mut *mut sqlite3_value);
+
+extern "C" {
+    fn sqlite3_create_function_v2(
+        pdb: *mut sqlite3,
+        zname: *const c_char,
+        narg: c_int,
+        etextrep: c_int,
+        papp: *mut c_void,
+        xfunc: Option<XFunc>,
+        xstep: Option<XFunc>,
+        xfinal: Option<unsafe extern "C" fn(*mut sqlite3_context)>,
+        xdestroy: Option<unsafe extern "C" fn(*mut c_void)>,
+    ) -> c_int;
+    fn sqlite3_user_data(pctx: *mut sqlite3_context) -> *mut c_void;
+    fn sqlite3_result_error(pctx: *mut sqlite3_context, zmsg: *const c_char, nbyte: c_int);
+    fn sqlite3_result_error_code(pctx: *mut sqlite3_context, code: c_int);
+}
+
+/// SQL function registered by [`Connection::create_scalar_function`] , which
+/// [`Connection::functions`] returns.
+///
+/// [`Connection::create_scalar_function`]: struct.Connection.html#method.create_scalar_function
+/// [`Connection::functions`]: struct.Connection.html#method.functions
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FunctionInfo {
+    /// The name of the function.
+    pub name: String,
+    /// The number of the arguments, or -1 if the function takes any number of arguments.
+    pub n_args: i32,
+    /// Whether the function is registered as deterministic or not.
+    pub deterministic: bool,
+}
+
+impl FunctionInfo {
+    /// Returns whether `self` is the function of `name` and `n_args` or not. The name is
+    /// compared case insensitively as SQLite does.
+    fn is(&self, name: &str, n_args: i32) -> bool {
+        self.n_args == n_args && self.name.eq_ignore_ascii_case(name)
+    }
+}
+
+impl Connection {
+    /// Wrapper of C function [`sqlite3_create_function_v2`] to register `f` as SQL scalar
+    /// function `name` taking `n_args` arguments (-1 for any number of arguments.)
+    ///
+    /// `f` takes the arguments and returns the result. If `f` returns `Err` , the SQL statement
+    /// calling the function fails with the error code (and the message if any.)
+    ///
+    /// If a function of the same `name` and `n_args` has already been registered, it is replaced
+    /// and the old closure is dropped. Set `deterministic` `true` if `f` always returns the same
+    /// result for the same arguments, so that the function can be used in index expressions and
+    /// the query planner can optimize it.
+    ///
+    /// Returns `SQLITE_BUSY` if a statement is running, which may be using the old function.
+    ///
+    /// [`sqlite3_create_function_v2`]: https://www.sqlite.org/c3ref/create_function.html
+    pub fn create_scalar_function<F>(
+        &mut self,
+        name: &str,
+        n_args: i32,
+        deterministic: bool,
+        f: F,
+    ) -> Result<(), Error>
+    where
+        F: Fn(&[ValueRef<'_>]) -> Result<Value, Error> + Send + 'static,
+    {
+        let zname = CString::new(name).map_err(|_| Error::new(SQLITE_MISUSE))?;
+        let flags = if deterministic {
+            SQLITE_UTF8 | SQLITE_DETERMINISTIC
+        } else {
+            SQLITE_UTF8
+        };
+        let papp = Box::into_raw(Box::new(f)) as *mut c_void;
+
+        // libsqlite3 calls the destructor even if it fails.
+        let code = unsafe {
+            sqlite3_create_function_v2(
+                self.raw(),
+                zname.as_ptr(),
+                n_args,
+                flags,
+                papp,
+                Some(x_func::<F>),
+                None,
+                None,
+                Some(drop_boxed::<F>),
+            )
+        };
+        match Error::new(code) {
+            e if e == Error::OK => {
+                self.functions.retain(|info| !info.is(name, n_args));
+                self.functions.push(FunctionInfo {
+                    name: name.to_string(),
+                    n_args,
+                    deterministic,
+                });
+                Ok(())
+            }
+            e => Err(e),
+        }
+    }
+
+    /// Unregisters SQL function `name` taking `n_args` arguments, and drops the closure if it was
+    /// registered by [`create_scalar_function`] .
+    ///
+    /// Returns `SQLITE_BUSY` if a statement is running.
+    ///
+    /// [`create_scalar_function`]: #method.create_scalar_function
+    pub fn remove_function(&mut self, name: &str, n_args: i32) -> Result<(), Error> {
+        let zname = CString::new(name).map_err(|_| Error::new(SQLITE_MISUSE))?;
+        let code = unsafe {
+            sqlite3_create_function_v2(
+                self.raw(),
+                zname.as_ptr(),
+                n_args,
+                SQLITE_UTF8,
+                core::ptr::null_mut(),
+                None,
+                None,
+                None,
+                None,
+            )
+        };
+        match Error::new(code) {
+            e if e == Error::OK => {
+                self.functions.retain(|info| !info.is(name, n_args));
+                Ok(())
+            }
+            e => Err(e),
+        }
+    }
+
+    /// Returns the SQL functions registered by [`create_scalar_function`] in the order of the
+    /// registration.
+    ///
+    /// libsqlite3 has no API to enumerate the functions, so the built-in functions and the ones
+    /// registered by calling the C functions directly are not included.
+    ///
+    /// [`create_scalar_function`]: #method.create_scalar_function
+    #[inline]
+    pub fn functions(&self) -> Vec<FunctionInfo> {
+        self.functions.clone()
+    }
+}
+
+unsafe extern "C" fn x_func<F>(
+    ctx: *mut sqlite3_context,
+    argc: c_int,
+    argv: *mut *mut sqlite3_value,
+) where
+    F: Fn(&[ValueRef<'_>]) -> Result<Value, Error>,
+{
+    let f = &*(sqlite3_user_data(ctx) as *const F);
+    let args: Vec<ValueRef<'_>> = (0..argc as usize)
+        .map(|i| value_from_raw(*argv.add(i)))
+        .collect();
+
+    // Unwinding across the FFI boundary is not allowed.
+    let result = match std::panic::catch_unwind(AssertUnwindSafe(|| f(&args))) {
+        Ok(result) => result,
+        Err(_) => {
+            const MESSAGE: &str = "the application-defined function panicked";
+            sqlite3_result_error(
+                ctx,
+                MESSAGE.as_ptr() as *const c_char,
+                MESSAGE.len() as c_int,
+            );
+            return;
+        }
+    };
+
+    let result = result.and_then(|val| value_set_result(ctx, ValueRef::from(&val)));
+    if let Err(e) = result {
+        if let Some(message) = e.message() {
+            let len = c_int::try_from(message.len()).unwrap_or(c_int::MAX);
+            sqlite3_result_error(ctx, message.as_ptr() as *const c_char, len);
+        }
+        sqlite3_result_error_code(ctx, e.code());
+    }
+}
+
+unsafe extern "C" fn drop_boxed<F>(data: *mut c_void) {
+    drop(Box::from_raw(data as *mut F));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SQLITE_BUSY;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Counts the drops.
+    struct DropCounter(Arc<AtomicUsize>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn create_scalar_function() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.create_scalar_function("add_one", 1, true, |args| match args[0] {
+            ValueRef::Integer(i) => Ok(Value::Integer(i + 1)),
+            _ => Err(Error::with_message(
+                crate::SQLITE_MISMATCH,
+                "not an integer",
+            )),
+        })
+        .unwrap();
+
+        let ret = con.query_one::<(i64,)>("SELECT add_one(41)", &[]);
+        assert_eq!(Ok(Some((42,))), ret);
+
+        let e = con
+            .query_one::<(i64,)>("SELECT add_one('a')", &[])
+            .unwrap_err();
+        assert_eq!(crate::SQLITE_MISMATCH, e.code());
+        let (_, message) = con.last_error().unwrap();
+        assert_eq!("not an integer", message);
+
+        let expected = vec![FunctionInfo {
+            name: "add_one".to_string(),
+            n_args: 1,
+            deterministic: true,
+        }];
+        assert_eq!(expected, con.functions());
+    }
+
+    #[test]
+    fn panic() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.create_scalar_function("boom", 0, false, |_| panic!("boom"))
+            .unwrap();
+
+        let e = con.query_one::<(i64,)>("SELECT boom()", &[]).unwrap_err();
+        assert_eq!(crate::SQLITE_ERROR, e.code());
+        let (_, message) = con.last_error().unwrap();
+        assert_eq!("the application-defined function panicked", message);
+
+        // The connection is still available.
+        assert_eq!(Ok(Some((1,))), con.query_one::<(i64,)>("SELECT 1", &[]));
+    }
+
+    #[test]
+    fn replace_and_remove() {
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let mut con = Connection::open_memory_db().unwrap();
+
+        let counter = DropCounter(dropped.clone());
+        con.create_scalar_function("f", 0, false, move |_| {
+            let _ = &counter;
+            Ok(Value::Integer(1))
+        })
+        .unwrap();
+        assert_eq!(Ok(Some((1,))), con.query_one::<(i64,)>("SELECT f()", &[]));
+
+        // Replaces the old one.
+        let counter = DropCounter(dropped.clone());
+        con.clear_stmt_cache();
+        con.create_scalar_function("F", 0, false, move |_| {
+            let _ = &counter;
+            Ok(Value::Integer(2))
+        })
+        .unwrap();
+        assert_eq!(1, dropped.load(Ordering::SeqCst));
+        assert_eq!(Ok(Some((2,))), con.query_one::<(i64,)>("SELECT f()", &[]));
+        assert_eq!(1, con.functions().len());
+        assert_eq!("F", con.functions()[0].name);
+
+        // Another number of the arguments is another function.
+        con.create_scalar_function("f", 1, false, |args| Ok(args[0].to_owned()))
+            .unwrap();
+        assert_eq!(2, con.functions().len());
+
+        // Cannot replace while a statement is running.
+        let mut stmt = con
+            .stmt_once("SELECT f() FROM (SELECT 1 UNION SELECT 2)")
+            .unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        let e = con.remove_function("f", 0).unwrap_err();
+        assert_eq!(SQLITE_BUSY, e.code());
+        drop(stmt);
+
+        con.clear_stmt_cache();
+        con.remove_function("f", 0).unwrap();
+        assert_eq!(2, dropped.load(Ordering::SeqCst));
+        assert!(con.query_one::<(i64,)>("SELECT f()", &[]).is_err());
+        let (_, message) = con.last_error().unwrap();
+        assert!(message.contains("wrong number of arguments"), "{}", message);
+        assert_eq!(Ok(Some((3,))), con.query_one::<(i64,)>("SELECT f(3)", &[]));
+        assert_eq!(1, con.functions().len());
+
+        con.clear_stmt_cache();
+        con.remove_function("f", 1).unwrap();
+        let e = con.query_one::<(i64,)>("SELECT f(3)", &[]).unwrap_err();
+        assert_eq!(crate::SQLITE_ERROR, e.code());
+        let (_, message) = con.last_error().unwrap();
+        assert!(message.contains("no such function: f"), "{}", message);
+        assert_eq!(Vec::<FunctionInfo>::new(), con.functions());
+
+        // Dropped exactly once.
+        drop(con);
+        assert_eq!(2, dropped.load(Ordering::SeqCst));
+    }
+}
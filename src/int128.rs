@@ -0,0 +1,203 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{Error, FromSql, FromSqlError, Stmt, ToSql, ToSqlOutput, Value, ValueRef};
+
+/// The bit to flip so that the unsigned order of the bytes equals the signed order of `i128` .
+const SIGN_BIT: u128 = 1 << 127;
+
+impl ToSql for u128 {
+    /// Binds as 16 bytes BLOB in big endian.
+    ///
+    /// The byte-wise comparison of the BLOB (i.e. `ORDER BY` under the default `BINARY`
+    /// collation) equals the numeric comparison.
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        Ok(ToSqlOutput::Owned(Value::Blob(self.to_be_bytes().to_vec())))
+    }
+}
+
+impl FromSql<'_> for u128 {
+    /// Accepts 16 bytes BLOB in big endian.
+    #[inline]
+    fn column_result(value: ValueRef<'_>) -> Result<Self, FromSqlError> {
+        <[u8; 16]>::column_result(value).map(u128::from_be_bytes)
+    }
+}
+
+impl ToSql for i128 {
+    /// Binds as 16 bytes BLOB in big endian after flipping the sign bit; i.e. the value plus
+    /// 2<sup>127</sup> as `u128` .
+    ///
+    /// For example, `i128::MIN` is bound as `X'00000000000000000000000000000000'` , -1 as
+    /// `X'7FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF'` , 0 as `X'80000000000000000000000000000000'` , and
+    /// `i128::MAX` as `X'FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF'` .
+    ///
+    /// The byte-wise comparison of the BLOB (i.e. `ORDER BY` under the default `BINARY`
+    /// collation) equals the numeric comparison.
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        let bytes = (*self as u128 ^ SIGN_BIT).to_be_bytes();
+        Ok(ToSqlOutput::Owned(Value::Blob(bytes.to_vec())))
+    }
+}
+
+impl FromSql<'_> for i128 {
+    /// Accepts 16 bytes BLOB in the format of [`ToSql`] .
+    ///
+    /// [`ToSql`]: trait.ToSql.html
+    #[inline]
+    fn column_result(value: ValueRef<'_>) -> Result<Self, FromSqlError> {
+        u128::column_result(value).map(|u| (u ^ SIGN_BIT) as i128)
+    }
+}
+
+impl Stmt {
+    /// Binds `val` to the parameter `index` as 16 bytes BLOB, whose byte-wise order equals the
+    /// numeric order.
+    ///
+    /// This is the same as binding `val` with [`bind`] ; see the implementation of `ToSql` for
+    /// `i128` for the encoding.
+    ///
+    /// Note that `index` starts at 1, not 0.
+    ///
+    /// [`bind`]: #method.bind
+    #[inline]
+    pub fn bind_i128(&mut self, index: usize, val: i128) -> Result<(), Error> {
+        self.bind(index, &val)
+    }
+
+    /// Returns the value of the column `index` stored by [`bind_i128`] , or `None` if the value
+    /// is NULL.
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// Returns `SQLITE_MISUSE` if the previous [`step`] did not return `true` or [`step`] was not
+    /// called, `SQLITE_RANGE` if `index` is out of range, or `SQLITE_MISMATCH` if the column value
+    /// type is neither Null nor Blob, or if the length of the BLOB is not 16.
+    ///
+    /// [`bind_i128`]: #method.bind_i128
+    /// [`step`]: #method.step
+    #[inline]
+    pub fn column_i128(&mut self, index: usize) -> Result<Option<i128>, Error> {
+        self.column_value(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Connection, SQLITE_MISMATCH};
+
+    #[test]
+    fn i128_order() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("value" BLOB)"#)
+            .unwrap();
+
+        let mut values = vec![
+            0,
+            1,
+            -1,
+            i128::MIN,
+            i128::MAX,
+            i128::MIN + 1,
+            i128::MAX - 1,
+            i64::MIN as i128 - 1,
+            i64::MAX as i128 + 1,
+            -256,
+            255,
+            256,
+        ];
+        for &v in values.iter() {
+            let mut stmt = con.stmt_once(r#"INSERT INTO "foo" VALUES (?1)"#).unwrap();
+            stmt.bind_i128(1, v).unwrap();
+            assert_eq!(Ok(false), stmt.step());
+        }
+
+        values.sort_unstable();
+        let mut stmt = con
+            .stmt_once(r#"SELECT "value" FROM "foo" ORDER BY "value""#)
+            .unwrap();
+        for &v in values.iter() {
+            assert_eq!(Ok(true), stmt.step());
+            assert_eq!(Ok(Some(v)), stmt.column_i128(0));
+        }
+        assert_eq!(Ok(false), stmt.step());
+
+        let mut stmt = con
+            .stmt_once(r#"SELECT count(*) FROM "foo" WHERE ?1 <= "value" AND "value" < ?2"#)
+            .unwrap();
+        stmt.bind_i128(1, -1).unwrap();
+        stmt.bind_i128(2, 256).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(4), stmt.get::<i64>(0));
+    }
+
+    #[test]
+    fn u128_round_trip() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let mut stmt = con.stmt_once("SELECT ?1, ?2, ?3, NULL, X'00'").unwrap();
+        stmt.bind(1, &u128::MIN).unwrap();
+        stmt.bind(2, &u128::MAX).unwrap();
+        stmt.bind(3, &(u64::MAX as u128 + 1)).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+
+        assert_eq!(Ok(&[0; 16][..]), stmt.get::<&[u8]>(0));
+        assert_eq!(Ok(u128::MIN), stmt.get(0));
+        assert_eq!(Ok(u128::MAX), stmt.get(1));
+        assert_eq!(Ok(u64::MAX as u128 + 1), stmt.get(2));
+        assert_eq!(Ok(None), stmt.column_i128(3));
+        assert_eq!(SQLITE_MISMATCH, stmt.get::<u128>(4).unwrap_err().code());
+        assert_eq!(SQLITE_MISMATCH, stmt.column_i128(4).unwrap_err().code());
+    }
+}
@@ -0,0 +1,230 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{sqlite3, sqlite3_stmt, sqlite3_stmt_busy, Connection};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+extern "C" {
+    fn sqlite3_interrupt(db: *mut sqlite3);
+    fn sqlite3_next_stmt(db: *mut sqlite3, pstmt: *mut sqlite3_stmt) -> *mut sqlite3_stmt;
+}
+
+/// Address of `sqlite3` shared with [`InterruptHandle`] , or null after the connection is
+/// closed.
+///
+/// [`InterruptHandle`]: struct.InterruptHandle.html
+struct RawDb(*mut sqlite3);
+
+// Safety: C function sqlite3_interrupt() is the only function to be called via the pointer from
+// another thread, which is thread safe.
+unsafe impl Send for RawDb {}
+
+/// State shared by [`Connection`] and [`InterruptHandle`] s.
+///
+/// [`Connection`]: struct.Connection.html
+/// [`InterruptHandle`]: struct.InterruptHandle.html
+pub(crate) struct InterruptState {
+    db: Mutex<RawDb>,
+    interrupted: AtomicBool,
+}
+
+impl InterruptState {
+    pub(crate) fn new(db: *mut sqlite3) -> Arc<Self> {
+        Arc::new(Self {
+            db: Mutex::new(RawDb(db)),
+            interrupted: AtomicBool::new(false),
+        })
+    }
+
+    /// Makes the [`InterruptHandle`] s no-op. This must be called before the connection is
+    /// closed.
+    ///
+    /// [`InterruptHandle`]: struct.InterruptHandle.html
+    pub(crate) fn close(&self) {
+        self.db.lock().unwrap_or_else(PoisonError::into_inner).0 = core::ptr::null_mut();
+    }
+}
+
+/// Handle to interrupt the running queries of a [`Connection`] from another thread.
+///
+/// The handle is `Send` and `Sync` , and is still safe to use after the connection is closed;
+/// then, [`interrupt`] does nothing.
+///
+/// [`Connection`]: struct.Connection.html
+/// [`interrupt`]: #method.interrupt
+#[derive(Clone)]
+pub struct InterruptHandle {
+    state: Arc<InterruptState>,
+}
+
+impl InterruptHandle {
+    /// Wrapper of C function [`sqlite3_interrupt`] .
+    ///
+    /// Makes the running statements of the connection fail with `SQLITE_INTERRUPT` as soon as
+    /// possible.
+    ///
+    /// libsqlite3 keeps the interruption until no statement of the connection is running; so a
+    /// statement started before the running ones finish fails as well, while a statement started
+    /// after that is not affected. See [`Connection::is_interrupted`] .
+    ///
+    /// [`sqlite3_interrupt`]: https://www.sqlite.org/c3ref/interrupt.html
+    /// [`Connection::is_interrupted`]: struct.Connection.html#method.is_interrupted
+    pub fn interrupt(&self) {
+        let db = self.state.db.lock().unwrap_or_else(PoisonError::into_inner);
+        if !db.0.is_null() {
+            self.state.interrupted.store(true, Ordering::SeqCst);
+            unsafe { sqlite3_interrupt(db.0) };
+        }
+    }
+}
+
+impl Connection {
+    /// Returns a new [`InterruptHandle`] of `self` .
+    ///
+    /// [`InterruptHandle`]: struct.InterruptHandle.html
+    #[inline]
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        InterruptHandle {
+            state: self.interrupt_state().clone(),
+        }
+    }
+
+    /// Returns whether [`InterruptHandle::interrupt`] is in effect; i.e. it was called while any
+    /// statement of `self` was running and the statements are still running.
+    ///
+    /// libsqlite3 clears the interruption when a statement starts with no other statement
+    /// running, so a new query after all the interrupted ones are finished (returned an error or
+    /// was reset) succeeds. This method returns `false` in such a case.
+    ///
+    /// This is the same to C function [`sqlite3_is_interrupted`] (available since SQLite 3.41.0)
+    /// except that only the interruption by [`InterruptHandle`] is tracked.
+    ///
+    /// [`InterruptHandle::interrupt`]: struct.InterruptHandle.html#method.interrupt
+    /// [`InterruptHandle`]: struct.InterruptHandle.html
+    /// [`sqlite3_is_interrupted`]: https://www.sqlite.org/c3ref/interrupt.html
+    pub fn is_interrupted(&self) -> bool {
+        let state = self.interrupt_state();
+        if !state.interrupted.load(Ordering::SeqCst) {
+            return false;
+        }
+
+        let mut stmt = unsafe { sqlite3_next_stmt(self.raw(), core::ptr::null_mut()) };
+        while !stmt.is_null() {
+            if unsafe { sqlite3_stmt_busy(stmt) } != 0 {
+                return true;
+            }
+            stmt = unsafe { sqlite3_next_stmt(self.raw(), stmt) };
+        }
+
+        // The next statement will clear the interruption.
+        state.interrupted.store(false, Ordering::SeqCst);
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const SQLITE_INTERRUPT: i32 = 9;
+    const LONG_QUERY: &str = r#"
+        WITH RECURSIVE "s"("i") AS (SELECT 1 UNION ALL SELECT "i" + 1 FROM "s")
+        SELECT count(*) FROM "s""#;
+
+    #[test]
+    fn interrupt() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let handle = con.interrupt_handle();
+        assert!(!con.is_interrupted());
+
+        let mut stmt = con.stmt_once(r#"SELECT 1 UNION ALL SELECT 2"#).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        handle.interrupt();
+        assert!(con.is_interrupted());
+        assert_eq!(SQLITE_INTERRUPT, stmt.step().unwrap_err().code());
+        assert!(!con.is_interrupted());
+
+        // A fresh query succeeds.
+        assert_eq!(Ok(true), stmt.step());
+        assert!(con.execute_batch("SELECT 1").is_ok());
+        drop(stmt);
+
+        // Interrupt between the queries.
+        handle.interrupt();
+        assert!(!con.is_interrupted());
+        assert!(con.execute_batch("SELECT 1").is_ok());
+    }
+
+    #[test]
+    fn interrupt_from_another_thread() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let handle = con.interrupt_handle();
+
+        let thread = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            handle.interrupt();
+            handle
+        });
+        let e = con.execute_batch(LONG_QUERY).unwrap_err();
+        assert_eq!(SQLITE_INTERRUPT, e.code());
+        assert!(!con.is_interrupted());
+        assert!(con.execute_batch("SELECT 1").is_ok());
+
+        // No-op after the connection is closed.
+        let handle = thread.join().unwrap();
+        drop(con);
+        handle.interrupt();
+    }
+}
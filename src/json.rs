@@ -0,0 +1,246 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    BlobEncoding, Error, FromSql, FromSqlError, Stmt, ToSql, ToSqlOutput, Value, ValueRef,
+    SQLITE_MISMATCH,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+impl ToSql for serde_json::Value {
+    /// Binds as TEXT of the JSON representation.
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        Ok(ToSqlOutput::Owned(Value::Text(self.to_string())))
+    }
+}
+
+impl FromSql<'_> for serde_json::Value {
+    /// Parses TEXT or BLOB as JSON. NULL, INTEGER, and REAL are converted into the JSON value of
+    /// the same type.
+    fn column_result(value: ValueRef<'_>) -> Result<Self, FromSqlError> {
+        match value {
+            ValueRef::Null => Ok(serde_json::Value::Null),
+            ValueRef::Integer(i) => Ok(serde_json::Value::from(i)),
+            ValueRef::Real(f) => Ok(serde_json::Value::from(f)),
+            ValueRef::Text(s) | ValueRef::Blob(s) => {
+                serde_json::from_slice(s).map_err(|e| FromSqlError::Other(Box::new(e)))
+            }
+        }
+    }
+}
+
+impl Stmt {
+    /// Executes the statement from the beginning and returns all the rows as a JSON array of
+    /// objects from the column name to the value.
+    ///
+    /// This method is enabled by feature "json".
+    ///
+    /// NULL, INTEGER, and TEXT are converted into the JSON value of the same type, REAL into the
+    /// JSON number (or null if it is not finite), and BLOB into the string encoded by `blob` .
+    /// Invalid UTF-8 sequences in TEXT are replaced with `U+FFFD` .
+    ///
+    /// If more than one column has the same name, the object holds the value of the last one;
+    /// give the columns distinct aliases to keep all of them.
+    ///
+    /// If the statement is in the middle of the iteration, it is reset at first.
+    /// The bound parameters are kept.
+    pub fn rows_as_json(&mut self, blob: BlobEncoding) -> Result<serde_json::Value, Error> {
+        let rows = self.rows_as_maps()?;
+        let rows = rows
+            .into_iter()
+            .map(|row| {
+                let object = row
+                    .into_iter()
+                    .map(|(name, value)| {
+                        let value = match value {
+                            Value::Null => serde_json::Value::Null,
+                            Value::Integer(i) => serde_json::Value::from(i),
+                            Value::Real(f) => serde_json::Value::from(f),
+                            Value::Text(s) => serde_json::Value::String(s),
+                            Value::Blob(b) => serde_json::Value::String(blob.encode(&b)),
+                        };
+                        (name, value)
+                    })
+                    .collect();
+                serde_json::Value::Object(object)
+            })
+            .collect();
+        Ok(serde_json::Value::Array(rows))
+    }
+
+    /// Serializes `val` into JSON and binds it as TEXT to the parameter `index` .
+    ///
+    /// This method is enabled by feature "json".
+    ///
+    /// Note that `index` starts at 1, not 0.
+    pub fn bind_json<T>(&mut self, index: usize, val: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let json = serde_json::to_string(val).map_err(|e| {
+            let message = format!("parameter {}: {}", index, e);
+            Error::with_message(SQLITE_MISMATCH, message)
+        })?;
+        self.bind_value_ref(index, ValueRef::Text(json.as_bytes()))
+    }
+
+    /// Parses the TEXT or BLOB value of the column `index` of the current row as JSON and
+    /// deserializes it into `T` , or returns `None` if the value is NULL.
+    ///
+    /// This method is enabled by feature "json".
+    ///
+    /// Note that `index` starts at 0, not 1.
+    pub fn column_json<T>(&mut self, index: usize) -> Result<Option<T>, Error>
+    where
+        T: DeserializeOwned,
+    {
+        let bytes = match self.column_value_ref(index)? {
+            ValueRef::Null => return Ok(None),
+            ValueRef::Text(s) | ValueRef::Blob(s) => s,
+            _ => {
+                let message = format!("column {}: {}", index, FromSqlError::InvalidType);
+                return Err(Error::with_message(SQLITE_MISMATCH, message));
+            }
+        };
+
+        serde_json::from_slice(bytes).map(Some).map_err(|e| {
+            let message = format!("column {}: {}", index, e);
+            Error::with_message(SQLITE_MISMATCH, message)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Connection;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Inner {
+        tags: Vec<String>,
+        score: f64,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Doc {
+        id: i64,
+        inner: Inner,
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("doc" TEXT, "raw" TEXT)"#)
+            .unwrap();
+
+        let doc = Doc {
+            id: 3,
+            inner: Inner {
+                tags: vec!["a".to_string(), "b".to_string()],
+                score: 1.5,
+            },
+        };
+        let raw = serde_json::json!({"a": [1, 2, {"b": null}]});
+
+        {
+            let mut stmt = con
+                .stmt_once(r#"INSERT INTO "foo" VALUES (?1, ?2)"#)
+                .unwrap();
+            stmt.bind_json(1, &doc).unwrap();
+            stmt.bind(2, &raw).unwrap();
+            assert_eq!(Ok(false), stmt.step());
+        }
+
+        let sql = r#"SELECT "doc", json_extract("doc", '$.inner.tags[1]'),
+                            json_extract("doc", '$.inner'), "raw", NULL, 'invalid'
+                     FROM "foo""#;
+        let mut stmt = con.stmt_once(sql).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+
+        assert_eq!(Ok(Some(doc)), stmt.column_json::<Doc>(0));
+        assert_eq!(Ok("b"), stmt.get::<&str>(1));
+        assert_eq!(
+            Ok(Some(1.5)),
+            stmt.column_json::<Inner>(2).map(|i| i.map(|i| i.score))
+        );
+        assert_eq!(Ok(raw), stmt.get::<serde_json::Value>(3));
+        assert_eq!(Ok(None), stmt.column_json::<Doc>(4));
+
+        let e = stmt.column_json::<Doc>(5).unwrap_err();
+        assert!(e.message().unwrap().starts_with("column 5: "));
+        let e = stmt.column_json::<Doc>(3).unwrap_err();
+        assert!(e.message().unwrap().starts_with("column 3: "));
+    }
+
+    #[test]
+    fn rows_as_json() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let sql = r#"SELECT 1 AS "id", 'foo' AS "name", NULL AS "none", x'fb00ff' AS "data",
+                            1.5 AS "dup", 'last' AS "dup"
+                     UNION ALL SELECT 2, NULL, NULL, x'', 2.5, NULL"#;
+        let mut stmt = con.stmt_once(sql).unwrap();
+
+        let expected = serde_json::json!([
+            {"id": 1, "name": "foo", "none": null, "data": "+wD/", "dup": "last"},
+            {"id": 2, "name": null, "none": null, "data": "", "dup": null},
+        ]);
+        assert_eq!(Ok(expected), stmt.rows_as_json(BlobEncoding::Base64));
+
+        let rows = stmt.rows_as_json(BlobEncoding::Hex).unwrap();
+        assert_eq!("fb00ff", rows[0]["data"]);
+        assert_eq!("", rows[1]["data"]);
+    }
+}
@@ -55,36 +55,239 @@
 
 #![deny(missing_docs)]
 
+mod array;
+#[cfg(feature = "async-tokio")]
+mod async_tokio;
+mod bulk;
+mod close_behavior;
+#[cfg(feature = "column-metadata")]
+mod column_metadata;
+mod compat;
+mod complete;
+mod config;
 mod connection;
+mod csv_export;
+#[cfg(feature = "chrono")]
+mod datetime;
+mod db_settings;
+mod db_status;
+mod dbstat;
+#[cfg(feature = "serde")]
+mod de;
+mod dump;
+mod encoding;
 mod error;
+mod explain;
+mod function;
+mod int128;
+mod interrupt;
+#[cfg(feature = "json")]
+mod json;
+mod memory;
+mod migrations;
+mod open_options;
+mod optimize;
+mod owned_rows;
+mod path;
+mod pool;
+mod pragma;
+mod prepare_flags;
+mod prepare_report;
+#[cfg(feature = "preupdate-hook")]
+mod preupdate_hook;
+mod query_plan;
+pub mod quote;
+mod read_only;
+mod row;
+mod schema;
+mod script;
+#[cfg(feature = "session")]
+mod session;
+#[cfg(feature = "snapshot")]
+mod snapshot;
+pub mod sql;
+#[cfg(feature = "sqlcipher")]
+mod sqlcipher;
+mod std_time;
 mod stmt;
+mod stmt_cache;
+mod stmt_stats;
+mod stmt_status;
+mod stmt_timeout;
+mod stream;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+#[cfg(feature = "tracing")]
+mod trace;
+mod transaction;
+mod typed_stmt;
+mod types;
+#[cfg(feature = "unlock-notify")]
+mod unlock_notify;
+#[cfg(feature = "uuid")]
+mod uuid;
+mod vacuum;
+mod value;
+mod version;
+mod vtab;
 
+#[cfg(feature = "async-tokio")]
+pub use async_tokio::AsyncConnection;
+pub use bulk::{BindRow, BulkInsertError};
+pub use close_behavior::CloseBehavior;
+#[cfg(feature = "column-metadata")]
+pub use column_metadata::{ColumnMetadata, ColumnOrigin};
+pub use compat::{
+    HAS_CHANGES64, HAS_DESERIALIZE, HAS_ERROR_OFFSET, HAS_HARD_HEAP_LIMIT, HAS_KEYWORDS,
+    HAS_PREPARE_V3, HAS_TXN_STATE,
+};
+pub use complete::{is_complete, keyword_check, keywords};
+pub use config::{
+    config_log, config_lookaside, config_memstatus, config_pagecache, initialize, shutdown,
+};
 pub use connection::Connection;
+pub use csv_export::CsvOptions;
+#[cfg(feature = "chrono")]
+pub use datetime::{EpochMillis, EpochSeconds};
+pub use db_settings::{DbSettings, JournalMode, Synchronous, TempStore};
+pub use db_status::DbStatus;
+pub use dbstat::TableSize;
+#[cfg(feature = "serde")]
+pub use de::RowDeserializer;
+pub use dump::DumpOptions;
+pub use encoding::{BlobEncoding, DisplayBase64, DisplayHex};
 pub use error::Error;
-use std::os::raw::{c_char, c_int, c_void};
+pub use explain::VdbeOp;
+pub use function::FunctionInfo;
+pub use interrupt::InterruptHandle;
+pub use memory::{hard_heap_limit, release_memory, soft_heap_limit};
+pub use migrations::{AppliedReport, Migration, Migrations};
+pub use open_options::OpenOptions;
+pub use optimize::OptimizeMask;
+pub use owned_rows::{OwnedRow, OwnedRows};
+pub use pool::{Pool, PoolBuilder, PooledConnection};
+pub use pragma::FkViolation;
+pub use prepare_flags::PrepareFlags;
+pub use prepare_report::PrepareReport;
+#[cfg(feature = "preupdate-hook")]
+pub use preupdate_hook::{Action, PreUpdateCase};
+pub use query_plan::{PlanNode, QueryPlan};
+pub use read_only::ReadOnlyConnection;
+use row::from_stmt as row_from_stmt;
+use row::query_as as row_query_as;
+pub use row::{FromRow, QueryAs, Row};
+pub use schema::{ColumnInfo, IndexInfo};
+pub use script::ScriptProgress;
+#[cfg(feature = "session")]
+pub use session::{ChangeOp, Conflict, ConflictResolution, ConflictType, Session};
+#[cfg(feature = "snapshot")]
+pub use snapshot::Snapshot;
+use std::os::raw::{c_char, c_int, c_uint, c_void};
 use stmt::from_raw as stmt_from_raw;
-pub use stmt::Stmt;
+pub use stmt::{StepResult, Stmt, StmtState};
+pub use stmt_cache::StmtCacheStats;
+pub use stmt_stats::StmtStats;
+pub use stmt_status::{StmtStatus, StmtStatusSnapshot};
+pub use stream::{RowReceiver, StreamDriver};
+pub use transaction::{Transaction, TransactionBehavior, TxnState};
+pub use typed_stmt::{BindParams, TypedStmt};
+pub use types::{FromSql, FromSqlError, ToSql, ToSqlOutput};
+use value::from_raw as value_from_raw;
+use value::set_result as value_set_result;
+pub use value::{Value, ValueRef};
+pub use version::{compile_option_used, compile_options, version, version_number, Feature};
+pub use vtab::{BestIndexInfo, ConstraintOp, IndexConstraint, IndexOrderBy, VTab, VTabCursor};
+
+#[cfg(feature = "derive")]
+pub use mouse_sqlite3_derive::{sql, BindParams};
+
+/// Checks that macro `sql!` fails the build for an invalid SQL.
+///
+/// ```
+/// let _ = mouse_sqlite3::sql!(r#"SELECT "name" FROM "country""#, schema = "testdata/schema.sql");
+/// ```
+///
+/// ```compile_fail
+/// let _ = mouse_sqlite3::sql!(r#"SELECT "nmae" FROM "country""#, schema = "testdata/schema.sql");
+/// ```
+///
+/// ```compile_fail
+/// let _ = mouse_sqlite3::sql!("SELECT FROM", schema = "testdata/schema.sql");
+/// ```
+///
+/// ```compile_fail
+/// let _ = mouse_sqlite3::sql!("SELECT 1; SELECT 2", schema = "testdata/schema.sql");
+/// ```
+#[cfg(all(doctest, feature = "derive"))]
+pub struct SqlCompileFail;
 
 mod libsqlite3 {
+    /// Opaque type of C [`sqlite3`] . See [`Connection::as_ptr`] .
+    ///
+    /// [`sqlite3`]: https://www.sqlite.org/c3ref/sqlite3.html
+    /// [`Connection::as_ptr`]: struct.Connection.html#method.as_ptr
     #[allow(non_camel_case_types)]
     pub enum sqlite3 {}
 
+    /// Opaque type of C [`sqlite3_stmt`] . See [`Stmt::as_ptr`] .
+    ///
+    /// [`sqlite3_stmt`]: https://www.sqlite.org/c3ref/stmt.html
+    /// [`Stmt::as_ptr`]: struct.Stmt.html#method.as_ptr
     #[allow(non_camel_case_types)]
     pub enum sqlite3_stmt {}
+
+    #[allow(non_camel_case_types)]
+    pub enum sqlite3_value {}
+
+    #[allow(non_camel_case_types)]
+    pub enum sqlite3_context {}
 }
 use libsqlite3::*;
+pub use libsqlite3::{sqlite3, sqlite3_stmt};
 
 // Constants for sqlite3_open_v2()
 // https://www.sqlite.org/draft/c3ref/c_open_autoproxy.html
+const SQLITE_OPEN_READONLY: c_int = 0x00000001;
 const SQLITE_OPEN_READWRITE: c_int = 0x00000002;
 const SQLITE_OPEN_CREATE: c_int = 0x00000004;
+const SQLITE_OPEN_URI: c_int = 0x00000040;
 const SQLITE_OPEN_MEMORY: c_int = 0x00000080;
-const SQLITE_OPEN_NOMUTEX: c_int = 0x00008000;
+const SQLITE_OPEN_FULLMUTEX: c_int = 0x00010000;
+
+// Configuration options for sqlite3_config()
+// https://www.sqlite.org/c3ref/c_config_covering_index_scan.html
+const SQLITE_CONFIG_PAGECACHE: c_int = 7;
+const SQLITE_CONFIG_MEMSTATUS: c_int = 9;
+const SQLITE_CONFIG_LOOKASIDE: c_int = 13;
+const SQLITE_CONFIG_LOG: c_int = 16;
+
+// Configuration options for sqlite3_db_config()
+// https://www.sqlite.org/c3ref/c_dbconfig_defensive.html
+const SQLITE_DBCONFIG_LOOKASIDE: c_int = 1001;
+
+// Flags for sqlite3_deserialize()
+// https://www.sqlite.org/c3ref/c_deserialize_freeonclose.html
+const SQLITE_DESERIALIZE_READONLY: c_uint = 4;
 
 // Error constants
 // https://www.sqlite.org/draft/rescode.html
 const SQLITE_OK: c_int = 0;
+const SQLITE_ERROR: c_int = 1;
+const SQLITE_PERM: c_int = 3;
+const SQLITE_BUSY: c_int = 5;
+const SQLITE_LOCKED: c_int = 6;
+const SQLITE_NOMEM: c_int = 7;
+const SQLITE_READONLY: c_int = 8;
+const SQLITE_INTERRUPT: c_int = 9;
+const SQLITE_IOERR: c_int = 10;
+const SQLITE_NOTFOUND: c_int = 12;
+const SQLITE_FULL: c_int = 13;
+const SQLITE_CANTOPEN: c_int = 14;
+const SQLITE_SCHEMA: c_int = 17;
 const SQLITE_TOOBIG: c_int = 18;
+const SQLITE_MISMATCH: c_int = 20;
+const SQLITE_MISUSE: c_int = 21;
+const SQLITE_AUTH: c_int = 23;
 const SQLITE_RANGE: c_int = 25;
 const SQLITE_DONE: c_int = 101;
 const SQLITE_ROW: c_int = 100;
@@ -92,11 +295,30 @@ const SQLITE_ROW: c_int = 100;
 // Constants for column type
 // https://www.sqlite.org/draft/c3ref/c_blob.html
 const SQLITE_INTEGER: c_int = 1;
+const SQLITE_FLOAT: c_int = 2;
+const SQLITE_TEXT: c_int = 3;
 const SQLITE_BLOB: c_int = 4;
 const SQLITE_NULL: c_int = 5;
 
+// Special destructor for sqlite3_bind_blob() and sqlite3_bind_text()
+// https://www.sqlite.org/c3ref/c_static.html
+const SQLITE_TRANSIENT: isize = -1;
+
+// Run-time limit categories
+// https://www.sqlite.org/c3ref/c_limit_attached.html
+const SQLITE_LIMIT_VARIABLE_NUMBER: c_int = 9;
+
 #[link(name = "sqlite3")]
 extern "C" {
+    fn sqlite3_libversion() -> *const c_char;
+    fn sqlite3_libversion_number() -> c_int;
+    fn sqlite3_compileoption_used(zoptname: *const c_char) -> c_int;
+    fn sqlite3_compileoption_get(n: c_int) -> *const c_char;
+    fn sqlite3_complete(sql: *const c_char) -> c_int;
+    fn sqlite3_initialize() -> c_int;
+    fn sqlite3_shutdown() -> c_int;
+    fn sqlite3_config(op: c_int, ...) -> c_int;
+
     fn sqlite3_open_v2(
         filename: *const c_char,
         ppdb: *mut *mut sqlite3,
@@ -104,16 +326,35 @@ extern "C" {
         zvfs: *const c_char,
     ) -> c_int;
     fn sqlite3_close(pdb: *mut sqlite3) -> c_int;
-
-    fn sqlite3_prepare_v2(
+    fn sqlite3_db_filename(pdb: *mut sqlite3, zdbname: *const c_char) -> *const c_char;
+    fn sqlite3_db_readonly(pdb: *mut sqlite3, zdbname: *const c_char) -> c_int;
+    fn sqlite3_get_autocommit(pdb: *mut sqlite3) -> c_int;
+    fn sqlite3_limit(pdb: *mut sqlite3, id: c_int, newval: c_int) -> c_int;
+    fn sqlite3_db_config(db: *mut sqlite3, op: c_int, ...) -> c_int;
+    fn sqlite3_db_status(
         pdb: *mut sqlite3,
-        zsql: *const c_char,
-        nbyte: c_int,
-        ppstmt: *mut *mut sqlite3_stmt,
-        pztail: *mut *const c_char,
+        op: c_int,
+        pcur: *mut c_int,
+        phiwtr: *mut c_int,
+        resetflg: c_int,
     ) -> c_int;
+    fn sqlite3_db_release_memory(pdb: *mut sqlite3) -> c_int;
+    fn sqlite3_release_memory(n: c_int) -> c_int;
+    fn sqlite3_soft_heap_limit64(n: i64) -> i64;
+
     fn sqlite3_finalize(pstmt: *mut sqlite3_stmt) -> c_int;
+    fn sqlite3_transfer_bindings(pfrom: *mut sqlite3_stmt, pto: *mut sqlite3_stmt) -> c_int;
+    fn sqlite3_db_handle(pstmt: *mut sqlite3_stmt) -> *mut sqlite3;
+    fn sqlite3_sql(pstmt: *mut sqlite3_stmt) -> *const c_char;
+    fn sqlite3_expanded_sql(pstmt: *mut sqlite3_stmt) -> *mut c_char;
+    fn sqlite3_stmt_readonly(pstmt: *mut sqlite3_stmt) -> c_int;
+    fn sqlite3_stmt_busy(pstmt: *mut sqlite3_stmt) -> c_int;
+    fn sqlite3_bind_parameter_count(pstmt: *mut sqlite3_stmt) -> c_int;
+    fn sqlite3_bind_parameter_index(pstmt: *mut sqlite3_stmt, zname: *const c_char) -> c_int;
+    fn sqlite3_bind_parameter_name(pstmt: *mut sqlite3_stmt, index: c_int) -> *const c_char;
+    fn sqlite3_stmt_status(pstmt: *mut sqlite3_stmt, op: c_int, reset_flg: c_int) -> c_int;
     fn sqlite3_column_count(pstmt: *mut sqlite3_stmt) -> c_int;
+    fn sqlite3_column_name(pstmt: *mut sqlite3_stmt, n: c_int) -> *const c_char;
 
     fn sqlite3_bind_blob(
         pstmt: *mut sqlite3_stmt,
@@ -122,8 +363,24 @@ extern "C" {
         vlen: c_int,
         destructor: *const c_void,
     ) -> c_int;
+    fn sqlite3_bind_double(pstmt: *mut sqlite3_stmt, index: c_int, val: f64) -> c_int;
     fn sqlite3_bind_int64(pstmt: *mut sqlite3_stmt, index: c_int, val: i64) -> c_int;
     fn sqlite3_bind_null(pstmt: *mut sqlite3_stmt, index: c_int) -> c_int;
+    fn sqlite3_bind_zeroblob(pstmt: *mut sqlite3_stmt, index: c_int, n: c_int) -> c_int;
+    fn sqlite3_bind_pointer(
+        pstmt: *mut sqlite3_stmt,
+        index: c_int,
+        ptr: *mut c_void,
+        ztype: *const c_char,
+        destructor: Option<unsafe extern "C" fn(*mut c_void)>,
+    ) -> c_int;
+    fn sqlite3_bind_text(
+        pstmt: *mut sqlite3_stmt,
+        index: c_int,
+        pval: *const c_char,
+        vlen: c_int,
+        destructor: *const c_void,
+    ) -> c_int;
 
     fn sqlite3_clear_bindings(pstmt: *mut sqlite3_stmt) -> c_int;
     fn sqlite3_reset(pstmt: *mut sqlite3_stmt) -> c_int;
@@ -133,5 +390,42 @@ extern "C" {
     fn sqlite3_column_type(pstmt: *mut sqlite3_stmt, icol: c_int) -> c_int;
     fn sqlite3_column_blob(pstmt: *mut sqlite3_stmt, icol: c_int) -> *const c_void;
     fn sqlite3_column_bytes(pstmt: *mut sqlite3_stmt, icol: c_int) -> c_int;
+    fn sqlite3_column_double(pstmt: *mut sqlite3_stmt, icol: c_int) -> f64;
     fn sqlite3_column_int64(pstmt: *mut sqlite3_stmt, icol: c_int) -> i64;
+    fn sqlite3_column_text(pstmt: *mut sqlite3_stmt, icol: c_int) -> *const u8;
+
+    fn sqlite3_create_module_v2(
+        pdb: *mut sqlite3,
+        zname: *const c_char,
+        pmodule: *const c_void,
+        pclient_data: *mut c_void,
+        xdestroy: Option<unsafe extern "C" fn(*mut c_void)>,
+    ) -> c_int;
+    fn sqlite3_declare_vtab(pdb: *mut sqlite3, zsql: *const c_char) -> c_int;
+
+    fn sqlite3_value_type(pval: *mut sqlite3_value) -> c_int;
+    fn sqlite3_value_blob(pval: *mut sqlite3_value) -> *const c_void;
+    fn sqlite3_value_bytes(pval: *mut sqlite3_value) -> c_int;
+    fn sqlite3_value_double(pval: *mut sqlite3_value) -> f64;
+    fn sqlite3_value_int64(pval: *mut sqlite3_value) -> i64;
+    fn sqlite3_value_text(pval: *mut sqlite3_value) -> *const u8;
+
+    fn sqlite3_result_blob(
+        pctx: *mut sqlite3_context,
+        pval: *const c_void,
+        vlen: c_int,
+        destructor: *const c_void,
+    );
+    fn sqlite3_result_double(pctx: *mut sqlite3_context, val: f64);
+    fn sqlite3_result_int64(pctx: *mut sqlite3_context, val: i64);
+    fn sqlite3_result_null(pctx: *mut sqlite3_context);
+    fn sqlite3_result_text(
+        pctx: *mut sqlite3_context,
+        pval: *const c_char,
+        vlen: c_int,
+        destructor: *const c_void,
+    );
+
+    fn sqlite3_mprintf(zformat: *const c_char, ...) -> *mut c_char;
+    fn sqlite3_free(ptr: *mut c_void);
 }
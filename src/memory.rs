@@ -0,0 +1,266 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    sqlite3_db_config, sqlite3_db_release_memory, sqlite3_release_memory,
+    sqlite3_soft_heap_limit64, Connection, Error, SQLITE_BUSY, SQLITE_DBCONFIG_LOOKASIDE,
+    SQLITE_TOOBIG,
+};
+use core::convert::TryFrom;
+use std::os::raw::{c_int, c_void};
+
+/// Wrapper of C function [`sqlite3_release_memory`] .
+///
+/// Tries to free `n_bytes` of the heap memory held by all the database connections (e.g. the
+/// unused pages in the page caches), and returns the number of the bytes actually freed.
+///
+/// It does nothing and returns 0 unless libsqlite3 is built with
+/// `SQLITE_ENABLE_MEMORY_MANAGEMENT` . Use [`Connection::release_memory`] instead, which works
+/// regardless of the option.
+///
+/// [`sqlite3_release_memory`]: https://www.sqlite.org/c3ref/release_memory.html
+/// [`Connection::release_memory`]: struct.Connection.html#method.release_memory
+#[inline]
+pub fn release_memory(n_bytes: i32) -> i32 {
+    unsafe { sqlite3_release_memory(n_bytes) }
+}
+
+/// Wrapper of C function [`sqlite3_soft_heap_limit64`] .
+///
+/// Sets the soft limit of the heap memory that libsqlite3 allocates to `bytes` , and returns the
+/// previous limit. 0 means no limit, and a negative `bytes` does not change the limit (i.e. just
+/// returns the current limit.)
+///
+/// Exceeding the soft limit is not an error; libsqlite3 tries to release the page cache memory
+/// to keep the limit, more aggressively if built with `SQLITE_ENABLE_MEMORY_MANAGEMENT` .
+///
+/// [`sqlite3_soft_heap_limit64`]: https://www.sqlite.org/c3ref/hard_heap_limit64.html
+#[inline]
+pub fn soft_heap_limit(bytes: i64) -> i64 {
+    unsafe { sqlite3_soft_heap_limit64(bytes) }
+}
+
+/// Wrapper of C function [`sqlite3_hard_heap_limit64`] .
+///
+/// Sets the hard limit of the heap memory that libsqlite3 allocates to `bytes` , and returns the
+/// previous limit. 0 means no limit, and a negative `bytes` does not change the limit (i.e. just
+/// returns the current limit.) The memory allocation fails with `SQLITE_NOMEM` if the limit is
+/// exceeded.
+///
+/// Returns [`Error::UNSUPPORTED`] if SQLite is older than 3.31.0, which lacks
+/// [`sqlite3_hard_heap_limit64`] ; see [`HAS_HARD_HEAP_LIMIT`] .
+///
+/// [`sqlite3_hard_heap_limit64`]: https://www.sqlite.org/c3ref/hard_heap_limit64.html
+/// [`Error::UNSUPPORTED`]: struct.Error.html#associatedconstant.UNSUPPORTED
+/// [`HAS_HARD_HEAP_LIMIT`]: constant.HAS_HARD_HEAP_LIMIT.html
+#[inline]
+pub fn hard_heap_limit(bytes: i64) -> Result<i64, Error> {
+    crate::compat::hard_heap_limit64(bytes)
+}
+
+impl Connection {
+    /// Wrapper of C function [`sqlite3_db_release_memory`] .
+    ///
+    /// Frees as much heap memory as possible from the page cache of `self` . Unlike
+    /// [`release_memory`] , it works even if libsqlite3 is not built with
+    /// `SQLITE_ENABLE_MEMORY_MANAGEMENT` .
+    ///
+    /// [`sqlite3_db_release_memory`]: https://www.sqlite.org/c3ref/db_release_memory.html
+    /// [`release_memory`]: fn.release_memory.html
+    #[inline]
+    pub fn release_memory(&mut self) -> Result<(), Error> {
+        let code = unsafe { sqlite3_db_release_memory(self.raw()) };
+        match Error::new(code) {
+            e if e == Error::OK => Ok(()),
+            e => Err(e),
+        }
+    }
+
+    /// Wrapper of C function [`sqlite3_db_config`] with option [`SQLITE_DBCONFIG_LOOKASIDE`] .
+    ///
+    /// Replaces the lookaside memory allocator of `self` with a new one of `n_slots` slots of
+    /// `slot_size` bytes, which libsqlite3 allocates. The lookaside allocator is disabled if
+    /// either is 0. The default is set by [`config_lookaside`] .
+    ///
+    /// The lookaside memory cannot be replaced while it is in use, so all the statements of
+    /// `self` must be finalized before calling this method; call [`clear_stmt_cache`] and drop
+    /// the [`Stmt`] instances that [`stmt_once`] returned. The counters of the lookaside memory
+    /// are available via [`db_status`] .
+    ///
+    /// Returns `SQLITE_BUSY` if any statement is cached or the lookaside memory is in use, or
+    /// `SQLITE_TOOBIG` if `slot_size` or `n_slots` exceeds `c_int` .
+    ///
+    /// [`sqlite3_db_config`]: https://www.sqlite.org/c3ref/db_config.html
+    /// [`SQLITE_DBCONFIG_LOOKASIDE`]: https://www.sqlite.org/c3ref/c_dbconfig_defensive.html
+    /// [`config_lookaside`]: fn.config_lookaside.html
+    /// [`clear_stmt_cache`]: #method.clear_stmt_cache
+    /// [`Stmt`]: struct.Stmt.html
+    /// [`stmt_once`]: #method.stmt_once
+    /// [`db_status`]: #method.db_status
+    pub fn configure_lookaside(&mut self, slot_size: usize, n_slots: usize) -> Result<(), Error> {
+        if self.cached_stmt_count() != 0 {
+            let message = "the statement cache is not empty";
+            return Err(Error::with_message(SQLITE_BUSY, message));
+        }
+
+        let slot_size = c_int::try_from(slot_size).map_err(|_| Error::new(SQLITE_TOOBIG))?;
+        let n_slots = c_int::try_from(n_slots).map_err(|_| Error::new(SQLITE_TOOBIG))?;
+        let buffer: *mut c_void = core::ptr::null_mut();
+        let code = unsafe {
+            sqlite3_db_config(
+                self.raw(),
+                SQLITE_DBCONFIG_LOOKASIDE,
+                buffer,
+                slot_size,
+                n_slots,
+            )
+        };
+        match Error::new(code) {
+            e if e == Error::OK => Ok(()),
+            e if e.code() == SQLITE_BUSY => {
+                let message = "the lookaside memory is in use";
+                Err(Error::with_message(SQLITE_BUSY, message))
+            }
+            e => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DbStatus;
+    use core::convert::TryFrom;
+    use tempfile::tempdir;
+
+    #[test]
+    fn db_release_memory() {
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("test_sqlite");
+        {
+            let mut con = Connection::try_from(path.as_path()).unwrap();
+            con.execute_batch(
+                r#"CREATE TABLE "foo" ("value" BLOB);
+                   WITH RECURSIVE "s"("i") AS (SELECT 1 UNION ALL SELECT "i" + 1 FROM "s" LIMIT 256)
+                   INSERT INTO "foo" SELECT randomblob(4096) FROM "s""#,
+            )
+            .unwrap();
+        }
+
+        let mut con = Connection::try_from(path.as_path()).unwrap();
+        con.execute_batch(r#"SELECT length(hex("value")) FROM "foo""#)
+            .unwrap();
+        let (loaded, _) = con.db_status(DbStatus::CacheUsed, false).unwrap();
+        assert!(256 * 4096 < loaded);
+
+        assert_eq!(Ok(()), con.release_memory());
+        let (released, _) = con.db_status(DbStatus::CacheUsed, false).unwrap();
+        assert!(released < loaded);
+    }
+
+    #[test]
+    fn heap_limit() {
+        // Negative value does not change the limit.
+        let soft = soft_heap_limit(-1);
+        assert_eq!(soft, soft_heap_limit(-1));
+        match hard_heap_limit(-1) {
+            Ok(hard) => assert_eq!(Ok(hard), hard_heap_limit(-1)),
+            Err(e) => assert_eq!(Error::UNSUPPORTED, e),
+        }
+
+        assert!(0 <= release_memory(1024));
+    }
+
+    #[test]
+    fn configure_lookaside() {
+        // The lookaside memory is never used; the counters below are always 0.
+        if crate::compile_option_used("OMIT_LOOKASIDE") {
+            return;
+        }
+
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute(r#"CREATE TABLE "foo" ("value" TEXT)"#, &[])
+            .unwrap();
+        let e = con.configure_lookaside(0, 0).unwrap_err();
+        assert_eq!(SQLITE_BUSY, e.code());
+
+        con.clear_stmt_cache();
+        con.configure_lookaside(0, 0).unwrap();
+        for i in 0..16 {
+            con.execute(r#"INSERT INTO "foo" VALUES (?)"#, &[&i.to_string()])
+                .unwrap();
+            con.execute_batch(r#"SELECT * FROM "foo" ORDER BY "value""#)
+                .unwrap();
+        }
+        assert_eq!(Ok((0, 0)), con.db_status(DbStatus::LookasideUsed, false));
+
+        con.clear_stmt_cache();
+        con.configure_lookaside(256, 64).unwrap();
+        con.execute(r#"INSERT INTO "foo" VALUES (?)"#, &[&"foo"])
+            .unwrap();
+        // The cached statement holds some slots.
+        let (used, highwater) = con.db_status(DbStatus::LookasideUsed, false).unwrap();
+        assert!(0 < used, "{}", used);
+        assert!(
+            used <= highwater && highwater <= 64,
+            "{} {}",
+            used,
+            highwater
+        );
+
+        // The slots are released with the statement.
+        con.clear_stmt_cache();
+        let (released, _) = con.db_status(DbStatus::LookasideUsed, false).unwrap();
+        assert!(released < used, "{} {}", released, used);
+    }
+}
@@ -0,0 +1,255 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{Connection, Error, TransactionBehavior, SQLITE_MISUSE};
+
+/// A step of schema migration.
+///
+/// See [`Migrations`] .
+///
+/// [`Migrations`]: struct.Migrations.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Migration {
+    /// The version, which is stored in `PRAGMA user_version` after this migration is applied.
+    /// It must be greater than 0.
+    pub version: i32,
+    /// SQL statements to migrate the database to `version` .
+    pub up: &'static str,
+    /// SQL statements to revert this migration if any. [`Migrations`] does not execute it; it is
+    /// kept for the application to revert the migration by itself.
+    ///
+    /// [`Migrations`]: struct.Migrations.html
+    pub down: Option<&'static str>,
+}
+
+impl Migration {
+    /// Creates a new instance without down-SQL.
+    #[inline]
+    pub const fn new(version: i32, up: &'static str) -> Self {
+        Self {
+            version,
+            up,
+            down: None,
+        }
+    }
+
+    /// Creates a new instance with down-SQL.
+    #[inline]
+    pub const fn with_down(version: i32, up: &'static str, down: &'static str) -> Self {
+        Self {
+            version,
+            up,
+            down: Some(down),
+        }
+    }
+}
+
+/// The result of [`Migrations::apply`] .
+///
+/// [`Migrations::apply`]: struct.Migrations.html#method.apply
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AppliedReport {
+    /// `PRAGMA user_version` before the migration.
+    pub from_version: i32,
+    /// `PRAGMA user_version` after the migration.
+    pub to_version: i32,
+    /// The versions of the executed [`Migration`] in the executed order.
+    ///
+    /// [`Migration`]: struct.Migration.html
+    pub applied: Vec<i32>,
+}
+
+/// Schema migration runner keyed on `PRAGMA user_version` .
+///
+/// Each [`Migration`] is executed in its own transaction together with the update of
+/// `PRAGMA user_version` , so that the version never disagrees with the schema.
+///
+/// [`Migration`]: struct.Migration.html
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Migrations {
+    migrations: Vec<Migration>,
+}
+
+impl Migrations {
+    /// Creates a new instance.
+    ///
+    /// Returns `SQLITE_MISUSE` if `migrations` is not sorted by the version in strictly ascending
+    /// order, or if any version is less than or equals to 0.
+    pub fn new(migrations: &[Migration]) -> Result<Self, Error> {
+        let mut prev = 0;
+        for m in migrations {
+            if m.version <= prev {
+                return Err(Error::new(SQLITE_MISUSE));
+            }
+            prev = m.version;
+        }
+
+        Ok(Self {
+            migrations: migrations.to_vec(),
+        })
+    }
+
+    /// Applies the migrations whose version is greater than the current `PRAGMA user_version` in
+    /// order.
+    ///
+    /// If a migration failed, it is rolled back and the error is returned. The migrations applied
+    /// before that are kept.
+    pub fn apply(&self, con: &mut Connection) -> Result<AppliedReport, Error> {
+        let from_version = con.user_version(None)?;
+        let mut report = AppliedReport {
+            from_version,
+            to_version: from_version,
+            applied: Vec::new(),
+        };
+
+        for m in self.migrations.iter().filter(|m| from_version < m.version) {
+            let mut tx = con.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            tx.execute_batch(m.up)?;
+            tx.set_user_version(None, m.version)?;
+            tx.commit()?;
+
+            report.to_version = m.version;
+            report.applied.push(m.version);
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIGRATIONS: &[Migration] = &[
+        Migration::with_down(
+            1,
+            r#"CREATE TABLE "foo" ("_id" INTEGER PRIMARY KEY)"#,
+            r#"DROP TABLE "foo""#,
+        ),
+        Migration::with_down(
+            2,
+            r#"ALTER TABLE "foo" ADD COLUMN "value" TEXT;
+               CREATE INDEX "foo_value" ON "foo" ("value");"#,
+            r#"DROP INDEX "foo_value"; ALTER TABLE "foo" DROP COLUMN "value";"#,
+        ),
+        Migration::new(5, r#"CREATE TABLE "bar" ("_id" INTEGER PRIMARY KEY)"#),
+    ];
+
+    #[test]
+    fn new() {
+        assert!(Migrations::new(MIGRATIONS).is_ok());
+        assert!(Migrations::new(&[]).is_ok());
+
+        let duplicated = [MIGRATIONS[0], MIGRATIONS[0]];
+        assert!(Migrations::new(&duplicated).is_err());
+
+        let out_of_order = [MIGRATIONS[1], MIGRATIONS[0]];
+        assert!(Migrations::new(&out_of_order).is_err());
+
+        let zero = [Migration::new(0, "")];
+        assert!(Migrations::new(&zero).is_err());
+    }
+
+    #[test]
+    fn apply_fresh() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let migrations = Migrations::new(MIGRATIONS).unwrap();
+
+        let report = migrations.apply(&mut con).unwrap();
+        assert_eq!(0, report.from_version);
+        assert_eq!(5, report.to_version);
+        assert_eq!(vec![1, 2, 5], report.applied);
+        assert_eq!(Ok(5), con.user_version(None));
+        assert_eq!(Ok(true), con.table_exists("bar"));
+
+        // Nothing to do any more.
+        let report = migrations.apply(&mut con).unwrap();
+        assert!(report.applied.is_empty());
+        assert_eq!(5, report.to_version);
+    }
+
+    #[test]
+    fn apply_tail() {
+        let mut con = Connection::open_memory_db().unwrap();
+
+        let migrations = Migrations::new(&MIGRATIONS[..1]).unwrap();
+        migrations.apply(&mut con).unwrap();
+        assert_eq!(Ok(1), con.user_version(None));
+
+        let migrations = Migrations::new(MIGRATIONS).unwrap();
+        let report = migrations.apply(&mut con).unwrap();
+        assert_eq!(1, report.from_version);
+        assert_eq!(vec![2, 5], report.applied);
+        assert_eq!(2, con.table_info("foo").unwrap().len());
+    }
+
+    #[test]
+    fn apply_failure() {
+        let mut con = Connection::open_memory_db().unwrap();
+
+        let failing = [
+            MIGRATIONS[0],
+            Migration::new(
+                2,
+                r#"CREATE TABLE "baz" ("_id" INTEGER);
+                   INSERT INTO "no_such_table" VALUES (1);"#,
+            ),
+        ];
+        let migrations = Migrations::new(&failing).unwrap();
+        assert!(migrations.apply(&mut con).is_err());
+
+        assert_eq!(Ok(1), con.user_version(None));
+        assert_eq!(Ok(true), con.table_exists("foo"));
+        assert_eq!(Ok(false), con.table_exists("baz"));
+    }
+}
@@ -0,0 +1,350 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::connection::Init;
+use crate::{
+    Connection, Error, SQLITE_CANTOPEN, SQLITE_OPEN_CREATE, SQLITE_OPEN_FULLMUTEX,
+    SQLITE_OPEN_READONLY, SQLITE_OPEN_READWRITE, SQLITE_OPEN_URI,
+};
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::time::Duration;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Builder of [`Connection`] , which specifies how to open the database.
+///
+/// The default options are the same as those of `TryFrom<&Path>` implementation of
+/// [`Connection`] ; i.e. read-write, creates the file if not exists, and does not interpret the
+/// filename as a URI.
+///
+/// [`Connection`]: struct.Connection.html
+#[derive(Clone)]
+pub struct OpenOptions {
+    read_only: bool,
+    create: bool,
+    uri: bool,
+    /// Closure to set the key before `inits` .
+    #[cfg(feature = "sqlcipher")]
+    key: Option<Arc<Init>>,
+    inits: Vec<Arc<Init>>,
+}
+
+impl fmt::Debug for OpenOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut f = f.debug_struct("OpenOptions");
+        f.field("read_only", &self.read_only)
+            .field("create", &self.create)
+            .field("uri", &self.uri);
+        // Never show the key.
+        #[cfg(feature = "sqlcipher")]
+        f.field("key", &self.key.is_some());
+        f.field("inits", &self.inits.len()).finish()
+    }
+}
+
+impl PartialEq for OpenOptions {
+    /// The closures registered by [`init`] (and the keys by `key` ) are compared by the address.
+    ///
+    /// [`init`]: #method.init
+    fn eq(&self, other: &Self) -> bool {
+        #[cfg(feature = "sqlcipher")]
+        {
+            let same_key = match (&self.key, &other.key) {
+                (None, None) => true,
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+                _ => false,
+            };
+            if !same_key {
+                return false;
+            }
+        }
+
+        self.read_only == other.read_only
+            && self.create == other.create
+            && self.uri == other.uri
+            && self.inits.len() == other.inits.len()
+            && self
+                .inits
+                .iter()
+                .zip(&other.inits)
+                .all(|(a, b)| Arc::ptr_eq(a, b))
+    }
+}
+
+impl Eq for OpenOptions {}
+
+impl Hash for OpenOptions {
+    fn hash<H>(&self, hasher: &mut H)
+    where
+        H: Hasher,
+    {
+        self.read_only.hash(hasher);
+        self.create.hash(hasher);
+        self.uri.hash(hasher);
+        #[cfg(feature = "sqlcipher")]
+        self.key.is_some().hash(hasher);
+        self.inits.len().hash(hasher);
+    }
+}
+
+impl Default for OpenOptions {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OpenOptions {
+    /// Creates a new instance with the default options.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            read_only: false,
+            create: true,
+            uri: false,
+            #[cfg(feature = "sqlcipher")]
+            key: None,
+            inits: Vec::new(),
+        }
+    }
+
+    /// Sets the option to open the database read-only. (Flag `SQLITE_OPEN_READONLY` .)
+    ///
+    /// Option [`create`] is ignored if this option is `true` .
+    ///
+    /// [`create`]: #method.create
+    #[inline]
+    pub fn read_only(&mut self, read_only: bool) -> &mut Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Sets the option to create the database file if it does not exist yet. (Flag
+    /// `SQLITE_OPEN_CREATE` .)
+    #[inline]
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    /// Sets the option to interpret the filename as a URI. (Flag `SQLITE_OPEN_URI` .)
+    #[inline]
+    pub fn uri(&mut self, uri: bool) -> &mut Self {
+        self.uri = uri;
+        self
+    }
+
+    /// Sets the key to call [`Connection::set_key`] with on each connection right after it is
+    /// opened, before any closure registered by [`init`] .
+    ///
+    /// The options hold a copy of `key` , which is overwritten with zeros when the options and
+    /// all the connections opened with them are dropped.
+    ///
+    /// This method is available if feature "sqlcipher" is enabled.
+    ///
+    /// [`Connection::set_key`]: struct.Connection.html#method.set_key
+    /// [`init`]: #method.init
+    #[cfg(feature = "sqlcipher")]
+    pub fn key(&mut self, key: &[u8]) -> &mut Self {
+        self.key = Some(crate::sqlcipher::key_init(key));
+        self
+    }
+
+    /// Makes each connection execute "PRAGMA optimize" at most once per `interval` ; see
+    /// [`Connection::set_auto_optimize`] .
+    ///
+    /// This is a shorthand of [`init`] calling [`Connection::set_auto_optimize`] , so the
+    /// connections that [`Connection::try_clone`] and [`Pool`] open inherit it.
+    ///
+    /// [`Connection::set_auto_optimize`]: struct.Connection.html#method.set_auto_optimize
+    /// [`init`]: #method.init
+    /// [`Connection::try_clone`]: struct.Connection.html#method.try_clone
+    /// [`Pool`]: struct.Pool.html
+    pub fn auto_optimize(&mut self, interval: Duration) -> &mut Self {
+        self.init(move |con| con.set_auto_optimize(Some(interval)))
+    }
+
+    /// Adds a closure to be called on each connection right after it is opened, for example, to
+    /// execute PRAGMA statements or to register SQL functions.
+    ///
+    /// The closures are called in the registered order, and [`open`] fails with the error if any
+    /// of them returns an error. The connection retains the closures, so that
+    /// [`Connection::try_clone`] calls them again; so does [`Pool`] opened with these options
+    /// on each new connection.
+    ///
+    /// [`open`]: #method.open
+    /// [`Connection::try_clone`]: struct.Connection.html#method.try_clone
+    /// [`Pool`]: struct.Pool.html
+    pub fn init<F>(&mut self, init: F) -> &mut Self
+    where
+        F: 'static + Fn(&mut Connection) -> Result<(), Error> + Send + Sync,
+    {
+        self.inits.push(Arc::new(init));
+        self
+    }
+
+    /// Returns the flags to be passed to C function `sqlite3_open_v2` .
+    pub(crate) fn flags(&self) -> c_int {
+        let mut flags = SQLITE_OPEN_FULLMUTEX;
+        if self.read_only {
+            flags |= SQLITE_OPEN_READONLY;
+        } else {
+            flags |= SQLITE_OPEN_READWRITE;
+            if self.create {
+                flags |= SQLITE_OPEN_CREATE;
+            }
+        }
+        if self.uri {
+            flags |= SQLITE_OPEN_URI;
+        }
+        flags
+    }
+
+    /// Opens the database `path` with the options.
+    ///
+    /// Returns `SQLITE_CANTOPEN` if `path` includes NUL character, or the error that a closure
+    /// registered by [`init`] returned.
+    ///
+    /// [`init`]: #method.init
+    pub fn open<P>(&self, path: P) -> Result<Connection, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let filename = path.as_ref().to_string_lossy();
+        let filename =
+            CString::new(filename.as_bytes()).map_err(|_| Error::new(SQLITE_CANTOPEN))?;
+        const ZVFS: *const c_char = core::ptr::null();
+
+        let mut con = Connection::open_v2(&filename, self.flags(), ZVFS)?;
+        #[cfg(feature = "sqlcipher")]
+        con.run_inits(self.key.as_slice())?;
+        con.run_inits(&self.inits)?;
+        Ok(con)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn read_only() {
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("test_sqlite");
+
+        // Fails because the file does not exist.
+        assert!(OpenOptions::new().create(false).open(&path).is_err());
+        assert!(OpenOptions::new().read_only(true).open(&path).is_err());
+
+        {
+            let mut con = OpenOptions::new().open(&path).unwrap();
+            con.execute_batch(r#"CREATE TABLE "foo" ("value" INTEGER)"#)
+                .unwrap();
+        }
+
+        let mut con = OpenOptions::new().read_only(true).open(&path).unwrap();
+        assert_eq!(Ok(true), con.is_readonly("main"));
+        assert!(con.db_filename("main").is_some());
+        assert!(con
+            .execute_batch(r#"INSERT INTO "foo" VALUES (1)"#)
+            .is_err());
+        assert!(con.execute_batch(r#"SELECT * FROM "foo""#).is_ok());
+    }
+
+    #[test]
+    fn uri() {
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("test_sqlite");
+        OpenOptions::new().open(&path).unwrap();
+
+        let uri = format!("file:{}?mode=ro", path.to_string_lossy());
+        let con = OpenOptions::new().uri(true).open(&uri).unwrap();
+        assert_eq!(Ok(true), con.is_readonly("main"));
+    }
+
+    #[test]
+    fn init() {
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("test_sqlite");
+
+        let mut options = OpenOptions::new();
+        options.init(|con| con.execute_batch(r#"CREATE TEMP TABLE "foo" ("id" INTEGER)"#));
+        assert_ne!(OpenOptions::new(), options);
+        assert_eq!(options.clone(), options);
+
+        const SQL: &str = r#"SELECT * FROM temp."foo""#;
+        let mut con = options.open(&path).unwrap();
+        assert!(con.execute_batch(SQL).is_ok());
+        let mut clone = con.try_clone().unwrap();
+        assert!(clone.execute_batch(SQL).is_ok());
+
+        let pool = crate::Pool::builder()
+            .max_size(3)
+            .path(&path)
+            .open_options(options.clone())
+            .build()
+            .unwrap();
+        let mut members: Vec<_> = (0..3).map(|_| pool.get().unwrap()).collect();
+        for con in members.iter_mut() {
+            assert!(con.execute_batch(SQL).is_ok());
+        }
+
+        // An error fails the open.
+        options.init(|con| con.execute_batch("INVALID SQL"));
+        assert!(options.open(&path).is_err());
+    }
+}
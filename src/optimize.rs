@@ -0,0 +1,382 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::stmt_stats::Stats;
+use crate::{sqlite3, sqlite3_get_autocommit, Connection, Error, PrepareFlags, StepResult};
+use crate::{StmtStats, SQLITE_MISUSE};
+use core::ops::{BitOr, BitOrAssign};
+use core::time::Duration;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+
+/// The SQL that [`Connection::optimize`] executes.
+///
+/// [`Connection::optimize`]: struct.Connection.html#method.optimize
+const OPTIMIZE: &str = "PRAGMA optimize";
+
+/// Argument of [`Connection::optimize_with`] , which is passed to [`PRAGMA optimize`] .
+///
+/// Masks are combined with operator `|` .
+///
+/// [`Connection::optimize_with`]: struct.Connection.html#method.optimize_with
+/// [`PRAGMA optimize`]: https://www.sqlite.org/pragma.html#pragma_optimize
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OptimizeMask(u32);
+
+impl OptimizeMask {
+    /// 0x01; makes "PRAGMA optimize" return the SQL statements it would run instead of running
+    /// them. [`Connection::optimize_with`] discards the result, so this is useful only to check
+    /// that the statement compiles.
+    ///
+    /// [`Connection::optimize_with`]: struct.Connection.html#method.optimize_with
+    pub const DEBUG: Self = Self(0x01);
+    /// 0x02; runs "ANALYZE" on the tables that might benefit from it.
+    pub const ANALYZE: Self = Self(0x02);
+    /// 0x10000; checks all the tables, not only the ones that the connection has used.
+    pub const CHECK_ALL_TABLES: Self = Self(0x10000);
+    /// 0xfffe; what "PRAGMA optimize" without argument does.
+    pub const DEFAULT: Self = Self(0xfffe);
+
+    /// Returns the raw value.
+    #[inline]
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Returns `true` if `self` includes all the bits of `other` .
+    #[inline]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl Default for OptimizeMask {
+    #[inline]
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl BitOr for OptimizeMask {
+    type Output = Self;
+
+    #[inline]
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for OptimizeMask {
+    #[inline]
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+/// Schedule of "PRAGMA optimize" that [`Connection::set_auto_optimize`] sets, which the
+/// connection and its statements share.
+///
+/// [`Connection::set_auto_optimize`]: struct.Connection.html#method.set_auto_optimize
+#[derive(Debug)]
+pub(crate) struct AutoOptimize {
+    interval: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    /// The time when "PRAGMA optimize" ran last, or when the schedule was set.
+    last: Instant,
+    /// `None` unless the statistics are enabled.
+    stats: Option<Stats>,
+}
+
+impl AutoOptimize {
+    fn new(interval: Duration, stats_enabled: bool) -> Self {
+        Self {
+            interval,
+            state: Mutex::new(State {
+                last: Instant::now(),
+                stats: if stats_enabled {
+                    Some(Stats::default())
+                } else {
+                    None
+                },
+            }),
+        }
+    }
+
+    /// Executes "PRAGMA optimize" on `db` if the interval has elapsed since the last run.
+    ///
+    /// This is called whenever a statement has finished. It does nothing while a transaction is
+    /// open not to extend the transaction, and ignores the error of "PRAGMA optimize" (but
+    /// records it to the statistics) because it is opportunistic.
+    pub fn after_done(&self, db: *mut sqlite3) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.last.elapsed() < self.interval || unsafe { sqlite3_get_autocommit(db) } == 0 {
+            return;
+        }
+        state.last = Instant::now();
+
+        let start = state.stats.as_mut().map(Stats::start_step);
+        let ret = run(db);
+        if let (Some(stats), Some(start)) = (state.stats.as_mut(), start) {
+            stats.end_step(start, ret.as_ref().map(|_| false));
+        }
+    }
+
+    fn enable_stats(&self, enabled: bool) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        match (enabled, state.stats.is_some()) {
+            (true, false) => state.stats = Some(Stats::default()),
+            (false, true) => state.stats = None,
+            _ => (),
+        }
+    }
+
+    fn stats(&self) -> Option<StmtStats> {
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.stats.as_ref().and_then(|s| s.to_stmt_stats(OPTIMIZE))
+    }
+}
+
+/// Executes "PRAGMA optimize" on `db` with a statement that is not scheduled by itself.
+fn run(db: *mut sqlite3) -> Result<(), Error> {
+    if let (Some(mut stmt), _) = Connection::prepare(db, OPTIMIZE, PrepareFlags::EMPTY)? {
+        while let StepResult::Row = stmt.step2()? {}
+    }
+    Ok(())
+}
+
+impl Connection {
+    /// Executes [`PRAGMA optimize`] , which runs "ANALYZE" on the tables that might benefit from
+    /// it.
+    ///
+    /// SQLite recommends to call this periodically, and before closing a long-lived connection.
+    /// See also [`set_auto_optimize`] and [`CloseBehavior::OptimizeAndCheckpoint`] .
+    ///
+    /// "PRAGMA optimize" writes the database if it runs "ANALYZE", so this method fails with
+    /// [`Error::AUTOCOMMIT_WRITE`] out of any transaction if
+    /// [`require_explicit_transactions`] is enabled.
+    ///
+    /// [`PRAGMA optimize`]: https://www.sqlite.org/pragma.html#pragma_optimize
+    /// [`set_auto_optimize`]: #method.set_auto_optimize
+    /// [`CloseBehavior::OptimizeAndCheckpoint`]:
+    /// enum.CloseBehavior.html#variant.OptimizeAndCheckpoint
+    /// [`Error::AUTOCOMMIT_WRITE`]: struct.Error.html#associatedconstant.AUTOCOMMIT_WRITE
+    /// [`require_explicit_transactions`]: #method.require_explicit_transactions
+    #[inline]
+    pub fn optimize(&mut self) -> Result<(), Error> {
+        self.execute_batch(OPTIMIZE)
+    }
+
+    /// Same to [`optimize`] except for passing `mask` to "PRAGMA optimize".
+    ///
+    /// [`optimize`]: #method.optimize
+    #[inline]
+    pub fn optimize_with(&mut self, mask: OptimizeMask) -> Result<(), Error> {
+        self.execute_batch(&format!("{}({})", OPTIMIZE, mask.bits()))
+    }
+
+    /// Makes `self` execute "PRAGMA optimize" at most once per `interval` , or stops it if
+    /// `interval` is `None` . (The default is `None` .)
+    ///
+    /// There is no background thread; whenever a statement of `self` has finished, it checks
+    /// the time and runs "PRAGMA optimize" if `interval` has elapsed since the last run (or since
+    /// this method was called.) It does not run while a transaction is open, and the error is
+    /// ignored.
+    ///
+    /// The runs are included in [`stats`] as "PRAGMA optimize" if [`enable_stats`] is enabled; the
+    /// statistics of the statement that triggered the run exclude it.
+    ///
+    /// "PRAGMA optimize" may write the database in autocommit mode, however, the runs are exempt
+    /// from [`require_explicit_transactions`] .
+    ///
+    /// The setting applies to the statements cached or created by `self` from now on and the
+    /// cached ones, but not to the ones that [`stmt_once`] has already returned.
+    ///
+    /// Returns `SQLITE_MISUSE` if `interval` is 0.
+    ///
+    /// [`stats`]: #method.stats
+    /// [`enable_stats`]: #method.enable_stats
+    /// [`require_explicit_transactions`]: #method.require_explicit_transactions
+    /// [`stmt_once`]: #method.stmt_once
+    pub fn set_auto_optimize(&mut self, interval: Option<Duration>) -> Result<(), Error> {
+        let auto_optimize = match interval {
+            Some(interval) if interval == Duration::default() => {
+                let message = "the interval of auto optimize must not be 0";
+                return Err(Error::with_message(SQLITE_MISUSE, message));
+            }
+            Some(interval) => Some(Arc::new(AutoOptimize::new(interval, self.stats_enabled))),
+            None => None,
+        };
+        self.auto_optimize = auto_optimize;
+        for stmt in self.stmts.values_mut() {
+            stmt.set_auto_optimize(self.auto_optimize.clone());
+        }
+        Ok(())
+    }
+
+    /// Returns the interval that [`set_auto_optimize`] set.
+    ///
+    /// [`set_auto_optimize`]: #method.set_auto_optimize
+    #[inline]
+    pub fn auto_optimize_interval(&self) -> Option<Duration> {
+        self.auto_optimize.as_ref().map(|a| a.interval)
+    }
+
+    /// Passes `enabled` to the schedule of "PRAGMA optimize" if any.
+    pub(crate) fn enable_auto_optimize_stats(&self, enabled: bool) {
+        if let Some(auto_optimize) = self.auto_optimize.as_ref() {
+            auto_optimize.enable_stats(enabled);
+        }
+    }
+
+    /// Returns the statistics of "PRAGMA optimize" that the schedule ran.
+    pub(crate) fn auto_optimize_stats(&self) -> Option<StmtStats> {
+        self.auto_optimize.as_ref().and_then(|a| a.stats())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestDb;
+    use crate::Value;
+    use std::thread::sleep;
+
+    fn populated() -> TestDb {
+        let rows = (0..100).map(|i| vec![Value::Integer(i), Value::Text(format!("{}", i % 7))]);
+        let mut con = TestDb::new()
+            .with_schema(
+                r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "name" TEXT);
+                   CREATE INDEX "foo_name" ON "foo" ("name")"#,
+            )
+            .seed("foo", rows);
+        con.execute_batch(r#"SELECT * FROM "foo" WHERE "name" = '3'"#)
+            .unwrap();
+        con
+    }
+
+    #[test]
+    fn optimize() {
+        let mut con = populated();
+        assert_eq!(Ok(()), con.optimize());
+        assert_eq!(Ok(()), con.optimize_with(OptimizeMask::DEBUG));
+        let mask = OptimizeMask::ANALYZE | OptimizeMask::CHECK_ALL_TABLES;
+        assert!(mask.contains(OptimizeMask::ANALYZE));
+        assert_eq!(Ok(()), con.optimize_with(mask));
+        assert_eq!(OptimizeMask::DEFAULT, OptimizeMask::default());
+    }
+
+    #[test]
+    fn auto_optimize() {
+        const SELECT: &str = r#"SELECT count(*) FROM "foo""#;
+
+        let mut con = populated();
+        let e = con
+            .set_auto_optimize(Some(Duration::default()))
+            .unwrap_err();
+        assert_eq!(SQLITE_MISUSE, e.code());
+
+        con.enable_stats(true);
+        let interval = Duration::from_millis(100);
+        con.set_auto_optimize(Some(interval)).unwrap();
+        assert_eq!(Some(interval), con.auto_optimize_interval());
+
+        let optimize_count = |con: &Connection| {
+            con.stats()
+                .iter()
+                .find(|s| s.sql == OPTIMIZE)
+                .map(|s| s.count)
+                .unwrap_or(0)
+        };
+
+        // The interval has not elapsed yet.
+        for _ in 0..10 {
+            con.execute_batch(SELECT).unwrap();
+        }
+        assert_eq!(0, optimize_count(&con));
+
+        // A burst of statements triggers it only once.
+        sleep(interval);
+        for _ in 0..10 {
+            con.execute_batch(SELECT).unwrap();
+        }
+        assert_eq!(1, optimize_count(&con));
+
+        // Not in a transaction.
+        sleep(interval);
+        con.execute_batch("BEGIN").unwrap();
+        con.execute_batch(SELECT).unwrap();
+        assert_eq!(1, optimize_count(&con));
+        con.execute_batch("COMMIT").unwrap();
+        assert_eq!(2, optimize_count(&con));
+
+        // Exempt from `require_explicit_transactions` .
+        con.require_explicit_transactions(true);
+        sleep(interval);
+        con.execute_batch(SELECT).unwrap();
+        assert_eq!(3, optimize_count(&con));
+        let stats = con.stats();
+        let optimize = stats.iter().find(|s| s.sql == OPTIMIZE).unwrap();
+        assert_eq!(None, optimize.last_error);
+        con.require_explicit_transactions(false);
+
+        con.set_auto_optimize(None).unwrap();
+        sleep(interval);
+        con.execute_batch(SELECT).unwrap();
+        assert_eq!(None, con.auto_optimize_interval());
+        assert_eq!(0, optimize_count(&con));
+    }
+}
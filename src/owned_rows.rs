@@ -0,0 +1,242 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{Error, Stmt, Value};
+use std::sync::Arc;
+
+/// A row of the result whose values are copied from the statement.
+///
+/// See [`Stmt::into_rows`] .
+///
+/// [`Stmt::into_rows`]: struct.Stmt.html#method.into_rows
+#[derive(Debug, Clone, PartialEq)]
+pub struct OwnedRow {
+    columns: Arc<[String]>,
+    values: Vec<Value>,
+}
+
+impl OwnedRow {
+    /// Returns the column names, which all the rows of the statement share.
+    #[inline]
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// Returns the values of the columns.
+    #[inline]
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    /// Consumes `self` and returns the values of the columns.
+    #[inline]
+    pub fn into_values(self) -> Vec<Value> {
+        self.values
+    }
+
+    /// Returns the value of the column `index` , or `None` if `index` is out of range.
+    ///
+    /// Note that `index` starts at 0, not 1.
+    #[inline]
+    pub fn get(&self, index: usize) -> Option<&Value> {
+        self.values.get(index)
+    }
+
+    /// Returns the value of the first column named `name` , or `None` if no such column.
+    #[inline]
+    pub fn get_by_name(&self, name: &str) -> Option<&Value> {
+        let index = self.columns.iter().position(|c| c == name)?;
+        self.get(index)
+    }
+}
+
+/// Iterator of [`OwnedRow`] , which owns the statement.
+///
+/// The statement is finalized when the iterator is dropped, even if it is not exhausted.
+/// The iterator stops after it returns an error.
+///
+/// See [`Stmt::into_rows`] .
+///
+/// [`OwnedRow`]: struct.OwnedRow.html
+/// [`Stmt::into_rows`]: struct.Stmt.html#method.into_rows
+#[derive(Debug)]
+pub struct OwnedRows {
+    stmt: Stmt,
+    columns: Arc<[String]>,
+    is_finished: bool,
+}
+
+impl Iterator for OwnedRows {
+    type Item = Result<OwnedRow, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_finished {
+            return None;
+        }
+
+        let ret = match self.stmt.step() {
+            Ok(true) => self.current_row(),
+            Ok(false) => {
+                self.is_finished = true;
+                return None;
+            }
+            Err(e) => Err(e),
+        };
+
+        if ret.is_err() {
+            self.is_finished = true;
+            self.stmt.reset();
+        }
+        Some(ret)
+    }
+}
+
+impl core::iter::FusedIterator for OwnedRows {}
+
+impl OwnedRows {
+    fn current_row(&mut self) -> Result<OwnedRow, Error> {
+        // The statement may be re-prepared after a schema change.
+        if self.columns.len() != self.stmt.column_count() {
+            self.columns = column_names(&self.stmt);
+        }
+
+        let values = (0..self.stmt.column_count())
+            .map(|i| self.stmt.column_value_ref(i).map(|v| v.to_owned()))
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(OwnedRow {
+            columns: self.columns.clone(),
+            values,
+        })
+    }
+}
+
+fn column_names(stmt: &Stmt) -> Arc<[String]> {
+    (0..stmt.column_count())
+        .map(|i| stmt.column_name(i).unwrap_or_default().to_string())
+        .collect()
+}
+
+impl Stmt {
+    /// Consumes `self` and returns an iterator that executes the statement from the beginning,
+    /// and copies each row into [`OwnedRow`] .
+    ///
+    /// Unlike [`query_as_iter`] , the rows do not borrow the statement, which makes it easy to
+    /// use for a small result of a statement created by [`Connection::stmt_once`] .
+    ///
+    /// Invalid UTF-8 sequences in TEXT are replaced with `U+FFFD` .
+    /// The bound parameters are kept.
+    ///
+    /// [`OwnedRow`]: struct.OwnedRow.html
+    /// [`query_as_iter`]: #method.query_as_iter
+    /// [`Connection::stmt_once`]: struct.Connection.html#method.stmt_once
+    pub fn into_rows(mut self) -> OwnedRows {
+        self.reset();
+        let columns = column_names(&self);
+        OwnedRows {
+            stmt: self,
+            columns,
+            is_finished: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Connection, DbStatus};
+
+    const SQL: &str = r#"SELECT 1 AS "id", 'a' AS "name"
+                         UNION ALL SELECT 2, NULL
+                         UNION ALL SELECT 3, 'c'"#;
+
+    #[test]
+    fn into_rows() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let stmt = con.stmt_once(SQL).unwrap();
+        let rows = stmt.into_rows().collect::<Result<Vec<_>, Error>>().unwrap();
+        assert_eq!(3, rows.len());
+
+        assert_eq!(&["id".to_string(), "name".to_string()], rows[0].columns());
+        assert_eq!(Some(&Value::Integer(1)), rows[0].get(0));
+        assert_eq!(
+            Some(&Value::Text("a".to_string())),
+            rows[0].get_by_name("name")
+        );
+        assert_eq!(None, rows[0].get(2));
+        assert_eq!(None, rows[0].get_by_name("foo"));
+        assert_eq!(&[Value::Integer(2), Value::Null], rows[1].values());
+        assert_eq!(
+            vec![Value::Integer(3), Value::Text("c".to_string())],
+            rows[2].clone().into_values()
+        );
+    }
+
+    #[test]
+    fn drop_before_exhausted() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let (before, _) = con.db_status(DbStatus::StmtUsed, false).unwrap();
+
+        let stmt = con.stmt_once(SQL).unwrap();
+        let mut rows = stmt.into_rows();
+        assert_eq!(
+            Some(&Value::Integer(1)),
+            rows.next().unwrap().unwrap().get(0)
+        );
+        let (used, _) = con.db_status(DbStatus::StmtUsed, false).unwrap();
+        assert!(before < used);
+        drop(rows);
+
+        let (after, _) = con.db_status(DbStatus::StmtUsed, false).unwrap();
+        assert_eq!(before, after);
+    }
+}
@@ -0,0 +1,242 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Implements [`ToSql`] and [`FromSql`] for file paths.
+//!
+//! A path that is a valid UTF-8 is stored as TEXT, so that it is readable from SQL. Otherwise,
+//! it is stored as BLOB of the platform specific representation:
+//!
+//! - On Unix, the raw bytes of the path.
+//! - On Windows, the UTF-16 code units of the path in little endian. (The path may contain
+//!   unpaired surrogates.)
+//!
+//! Reading a path accepts both TEXT and BLOB, so every path round-trips exactly on the same
+//! platform.
+//!
+//! [`ToSql`]: trait.ToSql.html
+//! [`FromSql`]: trait.FromSql.html
+
+use crate::{Error, FromSql, FromSqlError, ToSql, ToSqlOutput, ValueRef};
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+#[cfg(unix)]
+fn to_blob(s: &OsStr) -> Result<ToSqlOutput<'_>, Error> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(ToSqlOutput::Borrowed(ValueRef::Blob(s.as_bytes())))
+}
+
+#[cfg(windows)]
+fn to_blob(s: &OsStr) -> Result<ToSqlOutput<'_>, Error> {
+    use std::os::windows::ffi::OsStrExt;
+    let bytes = s.encode_wide().flat_map(u16::to_le_bytes).collect();
+    Ok(ToSqlOutput::Owned(crate::Value::Blob(bytes)))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn to_blob(s: &OsStr) -> Result<ToSqlOutput<'_>, Error> {
+    let message = format!("{:?} is not a valid UTF-8", s);
+    Err(Error::with_message(crate::SQLITE_MISMATCH, message))
+}
+
+#[cfg(unix)]
+fn from_bytes(bytes: &[u8]) -> Result<OsString, FromSqlError> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(OsStr::from_bytes(bytes).to_os_string())
+}
+
+#[cfg(windows)]
+fn from_bytes(bytes: &[u8]) -> Result<OsString, FromSqlError> {
+    use std::os::windows::ffi::OsStringExt;
+    if bytes.len() % 2 != 0 {
+        return Err(FromSqlError::InvalidLength {
+            expected: bytes.len() + 1,
+            actual: bytes.len(),
+        });
+    }
+    let wide: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    Ok(OsString::from_wide(&wide))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn from_bytes(_bytes: &[u8]) -> Result<OsString, FromSqlError> {
+    Err(FromSqlError::InvalidType)
+}
+
+impl ToSql for OsStr {
+    /// Binds as TEXT if `self` is a valid UTF-8, or as BLOB of the platform specific
+    /// representation otherwise.
+    ///
+    /// Returns `SQLITE_MISMATCH` if `self` is not a valid UTF-8 on a platform other than Unix or
+    /// Windows.
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        match self.to_str() {
+            Some(s) => s.to_sql(),
+            None => to_blob(self),
+        }
+    }
+}
+
+impl ToSql for OsString {
+    /// Same to `OsStr` .
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        self.as_os_str().to_sql()
+    }
+}
+
+impl ToSql for Path {
+    /// Same to `OsStr` .
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        self.as_os_str().to_sql()
+    }
+}
+
+impl ToSql for PathBuf {
+    /// Same to `OsStr` .
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        self.as_os_str().to_sql()
+    }
+}
+
+impl FromSql<'_> for OsString {
+    /// Accepts TEXT and BLOB.
+    ///
+    /// TEXT is read as UTF-8 (on Unix, it need not be valid), and BLOB as the platform specific
+    /// representation.
+    #[inline]
+    fn column_result(value: ValueRef<'_>) -> Result<Self, FromSqlError> {
+        match value {
+            ValueRef::Text(s) => match core::str::from_utf8(s) {
+                Ok(s) => Ok(OsString::from(s)),
+                Err(e) if cfg!(unix) => from_bytes(s).map_err(|_| FromSqlError::Utf8(e)),
+                Err(e) => Err(FromSqlError::Utf8(e)),
+            },
+            ValueRef::Blob(b) => from_bytes(b),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl FromSql<'_> for PathBuf {
+    /// Same to `OsString` .
+    #[inline]
+    fn column_result(value: ValueRef<'_>) -> Result<Self, FromSqlError> {
+        OsString::column_result(value).map(PathBuf::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Connection;
+
+    /// Binds `path` and returns the storage class and the value read back.
+    fn round_trip(con: &mut Connection, path: &Path) -> (String, PathBuf) {
+        let mut stmt = con.stmt_once("SELECT typeof(?1), ?1").unwrap();
+        stmt.bind(1, &path).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        (stmt.get(0).unwrap(), stmt.get(1).unwrap())
+    }
+
+    #[test]
+    fn ascii_path() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let path = Path::new("foo").join("bar.txt");
+
+        let (class, restored) = round_trip(&mut con, &path);
+        assert_eq!("text", class);
+        assert_eq!(path, restored);
+
+        let (class, restored) = round_trip(&mut con, Path::new(""));
+        assert_eq!("text", class);
+        assert_eq!(PathBuf::new(), restored);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_path() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut con = Connection::open_memory_db().unwrap();
+        let bytes = b"/tmp/\xff\xfe\x80.txt";
+        let path = Path::new(OsStr::from_bytes(bytes));
+
+        let (class, restored) = round_trip(&mut con, path);
+        assert_eq!("blob", class);
+        assert_eq!(&bytes[..], restored.as_os_str().as_bytes());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn unpaired_surrogate_path() {
+        use std::os::windows::ffi::{OsStrExt, OsStringExt};
+
+        let mut con = Connection::open_memory_db().unwrap();
+        let wide = [0x0043, 0x003a, 0x005c, 0xd800, 0x0061];
+        let path = PathBuf::from(OsString::from_wide(&wide));
+
+        let (class, restored) = round_trip(&mut con, &path);
+        assert_eq!("blob", class);
+        assert_eq!(
+            &wide[..],
+            &restored.as_os_str().encode_wide().collect::<Vec<_>>()[..]
+        );
+    }
+}
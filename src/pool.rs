@@ -0,0 +1,492 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{Connection, Error, OpenOptions, SQLITE_BUSY, SQLITE_MISUSE};
+use core::ops::{Deref, DerefMut};
+use core::time::Duration;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Instant;
+
+type OnCreate = dyn Fn(&mut Connection) -> Result<(), Error> + Send + Sync;
+
+/// Builder of [`Pool`] .
+///
+/// [`Pool`]: struct.Pool.html
+#[derive(Clone)]
+pub struct PoolBuilder {
+    max_size: usize,
+    path: Option<PathBuf>,
+    options: OpenOptions,
+    on_create: Option<Arc<OnCreate>>,
+}
+
+impl Default for PoolBuilder {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PoolBuilder {
+    /// Creates a new instance.
+    ///
+    /// The default `max_size` is 8.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            max_size: 8,
+            path: None,
+            options: OpenOptions::new(),
+            on_create: None,
+        }
+    }
+
+    /// Sets the max number of the connections the pool opens.
+    #[inline]
+    pub fn max_size(&mut self, max_size: usize) -> &mut Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Sets the path of the database file. This option is required.
+    #[inline]
+    pub fn path<P>(&mut self, path: P) -> &mut Self
+    where
+        P: AsRef<Path>,
+    {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets the options to open each connection.
+    #[inline]
+    pub fn open_options(&mut self, options: OpenOptions) -> &mut Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets a closure to be called on each new connection, for example, to execute PRAGMA
+    /// statements.
+    ///
+    /// If `on_create` returns an error, the connection is discarded and the error is returned to
+    /// the caller of [`Pool::get`] .
+    ///
+    /// [`Pool::get`]: struct.Pool.html#method.get
+    #[inline]
+    pub fn on_create<F>(&mut self, on_create: F) -> &mut Self
+    where
+        F: 'static + Fn(&mut Connection) -> Result<(), Error> + Send + Sync,
+    {
+        self.on_create = Some(Arc::new(on_create));
+        self
+    }
+
+    /// Builds a new [`Pool`] .
+    ///
+    /// No connection is opened at this time; each connection is opened lazily on demand.
+    ///
+    /// Returns `SQLITE_MISUSE` if `path` is not set or if `max_size` is 0.
+    ///
+    /// [`Pool`]: struct.Pool.html
+    pub fn build(&self) -> Result<Pool, Error> {
+        let path = match &self.path {
+            Some(p) => p.clone(),
+            None => return Err(Error::with_message(SQLITE_MISUSE, "path is not set")),
+        };
+        if self.max_size == 0 {
+            return Err(Error::with_message(SQLITE_MISUSE, "max_size must not be 0"));
+        }
+
+        let inner = Inner {
+            max_size: self.max_size,
+            path,
+            options: self.options.clone(),
+            on_create: self.on_create.clone(),
+            state: Mutex::new(State {
+                idle: Vec::new(),
+                size: 0,
+            }),
+            condvar: Condvar::new(),
+        };
+        Ok(Pool {
+            inner: Arc::new(inner),
+        })
+    }
+}
+
+struct Inner {
+    max_size: usize,
+    path: PathBuf,
+    options: OpenOptions,
+    on_create: Option<Arc<OnCreate>>,
+    state: Mutex<State>,
+    condvar: Condvar,
+}
+
+struct State {
+    idle: Vec<Connection>,
+    /// Number of the connections, including the ones being opened.
+    size: usize,
+}
+
+impl Inner {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // The state is always consistent even if another thread panicked.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn open(&self) -> Result<Connection, Error> {
+        let mut con = self.options.open(&self.path)?;
+        if let Some(on_create) = &self.on_create {
+            on_create(&mut con)?;
+        }
+        Ok(con)
+    }
+}
+
+/// Pool of [`Connection`] s to the same database file.
+///
+/// Each [`Connection`] is used by one thread at a time. Cloning `Pool` is cheap, and the clones
+/// share the connections.
+///
+/// [`Connection`]: struct.Connection.html
+#[derive(Clone)]
+pub struct Pool {
+    inner: Arc<Inner>,
+}
+
+impl Pool {
+    /// Creates a new [`PoolBuilder`] .
+    ///
+    /// [`PoolBuilder`]: struct.PoolBuilder.html
+    #[inline]
+    pub fn builder() -> PoolBuilder {
+        PoolBuilder::new()
+    }
+
+    /// Returns the max number of the connections.
+    #[inline]
+    pub fn max_size(&self) -> usize {
+        self.inner.max_size
+    }
+
+    /// Returns the number of the connections currently opened, including the ones in use.
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.inner.lock().size
+    }
+
+    /// Returns the number of the idle connections.
+    #[inline]
+    pub fn idle_count(&self) -> usize {
+        self.inner.lock().idle.len()
+    }
+
+    /// Checks out a connection, blocking until one is available.
+    ///
+    /// An idle connection is reused if any, or a new connection is opened if the pool has not
+    /// reached `max_size` yet.
+    #[inline]
+    pub fn get(&self) -> Result<PooledConnection, Error> {
+        self.checkout(None)
+    }
+
+    /// Same as [`get`] except for returning `SQLITE_BUSY` if no connection is available within
+    /// `timeout` .
+    ///
+    /// [`get`]: #method.get
+    #[inline]
+    pub fn get_timeout(&self, timeout: Duration) -> Result<PooledConnection, Error> {
+        self.checkout(Some(Instant::now() + timeout))
+    }
+
+    fn checkout(&self, deadline: Option<Instant>) -> Result<PooledConnection, Error> {
+        let inner = &self.inner;
+        let mut state = inner.lock();
+
+        loop {
+            if let Some(con) = state.idle.pop() {
+                return Ok(PooledConnection::new(inner.clone(), con));
+            }
+
+            if state.size < inner.max_size {
+                state.size += 1;
+                drop(state);
+
+                // `on_create` may panic as well as fail.
+                let reserved = Reserved(inner);
+                let con = inner.open()?;
+                core::mem::forget(reserved);
+                return Ok(PooledConnection::new(inner.clone(), con));
+            }
+
+            state = match deadline {
+                None => inner.condvar.wait(state).unwrap_or_else(|e| e.into_inner()),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if deadline <= now {
+                        let message = "timed out waiting for a connection";
+                        return Err(Error::with_message(SQLITE_BUSY, message));
+                    }
+                    let (state, _) = inner
+                        .condvar
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(|e| e.into_inner());
+                    state
+                }
+            };
+        }
+    }
+}
+
+/// Guard of the slot of `State::size` reserved to open a new connection, which releases the slot
+/// on drop; i.e. unless it is forgotten after the connection is opened.
+struct Reserved<'a>(&'a Inner);
+
+impl Drop for Reserved<'_> {
+    fn drop(&mut self) {
+        self.0.lock().size -= 1;
+        self.0.condvar.notify_one();
+    }
+}
+
+/// RAII guard of a [`Connection`] checked out from [`Pool`] .
+///
+/// `PooledConnection` dereferences to [`Connection`] , and returns the connection to the pool on
+/// drop. If a transaction is left open, it is rolled back before that.
+///
+/// [`Connection`]: struct.Connection.html
+/// [`Pool`]: struct.Pool.html
+pub struct PooledConnection {
+    pool: Arc<Inner>,
+    con: Option<Connection>,
+}
+
+impl PooledConnection {
+    fn new(pool: Arc<Inner>, con: Connection) -> Self {
+        Self {
+            pool,
+            con: Some(con),
+        }
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        let mut con = match self.con.take() {
+            Some(c) => c,
+            None => return,
+        };
+
+        if !con.is_autocommit() {
+            // Discard the connection if "ROLLBACK" failed.
+            if con.execute_batch("ROLLBACK").is_err() {
+                drop(con);
+                self.pool.lock().size -= 1;
+                self.pool.condvar.notify_one();
+                return;
+            }
+        }
+
+        self.pool.lock().idle.push(con);
+        self.pool.condvar.notify_one();
+    }
+}
+
+impl Deref for PooledConnection {
+    type Target = Connection;
+
+    #[inline]
+    fn deref(&self) -> &Connection {
+        self.con.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledConnection {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.con.as_mut().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use tempfile::tempdir;
+
+    #[test]
+    fn build() {
+        assert!(Pool::builder().build().is_err());
+        assert!(Pool::builder().path("foo").max_size(0).build().is_err());
+    }
+
+    #[test]
+    fn concurrent() {
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("test_sqlite");
+        let created = Arc::new(AtomicUsize::new(0));
+
+        let pool = {
+            let created = created.clone();
+            Pool::builder()
+                .max_size(2)
+                .path(&path)
+                .on_create(move |con| {
+                    created.fetch_add(1, Ordering::SeqCst);
+                    con.execute_batch("PRAGMA busy_timeout = 5000")?;
+                    con.set_user_version(None, 7)
+                })
+                .build()
+                .unwrap()
+        };
+        assert_eq!(0, pool.size());
+
+        pool.get()
+            .unwrap()
+            .execute_batch(r#"CREATE TABLE "foo" ("value" INTEGER)"#)
+            .unwrap();
+        assert_eq!(1, pool.size());
+        assert_eq!(1, pool.idle_count());
+
+        let in_use = Arc::new(AtomicUsize::new(0));
+        let max_in_use = Arc::new(AtomicUsize::new(0));
+        let handles: Vec<_> = (0..6)
+            .map(|i| {
+                let pool = pool.clone();
+                let in_use = in_use.clone();
+                let max_in_use = max_in_use.clone();
+                thread::spawn(move || {
+                    let mut con = pool.get().unwrap();
+                    let n = in_use.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_use.fetch_max(n, Ordering::SeqCst);
+
+                    assert_eq!(Ok(7), con.user_version(None));
+                    let sql = format!(r#"INSERT INTO "foo" VALUES ({})"#, i);
+                    con.execute_batch(&sql).unwrap();
+                    thread::sleep(Duration::from_millis(20));
+
+                    in_use.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+
+        assert!(max_in_use.load(Ordering::SeqCst) <= 2);
+        assert_eq!(2, pool.size());
+        assert_eq!(2, created.load(Ordering::SeqCst));
+
+        let mut con = pool.get().unwrap();
+        let mut stmt = con.stmt_once(r#"SELECT count(*) FROM "foo""#).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(6), stmt.get::<i64>(0));
+    }
+
+    #[test]
+    fn get_timeout() {
+        let tmp = tempdir().unwrap();
+        let pool = Pool::builder()
+            .max_size(1)
+            .path(tmp.path().join("test_sqlite"))
+            .build()
+            .unwrap();
+
+        let mut con = pool.get_timeout(Duration::from_millis(10)).unwrap();
+        let e = pool
+            .get_timeout(Duration::from_millis(10))
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(SQLITE_BUSY, e.code());
+
+        // An open transaction is rolled back when the connection returns to the pool.
+        con.execute_batch("BEGIN").unwrap();
+        drop(con);
+        let con = pool.get_timeout(Duration::from_millis(10)).unwrap();
+        assert!(con.is_autocommit());
+    }
+
+    #[test]
+    fn on_create_panic() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let tmp = tempdir().unwrap();
+        let panics = Arc::new(AtomicBool::new(true));
+        let pool = {
+            let panics = panics.clone();
+            Pool::builder()
+                .max_size(1)
+                .path(tmp.path().join("test_sqlite"))
+                .on_create(move |_| {
+                    if panics.load(Ordering::SeqCst) {
+                        panic!("on_create");
+                    }
+                    Ok(())
+                })
+                .build()
+                .unwrap()
+        };
+
+        let p = pool.clone();
+        assert!(std::thread::spawn(move || p.get().map(|_| ()))
+            .join()
+            .is_err());
+        assert_eq!(0, pool.size());
+
+        // The slot is available again.
+        panics.store(false, Ordering::SeqCst);
+        assert!(pool.get_timeout(Duration::from_millis(10)).is_ok());
+        assert_eq!(1, pool.size());
+    }
+}
@@ -0,0 +1,305 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::quote::quote_identifier;
+use crate::{Connection, Error, StepResult};
+use core::fmt::Display;
+
+/// A row that `PRAGMA foreign_key_check` returns.
+///
+/// See [`Connection::foreign_key_check`] .
+///
+/// [`Connection::foreign_key_check`]: struct.Connection.html#method.foreign_key_check
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FkViolation {
+    /// The name of the table that contains the violating row.
+    pub table: String,
+    /// The rowid of the violating row, or `None` if the table is a "WITHOUT ROWID" table.
+    pub rowid: Option<i64>,
+    /// The name of the table that the foreign key refers to.
+    pub referenced_table: String,
+    /// The index of the foreign key constraint in the table. (Same to "id" column of
+    /// `PRAGMA foreign_key_list` .)
+    pub fk_index: usize,
+}
+
+/// Builds "PRAGMA" statement SQL for `name` (and `schema` if any.)
+fn pragma_sql(schema: Option<&str>, name: &str) -> Result<String, Error> {
+    match schema {
+        None => Ok(format!("PRAGMA {}", name)),
+        Some(schema) => Ok(format!("PRAGMA {}.{}", quote_identifier(schema)?, name)),
+    }
+}
+
+impl Connection {
+    /// Executes "PRAGMA `name`" and returns the first column of the first row as an integer.
+    ///
+    /// Returns `None` if the pragma returns no row or the value is NULL.
+    pub(crate) fn pragma_int(
+        &mut self,
+        schema: Option<&str>,
+        name: &str,
+    ) -> Result<Option<i64>, Error> {
+        let sql = pragma_sql(schema, name)?;
+        let mut stmt = self.stmt_once(&sql)?;
+        if stmt.step()? {
+            Ok(stmt.column_int(0))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Executes "PRAGMA `name`" and returns the first column of the first row as a text.
+    ///
+    /// Returns `None` if the pragma returns no row or the value is NULL.
+    pub(crate) fn pragma_text(
+        &mut self,
+        schema: Option<&str>,
+        name: &str,
+    ) -> Result<Option<String>, Error> {
+        let sql = pragma_sql(schema, name)?;
+        let mut stmt = self.stmt_once(&sql)?;
+        if stmt.step()? {
+            stmt.get(0)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Executes "PRAGMA `name` = `value`" and discards the result rows if any.
+    ///
+    /// `value` is embedded in the SQL as it is; the caller must make sure that it is a valid
+    /// pragma value.
+    pub(crate) fn pragma_update<V>(
+        &mut self,
+        schema: Option<&str>,
+        name: &str,
+        value: V,
+    ) -> Result<(), Error>
+    where
+        V: Display,
+    {
+        let sql = format!("{} = {}", pragma_sql(schema, name)?, value);
+        let mut stmt = self.stmt_once(&sql)?;
+        while let StepResult::Row = stmt.step2()? {}
+        Ok(())
+    }
+
+    /// Executes `PRAGMA integrity_check(max_errors)` and returns the reported problems.
+    ///
+    /// Returns an empty `Vec` if no problem is found. (i.e. the pragma returns "ok".)
+    /// `max_errors` 0 is treated as 1.
+    #[inline]
+    pub fn integrity_check(&mut self, max_errors: usize) -> Result<Vec<String>, Error> {
+        self.check_pragma("integrity_check", max_errors)
+    }
+
+    /// Executes `PRAGMA quick_check(max_errors)` and returns the reported problems.
+    ///
+    /// It is faster than [`integrity_check`] but does not verify the indices.
+    /// Returns an empty `Vec` if no problem is found. (i.e. the pragma returns "ok".)
+    ///
+    /// [`integrity_check`]: #method.integrity_check
+    #[inline]
+    pub fn quick_check(&mut self, max_errors: usize) -> Result<Vec<String>, Error> {
+        self.check_pragma("quick_check", max_errors)
+    }
+
+    /// Implementation of [`integrity_check`] and [`quick_check`] .
+    ///
+    /// [`integrity_check`]: #method.integrity_check
+    /// [`quick_check`]: #method.quick_check
+    fn check_pragma(&mut self, name: &str, max_errors: usize) -> Result<Vec<String>, Error> {
+        let max_errors = max_errors.clamp(1, i32::MAX as usize);
+        let sql = format!("PRAGMA {}({})", name, max_errors);
+        let mut stmt = self.stmt_once(&sql)?;
+
+        let mut ret = Vec::new();
+        while stmt.step()? {
+            let message: String = stmt.get(0)?;
+            if message != "ok" {
+                ret.push(message);
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Executes `PRAGMA foreign_key_check` and returns the rows violating the foreign key
+    /// constraints.
+    ///
+    /// The pragma checks the rows regardless of `PRAGMA foreign_keys` .
+    pub fn foreign_key_check(&mut self) -> Result<Vec<FkViolation>, Error> {
+        let mut stmt = self.stmt_once("PRAGMA foreign_key_check")?;
+
+        // The columns are "table", "rowid", "parent", and "fkid".
+        let mut ret = Vec::new();
+        while stmt.step()? {
+            ret.push(FkViolation {
+                table: stmt.get(0)?,
+                rowid: stmt.get(1)?,
+                referenced_table: stmt.get(2)?,
+                fk_index: stmt.get::<i64>(3)? as usize,
+            });
+        }
+        Ok(ret)
+    }
+
+    /// Returns the value of `PRAGMA user_version` of `schema` ("main" if `None` .)
+    #[inline]
+    pub fn user_version(&mut self, schema: Option<&str>) -> Result<i32, Error> {
+        self.pragma_int(schema, "user_version")
+            .map(|v| v.unwrap_or(0) as i32)
+    }
+
+    /// Sets `PRAGMA user_version` of `schema` ("main" if `None` ) to `v` .
+    #[inline]
+    pub fn set_user_version(&mut self, schema: Option<&str>, v: i32) -> Result<(), Error> {
+        self.pragma_update(schema, "user_version", v)
+    }
+
+    /// Returns the value of `PRAGMA application_id` of `schema` ("main" if `None` .)
+    #[inline]
+    pub fn application_id(&mut self, schema: Option<&str>) -> Result<i32, Error> {
+        self.pragma_int(schema, "application_id")
+            .map(|v| v.unwrap_or(0) as i32)
+    }
+
+    /// Sets `PRAGMA application_id` of `schema` ("main" if `None` ) to `id` .
+    #[inline]
+    pub fn set_application_id(&mut self, schema: Option<&str>, id: i32) -> Result<(), Error> {
+        self.pragma_update(schema, "application_id", id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::TryFrom;
+    use tempfile::tempdir;
+
+    #[test]
+    fn user_version_application_id() {
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("test_sqlite");
+
+        {
+            let mut con = Connection::try_from(path.as_ref()).unwrap();
+            assert_eq!(Ok(0), con.user_version(None));
+            assert_eq!(Ok(0), con.application_id(None));
+
+            con.set_user_version(None, 3).unwrap();
+            con.set_application_id(None, -0x1234).unwrap();
+        }
+
+        let mut con = Connection::try_from(path.as_ref()).unwrap();
+        assert_eq!(Ok(3), con.user_version(None));
+        assert_eq!(Ok(-0x1234), con.application_id(None));
+        assert_eq!(Ok(3), con.user_version(Some("main")));
+    }
+
+    #[test]
+    fn attached_schema() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.stmt_once(r#"ATTACH DATABASE ':memory:' AS "a""ux""#)
+            .unwrap()
+            .step()
+            .unwrap();
+
+        con.set_user_version(Some(r#"a"ux"#), 7).unwrap();
+        assert_eq!(Ok(7), con.user_version(Some(r#"a"ux"#)));
+        assert_eq!(Ok(0), con.user_version(None));
+
+        assert!(con.user_version(Some("no_such_schema")).is_err());
+    }
+
+    #[test]
+    fn integrity_check() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(
+            r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "name" TEXT UNIQUE);
+               INSERT INTO "foo" VALUES (1, 'a'), (2, 'b')"#,
+        )
+        .unwrap();
+
+        assert_eq!(Ok(Vec::new()), con.integrity_check(100));
+        assert_eq!(Ok(Vec::new()), con.quick_check(0));
+    }
+
+    #[test]
+    fn foreign_key_check() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(
+            r#"PRAGMA foreign_keys = OFF;
+               CREATE TABLE "parent" ("id" INTEGER PRIMARY KEY);
+               CREATE TABLE "child" (
+                   "name" TEXT,
+                   "parent_id" INTEGER REFERENCES "parent" ("id")
+               );
+               INSERT INTO "parent" VALUES (1);
+               INSERT INTO "child" VALUES ('ok', 1), ('ng', 2)"#,
+        )
+        .unwrap();
+
+        let expected = FkViolation {
+            table: "child".to_string(),
+            rowid: Some(2),
+            referenced_table: "parent".to_string(),
+            fk_index: 0,
+        };
+        assert_eq!(Ok(vec![expected]), con.foreign_key_check());
+
+        con.execute_batch(r#"INSERT INTO "parent" VALUES (2)"#)
+            .unwrap();
+        assert_eq!(Ok(Vec::new()), con.foreign_key_check());
+    }
+}
@@ -0,0 +1,280 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{Connection, Error};
+
+/// Result of [`Connection::prepare_all`] .
+///
+/// [`Connection::prepare_all`]: struct.Connection.html#method.prepare_all
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct PrepareReport {
+    /// The SQL statements prepared and cached successfully.
+    pub prepared: Vec<&'static str>,
+    /// The SQL statements failed to prepare and the errors.
+    ///
+    /// The message of each error is that of C function [`sqlite3_errmsg`] ; e.g.
+    /// "no such table: foo".
+    ///
+    /// [`sqlite3_errmsg`]: https://www.sqlite.org/c3ref/errcode.html
+    pub failed: Vec<(&'static str, Error)>,
+}
+
+impl PrepareReport {
+    /// Returns `true` if all the statements were prepared.
+    #[inline]
+    pub fn is_ok(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+impl Connection {
+    /// Prepares and caches each statement of `sqls` as [`stmt`] does, for example, to save the
+    /// latency of the first execution and to make sure that all the SQL statements are valid
+    /// against the schema at the start of the application.
+    ///
+    /// If `fail_fast` is `true` , returns the first error immediately; the message of the error
+    /// includes the SQL statement. Otherwise, tries all the statements and returns the report
+    /// including the failed ones.
+    ///
+    /// The statements are prepared with flag `SQLITE_PREPARE_PERSISTENT` as [`stmt`] does. Note
+    /// that the statement cache may evict them if the capacity is smaller than `sqls` ; see
+    /// [`pin_stmt`] .
+    ///
+    /// [`stmt`]: #method.stmt
+    /// [`pin_stmt`]: #method.pin_stmt
+    pub fn prepare_all(
+        &mut self,
+        sqls: &[&'static str],
+        fail_fast: bool,
+    ) -> Result<PrepareReport, Error> {
+        let mut report = PrepareReport::default();
+
+        for &sql in sqls {
+            if let Err(e) = self.stmt(sql) {
+                let e = match self.last_error() {
+                    Some((_, message)) => Error::with_message(e.code(), message),
+                    None => e,
+                };
+                if fail_fast {
+                    let message = format!("{}: {}", e.message().unwrap_or_default(), sql);
+                    return Err(Error::with_message(e.code(), message));
+                }
+                report.failed.push((sql, e));
+            } else {
+                report.prepared.push(sql);
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Finalizes all the cached statements, calls `f` (which typically executes DDL statements
+    /// like "ALTER TABLE"), and prepares the statements pinned by [`pin_stmt`] again.
+    ///
+    /// Returns the value of `f` and the report of the pinned statements. The statements that
+    /// failed to prepare (e.g. referring to a dropped column) are no longer cached nor pinned.
+    /// The statements in the report are sorted by the SQL.
+    ///
+    /// The cached statements referring to a table can make DDL fail with `SQLITE_LOCKED` , and
+    /// they are re-prepared with the new schema anyway. This method saves them from being
+    /// prepared twice.
+    ///
+    /// If `f` returns an error, tries to prepare and pin the statements again, and returns the
+    /// error of `f` even if it fails to pin them again.
+    ///
+    /// [`pin_stmt`]: #method.pin_stmt
+    pub fn with_schema_change<F, T>(&mut self, f: F) -> Result<(T, PrepareReport), Error>
+    where
+        F: FnOnce(&mut Connection) -> Result<T, Error>,
+    {
+        let mut pinned = self.pinned_sqls();
+        pinned.sort_unstable();
+        self.clear_stmt_cache();
+
+        let ret = f(self);
+
+        let report = self.prepare_all(&pinned, false).and_then(|report| {
+            for sql in &report.prepared {
+                self.pin_stmt(sql)?;
+            }
+            Ok(report)
+        });
+        let ret = ret?;
+        report.map(|report| (ret, report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SQLITE_ERROR;
+
+    const SQLS: [&str; 3] = [
+        r#"SELECT * FROM "foo""#,
+        r#"SELECT * FROM "bar""#,
+        r#"INSERT INTO "foo" VALUES (?)"#,
+    ];
+
+    #[test]
+    fn prepare_all() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("value" INTEGER)"#)
+            .unwrap();
+
+        let report = con.prepare_all(&SQLS, false).unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(vec![SQLS[0], SQLS[2]], report.prepared);
+        assert_eq!(1, report.failed.len());
+        let (sql, e) = &report.failed[0];
+        assert_eq!(SQLS[1], *sql);
+        assert_eq!(SQLITE_ERROR, e.code());
+        assert_eq!(Some("no such table: bar"), e.message());
+
+        let stats = con.stmt_cache_stats();
+        assert_eq!(2, stats.len);
+        assert_eq!(2, stats.misses);
+
+        // Cached
+        con.stmt(SQLS[0]).unwrap();
+        con.stmt(SQLS[2]).unwrap();
+        assert_eq!(2, con.stmt_cache_stats().hits);
+
+        // Syntax error
+        let report = con.prepare_all(&[SQLS[0], "SELEC 1"], false).unwrap();
+        assert_eq!(vec![SQLS[0]], report.prepared);
+        assert_eq!("SELEC 1", report.failed[0].0);
+        let message = report.failed[0].1.message().unwrap();
+        assert!(message.contains("syntax error"), "{}", message);
+    }
+
+    #[test]
+    fn prepare_all_fail_fast() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("value" INTEGER)"#)
+            .unwrap();
+
+        let e = con.prepare_all(&SQLS, true).unwrap_err();
+        assert_eq!(SQLITE_ERROR, e.code());
+        let message = e.message().unwrap();
+        assert!(message.contains("no such table: bar"), "{}", message);
+        assert!(message.contains(SQLS[1]), "{}", message);
+
+        // The following statements are not prepared.
+        assert_eq!(1, con.stmt_cache_stats().len);
+
+        con.execute_batch(r#"CREATE TABLE "bar" ("value" INTEGER)"#)
+            .unwrap();
+        let report = con.prepare_all(&SQLS, true).unwrap();
+        assert!(report.is_ok());
+        assert_eq!(SQLS.to_vec(), report.prepared);
+    }
+
+    #[test]
+    fn with_schema_change() {
+        const SELECT_A: &str = r#"SELECT "a" FROM "foo""#;
+        // "b" without the table name is regarded as a string literal if the column is missing.
+        const SELECT_B: &str = r#"SELECT "foo"."b" FROM "foo""#;
+        const INSERT: &str = r#"INSERT INTO "foo" ("a") VALUES (?)"#;
+
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("a" INTEGER, "b" INTEGER)"#)
+            .unwrap();
+        con.pin_stmt(SELECT_B).unwrap();
+        con.pin_stmt(SELECT_A).unwrap();
+        con.pin_stmt(INSERT).unwrap();
+        con.execute(INSERT, &[&1]).unwrap();
+
+        let (ret, report) = con
+            .with_schema_change(|con| {
+                con.execute_batch(r#"ALTER TABLE "foo" DROP COLUMN "b""#)?;
+                Ok(42)
+            })
+            .unwrap();
+        assert_eq!(42, ret);
+        // Sorted by the SQL regardless of the order pinned.
+        assert_eq!(vec![INSERT, SELECT_A], report.prepared);
+        assert_eq!(1, report.failed.len());
+        assert_eq!(SELECT_B, report.failed[0].0);
+        let message = report.failed[0].1.message().unwrap();
+        assert!(message.contains("no such column: foo.b"), "{}", message);
+
+        let stats = con.stmt_cache_stats();
+        let mut pinned = stats.pinned.clone();
+        pinned.sort_unstable();
+        assert_eq!(vec![INSERT.to_string(), SELECT_A.to_string()], pinned);
+        assert_eq!(2, stats.len);
+
+        // The clean statements are prepared again.
+        con.execute(INSERT, &[&2]).unwrap();
+        let stmt = con.stmt(SELECT_A).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Some(1), stmt.column_int(0));
+
+        // The error of the closure.
+        let e = con
+            .with_schema_change(|con| con.execute_batch("INVALID SQL"))
+            .unwrap_err();
+        assert_eq!(SQLITE_ERROR, e.code());
+        assert_eq!(2, con.stmt_cache_stats().pinned.len());
+
+        // The error of the closure precedes that of pinning the statements again.
+        let e = con
+            .with_schema_change(|con| -> Result<(), Error> {
+                con.set_stmt_cache_capacity(Some(1))?;
+                Err(Error::with_message(SQLITE_ERROR, "closure"))
+            })
+            .unwrap_err();
+        assert_eq!(Some("closure"), e.message());
+        assert_eq!(1, con.stmt_cache_stats().pinned.len());
+    }
+}
@@ -0,0 +1,354 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{sqlite3, sqlite3_value, value_from_raw, Connection, Error, Value, SQLITE_RANGE};
+use core::convert::TryFrom;
+use core::marker::PhantomData;
+use core::ptr::null_mut;
+use std::borrow::Cow;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_void};
+use std::panic::AssertUnwindSafe;
+
+#[link(name = "sqlite3")]
+extern "C" {
+    fn sqlite3_preupdate_hook(
+        db: *mut sqlite3,
+        xpreupdate: Option<
+            unsafe extern "C" fn(
+                *mut c_void,
+                *mut sqlite3,
+                c_int,
+                *const c_char,
+                *const c_char,
+                i64,
+                i64,
+            ),
+        >,
+        parg: *mut c_void,
+    ) -> *mut c_void;
+    fn sqlite3_preupdate_old(db: *mut sqlite3, n: c_int, ppvalue: *mut *mut sqlite3_value)
+        -> c_int;
+    fn sqlite3_preupdate_new(db: *mut sqlite3, n: c_int, ppvalue: *mut *mut sqlite3_value)
+        -> c_int;
+    fn sqlite3_preupdate_count(db: *mut sqlite3) -> c_int;
+    fn sqlite3_preupdate_depth(db: *mut sqlite3) -> c_int;
+}
+
+// Authorizer action codes that the preupdate hook receives
+// https://www.sqlite.org/c3ref/c_alter_table.html
+const SQLITE_DELETE: c_int = 9;
+const SQLITE_INSERT: c_int = 18;
+const SQLITE_UPDATE: c_int = 23;
+
+/// The callback that [`Connection::preupdate_hook`] sets.
+///
+/// [`Connection::preupdate_hook`]: struct.Connection.html#method.preupdate_hook
+pub(crate) type Hook = Box<dyn FnMut(Action, &str, &str, &PreUpdateCase<'_>) + Send>;
+
+/// The kind of the change that the preupdate hook is notified of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    /// A row is being inserted.
+    Insert,
+    /// A row is being updated.
+    Update,
+    /// A row is being deleted.
+    Delete,
+}
+
+/// The row being changed, which the callback of [`Connection::preupdate_hook`] receives.
+///
+/// [`Connection::preupdate_hook`]: struct.Connection.html#method.preupdate_hook
+pub struct PreUpdateCase<'a> {
+    db: *mut sqlite3,
+    old_rowid: i64,
+    new_rowid: i64,
+    _marker: PhantomData<&'a mut Connection>,
+}
+
+impl PreUpdateCase<'_> {
+    /// Wrapper of C function [`sqlite3_preupdate_count`] .
+    ///
+    /// Returns the number of the columns of the row being changed.
+    ///
+    /// [`sqlite3_preupdate_count`]: https://www.sqlite.org/c3ref/preupdate_blobwrite.html
+    #[inline]
+    pub fn column_count(&self) -> usize {
+        unsafe { sqlite3_preupdate_count(self.db) as usize }
+    }
+
+    /// Wrapper of C function [`sqlite3_preupdate_depth`] .
+    ///
+    /// Returns 0 if the change is caused by a top level SQL statement, 1 if by a trigger fired by
+    /// a top level statement, 2 if by a trigger fired by such a trigger, and so on.
+    ///
+    /// [`sqlite3_preupdate_depth`]: https://www.sqlite.org/c3ref/preupdate_blobwrite.html
+    #[inline]
+    pub fn depth(&self) -> i32 {
+        unsafe { sqlite3_preupdate_depth(self.db) }
+    }
+
+    /// Returns the rowid of the row before the change. This is meaningless for
+    /// [`Action::Insert`] .
+    ///
+    /// [`Action::Insert`]: enum.Action.html#variant.Insert
+    #[inline]
+    pub fn old_rowid(&self) -> i64 {
+        self.old_rowid
+    }
+
+    /// Returns the rowid of the row after the change. This is meaningless for
+    /// [`Action::Delete`] .
+    ///
+    /// [`Action::Delete`]: enum.Action.html#variant.Delete
+    #[inline]
+    pub fn new_rowid(&self) -> i64 {
+        self.new_rowid
+    }
+
+    /// Wrapper of C function [`sqlite3_preupdate_old`] .
+    ///
+    /// Returns the value of the column `index` before the change.
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// Returns `SQLITE_MISUSE` if the action is [`Action::Insert`] , or `SQLITE_RANGE` if `index`
+    /// is out of range.
+    ///
+    /// [`sqlite3_preupdate_old`]: https://www.sqlite.org/c3ref/preupdate_blobwrite.html
+    /// [`Action::Insert`]: enum.Action.html#variant.Insert
+    #[inline]
+    pub fn old_value(&self, index: usize) -> Result<Value, Error> {
+        self.value(index, sqlite3_preupdate_old)
+    }
+
+    /// Wrapper of C function [`sqlite3_preupdate_new`] .
+    ///
+    /// Returns the value of the column `index` after the change.
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// Returns `SQLITE_MISUSE` if the action is [`Action::Delete`] , or `SQLITE_RANGE` if `index`
+    /// is out of range.
+    ///
+    /// [`sqlite3_preupdate_new`]: https://www.sqlite.org/c3ref/preupdate_blobwrite.html
+    /// [`Action::Delete`]: enum.Action.html#variant.Delete
+    #[inline]
+    pub fn new_value(&self, index: usize) -> Result<Value, Error> {
+        self.value(index, sqlite3_preupdate_new)
+    }
+
+    fn value(
+        &self,
+        index: usize,
+        f: unsafe extern "C" fn(*mut sqlite3, c_int, *mut *mut sqlite3_value) -> c_int,
+    ) -> Result<Value, Error> {
+        let index = c_int::try_from(index).map_err(|_| Error::new(SQLITE_RANGE))?;
+        let mut raw = null_mut();
+        let code = unsafe { f(self.db, index, &mut raw) };
+        match Error::new(code) {
+            e if e == Error::OK => Ok(unsafe { value_from_raw(raw) }.to_owned()),
+            e => Err(e),
+        }
+    }
+}
+
+/// Passes the change to the callback that [`Connection::preupdate_hook`] set.
+///
+/// `arg` is the callback.
+///
+/// [`Connection::preupdate_hook`]: struct.Connection.html#method.preupdate_hook
+unsafe extern "C" fn preupdate_trampoline(
+    arg: *mut c_void,
+    db: *mut sqlite3,
+    op: c_int,
+    zdb: *const c_char,
+    zname: *const c_char,
+    old_rowid: i64,
+    new_rowid: i64,
+) {
+    let hook = &mut *(arg as *mut Hook);
+    let action = match op {
+        SQLITE_INSERT => Action::Insert,
+        SQLITE_UPDATE => Action::Update,
+        SQLITE_DELETE => Action::Delete,
+        _ => return,
+    };
+    let to_str = |ptr: *const c_char| -> Cow<'_, str> {
+        if ptr.is_null() {
+            Cow::Borrowed("")
+        } else {
+            CStr::from_ptr(ptr).to_string_lossy()
+        }
+    };
+    let case = PreUpdateCase {
+        db,
+        old_rowid,
+        new_rowid,
+        _marker: PhantomData,
+    };
+
+    // Unwinding across the FFI boundary is not allowed.
+    let _ = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        hook(action, &to_str(zdb), &to_str(zname), &case)
+    }));
+}
+
+impl Connection {
+    /// Wrapper of C function [`sqlite3_preupdate_hook`] .
+    ///
+    /// Sets `f` to be called just before each row is inserted, updated, or deleted, or removes
+    /// the callback if `f` is `None` . The callback replaces the previous one if any.
+    ///
+    /// `f` receives the kind of the change, the database name (e.g. "main"), the table name, and
+    /// [`PreUpdateCase`] to read the values before and after the change. Changes to virtual
+    /// tables and WITHOUT ROWID tables are not notified.
+    ///
+    /// `f` must not use `self` ; e.g. preparing or executing any statement.
+    ///
+    /// This method is enabled by feature "preupdate-hook" , which requires libsqlite3 built with
+    /// option `SQLITE_ENABLE_PREUPDATE_HOOK` .
+    ///
+    /// [`sqlite3_preupdate_hook`]: https://www.sqlite.org/c3ref/preupdate_blobwrite.html
+    /// [`PreUpdateCase`]: struct.PreUpdateCase.html
+    pub fn preupdate_hook<F>(&mut self, f: Option<F>)
+    where
+        F: 'static + Send + FnMut(Action, &str, &str, &PreUpdateCase<'_>),
+    {
+        let hook: Option<Box<Hook>> = f.map(|f| Box::new(Box::new(f) as Hook));
+        unsafe {
+            match hook.as_ref() {
+                None => sqlite3_preupdate_hook(self.raw(), None, null_mut()),
+                Some(hook) => {
+                    let arg = hook.as_ref() as *const Hook as *mut c_void;
+                    sqlite3_preupdate_hook(self.raw(), Some(preupdate_trampoline), arg)
+                }
+            };
+        }
+        // Drops the previous callback after it is unregistered.
+        self.preupdate_hook = hook;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    type Log = Vec<(Action, String, i32, Vec<Value>, Vec<Value>)>;
+
+    #[test]
+    fn preupdate_hook() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(
+            r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "name" TEXT, "score" REAL);
+               INSERT INTO "foo" VALUES (1, 'alice', 1.5);"#,
+        )
+        .unwrap();
+
+        let log = Arc::new(Mutex::new(Log::new()));
+        let log_ = log.clone();
+        con.preupdate_hook(Some(
+            move |action, db: &str, table: &str, case: &PreUpdateCase<'_>| {
+                assert_eq!("main", db);
+                let values = |f: &dyn Fn(usize) -> Result<Value, Error>| {
+                    (0..case.column_count()).filter_map(|i| f(i).ok()).collect()
+                };
+                let old = values(&|i| case.old_value(i));
+                let new = values(&|i| case.new_value(i));
+                assert!(case.old_value(case.column_count()).is_err());
+                let entry = (action, table.to_string(), case.depth(), old, new);
+                log_.lock().unwrap().push(entry);
+            },
+        ));
+
+        con.execute_batch(
+            r#"UPDATE "foo" SET "name" = 'bob' WHERE "id" = 1;
+               INSERT INTO "foo" VALUES (2, 'carol', NULL);
+               DELETE FROM "foo" WHERE "id" = 2;"#,
+        )
+        .unwrap();
+
+        let row = |name: &str, score| {
+            vec![
+                Value::Integer(1),
+                Value::Text(name.to_string()),
+                Value::Real(score),
+            ]
+        };
+        let carol = vec![
+            Value::Integer(2),
+            Value::Text("carol".to_string()),
+            Value::Null,
+        ];
+        let foo = "foo".to_string();
+        let expected = vec![
+            (
+                Action::Update,
+                foo.clone(),
+                0,
+                row("alice", 1.5),
+                row("bob", 1.5),
+            ),
+            (Action::Insert, foo.clone(), 0, vec![], carol.clone()),
+            (Action::Delete, foo, 0, carol, vec![]),
+        ];
+        assert_eq!(expected, *log.lock().unwrap());
+
+        // Remove the hook.
+        con.preupdate_hook(None::<fn(Action, &str, &str, &PreUpdateCase<'_>)>);
+        con.execute_batch(r#"DELETE FROM "foo""#).unwrap();
+        assert_eq!(3, log.lock().unwrap().len());
+    }
+}
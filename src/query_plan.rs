@@ -0,0 +1,193 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{Connection, Error};
+
+/// A node of [`QueryPlan`] , which corresponds to a row of `EXPLAIN QUERY PLAN` .
+///
+/// [`QueryPlan`]: struct.QueryPlan.html
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PlanNode {
+    /// The description of the step, for example, "SEARCH foo USING INDEX foo_name (name=?)" .
+    pub detail: String,
+    /// The sub steps.
+    pub children: Vec<PlanNode>,
+}
+
+/// Tree of the query plan, which [`Connection::explain_query_plan`] returns.
+///
+/// See [`EXPLAIN QUERY PLAN`] for the format of the details.
+///
+/// [`Connection::explain_query_plan`]: struct.Connection.html#method.explain_query_plan
+/// [`EXPLAIN QUERY PLAN`]: https://www.sqlite.org/eqp.html
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct QueryPlan {
+    /// The top level steps.
+    pub roots: Vec<PlanNode>,
+}
+
+impl QueryPlan {
+    /// Returns an iterator over all the nodes in depth-first order.
+    pub fn iter(&self) -> impl Iterator<Item = &PlanNode> {
+        let mut stack: Vec<&PlanNode> = self.roots.iter().rev().collect();
+        core::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.children.iter().rev());
+            Some(node)
+        })
+    }
+
+    /// Returns `true` if any step uses the index `name` (including a covering index.)
+    pub fn uses_index(&self, name: &str) -> bool {
+        let index = format!("USING INDEX {}", name);
+        let covering = format!("USING COVERING INDEX {}", name);
+        self.iter().any(|node| {
+            [&index, &covering].iter().any(|pattern| {
+                node.detail
+                    .match_indices(pattern.as_str())
+                    .any(|(i, _)| is_word_end(&node.detail[i + pattern.len()..]))
+            })
+        })
+    }
+
+    /// Returns `true` if any step scans a whole table without any index.
+    pub fn has_full_scan(&self) -> bool {
+        self.iter().any(|node| {
+            node.detail.starts_with("SCAN ")
+                && !node.detail.contains(" USING ")
+                && node.detail != "SCAN CONSTANT ROW"
+        })
+    }
+}
+
+fn is_word_end(rest: &str) -> bool {
+    rest.is_empty() || rest.starts_with(' ')
+}
+
+impl Connection {
+    /// Executes `EXPLAIN QUERY PLAN` for `sql` and returns the result as a tree.
+    ///
+    /// Only the first statement in `sql` is explained.
+    pub fn explain_query_plan(&mut self, sql: &str) -> Result<QueryPlan, Error> {
+        let sql = format!("EXPLAIN QUERY PLAN {}", sql);
+        let mut stmt = self.stmt_once(&sql)?;
+
+        // (id, parent, detail)
+        let mut rows: Vec<(i64, i64, String)> = Vec::new();
+        while stmt.step()? {
+            rows.push((stmt.get(0)?, stmt.get(1)?, stmt.get(3)?));
+        }
+
+        fn build(rows: &[(i64, i64, String)], parent: i64) -> Vec<PlanNode> {
+            rows.iter()
+                .filter(|(_, p, _)| *p == parent)
+                .map(|(id, _, detail)| PlanNode {
+                    detail: detail.clone(),
+                    children: build(rows, *id),
+                })
+                .collect()
+        }
+
+        Ok(QueryPlan {
+            roots: build(&rows, 0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explain_query_plan() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(
+            r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "name" TEXT, "value" INTEGER);
+               CREATE INDEX "foo_name" ON "foo" ("name");"#,
+        )
+        .unwrap();
+
+        let plan = con
+            .explain_query_plan(r#"SELECT * FROM "foo" WHERE "name" = 'a'"#)
+            .unwrap();
+        assert_eq!(1, plan.roots.len());
+        assert!(plan.roots[0].detail.contains("USING INDEX"));
+        assert!(plan.uses_index("foo_name"));
+        assert!(!plan.uses_index("foo"));
+        assert!(!plan.has_full_scan());
+
+        let plan = con
+            .explain_query_plan(r#"SELECT * FROM "foo" WHERE "value" = 1"#)
+            .unwrap();
+        assert!(plan.roots[0].detail.starts_with("SCAN"));
+        assert!(!plan.uses_index("foo_name"));
+        assert!(plan.has_full_scan());
+
+        // Tree
+        let plan = con
+            .explain_query_plan(
+                r#"SELECT * FROM "foo" WHERE "id" IN (SELECT "value" FROM "foo" WHERE "name" = 'a')
+                   UNION ALL SELECT * FROM "foo""#,
+            )
+            .unwrap();
+        assert_eq!(1, plan.roots.len());
+        assert!(!plan.roots[0].children.is_empty());
+        assert!(plan.iter().count() > plan.roots.len());
+        assert!(plan.uses_index("foo_name"));
+        assert!(plan.has_full_scan());
+
+        assert!(con
+            .explain_query_plan("SELECT * FROM no_such_table")
+            .is_err());
+    }
+}
@@ -0,0 +1,208 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Helpers to quote identifiers and literals embedded in SQL text.
+
+use crate::{keyword_check, Error, ValueRef, SQLITE_MISUSE};
+
+/// Returns `name` quoted as an SQL identifier, i.e. enclosed in double quotes doubling the double
+/// quotes in it.
+///
+/// Returns `SQLITE_MISUSE` if `name` includes NUL, which libsqlite3 regards as the end of SQL.
+///
+/// # Examples
+///
+/// ```
+/// use mouse_sqlite3::quote::quote_identifier;
+///
+/// assert_eq!(Ok(r#""it's ""foo""""#.to_string()), quote_identifier(r#"it's "foo""#));
+/// assert!(quote_identifier("foo\0bar").is_err());
+/// ```
+pub fn quote_identifier(name: &str) -> Result<String, Error> {
+    if name.contains('\0') {
+        let message = format!("identifier {:?} includes NUL", name);
+        return Err(Error::with_message(SQLITE_MISUSE, message));
+    }
+
+    let mut ret = String::with_capacity(name.len() + 2);
+    ret.push('"');
+    for c in name.chars() {
+        if c == '"' {
+            ret.push('"');
+        }
+        ret.push(c);
+    }
+    ret.push('"');
+    Ok(ret)
+}
+
+/// Returns `s` as an SQL string literal, i.e. enclosed in single quotes doubling the single quotes
+/// in it.
+///
+/// `s` including NUL is written as `CAST(X'...' AS TEXT)` ; see [`Value::to_sql_literal`] .
+///
+/// [`Value::to_sql_literal`]: ../enum.Value.html#method.to_sql_literal
+pub fn quote_literal(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len() + 2);
+    // Writing into String never fails.
+    ValueRef::Text(s.as_bytes())
+        .write_sql_literal(&mut ret)
+        .unwrap();
+    ret
+}
+
+/// Returns `bytes` as an SQL blob literal, i.e. `X'...'` in hexadecimal.
+pub fn quote_blob(bytes: &[u8]) -> String {
+    let mut ret = String::with_capacity(bytes.len() * 2 + 3);
+    // Writing into String never fails.
+    ValueRef::Blob(bytes).write_sql_literal(&mut ret).unwrap();
+    ret
+}
+
+/// Returns whether `name` can be used as an SQL identifier without quoting.
+///
+/// That is, `name` is not empty, starts with an alphabet, '_', or a non-ASCII character,
+/// consists of them, digits, and '$' , and is not an SQL keyword (see [`keyword_check`] .)
+///
+/// Always returns `false` if SQLite cannot tell the keywords; see [`HAS_KEYWORDS`] .
+///
+/// [`keyword_check`]: ../fn.keyword_check.html
+/// [`HAS_KEYWORDS`]: ../constant.HAS_KEYWORDS.html
+pub fn validate_identifier(name: &str) -> bool {
+    if !crate::compat::has_keywords() {
+        return false;
+    }
+
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || !c.is_ascii() => (),
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$' || !c.is_ascii())
+        && !keyword_check(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Connection, Value};
+
+    #[test]
+    fn create_table() {
+        let names = [
+            "foo",
+            r#"it's "quoted""#,
+            "with space",
+            "\u{1f600}",
+            "select",
+            "",
+        ];
+
+        let mut con = Connection::open_memory_db().unwrap();
+        for name in names.iter() {
+            let table = quote_identifier(name).unwrap();
+            let sql = format!("CREATE TABLE {} ({} TEXT)", table, table);
+            con.execute_batch(&sql).unwrap();
+            assert!(con.table_exists(name).unwrap());
+            assert_eq!(name.to_string(), con.table_info(name).unwrap()[0].name);
+
+            let sql = format!("INSERT INTO {} VALUES ({})", table, quote_literal(name));
+            con.execute_batch(&sql).unwrap();
+            let sql = format!("SELECT {} FROM {}", table, table);
+            let mut stmt = con.stmt_once(&sql).unwrap();
+            assert_eq!(Ok(true), stmt.step());
+            assert_eq!(Ok(name.to_string()), stmt.get::<String>(0));
+        }
+
+        assert_eq!(SQLITE_MISUSE, quote_identifier("a\0b").unwrap_err().code());
+    }
+
+    #[test]
+    fn literals() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let sql = format!(
+            "SELECT {}, {}, {}",
+            quote_literal("it's"),
+            quote_literal("nul\0inside"),
+            quote_blob(&[0, 0xff])
+        );
+        let mut stmt = con.stmt_once(&sql).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        let expected = vec![
+            Value::Text("it's".to_string()),
+            Value::Text("nul\0inside".to_string()),
+            Value::Blob(vec![0, 0xff]),
+        ];
+        assert_eq!(Ok(expected), stmt.current_row_values());
+        assert_eq!("X''", quote_blob(&[]));
+    }
+
+    #[test]
+    fn validate() {
+        if !crate::HAS_KEYWORDS {
+            assert!(!validate_identifier("foo"));
+            return;
+        }
+
+        for name in ["foo", "_foo1", "foo$bar", "\u{1f600}", "t\u{e9}st"].iter() {
+            assert!(validate_identifier(name), "{}", name);
+        }
+        for name in [
+            "", "1foo", "foo bar", "it's", "a\"b", "select", "TABLE", "a-b",
+        ]
+        .iter()
+        {
+            assert!(!validate_identifier(name), "{}", name);
+        }
+    }
+}
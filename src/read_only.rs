@@ -0,0 +1,334 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    sqlite3, Connection, Error, FromRow, OpenOptions, Stmt, ToSql, SQLITE_AUTH, SQLITE_READONLY,
+};
+use core::ptr::null_mut;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_void};
+use std::path::{Path, PathBuf};
+
+#[link(name = "sqlite3")]
+extern "C" {
+    fn sqlite3_set_authorizer(
+        db: *mut sqlite3,
+        xauth: Option<
+            unsafe extern "C" fn(
+                *mut c_void,
+                c_int,
+                *const c_char,
+                *const c_char,
+                *const c_char,
+                *const c_char,
+            ) -> c_int,
+        >,
+        puserdata: *mut c_void,
+    ) -> c_int;
+}
+
+// Return values of the authorizer
+// https://www.sqlite.org/c3ref/c_deny.html
+const SQLITE_OK: c_int = 0;
+const SQLITE_DENY: c_int = 1;
+
+// Authorizer action codes
+// https://www.sqlite.org/c3ref/c_alter_table.html
+const SQLITE_PRAGMA: c_int = 19;
+const SQLITE_READ: c_int = 20;
+const SQLITE_SELECT: c_int = 21;
+const SQLITE_TRANSACTION: c_int = 22;
+const SQLITE_FUNCTION: c_int = 31;
+const SQLITE_SAVEPOINT: c_int = 32;
+const SQLITE_RECURSIVE: c_int = 33;
+
+/// PRAGMAs that take an argument only to specify what to read, e.g. "PRAGMA table_info(foo)".
+const READ_PRAGMAS: &[&str] = &[
+    "foreign_key_check",
+    "foreign_key_list",
+    "index_info",
+    "index_list",
+    "index_xinfo",
+    "integrity_check",
+    "quick_check",
+    "table_info",
+    "table_list",
+    "table_xinfo",
+];
+
+/// Authorizer that allows only reading; i.e. denies any write, DDL, ATTACH, and PRAGMA with a
+/// value except for `READ_PRAGMAS` .
+unsafe extern "C" fn authorize_read(
+    _: *mut c_void,
+    action: c_int,
+    arg1: *const c_char,
+    arg2: *const c_char,
+    _: *const c_char,
+    _: *const c_char,
+) -> c_int {
+    match action {
+        SQLITE_READ | SQLITE_SELECT | SQLITE_TRANSACTION | SQLITE_FUNCTION | SQLITE_SAVEPOINT
+        | SQLITE_RECURSIVE => SQLITE_OK,
+        // The 1st argument is the name of the PRAGMA, and the 2nd one is the value or the
+        // argument, e.g. "5" of "PRAGMA user_version = 5" or "foo" of "PRAGMA table_info(foo)".
+        SQLITE_PRAGMA if arg2.is_null() => SQLITE_OK,
+        SQLITE_PRAGMA => {
+            let name = CStr::from_ptr(arg1).to_bytes();
+            if READ_PRAGMAS
+                .iter()
+                .any(|p| p.as_bytes().eq_ignore_ascii_case(name))
+            {
+                SQLITE_OK
+            } else {
+                SQLITE_DENY
+            }
+        }
+        _ => SQLITE_DENY,
+    }
+}
+
+/// Returns `SQLITE_READONLY` for `sql` .
+fn write_error(sql: &str) -> Error {
+    let message = format!("read-only connection cannot execute {:?}", sql);
+    Error::with_message(SQLITE_READONLY, message)
+}
+
+/// Converts `SQLITE_AUTH` that the authorizer caused into `SQLITE_READONLY` .
+fn map_auth_error(e: Error, sql: &str) -> Error {
+    if e.code() == SQLITE_AUTH {
+        write_error(sql)
+    } else {
+        e
+    }
+}
+
+/// Wrapper of [`Connection`] that only reads the database.
+///
+/// `ReadOnlyConnection` exposes only the methods to query, and refuses to prepare any statement
+/// that may write the database (i.e. [`Stmt::is_readonly`] is `false` .) In addition, it
+/// installs an authorizer (C function [`sqlite3_set_authorizer`] ) denying writes, DDL,
+/// ATTACH, and PRAGMA with a value. PRAGMAs that only read the schema or check the database,
+/// e.g. "PRAGMA table_info(foo)" or "PRAGMA integrity_check(10)", are allowed.
+///
+/// Build an instance with [`OpenOptions::open_read_only`] or [`Connection::into_read_only`] .
+///
+/// [`Connection`]: struct.Connection.html
+/// [`Stmt::is_readonly`]: struct.Stmt.html#method.is_readonly
+/// [`sqlite3_set_authorizer`]: https://www.sqlite.org/c3ref/set_authorizer.html
+/// [`OpenOptions::open_read_only`]: struct.OpenOptions.html#method.open_read_only
+/// [`Connection::into_read_only`]: struct.Connection.html#method.into_read_only
+#[derive(Debug)]
+pub struct ReadOnlyConnection {
+    con: Connection,
+}
+
+impl ReadOnlyConnection {
+    /// Same to [`Connection::stmt`] except for returning `SQLITE_READONLY` if `sql` may write
+    /// the database.
+    ///
+    /// [`Connection::stmt`]: struct.Connection.html#method.stmt
+    pub fn stmt(&mut self, sql: &'static str) -> Result<&mut Stmt, Error> {
+        let stmt = self.con.stmt(sql).map_err(|e| map_auth_error(e, sql))?;
+        if stmt.is_readonly() {
+            Ok(stmt)
+        } else {
+            Err(write_error(sql))
+        }
+    }
+
+    /// Same to [`Connection::stmt_once`] except for returning `SQLITE_READONLY` if `sql` may
+    /// write the database.
+    ///
+    /// [`Connection::stmt_once`]: struct.Connection.html#method.stmt_once
+    pub fn stmt_once(&mut self, sql: &str) -> Result<Stmt, Error> {
+        let stmt = self
+            .con
+            .stmt_once(sql)
+            .map_err(|e| map_auth_error(e, sql))?;
+        if stmt.is_readonly() {
+            Ok(stmt)
+        } else {
+            Err(write_error(sql))
+        }
+    }
+
+    /// Same to [`Connection::query_one`] except for returning `SQLITE_READONLY` if `sql` may
+    /// write the database.
+    ///
+    /// [`Connection::query_one`]: struct.Connection.html#method.query_one
+    pub fn query_one<T>(
+        &mut self,
+        sql: &'static str,
+        params: &[&dyn ToSql],
+    ) -> Result<Option<T>, Error>
+    where
+        T: FromRow,
+    {
+        self.stmt(sql)?;
+        self.con.query_one(sql, params)
+    }
+
+    /// Same to [`Connection::db_filename`] .
+    ///
+    /// [`Connection::db_filename`]: struct.Connection.html#method.db_filename
+    #[inline]
+    pub fn db_filename(&self, schema: &str) -> Option<PathBuf> {
+        self.con.db_filename(schema)
+    }
+}
+
+impl Connection {
+    /// Installs the authorizer denying writes, and returns `self` as [`ReadOnlyConnection`] .
+    ///
+    /// The statements that `self` has cached are discarded.
+    ///
+    /// [`ReadOnlyConnection`]: struct.ReadOnlyConnection.html
+    pub fn into_read_only(mut self) -> Result<ReadOnlyConnection, Error> {
+        // The authorizer is consulted only when a statement is prepared.
+        self.clear_stmt_cache();
+
+        let code = unsafe { sqlite3_set_authorizer(self.raw(), Some(authorize_read), null_mut()) };
+        match Error::new(code) {
+            e if e == Error::OK => Ok(ReadOnlyConnection { con: self }),
+            e => Err(e),
+        }
+    }
+}
+
+impl OpenOptions {
+    /// Opens the database `path` in read-only mode regardless of option [`read_only`] , and
+    /// returns it as [`ReadOnlyConnection`] .
+    ///
+    /// Returns `SQLITE_CANTOPEN` if `path` includes NUL character.
+    ///
+    /// [`read_only`]: #method.read_only
+    /// [`ReadOnlyConnection`]: struct.ReadOnlyConnection.html
+    pub fn open_read_only<P>(&self, path: P) -> Result<ReadOnlyConnection, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut options = self.clone();
+        options.read_only(true).open(path)?.into_read_only()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn read_only_connection() {
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("test_sqlite");
+        {
+            let mut con = OpenOptions::new().open(&path).unwrap();
+            con.execute_batch(
+                r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "name" TEXT);
+                   INSERT INTO "foo" VALUES (1, 'alice');"#,
+            )
+            .unwrap();
+        }
+
+        let mut con = OpenOptions::new().open_read_only(&path).unwrap();
+        assert!(con.db_filename("main").is_some());
+
+        let row = con.query_one(r#"SELECT "name" FROM "foo" WHERE "id" = ?1"#, &[&1]);
+        assert_eq!(Ok(Some((String::from("alice"),))), row);
+        let stmt = con.stmt_once(r#"SELECT count(*) FROM "foo""#).unwrap();
+        drop(stmt);
+        assert!(con.stmt("PRAGMA user_version").is_ok());
+        let reads = [
+            r#"PRAGMA table_info("foo")"#,
+            r#"PRAGMA main.TABLE_INFO("foo")"#,
+            r#"PRAGMA index_list("foo")"#,
+            r#"PRAGMA foreign_key_list("foo")"#,
+            "PRAGMA integrity_check(10)",
+        ];
+        for sql in reads.iter() {
+            let mut stmt = con.stmt_once(sql).unwrap();
+            assert!(stmt.step().is_ok(), "{}", sql);
+        }
+        assert!(con.stmt_once("BEGIN").is_ok());
+
+        let writes = [
+            r#"UPDATE "foo" SET "name" = 'bob'"#,
+            r#"INSERT INTO "foo" VALUES (2, 'bob')"#,
+            r#"CREATE TABLE "bar" ("id" INTEGER)"#,
+            "PRAGMA user_version = 5",
+            "PRAGMA journal_mode = WAL",
+            "ATTACH ':memory:' AS \"other\"",
+        ];
+        for sql in writes.iter() {
+            let e = con.stmt_once(sql).unwrap_err();
+            assert_eq!(SQLITE_READONLY, e.code(), "{}", sql);
+        }
+        let e = con
+            .query_one::<(i64,)>(r#"DELETE FROM "foo" RETURNING "id""#, &[])
+            .unwrap_err();
+        assert_eq!(SQLITE_READONLY, e.code());
+    }
+
+    #[test]
+    fn into_read_only() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("value" INTEGER)"#)
+            .unwrap();
+        con.stmt(r#"INSERT INTO "foo" VALUES (1)"#).unwrap();
+
+        let mut con = con.into_read_only().unwrap();
+        let e = con.stmt(r#"INSERT INTO "foo" VALUES (1)"#).unwrap_err();
+        assert_eq!(SQLITE_READONLY, e.code());
+        assert!(con.stmt(r#"SELECT * FROM "foo""#).is_ok());
+    }
+}
@@ -0,0 +1,237 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{Error, FromSql, Stmt, Value, ValueRef, SQLITE_MISMATCH};
+
+/// Reference to the current row of [`Stmt`] .
+///
+/// Use [`Stmt::row`] to build an instance.
+///
+/// [`Stmt`]: struct.Stmt.html
+/// [`Stmt::row`]: struct.Stmt.html#method.row
+#[derive(Clone, Copy)]
+pub struct Row<'a> {
+    stmt: &'a Stmt,
+}
+
+/// Builds [`Row`] from [`Stmt`] .
+///
+/// The caller must make sure that `stmt` has the current row.
+///
+/// [`Row`]: struct.Row.html
+/// [`Stmt`]: struct.Stmt.html
+#[inline]
+pub fn from_stmt(stmt: &Stmt) -> Row<'_> {
+    Row { stmt }
+}
+
+impl<'a> Row<'a> {
+    /// Returns the number of the columns.
+    #[inline]
+    pub fn column_count(&self) -> usize {
+        self.stmt.column_count()
+    }
+
+    /// Returns the name of the column `index` , or `None` if `index` is out of range.
+    ///
+    /// Note that `index` starts at 0, not 1.
+    #[inline]
+    pub fn column_name(&self, index: usize) -> Option<&'a str> {
+        self.stmt.column_name(index)
+    }
+
+    /// Returns the value of the column `index` without copying.
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// Returns `SQLITE_RANGE` if `index` is out of range.
+    #[inline]
+    pub fn get_ref(&self, index: usize) -> Result<ValueRef<'a>, Error> {
+        self.stmt.column_value_ref(index)
+    }
+
+    /// Returns the value of the column `index` converted into `T` .
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// Returns `SQLITE_RANGE` if `index` is out of range, or `SQLITE_MISMATCH` if the value cannot
+    /// be converted into `T` .
+    #[inline]
+    pub fn get<T>(&self, index: usize) -> Result<T, Error>
+    where
+        T: FromSql<'a>,
+    {
+        self.stmt.column_value(index)
+    }
+
+    /// Copies all the column values into `Vec` , so that they outlive the next step.
+    ///
+    /// INTEGER, REAL, and NULL do not allocate except for the `Vec` itself. Invalid UTF-8
+    /// sequences in TEXT are replaced with `U+FFFD` .
+    pub fn to_values(&self) -> Vec<Value> {
+        (0..self.column_count())
+            .map(|i| match self.get_ref(i) {
+                Ok(v) => v.to_owned(),
+                // `self` always has the current row, and `i` is in range.
+                Err(e) => panic!("{}", e),
+            })
+            .collect()
+    }
+}
+
+/// Types that can be built from a row.
+///
+/// `FromRow` is implemented for tuples up to 12 elements of [`FromSql`] types; the row must have
+/// exactly the same number of columns as the tuple.
+///
+/// [`FromSql`]: trait.FromSql.html
+pub trait FromRow: Sized {
+    /// Builds a new instance from `row` .
+    fn from_row(row: &Row<'_>) -> Result<Self, Error>;
+}
+
+/// Returns an error unless `row` has `expected` columns.
+#[inline]
+fn check_column_count(row: &Row<'_>, expected: usize) -> Result<(), Error> {
+    let actual = row.column_count();
+    if actual == expected {
+        Ok(())
+    } else {
+        let message = format!("expected {} columns, but the row has {}", expected, actual);
+        Err(Error::with_message(SQLITE_MISMATCH, message))
+    }
+}
+
+macro_rules! impl_from_row_for_tuple {
+    ($len:expr; $($t:ident $i:tt),+) => {
+        impl<$($t),+> FromRow for ($($t,)+)
+        where
+            $($t: for<'a> FromSql<'a>,)+
+        {
+            #[inline]
+            fn from_row(row: &Row<'_>) -> Result<Self, Error> {
+                check_column_count(row, $len)?;
+                Ok(($(row.get::<$t>($i)?,)+))
+            }
+        }
+    };
+}
+
+impl_from_row_for_tuple!(1; A 0);
+impl_from_row_for_tuple!(2; A 0, B 1);
+impl_from_row_for_tuple!(3; A 0, B 1, C 2);
+impl_from_row_for_tuple!(4; A 0, B 1, C 2, D 3);
+impl_from_row_for_tuple!(5; A 0, B 1, C 2, D 3, E 4);
+impl_from_row_for_tuple!(6; A 0, B 1, C 2, D 3, E 4, F 5);
+impl_from_row_for_tuple!(7; A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+impl_from_row_for_tuple!(8; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+impl_from_row_for_tuple!(9; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8);
+impl_from_row_for_tuple!(10; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9);
+impl_from_row_for_tuple!(11; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10);
+impl_from_row_for_tuple!(12; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11);
+
+/// Iterator of the rows converted into `T` .
+///
+/// Use [`Stmt::query_as_iter`] to build an instance.
+///
+/// The iterator stops after it returns an error.
+///
+/// [`Stmt::query_as_iter`]: struct.Stmt.html#method.query_as_iter
+pub struct QueryAs<'a, T> {
+    stmt: &'a mut Stmt,
+    is_finished: bool,
+    _phantom: core::marker::PhantomData<fn() -> T>,
+}
+
+/// Builds [`QueryAs`] from [`Stmt`] .
+///
+/// [`QueryAs`]: struct.QueryAs.html
+/// [`Stmt`]: struct.Stmt.html
+#[inline]
+pub fn query_as<T>(stmt: &mut Stmt) -> QueryAs<'_, T> {
+    QueryAs {
+        stmt,
+        is_finished: false,
+        _phantom: core::marker::PhantomData,
+    }
+}
+
+impl<T> Iterator for QueryAs<'_, T>
+where
+    T: FromRow,
+{
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_finished {
+            return None;
+        }
+
+        let ret = match self.stmt.step() {
+            Ok(true) => self.stmt.row().and_then(|row| T::from_row(&row)),
+            Ok(false) => {
+                self.is_finished = true;
+                return None;
+            }
+            Err(e) => Err(e),
+        };
+
+        if ret.is_err() {
+            self.is_finished = true;
+            self.stmt.reset();
+        }
+        Some(ret)
+    }
+}
+
+impl<T> core::iter::FusedIterator for QueryAs<'_, T> where T: FromRow {}
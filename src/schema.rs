@@ -0,0 +1,236 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::quote::quote_identifier;
+use crate::{Connection, Error};
+
+/// Information of a column, which `PRAGMA table_xinfo` returns.
+///
+/// See [`Connection::table_info`] .
+///
+/// [`Connection::table_info`]: struct.Connection.html#method.table_info
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ColumnInfo {
+    /// The name of the column.
+    pub name: String,
+    /// The declared type of the column. It is an empty string if the type is not declared.
+    pub decl_type: String,
+    /// Whether the column has "NOT NULL" constraint or not.
+    pub not_null: bool,
+    /// The SQL text of the default value, or `None` if the column has no default value.
+    pub default: Option<String>,
+    /// The position (starts at 1) in the primary key, or `None` if the column is not a part of
+    /// the primary key.
+    pub pk_position: Option<usize>,
+}
+
+/// Information of an index, which `PRAGMA index_list` returns.
+///
+/// See [`Connection::index_list`] .
+///
+/// [`Connection::index_list`]: struct.Connection.html#method.index_list
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct IndexInfo {
+    /// The name of the index.
+    pub name: String,
+    /// Whether the index is UNIQUE or not.
+    pub unique: bool,
+    /// How the index was created; "c" for "CREATE INDEX", "u" for UNIQUE constraint, and "pk" for
+    /// PRIMARY KEY constraint.
+    pub origin: String,
+    /// Whether the index is a partial index or not.
+    pub partial: bool,
+}
+
+impl Connection {
+    /// Returns whether the table named `name` exists in the main database or not.
+    #[inline]
+    pub fn table_exists(&mut self, name: &str) -> Result<bool, Error> {
+        const SQL: &str = r#"SELECT 1 FROM "sqlite_master" WHERE "type" = 'table' AND "name" = ?1"#;
+
+        let stmt = self.stmt(SQL)?;
+        stmt.bind_text(1, name)?;
+        let ret = stmt.step()?;
+        stmt.reset();
+        Ok(ret)
+    }
+
+    /// Executes `PRAGMA table_xinfo` and returns the information of the columns in `table` .
+    ///
+    /// Returns an empty `Vec` if `table` does not exist.
+    pub fn table_info(&mut self, table: &str) -> Result<Vec<ColumnInfo>, Error> {
+        let sql = format!("PRAGMA table_xinfo({})", quote_identifier(table)?);
+        let mut stmt = self.stmt_once(&sql)?;
+
+        // The columns are "cid", "name", "type", "notnull", "dflt_value", "pk", and "hidden".
+        let mut ret = Vec::new();
+        while stmt.step()? {
+            let name = stmt.column_text(1).unwrap_or_default().to_string();
+            let decl_type = stmt.column_text(2).unwrap_or_default().to_string();
+            let not_null = stmt.column_int(3).unwrap_or(0) != 0;
+            let default = stmt.column_text(4).map(ToString::to_string);
+            let pk_position = match stmt.column_int(5).unwrap_or(0) {
+                0 => None,
+                n => Some(n as usize),
+            };
+
+            ret.push(ColumnInfo {
+                name,
+                decl_type,
+                not_null,
+                default,
+                pk_position,
+            });
+        }
+
+        Ok(ret)
+    }
+
+    /// Executes `PRAGMA index_list` and returns the information of the indices of `table` .
+    ///
+    /// Returns an empty `Vec` if `table` does not exist.
+    pub fn index_list(&mut self, table: &str) -> Result<Vec<IndexInfo>, Error> {
+        let sql = format!("PRAGMA index_list({})", quote_identifier(table)?);
+        let mut stmt = self.stmt_once(&sql)?;
+
+        // The columns are "seq", "name", "unique", "origin", and "partial".
+        let mut ret = Vec::new();
+        while stmt.step()? {
+            let name = stmt.column_text(1).unwrap_or_default().to_string();
+            let unique = stmt.column_int(2).unwrap_or(0) != 0;
+            let origin = stmt.column_text(3).unwrap_or_default().to_string();
+            let partial = stmt.column_int(4).unwrap_or(0) != 0;
+
+            ret.push(IndexInfo {
+                name,
+                unique,
+                origin,
+                partial,
+            });
+        }
+
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CREATE: &str = r#"CREATE TABLE "foo ""bar""" (
+        "a" INTEGER NOT NULL,
+        "b" TEXT DEFAULT (lower('ABC')),
+        "c" INTEGER GENERATED ALWAYS AS ("a" * 2),
+        PRIMARY KEY ("b", "a")
+    )"#;
+
+    fn setup() -> Connection {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.stmt_once(CREATE).unwrap().step().unwrap();
+        con.stmt_once(r#"CREATE UNIQUE INDEX "foo_c" ON "foo ""bar""" ("c") WHERE "c" > 0"#)
+            .unwrap()
+            .step()
+            .unwrap();
+        con
+    }
+
+    #[test]
+    fn table_exists() {
+        let mut con = setup();
+        assert_eq!(Ok(true), con.table_exists(r#"foo "bar""#));
+        assert_eq!(Ok(false), con.table_exists("foo"));
+        assert_eq!(Ok(false), con.table_exists("foo_c"));
+    }
+
+    #[test]
+    fn table_info() {
+        let mut con = setup();
+        let columns = con.table_info(r#"foo "bar""#).unwrap();
+        assert_eq!(3, columns.len());
+
+        assert_eq!("a", columns[0].name);
+        assert_eq!("INTEGER", columns[0].decl_type);
+        assert!(columns[0].not_null);
+        assert_eq!(None, columns[0].default);
+        assert_eq!(Some(2), columns[0].pk_position);
+
+        assert_eq!("b", columns[1].name);
+        assert_eq!("TEXT", columns[1].decl_type);
+        assert!(!columns[1].not_null);
+        assert_eq!(Some("lower('ABC')"), columns[1].default.as_deref());
+        assert_eq!(Some(1), columns[1].pk_position);
+
+        // Generated column is listed by "table_xinfo" .
+        assert_eq!("c", columns[2].name);
+        assert_eq!(None, columns[2].pk_position);
+
+        assert!(con.table_info("no such table").unwrap().is_empty());
+    }
+
+    #[test]
+    fn index_list() {
+        let mut con = setup();
+        let mut indices = con.index_list(r#"foo "bar""#).unwrap();
+        indices.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(2, indices.len());
+
+        assert_eq!("foo_c", indices[0].name);
+        assert!(indices[0].unique);
+        assert_eq!("c", indices[0].origin);
+        assert!(indices[0].partial);
+
+        assert!(indices[1].unique);
+        assert_eq!("pk", indices[1].origin);
+        assert!(!indices[1].partial);
+    }
+}
@@ -0,0 +1,250 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{is_complete, Connection, Error, PrepareFlags, StepResult};
+use std::io::BufRead;
+
+/// Progress of [`Connection::execute_script`] , which is passed to the callback.
+///
+/// [`Connection::execute_script`]: struct.Connection.html#method.execute_script
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ScriptProgress {
+    /// The number of the statements executed so far.
+    pub statements: u64,
+    /// The byte offset in the script where the last executed statement ends.
+    pub bytes: u64,
+}
+
+impl Connection {
+    /// Reads an SQL script from `r` , and executes the statements one by one as soon as each of
+    /// them is read.
+    ///
+    /// The end of a statement is detected by [`is_complete`] , so a semicolon in a string
+    /// literal, a comment, or a "BEGIN ... END" block of a trigger does not terminate the
+    /// statement. Only the statements that are not executed yet are held in memory.
+    ///
+    /// `progress` is called after each statement is executed.
+    ///
+    /// If a statement fails, returns the error immediately; the following statements are not
+    /// executed. The message of the error starts with the line number (starts at 1) where the
+    /// failed statement begins.
+    ///
+    /// Returns `SQLITE_IOERR` if failed to read `r` , including the case that the script is not
+    /// a valid UTF-8 string.
+    ///
+    /// [`is_complete`]: fn.is_complete.html
+    pub fn execute_script<R>(
+        &mut self,
+        mut r: R,
+        mut progress: Option<&mut dyn FnMut(ScriptProgress)>,
+    ) -> Result<(), Error>
+    where
+        R: BufRead,
+    {
+        let mut pending = String::new();
+        let mut state = ScriptProgress {
+            statements: 0,
+            bytes: 0,
+        };
+        // The line number and the byte offset where `pending` begins.
+        let mut line = 1;
+        let mut offset = 0;
+        let mut lines = 0;
+
+        loop {
+            let len = pending.len();
+            let n = r.read_line(&mut pending).map_err(|e| Error::from_io(&e))?;
+            lines += 1;
+
+            // A statement ends with ';' except for the last one.
+            if 0 < n && !(pending[len..].contains(';') && is_complete(&pending)?) {
+                continue;
+            }
+
+            self.execute_script_chunk(&pending, line, offset, &mut state, &mut progress)?;
+            if n == 0 {
+                return Ok(());
+            }
+            offset += pending.len() as u64;
+            pending.clear();
+            line = lines + 1;
+        }
+    }
+
+    /// Executes all the statements in `chunk` , which begins at line `line` and at byte `offset`
+    /// in the script.
+    fn execute_script_chunk(
+        &mut self,
+        chunk: &str,
+        line: usize,
+        offset: u64,
+        progress: &mut ScriptProgress,
+        callback: &mut Option<&mut dyn FnMut(ScriptProgress)>,
+    ) -> Result<(), Error> {
+        let with_line = |e: Error, rest: &str| {
+            let head = &chunk[..chunk.len() - rest.len()];
+            let skipped = rest.len() - rest.trim_start().len();
+            let line = line + head.matches('\n').count() + rest[..skipped].matches('\n').count();
+            let message = match e.message() {
+                Some(message) => format!("line {}: {}", line, message),
+                None => format!("line {}: {}", line, e),
+            };
+            Error::with_message(e.code(), message)
+        };
+
+        let mut rest = chunk;
+        while !rest.is_empty() {
+            let (stmt, tail) = Self::prepare(self.raw(), rest, PrepareFlags::EMPTY)
+                .map_err(|e| with_line(e, rest))?;
+            let mut stmt = match stmt {
+                Some(mut stmt) => {
+                    stmt.require_explicit_transaction(self.requires_explicit_transactions());
+                    stmt.set_auto_optimize(self.auto_optimize.clone());
+                    stmt
+                }
+                // An empty statement or a comment.
+                None => {
+                    rest = tail;
+                    continue;
+                }
+            };
+            loop {
+                match stmt.step2() {
+                    Ok(StepResult::Row) => continue,
+                    Ok(StepResult::Done { .. }) => break,
+                    Err(e) => return Err(with_line(e, rest)),
+                }
+            }
+
+            rest = tail;
+            progress.statements += 1;
+            progress.bytes = offset + (chunk.len() - rest.len()) as u64;
+            if let Some(callback) = callback.as_mut() {
+                callback(*progress);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SQLITE_ERROR;
+    use std::io::BufReader;
+
+    const SCRIPT: &str = r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "text" TEXT);
+CREATE TABLE "log" ("text" TEXT);
+-- A trigger includes semicolons.
+CREATE TRIGGER "foo_insert" AFTER INSERT ON "foo" BEGIN
+    INSERT INTO "log" VALUES ('inserted; ' || NEW."text");
+    INSERT INTO "log" VALUES ('done;');
+END;
+INSERT INTO "foo" ("text") VALUES ('a;
+b'); INSERT INTO "foo" ("text") VALUES ('c');
+/* The last statement does not end with ';' */
+INSERT INTO "foo" ("text") VALUES ('d')"#;
+
+    fn count(con: &mut Connection, table: &'static str) -> i64 {
+        let sql = format!(r#"SELECT COUNT(*) FROM "{}""#, table);
+        let mut stmt = con.stmt_once(&sql).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        stmt.column_int(0).unwrap()
+    }
+
+    #[test]
+    fn execute_script() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let mut progresses = Vec::new();
+        let mut callback = |p: ScriptProgress| progresses.push(p);
+
+        // Small buffer smaller than a statement.
+        let reader = BufReader::with_capacity(8, SCRIPT.as_bytes());
+        con.execute_script(reader, Some(&mut callback)).unwrap();
+
+        assert_eq!(3, count(&mut con, "foo"));
+        assert_eq!(6, count(&mut con, "log"));
+        let mut stmt = con
+            .stmt_once(r#"SELECT "text" FROM "log" WHERE rowid = 1"#)
+            .unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Some("inserted; a;\nb"), stmt.column_text(0));
+        drop(stmt);
+
+        assert_eq!(6, progresses.last().unwrap().statements);
+        assert_eq!(SCRIPT.len() as u64, progresses.last().unwrap().bytes);
+        let statements: Vec<_> = progresses.iter().map(|p| p.statements).collect();
+        assert_eq!(vec![1, 2, 3, 4, 5, 6], statements);
+        assert!(progresses.windows(2).all(|w| w[0].bytes < w[1].bytes));
+    }
+
+    #[test]
+    fn error_line() {
+        let script = "CREATE TABLE \"foo\" (\"id\");\n\
+                      INSERT INTO \"foo\" VALUES (1);\n\
+                      \n\
+                      INSERT INTO \"foo\" VALUES (2); INSERT INTO \"bar\"\n\
+                      VALUES (3);\n\
+                      INSERT INTO \"foo\" VALUES (4);\n";
+        let mut con = Connection::open_memory_db().unwrap();
+        let e = con.execute_script(script.as_bytes(), None).unwrap_err();
+        assert_eq!(SQLITE_ERROR, e.code());
+        assert!(e.message().unwrap().starts_with("line 4: "));
+        assert_eq!(2, count(&mut con, "foo"));
+
+        let e = con
+            .execute_script("SELECT 1;;\n\nSELECT;".as_bytes(), None)
+            .unwrap_err();
+        assert!(e.message().unwrap().starts_with("line 3: "));
+    }
+}
@@ -0,0 +1,569 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    sqlite3, sqlite3_free, sqlite3_value, value_from_raw, Connection, Error, ValueRef,
+    SQLITE_MISUSE, SQLITE_RANGE, SQLITE_TOOBIG,
+};
+use core::convert::TryFrom;
+use core::ops::{Deref, DerefMut};
+use core::ptr::null_mut;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::panic::AssertUnwindSafe;
+
+mod libsqlite3 {
+    #[allow(non_camel_case_types)]
+    pub enum sqlite3_session {}
+
+    #[allow(non_camel_case_types)]
+    pub enum sqlite3_changeset_iter {}
+}
+use libsqlite3::*;
+
+// Constants for the conflict handler of sqlite3changeset_apply()
+// https://www.sqlite.org/session/c_changeset_conflict.html
+const SQLITE_CHANGESET_DATA: c_int = 1;
+const SQLITE_CHANGESET_NOTFOUND: c_int = 2;
+const SQLITE_CHANGESET_CONFLICT: c_int = 3;
+const SQLITE_CHANGESET_CONSTRAINT: c_int = 4;
+const SQLITE_CHANGESET_FOREIGN_KEY: c_int = 5;
+
+// https://www.sqlite.org/session/c_changeset_abort.html
+const SQLITE_CHANGESET_OMIT: c_int = 0;
+const SQLITE_CHANGESET_REPLACE: c_int = 1;
+const SQLITE_CHANGESET_ABORT: c_int = 2;
+
+// Authorizer action codes that sqlite3changeset_op() returns
+// https://www.sqlite.org/c3ref/c_alter_table.html
+const SQLITE_DELETE: c_int = 9;
+const SQLITE_INSERT: c_int = 18;
+const SQLITE_UPDATE: c_int = 23;
+
+#[link(name = "sqlite3")]
+extern "C" {
+    fn sqlite3session_create(
+        pdb: *mut sqlite3,
+        zdb: *const c_char,
+        ppsession: *mut *mut sqlite3_session,
+    ) -> c_int;
+    fn sqlite3session_delete(psession: *mut sqlite3_session);
+    fn sqlite3session_attach(psession: *mut sqlite3_session, ztab: *const c_char) -> c_int;
+    fn sqlite3session_changeset(
+        psession: *mut sqlite3_session,
+        pnchangeset: *mut c_int,
+        ppchangeset: *mut *mut c_void,
+    ) -> c_int;
+    fn sqlite3session_patchset(
+        psession: *mut sqlite3_session,
+        pnpatchset: *mut c_int,
+        pppatchset: *mut *mut c_void,
+    ) -> c_int;
+
+    fn sqlite3changeset_apply(
+        pdb: *mut sqlite3,
+        nchangeset: c_int,
+        pchangeset: *mut c_void,
+        xfilter: Option<unsafe extern "C" fn(*mut c_void, *const c_char) -> c_int>,
+        xconflict: Option<
+            unsafe extern "C" fn(*mut c_void, c_int, *mut sqlite3_changeset_iter) -> c_int,
+        >,
+        pctx: *mut c_void,
+    ) -> c_int;
+    fn sqlite3changeset_op(
+        piter: *mut sqlite3_changeset_iter,
+        pztab: *mut *const c_char,
+        pncol: *mut c_int,
+        pop: *mut c_int,
+        pbindirect: *mut c_int,
+    ) -> c_int;
+    fn sqlite3changeset_old(
+        piter: *mut sqlite3_changeset_iter,
+        ival: c_int,
+        ppvalue: *mut *mut sqlite3_value,
+    ) -> c_int;
+    fn sqlite3changeset_new(
+        piter: *mut sqlite3_changeset_iter,
+        ival: c_int,
+        ppvalue: *mut *mut sqlite3_value,
+    ) -> c_int;
+    fn sqlite3changeset_conflict(
+        piter: *mut sqlite3_changeset_iter,
+        ival: c_int,
+        ppvalue: *mut *mut sqlite3_value,
+    ) -> c_int;
+}
+
+/// Wrapper of C [`sqlite3_session`] to record changes of a database.
+///
+/// `Session` dereferences to [`Connection`] ; the changes made through the `Session` to the
+/// attached tables are recorded.
+///
+/// This struct is enabled by feature "session".
+///
+/// [`sqlite3_session`]: https://www.sqlite.org/session/session.html
+/// [`Connection`]: struct.Connection.html
+pub struct Session<'a> {
+    con: &'a mut Connection,
+    raw: *mut sqlite3_session,
+}
+
+impl Drop for Session<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { sqlite3session_delete(self.raw) };
+    }
+}
+
+impl Deref for Session<'_> {
+    type Target = Connection;
+
+    #[inline]
+    fn deref(&self) -> &Connection {
+        self.con
+    }
+}
+
+impl DerefMut for Session<'_> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.con
+    }
+}
+
+impl<'a> Session<'a> {
+    /// Wrapper of C function [`sqlite3session_create`] .
+    ///
+    /// `db_name` is the schema name to record, for example, "main" .
+    ///
+    /// [`sqlite3session_create`]: https://www.sqlite.org/session/sqlite3session_create.html
+    pub fn new(con: &'a mut Connection, db_name: &str) -> Result<Self, Error> {
+        let db_name = CString::new(db_name).map_err(|_| Error::new(SQLITE_MISUSE))?;
+
+        let mut raw = null_mut();
+        let code = unsafe { sqlite3session_create(con.raw(), db_name.as_ptr(), &mut raw) };
+        match Error::new(code) {
+            e if e == Error::OK => Ok(Self { con, raw }),
+            e => Err(e),
+        }
+    }
+
+    /// Wrapper of C function [`sqlite3session_attach`] to start recording the changes of
+    /// `table` .
+    ///
+    /// [`sqlite3session_attach`]: https://www.sqlite.org/session/sqlite3session_attach.html
+    pub fn attach(&mut self, table: &str) -> Result<(), Error> {
+        let table = CString::new(table).map_err(|_| Error::new(SQLITE_MISUSE))?;
+
+        let code = unsafe { sqlite3session_attach(self.raw, table.as_ptr()) };
+        match Error::new(code) {
+            e if e == Error::OK => Ok(()),
+            e => Err(e),
+        }
+    }
+
+    /// Wrapper of C function [`sqlite3session_changeset`] .
+    ///
+    /// [`sqlite3session_changeset`]: https://www.sqlite.org/session/sqlite3session_changeset.html
+    #[inline]
+    pub fn changeset(&mut self) -> Result<Vec<u8>, Error> {
+        unsafe { self.collect(sqlite3session_changeset) }
+    }
+
+    /// Wrapper of C function [`sqlite3session_patchset`] .
+    ///
+    /// [`sqlite3session_patchset`]: https://www.sqlite.org/session/sqlite3session_patchset.html
+    #[inline]
+    pub fn patchset(&mut self) -> Result<Vec<u8>, Error> {
+        unsafe { self.collect(sqlite3session_patchset) }
+    }
+
+    unsafe fn collect(
+        &mut self,
+        f: unsafe extern "C" fn(*mut sqlite3_session, *mut c_int, *mut *mut c_void) -> c_int,
+    ) -> Result<Vec<u8>, Error> {
+        let mut len = 0;
+        let mut ptr = null_mut();
+
+        let code = f(self.raw, &mut len, &mut ptr);
+        let ret = match Error::new(code) {
+            e if e == Error::OK && len == 0 => Ok(Vec::new()),
+            e if e == Error::OK => {
+                Ok(core::slice::from_raw_parts(ptr as *const u8, len as usize).to_vec())
+            }
+            e => Err(e),
+        };
+        sqlite3_free(ptr);
+        ret
+    }
+}
+
+/// Type of the conflict passed to the conflict handler of [`Connection::apply_changeset`] .
+///
+/// [`Connection::apply_changeset`]: struct.Connection.html#method.apply_changeset
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConflictType {
+    /// "SQLITE_CHANGESET_DATA"; the row exists but the current values do not match the old
+    /// values of DELETE or UPDATE.
+    Data,
+    /// "SQLITE_CHANGESET_NOTFOUND"; the row to DELETE or UPDATE does not exist.
+    NotFound,
+    /// "SQLITE_CHANGESET_CONFLICT"; the primary key of INSERT already exists.
+    Conflict,
+    /// "SQLITE_CHANGESET_CONSTRAINT"; the change violates a constraint.
+    Constraint,
+    /// "SQLITE_CHANGESET_FOREIGN_KEY"; the changeset leaves foreign key violations.
+    ForeignKey,
+}
+
+/// Return value of the conflict handler of [`Connection::apply_changeset`] .
+///
+/// [`Connection::apply_changeset`]: struct.Connection.html#method.apply_changeset
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConflictResolution {
+    /// "SQLITE_CHANGESET_OMIT"; skips the change.
+    Omit,
+    /// "SQLITE_CHANGESET_REPLACE"; overwrites the row with the change.
+    ///
+    /// This is valid only for [`ConflictType::Data`] and [`ConflictType::Conflict`] .
+    ///
+    /// [`ConflictType::Data`]: enum.ConflictType.html#variant.Data
+    /// [`ConflictType::Conflict`]: enum.ConflictType.html#variant.Conflict
+    Replace,
+    /// "SQLITE_CHANGESET_ABORT"; rolls back all the changes and makes
+    /// [`Connection::apply_changeset`] fail with `SQLITE_ABORT` .
+    ///
+    /// [`Connection::apply_changeset`]: struct.Connection.html#method.apply_changeset
+    Abort,
+}
+
+/// Operation of a change in a changeset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChangeOp {
+    /// INSERT
+    Insert,
+    /// UPDATE
+    Update,
+    /// DELETE
+    Delete,
+}
+
+/// A conflicting change passed to the conflict handler of [`Connection::apply_changeset`] .
+///
+/// [`Connection::apply_changeset`]: struct.Connection.html#method.apply_changeset
+pub struct Conflict<'a> {
+    kind: ConflictType,
+    table: &'a str,
+    column_count: usize,
+    op: ChangeOp,
+    iter: *mut sqlite3_changeset_iter,
+}
+
+impl<'a> Conflict<'a> {
+    /// Returns the type of the conflict.
+    #[inline]
+    pub fn kind(&self) -> ConflictType {
+        self.kind
+    }
+
+    /// Returns the name of the table of the change.
+    #[inline]
+    pub fn table(&self) -> &'a str {
+        self.table
+    }
+
+    /// Returns the number of the columns of the table.
+    #[inline]
+    pub fn column_count(&self) -> usize {
+        self.column_count
+    }
+
+    /// Returns the operation of the change.
+    #[inline]
+    pub fn op(&self) -> ChangeOp {
+        self.op
+    }
+
+    /// Wrapper of C function [`sqlite3changeset_old`] .
+    ///
+    /// Returns the original value of the column `index` of UPDATE or DELETE, or `None` if the
+    /// column is not modified by UPDATE.
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// [`sqlite3changeset_old`]: https://www.sqlite.org/session/sqlite3changeset_old.html
+    #[inline]
+    pub fn old_value(&self, index: usize) -> Result<Option<ValueRef<'a>>, Error> {
+        unsafe { self.value(sqlite3changeset_old, index) }
+    }
+
+    /// Wrapper of C function [`sqlite3changeset_new`] .
+    ///
+    /// Returns the new value of the column `index` of UPDATE or INSERT, or `None` if the column
+    /// is not modified by UPDATE.
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// [`sqlite3changeset_new`]: https://www.sqlite.org/session/sqlite3changeset_new.html
+    #[inline]
+    pub fn new_value(&self, index: usize) -> Result<Option<ValueRef<'a>>, Error> {
+        unsafe { self.value(sqlite3changeset_new, index) }
+    }
+
+    /// Wrapper of C function [`sqlite3changeset_conflict`] .
+    ///
+    /// Returns the current value of the column `index` in the database, which is available only
+    /// for [`ConflictType::Data`] and [`ConflictType::Conflict`] .
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// [`sqlite3changeset_conflict`]:
+    /// https://www.sqlite.org/session/sqlite3changeset_conflict.html
+    /// [`ConflictType::Data`]: enum.ConflictType.html#variant.Data
+    /// [`ConflictType::Conflict`]: enum.ConflictType.html#variant.Conflict
+    #[inline]
+    pub fn conflicting_value(&self, index: usize) -> Result<Option<ValueRef<'a>>, Error> {
+        unsafe { self.value(sqlite3changeset_conflict, index) }
+    }
+
+    unsafe fn value(
+        &self,
+        f: unsafe extern "C" fn(
+            *mut sqlite3_changeset_iter,
+            c_int,
+            *mut *mut sqlite3_value,
+        ) -> c_int,
+        index: usize,
+    ) -> Result<Option<ValueRef<'a>>, Error> {
+        let index = c_int::try_from(index).map_err(|_| Error::new(SQLITE_RANGE))?;
+
+        let mut raw = null_mut();
+        match Error::new(f(self.iter, index, &mut raw)) {
+            e if e == Error::OK && raw.is_null() => Ok(None),
+            e if e == Error::OK => Ok(Some(value_from_raw(raw))),
+            e => Err(e),
+        }
+    }
+}
+
+impl Connection {
+    /// Wrapper of C function [`sqlite3changeset_apply`] .
+    ///
+    /// Applies the changeset or the patchset `data` to the "main" database. `conflict` is called
+    /// for each conflicting change and decides how to resolve it.
+    ///
+    /// This method is enabled by feature "session".
+    ///
+    /// [`sqlite3changeset_apply`]: https://www.sqlite.org/session/sqlite3changeset_apply.html
+    pub fn apply_changeset<F>(&mut self, data: &[u8], mut conflict: F) -> Result<(), Error>
+    where
+        F: FnMut(Conflict<'_>) -> ConflictResolution,
+    {
+        unsafe extern "C" fn x_conflict<F>(
+            ctx: *mut c_void,
+            kind: c_int,
+            iter: *mut sqlite3_changeset_iter,
+        ) -> c_int
+        where
+            F: FnMut(Conflict<'_>) -> ConflictResolution,
+        {
+            let kind = match kind {
+                SQLITE_CHANGESET_DATA => ConflictType::Data,
+                SQLITE_CHANGESET_NOTFOUND => ConflictType::NotFound,
+                SQLITE_CHANGESET_CONFLICT => ConflictType::Conflict,
+                SQLITE_CHANGESET_CONSTRAINT => ConflictType::Constraint,
+                SQLITE_CHANGESET_FOREIGN_KEY => ConflictType::ForeignKey,
+                _ => return SQLITE_CHANGESET_ABORT,
+            };
+
+            let mut table = core::ptr::null();
+            let mut column_count = 0;
+            let mut op = 0;
+            let mut indirect = 0;
+            let code =
+                sqlite3changeset_op(iter, &mut table, &mut column_count, &mut op, &mut indirect);
+            if Error::new(code) != Error::OK {
+                return SQLITE_CHANGESET_ABORT;
+            }
+
+            let op = match op {
+                SQLITE_INSERT => ChangeOp::Insert,
+                SQLITE_UPDATE => ChangeOp::Update,
+                SQLITE_DELETE => ChangeOp::Delete,
+                _ => return SQLITE_CHANGESET_ABORT,
+            };
+            let table = match CStr::from_ptr(table).to_str() {
+                Ok(s) => s,
+                Err(_) => return SQLITE_CHANGESET_ABORT,
+            };
+
+            let conflict = Conflict {
+                kind,
+                table,
+                column_count: column_count as usize,
+                op,
+                iter,
+            };
+            // Unwinding across the FFI boundary is not allowed; a panic aborts the changeset.
+            let f = &mut *(ctx as *mut F);
+            match std::panic::catch_unwind(AssertUnwindSafe(|| f(conflict))) {
+                Ok(ConflictResolution::Omit) => SQLITE_CHANGESET_OMIT,
+                Ok(ConflictResolution::Replace) => SQLITE_CHANGESET_REPLACE,
+                Ok(ConflictResolution::Abort) | Err(_) => SQLITE_CHANGESET_ABORT,
+            }
+        }
+
+        let len = c_int::try_from(data.len()).map_err(|_| Error::new(SQLITE_TOOBIG))?;
+        // sqlite3changeset_apply() does not modify the changeset.
+        let ptr = data.as_ptr() as *mut c_void;
+        let ctx = &mut conflict as *mut F as *mut c_void;
+
+        let code = unsafe {
+            sqlite3changeset_apply(self.raw(), len, ptr, None, Some(x_conflict::<F>), ctx)
+        };
+        match Error::new(code) {
+            e if e == Error::OK => Ok(()),
+            e => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // https://www.sqlite.org/rescode.html#abort
+    const SQLITE_ABORT: c_int = 4;
+
+    const CREATE: &str = r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "name" TEXT)"#;
+
+    fn names(con: &mut Connection) -> Vec<(i64, String)> {
+        con.stmt_once(r#"SELECT "id", "name" FROM "foo" ORDER BY "id""#)
+            .unwrap()
+            .query_as()
+            .unwrap()
+    }
+
+    #[test]
+    fn apply() {
+        let mut src = Connection::open_memory_db().unwrap();
+        let mut dest = Connection::open_memory_db().unwrap();
+        src.execute_batch(CREATE).unwrap();
+        dest.execute_batch(CREATE).unwrap();
+
+        let changeset = {
+            let mut session = Session::new(&mut src, "main").unwrap();
+            session.attach("foo").unwrap();
+            session
+                .execute_batch(
+                    r#"INSERT INTO "foo" VALUES (1, 'a'), (2, 'b');
+                       UPDATE "foo" SET "name" = 'B' WHERE "id" = 2;"#,
+                )
+                .unwrap();
+            assert!(!session.patchset().unwrap().is_empty());
+            session.changeset().unwrap()
+        };
+
+        dest.apply_changeset(&changeset, |_| ConflictResolution::Abort)
+            .unwrap();
+        let expected = vec![(1, "a".to_string()), (2, "B".to_string())];
+        assert_eq!(expected, names(&mut dest));
+
+        // Conflicting update
+        dest.execute_batch(r#"UPDATE "foo" SET "name" = 'x' WHERE "id" = 1"#)
+            .unwrap();
+        let changeset = {
+            let mut session = Session::new(&mut src, "main").unwrap();
+            session.attach("foo").unwrap();
+            session
+                .execute_batch(r#"UPDATE "foo" SET "name" = 'y' WHERE "id" = 1"#)
+                .unwrap();
+            session.changeset().unwrap()
+        };
+
+        let e = dest
+            .apply_changeset(&changeset, |_| ConflictResolution::Abort)
+            .unwrap_err();
+        assert_eq!(SQLITE_ABORT, e.code());
+        dest.apply_changeset(&changeset, |_| ConflictResolution::Omit)
+            .unwrap();
+        assert_eq!("x", names(&mut dest)[0].1);
+
+        let mut conflicts = 0;
+        dest.apply_changeset(&changeset, |c| {
+            conflicts += 1;
+            assert_eq!(ConflictType::Data, c.kind());
+            assert_eq!(ChangeOp::Update, c.op());
+            assert_eq!("foo", c.table());
+            assert_eq!(2, c.column_count());
+            assert_eq!(Ok(Some(ValueRef::Integer(1))), c.old_value(0));
+            assert_eq!(Ok(Some(ValueRef::Text(b"a"))), c.old_value(1));
+            assert_eq!(Ok(None), c.new_value(0));
+            assert_eq!(Ok(Some(ValueRef::Text(b"y"))), c.new_value(1));
+            assert_eq!(Ok(Some(ValueRef::Text(b"x"))), c.conflicting_value(1));
+            ConflictResolution::Replace
+        })
+        .unwrap();
+        assert_eq!(1, conflicts);
+        assert_eq!("y", names(&mut dest)[0].1);
+
+        // A panic in the callback aborts.
+        dest.execute_batch(r#"UPDATE "foo" SET "name" = 'x' WHERE "id" = 1"#)
+            .unwrap();
+        let e = dest
+            .apply_changeset(&changeset, |_| panic!("conflict"))
+            .unwrap_err();
+        assert_eq!(SQLITE_ABORT, e.code());
+        assert_eq!("x", names(&mut dest)[0].1);
+    }
+}
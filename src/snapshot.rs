@@ -0,0 +1,254 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{compile_option_used, sqlite3, Connection, Error, SQLITE_ERROR, SQLITE_MISUSE};
+use core::cmp::Ordering;
+use core::ptr::null_mut;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+
+mod libsqlite3 {
+    #[allow(non_camel_case_types)]
+    pub enum sqlite3_snapshot {}
+}
+use libsqlite3::*;
+
+#[link(name = "sqlite3")]
+extern "C" {
+    fn sqlite3_snapshot_get(
+        db: *mut sqlite3,
+        zschema: *const c_char,
+        ppsnapshot: *mut *mut sqlite3_snapshot,
+    ) -> c_int;
+    fn sqlite3_snapshot_open(
+        db: *mut sqlite3,
+        zschema: *const c_char,
+        psnapshot: *mut sqlite3_snapshot,
+    ) -> c_int;
+    fn sqlite3_snapshot_free(psnapshot: *mut sqlite3_snapshot);
+    fn sqlite3_snapshot_cmp(p1: *mut sqlite3_snapshot, p2: *mut sqlite3_snapshot) -> c_int;
+}
+
+/// Returns `SQLITE_ERROR` unless the runtime SQLite was compiled with `SQLITE_ENABLE_SNAPSHOT` .
+fn check_snapshot() -> Result<(), Error> {
+    if compile_option_used("ENABLE_SNAPSHOT") {
+        Ok(())
+    } else {
+        let message = "the runtime SQLite was not compiled with SQLITE_ENABLE_SNAPSHOT";
+        Err(Error::with_message(SQLITE_ERROR, message))
+    }
+}
+
+/// Wrapper of C [`sqlite3_snapshot`] , which identifies a version of a WAL mode database.
+///
+/// Instances are ordered by the age; i.e. an older snapshot is less than a newer one. The
+/// comparison is meaningful only between the snapshots of the same database file.
+///
+/// This type is enabled by feature "snapshot" , which requires libsqlite3 built with option
+/// `SQLITE_ENABLE_SNAPSHOT` .
+///
+/// [`sqlite3_snapshot`]: https://www.sqlite.org/c3ref/snapshot.html
+pub struct Snapshot {
+    raw: *mut sqlite3_snapshot,
+}
+
+// Safety:
+// `sqlite3_snapshot` is a plain memory block that libsqlite3 only reads; it is not bound to the
+// connection or the thread that created it.
+unsafe impl Send for Snapshot {}
+
+impl Drop for Snapshot {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { sqlite3_snapshot_free(self.raw) };
+    }
+}
+
+impl core::fmt::Debug for Snapshot {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Snapshot").field("raw", &self.raw).finish()
+    }
+}
+
+impl PartialEq for Snapshot {
+    /// Wrapper of C function [`sqlite3_snapshot_cmp`] .
+    ///
+    /// [`sqlite3_snapshot_cmp`]: https://www.sqlite.org/c3ref/snapshot_cmp.html
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Snapshot {
+    /// Wrapper of C function [`sqlite3_snapshot_cmp`] .
+    ///
+    /// [`sqlite3_snapshot_cmp`]: https://www.sqlite.org/c3ref/snapshot_cmp.html
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        let c = unsafe { sqlite3_snapshot_cmp(self.raw, other.raw) };
+        Some(c.cmp(&0))
+    }
+}
+
+impl Connection {
+    /// Wrapper of C function [`sqlite3_snapshot_get`] .
+    ///
+    /// Returns the snapshot of database `schema` (e.g. "main") that the current read
+    /// transaction of `self` observes.
+    ///
+    /// `self` must be in a read transaction that has read the database (e.g. "BEGIN" and then a
+    /// SELECT), and the database must be in WAL mode.
+    ///
+    /// Returns `SQLITE_ERROR` if the runtime SQLite was not compiled with
+    /// `SQLITE_ENABLE_SNAPSHOT` or if the conditions above are not satisfied, or `SQLITE_MISUSE`
+    /// if `schema` contains NUL.
+    ///
+    /// This method is enabled by feature "snapshot" .
+    ///
+    /// [`sqlite3_snapshot_get`]: https://www.sqlite.org/c3ref/snapshot_get.html
+    pub fn snapshot(&mut self, schema: &str) -> Result<Snapshot, Error> {
+        check_snapshot()?;
+        let schema = CString::new(schema).map_err(|_| Error::new(SQLITE_MISUSE))?;
+
+        let mut raw = null_mut();
+        let code = unsafe { sqlite3_snapshot_get(self.raw(), schema.as_ptr(), &mut raw) };
+        match Error::new(code) {
+            e if e == Error::OK => Ok(Snapshot { raw }),
+            e => Err(e),
+        }
+    }
+
+    /// Wrapper of C function [`sqlite3_snapshot_open`] .
+    ///
+    /// Makes the current read transaction of `self` observe `snapshot` of database `schema` .
+    /// Call this method after "BEGIN" and before any SELECT; `self` must not be reading the
+    /// database yet.
+    ///
+    /// `snapshot` may come from another connection to the same database file.
+    ///
+    /// Returns `SQLITE_ERROR` if the runtime SQLite was not compiled with
+    /// `SQLITE_ENABLE_SNAPSHOT` or if `self` is not in a transaction, `SQLITE_ERROR_SNAPSHOT`
+    /// if `snapshot` is no longer available (e.g. the WAL file has been checkpointed and
+    /// restarted since), or `SQLITE_MISUSE` if `schema` contains NUL.
+    ///
+    /// This method is enabled by feature "snapshot" .
+    ///
+    /// [`sqlite3_snapshot_open`]: https://www.sqlite.org/c3ref/snapshot_open.html
+    pub fn open_snapshot(&mut self, schema: &str, snapshot: &Snapshot) -> Result<(), Error> {
+        check_snapshot()?;
+        let schema = CString::new(schema).map_err(|_| Error::new(SQLITE_MISUSE))?;
+
+        let code = unsafe { sqlite3_snapshot_open(self.raw(), schema.as_ptr(), snapshot.raw) };
+        match Error::new(code) {
+            e if e == Error::OK => Ok(()),
+            e => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::TryFrom;
+    use tempfile::tempdir;
+
+    fn count(con: &mut Connection) -> i64 {
+        con.query_one::<(i64,)>(r#"SELECT count(*) FROM "foo""#, &[])
+            .unwrap()
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn snapshot() {
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("test_sqlite");
+
+        let mut con1 = Connection::try_from(path.as_path()).unwrap();
+        con1.execute_batch(
+            r#"PRAGMA journal_mode = WAL;
+               CREATE TABLE "foo" ("value" INTEGER);
+               INSERT INTO "foo" VALUES (1);"#,
+        )
+        .unwrap();
+        let mut con2 = Connection::try_from(path.as_path()).unwrap();
+        let mut con3 = Connection::try_from(path.as_path()).unwrap();
+
+        con1.execute_batch("BEGIN").unwrap();
+        assert_eq!(1, count(&mut con1));
+        let old = con1.snapshot("main").unwrap();
+        con1.execute_batch("COMMIT").unwrap();
+
+        con2.execute_batch(r#"INSERT INTO "foo" VALUES (2)"#)
+            .unwrap();
+
+        // The snapshot is still open on another connection.
+        con3.execute_batch("BEGIN").unwrap();
+        con3.open_snapshot("main", &old).unwrap();
+        assert_eq!(1, count(&mut con3));
+        con3.execute_batch("COMMIT").unwrap();
+
+        // A fresh read sees the new row.
+        con1.execute_batch("BEGIN").unwrap();
+        assert_eq!(2, count(&mut con1));
+        let new = con1.snapshot("main").unwrap();
+        con1.execute_batch("COMMIT").unwrap();
+
+        assert!(old < new);
+        assert!(old == old);
+
+        // Not in a read transaction.
+        assert_eq!(SQLITE_ERROR, con1.snapshot("main").unwrap_err().code());
+    }
+}
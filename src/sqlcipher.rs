@@ -0,0 +1,210 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::connection::Init;
+use crate::{sqlite3, Connection, Error, SQLITE_MISUSE, SQLITE_TOOBIG};
+use core::convert::TryFrom;
+use std::os::raw::{c_char, c_int, c_void};
+use std::sync::Arc;
+
+extern "C" {
+    fn sqlite3_key_v2(
+        db: *mut sqlite3,
+        zdbname: *const c_char,
+        pkey: *const c_void,
+        nkey: c_int,
+    ) -> c_int;
+    fn sqlite3_rekey_v2(
+        db: *mut sqlite3,
+        zdbname: *const c_char,
+        pkey: *const c_void,
+        nkey: c_int,
+    ) -> c_int;
+}
+
+/// Key material held by [`OpenOptions::key`] , which is overwritten with zeros on drop.
+///
+/// [`OpenOptions::key`]: struct.OpenOptions.html#method.key
+struct Key(Vec<u8>);
+
+impl Drop for Key {
+    fn drop(&mut self) {
+        zeroize(&mut self.0);
+    }
+}
+
+/// Overwrites `buf` with zeros, which the compiler does not optimize away.
+fn zeroize(buf: &mut [u8]) {
+    for b in buf.iter_mut() {
+        unsafe { core::ptr::write_volatile(b, 0) };
+    }
+    core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+}
+
+/// Returns the closure for [`OpenOptions::key`] to call [`Connection::set_key`] , which holds a
+/// copy of `key` .
+///
+/// [`OpenOptions::key`]: struct.OpenOptions.html#method.key
+/// [`Connection::set_key`]: struct.Connection.html#method.set_key
+pub(crate) fn key_init(key: &[u8]) -> Arc<Init> {
+    let key = Key(key.to_vec());
+    Arc::new(move |con: &mut Connection| con.set_key(&key.0))
+}
+
+/// Calls `f` (i.e. C function `sqlite3_key_v2` or `sqlite3_rekey_v2` ) for the "main" database.
+fn call_key(
+    db: *mut sqlite3,
+    key: &[u8],
+    f: unsafe extern "C" fn(*mut sqlite3, *const c_char, *const c_void, c_int) -> c_int,
+) -> Result<(), Error> {
+    if key.is_empty() {
+        let message = "the key is empty; use sqlcipher_export() to decrypt the database";
+        return Err(Error::with_message(SQLITE_MISUSE, message));
+    }
+    let len = c_int::try_from(key.len()).map_err(|_| Error::new(SQLITE_TOOBIG))?;
+
+    let code = unsafe { f(db, core::ptr::null(), key.as_ptr() as *const c_void, len) };
+    match Error::new(code) {
+        e if e == Error::OK => Ok(()),
+        e => Err(e),
+    }
+}
+
+impl Connection {
+    /// Wrapper of C function [`sqlite3_key_v2`] of SQLCipher.
+    ///
+    /// Sets the key to encrypt and decrypt the "main" database. This must be called before any
+    /// other statement accesses the database; [`OpenOptions::key`] does so. A wrong key is not
+    /// detected by this method but the first access to the database fails with `SQLITE_NOTADB`
+    /// .
+    ///
+    /// An empty `key` , which means no encryption to SQLCipher, is rejected with
+    /// `SQLITE_MISUSE` so that a database is not left in plaintext by mistake. To decrypt an
+    /// encrypted database, attach a plaintext one and call `sqlcipher_export()` .
+    ///
+    /// This method does not keep `key` ; SQLCipher copies it.
+    ///
+    /// This method is available if feature "sqlcipher" is enabled, which requires libsqlite3 to
+    /// be built from SQLCipher.
+    ///
+    /// [`sqlite3_key_v2`]: https://www.zetetic.net/sqlcipher/sqlcipher-api/#sqlite3_key
+    /// [`OpenOptions::key`]: struct.OpenOptions.html#method.key
+    #[inline]
+    pub fn set_key(&mut self, key: &[u8]) -> Result<(), Error> {
+        call_key(self.raw(), key, sqlite3_key_v2)
+    }
+
+    /// Wrapper of C function [`sqlite3_rekey_v2`] of SQLCipher.
+    ///
+    /// Re-encrypts the "main" database with `new_key` . The current key must have been set by
+    /// [`set_key`] . An empty `new_key` is rejected with `SQLITE_MISUSE` as [`set_key`] does.
+    ///
+    /// This method is available if feature "sqlcipher" is enabled.
+    ///
+    /// [`sqlite3_rekey_v2`]: https://www.zetetic.net/sqlcipher/sqlcipher-api/#sqlite3_rekey
+    /// [`set_key`]: #method.set_key
+    #[inline]
+    pub fn rekey(&mut self, new_key: &[u8]) -> Result<(), Error> {
+        call_key(self.raw(), new_key, sqlite3_rekey_v2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OpenOptions;
+    use tempfile::tempdir;
+
+    const SQLITE_NOTADB: i32 = 26;
+    const SELECT: &str = r#"SELECT count(*) FROM "foo""#;
+
+    #[test]
+    fn key() {
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("test_sqlite");
+        {
+            let mut con = OpenOptions::new().key(b"secret").open(&path).unwrap();
+            con.execute_batch(r#"CREATE TABLE "foo" ("value" INTEGER)"#)
+                .unwrap();
+        }
+
+        // Wrong key, and no key.
+        let mut con = OpenOptions::new().key(b"wrong").open(&path).unwrap();
+        let e = con.execute_batch(SELECT).unwrap_err();
+        assert_eq!(SQLITE_NOTADB, e.code());
+        let mut con = OpenOptions::new().open(&path).unwrap();
+        let e = con.execute_batch(SELECT).unwrap_err();
+        assert_eq!(SQLITE_NOTADB, e.code());
+
+        let mut con = OpenOptions::new().key(b"secret").open(&path).unwrap();
+        assert!(con.execute_batch(SELECT).is_ok());
+        let mut clone = con.try_clone().unwrap();
+        assert!(clone.execute_batch(SELECT).is_ok());
+
+        assert_eq!(SQLITE_MISUSE, con.rekey(b"").unwrap_err().code());
+        con.rekey(b"new secret").unwrap();
+        drop(clone);
+        drop(con);
+
+        let mut con = OpenOptions::new().open(&path).unwrap();
+        con.set_key(b"new secret").unwrap();
+        assert!(con.execute_batch(SELECT).is_ok());
+    }
+
+    #[test]
+    fn zeroize_key() {
+        let mut buf = b"secret".to_vec();
+        zeroize(&mut buf);
+        assert_eq!(vec![0; 6], buf);
+    }
+}
@@ -0,0 +1,249 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{Error, FromSql, FromSqlError, Stmt, ToSql, ToSqlOutput, ValueRef, SQLITE_TOOBIG};
+use core::convert::TryFrom;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Returns the unix time of `t` in `unit` nanoseconds, rounded down toward the past.
+fn epoch(t: SystemTime, unit: u32) -> Result<i64, Error> {
+    let unit = u128::from(unit);
+    let n = match t.duration_since(UNIX_EPOCH) {
+        Ok(d) => i128::try_from(d.as_nanos() / unit).ok(),
+        Err(e) => {
+            let nanos = e.duration().as_nanos();
+            i128::try_from(nanos.div_ceil(unit)).ok().map(|n| -n)
+        }
+    };
+
+    n.and_then(|n| i64::try_from(n).ok()).ok_or_else(|| {
+        let message = format!("{:?} is out of range of i64", t);
+        Error::with_message(SQLITE_TOOBIG, message)
+    })
+}
+
+/// Returns the time `n` milliseconds after the unix epoch.
+fn from_epoch_millis(n: i64) -> Result<SystemTime, FromSqlError> {
+    let d = Duration::from_millis(n.unsigned_abs());
+    let t = if 0 <= n {
+        UNIX_EPOCH.checked_add(d)
+    } else {
+        UNIX_EPOCH.checked_sub(d)
+    };
+    t.ok_or(FromSqlError::OutOfRange(n))
+}
+
+impl ToSql for SystemTime {
+    /// Binds as INTEGER of unix time in milliseconds.
+    ///
+    /// The time before the unix epoch is bound as a negative number. The sub-millisecond part is
+    /// truncated toward the past; e.g. 0.5 milliseconds before the epoch is bound as -1.
+    ///
+    /// Returns `SQLITE_TOOBIG` if the milliseconds is out of range of i64.
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        let millis = epoch(*self, 1_000_000)?;
+        Ok(ToSqlOutput::Borrowed(ValueRef::Integer(millis)))
+    }
+}
+
+impl FromSql<'_> for SystemTime {
+    /// Accepts INTEGER of unix time in milliseconds, which may be negative.
+    #[inline]
+    fn column_result(value: ValueRef<'_>) -> Result<Self, FromSqlError> {
+        match value {
+            ValueRef::Integer(i) => from_epoch_millis(i),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl ToSql for Duration {
+    /// Binds as INTEGER of microseconds.
+    ///
+    /// The sub-microsecond part is truncated.
+    ///
+    /// Returns `SQLITE_TOOBIG` if the microseconds is out of range of i64.
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        match i64::try_from(self.as_micros()) {
+            Ok(micros) => Ok(ToSqlOutput::Borrowed(ValueRef::Integer(micros))),
+            Err(_) => {
+                let message = format!("{:?} is out of range of i64 microseconds", self);
+                Err(Error::with_message(SQLITE_TOOBIG, message))
+            }
+        }
+    }
+}
+
+impl FromSql<'_> for Duration {
+    /// Accepts non-negative INTEGER of microseconds.
+    #[inline]
+    fn column_result(value: ValueRef<'_>) -> Result<Self, FromSqlError> {
+        match value {
+            ValueRef::Integer(i) => match u64::try_from(i) {
+                Ok(micros) => Ok(Duration::from_micros(micros)),
+                Err(_) => Err(FromSqlError::OutOfRange(i)),
+            },
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl Stmt {
+    /// Binds `t` as INTEGER of unix time in seconds to the parameter `index` .
+    ///
+    /// The time before the unix epoch is bound as a negative number. The sub-second part is
+    /// truncated toward the past.
+    ///
+    /// Note that `index` starts at 1, not 0.
+    ///
+    /// Returns `SQLITE_TOOBIG` if the seconds is out of range of i64.
+    #[inline]
+    pub fn bind_epoch_secs(&mut self, index: usize, t: SystemTime) -> Result<(), Error> {
+        let secs = epoch(t, 1_000_000_000)?;
+        self.bind_int(index, secs)
+    }
+
+    /// Binds `t` as INTEGER of unix time in milliseconds to the parameter `index` .
+    ///
+    /// This is the same as binding `t` with [`bind`] .
+    ///
+    /// Note that `index` starts at 1, not 0.
+    ///
+    /// [`bind`]: #method.bind
+    #[inline]
+    pub fn bind_epoch_millis(&mut self, index: usize, t: SystemTime) -> Result<(), Error> {
+        let millis = epoch(t, 1_000_000)?;
+        self.bind_int(index, millis)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Connection;
+
+    fn round_trip<T>(con: &mut Connection, val: &T) -> (i64, T)
+    where
+        T: ToSql + for<'a> FromSql<'a>,
+    {
+        let mut stmt = con.stmt_once("SELECT ?1, ?1").unwrap();
+        stmt.bind(1, val).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        (stmt.get(0).unwrap(), stmt.get(1).unwrap())
+    }
+
+    #[test]
+    fn system_time() {
+        let mut con = Connection::open_memory_db().unwrap();
+
+        let t = UNIX_EPOCH + Duration::from_millis(1_600_000_000_123);
+        assert_eq!((1_600_000_000_123, t), round_trip(&mut con, &t));
+
+        // Year 33658
+        let t = UNIX_EPOCH + Duration::from_secs(1_000_000_000_000);
+        assert_eq!((1_000_000_000_000_000, t), round_trip(&mut con, &t));
+
+        let t = UNIX_EPOCH - Duration::from_millis(1_500);
+        assert_eq!((-1_500, t), round_trip(&mut con, &t));
+
+        // Sub-millisecond is truncated toward the past.
+        let t = UNIX_EPOCH + Duration::from_micros(2_999);
+        let expected = UNIX_EPOCH + Duration::from_millis(2);
+        assert_eq!((2, expected), round_trip(&mut con, &t));
+        let t = UNIX_EPOCH - Duration::from_micros(500);
+        let expected = UNIX_EPOCH - Duration::from_millis(1);
+        assert_eq!((-1, expected), round_trip(&mut con, &t));
+
+        // Explicit units
+        let t = UNIX_EPOCH + Duration::from_millis(1_600_000_000_999);
+        let mut stmt = con.stmt_once("SELECT ?1, ?2").unwrap();
+        stmt.bind_epoch_secs(1, t).unwrap();
+        stmt.bind_epoch_millis(2, t).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(1_600_000_000), stmt.get::<i64>(0));
+        assert_eq!(Ok(1_600_000_000_999), stmt.get::<i64>(1));
+
+        // REAL and TEXT are rejected.
+        let mut stmt = con.stmt_once("SELECT 1.5, '1970-01-01'").unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        assert!(stmt.get::<SystemTime>(0).is_err());
+        assert!(stmt.get::<SystemTime>(1).is_err());
+    }
+
+    #[test]
+    fn duration() {
+        let mut con = Connection::open_memory_db().unwrap();
+
+        let d = Duration::from_micros(1_234_567);
+        assert_eq!((1_234_567, d), round_trip(&mut con, &d));
+
+        // Sub-microsecond is truncated.
+        let d = Duration::from_nanos(1_999);
+        assert_eq!((1, Duration::from_micros(1)), round_trip(&mut con, &d));
+        let d = Duration::from_nanos(999);
+        assert_eq!((0, Duration::ZERO), round_trip(&mut con, &d));
+
+        // Overflow
+        let e = Duration::MAX.to_sql().unwrap_err();
+        assert_eq!(SQLITE_TOOBIG, e.code());
+
+        // Negative, REAL, and TEXT are rejected.
+        let mut stmt = con.stmt_once("SELECT -1, 1.5, '1'").unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        assert!(stmt.get::<Duration>(0).is_err());
+        assert!(stmt.get::<Duration>(1).is_err());
+        assert!(stmt.get::<Duration>(2).is_err());
+    }
+}
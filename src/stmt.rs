@@ -51,25 +51,155 @@
 // ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
 // POSSIBILITY OF SUCH DAMAGE.
 
+use crate::optimize::AutoOptimize;
+use crate::stmt_stats::Stats;
 use crate::{
-    sqlite3_bind_blob, sqlite3_bind_int64, sqlite3_bind_null, sqlite3_clear_bindings,
-    sqlite3_column_blob, sqlite3_column_bytes, sqlite3_column_count, sqlite3_column_int64,
-    sqlite3_column_type, sqlite3_finalize, sqlite3_reset, sqlite3_step, sqlite3_stmt, Error,
-    SQLITE_BLOB, SQLITE_INTEGER, SQLITE_NULL, SQLITE_RANGE, SQLITE_TOOBIG,
+    sqlite3_bind_blob, sqlite3_bind_double, sqlite3_bind_int64, sqlite3_bind_null,
+    sqlite3_bind_parameter_count, sqlite3_bind_parameter_index, sqlite3_bind_parameter_name,
+    sqlite3_bind_pointer, sqlite3_bind_text, sqlite3_bind_zeroblob, sqlite3_clear_bindings,
+    sqlite3_column_blob, sqlite3_column_bytes, sqlite3_column_count, sqlite3_column_double,
+    sqlite3_column_int64, sqlite3_column_name, sqlite3_column_text, sqlite3_column_type,
+    sqlite3_db_handle, sqlite3_expanded_sql, sqlite3_finalize, sqlite3_free,
+    sqlite3_get_autocommit, sqlite3_reset, sqlite3_sql, sqlite3_step, sqlite3_stmt,
+    sqlite3_stmt_busy, sqlite3_stmt_readonly, sqlite3_transfer_bindings, Error, FromRow, FromSql,
+    PrepareFlags, QueryAs, Row, StmtStatus, ToSql, Value, ValueRef, SQLITE_BLOB, SQLITE_DONE,
+    SQLITE_ERROR, SQLITE_FLOAT, SQLITE_INTEGER, SQLITE_MISMATCH, SQLITE_MISUSE, SQLITE_NULL,
+    SQLITE_RANGE, SQLITE_ROW, SQLITE_SCHEMA, SQLITE_TEXT, SQLITE_TOOBIG, SQLITE_TRANSIENT,
 };
 use core::convert::TryFrom;
+use core::fmt;
 use core::ptr::NonNull;
-use std::os::raw::{c_int, c_void};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::sync::Arc;
+
+/// "RETURNING" clause is available since SQLite 3.35.0.
+const RETURNING_VERSION: u32 = 3_035_000;
+
+/// Returns `SQLITE_ERROR` if SQLite `version` does not support "RETURNING" clause.
+fn check_returning(version: u32) -> Result<(), Error> {
+    if version < RETURNING_VERSION {
+        let message = format!(
+            "RETURNING clause requires SQLite 3.35.0 or later, but the runtime SQLite is {}.{}.{}",
+            version / 1_000_000,
+            version / 1_000 % 1_000,
+            version % 1_000
+        );
+        Err(Error::with_message(SQLITE_ERROR, message))
+    } else {
+        Ok(())
+    }
+}
+
+/// Result of [`Stmt::step2`] .
+///
+/// [`Stmt::step2`]: struct.Stmt.html#method.step2
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StepResult {
+    /// A row of the result is available.
+    Row,
+    /// The statement has finished.
+    Done {
+        /// Number of the rows modified, inserted, or deleted by the statement.
+        ///
+        /// This is the value of C function [`sqlite3_changes64`] at the moment the statement
+        /// finished, or 0 if the statement is read only.
+        ///
+        /// [`sqlite3_changes64`]: https://www.sqlite.org/c3ref/changes.html
+        changes: u64,
+    },
+}
+
+/// Execution state of [`Stmt`] , which [`Stmt::state`] returns.
+///
+/// The methods to fetch the column values require [`Row`] ; otherwise they return
+/// `SQLITE_MISUSE` naming the actual state. (See [`Error::misuse`] .)
+///
+/// [`Stmt`]: struct.Stmt.html
+/// [`Stmt::state`]: struct.Stmt.html#method.state
+/// [`Row`]: #variant.Row
+/// [`Error::misuse`]: struct.Error.html#method.misuse
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StmtState {
+    /// The statement has been prepared or reset, and is not running.
+    Ready,
+    /// The previous [`Stmt::step`] returned `true` ; i.e. the statement points to a row.
+    ///
+    /// [`Stmt::step`]: struct.Stmt.html#method.step
+    Row,
+    /// The previous [`Stmt::step`] returned `false` . The statement has been reset to run again.
+    ///
+    /// [`Stmt::step`]: struct.Stmt.html#method.step
+    Done,
+    /// The previous [`Stmt::step`] failed with the error code. The statement has been reset to
+    /// run again.
+    ///
+    /// [`Stmt::step`]: struct.Stmt.html#method.step
+    Errored(c_int),
+}
+
+impl fmt::Display for StmtState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ready => f.write_str("Ready"),
+            Self::Row => f.write_str("Row"),
+            Self::Done => f.write_str("Done"),
+            Self::Errored(code) => write!(f, "Errored({})", code),
+        }
+    }
+}
 
 /// Wrapper of C [`sqlite3_stmt`] .
 ///
+/// `Stmt` is `Send` as [`Connection`] is. An instance that [`Connection::stmt_once`] returns
+/// does not borrow the [`Connection`] , so it may be moved to another thread and used there while
+/// the connection is used in the original one; the "serialized" mode that the connection is
+/// opened in makes it safe.
+///
+/// # Resetting
+///
+/// `Stmt` has the execution state ( [`StmtState`] ) and the bound parameters. The following
+/// methods change them.
+///
+/// - [`reset`] resets the execution state to run the statement from the beginning again. The bound
+///   parameters are kept.
+/// - [`clear_bindings`] sets all the bound parameters to NULL. The execution state is kept unless
+///   the statement points to a row, in which case [`reset`] is called first as `bind_*` methods
+///   do.
+/// - [`clear`] does both of them.
+///
 /// [`sqlite3_stmt`]: https://www.sqlite.org/c3ref/stmt.html
+/// [`StmtState`]: enum.StmtState.html
+/// [`Connection`]: struct.Connection.html
+/// [`Connection::stmt_once`]: struct.Connection.html#method.stmt_once
+/// [`reset`]: #method.reset
+/// [`clear_bindings`]: #method.clear_bindings
+/// [`clear`]: #method.clear
 pub struct Stmt {
     raw: *mut sqlite3_stmt,
+    flags: PrepareFlags,
     column_count: c_int,
-    is_row: bool,
+    /// Value of `SQLITE_STMTSTATUS_REPREPARE` when `column_count` was taken.
+    reprepare: c_int,
+    state: StmtState,
+    /// The number of the rows changed by the last execution, which is taken when it finished.
+    last_changes: Option<u64>,
+    /// Whether to refuse writing the database in autocommit mode.
+    explicit_txn: bool,
+    /// The schedule of "PRAGMA optimize" shared with the connection.
+    auto_optimize: Option<Arc<AutoOptimize>>,
+    /// `None` unless the statistics are enabled.
+    stats: Option<Box<Stats>>,
 }
 
+// Safety:
+// The connection is always opened in the "serialized" mode (see `Connection::open_v2` ), in which
+// libsqlite3 serializes every call on a prepared statement with the mutex of the connection. The
+// other fields are owned by `self` or shared through `Arc` with a `Mutex` inside.
+unsafe impl Send for Stmt {}
+
 impl Drop for Stmt {
     #[inline]
     fn drop(&mut self) {
@@ -77,20 +207,71 @@ impl Drop for Stmt {
     }
 }
 
+impl fmt::Debug for Stmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Not to use `is_busy` , which asserts the state.
+        let parameter_count = unsafe { sqlite3_bind_parameter_count(self.raw) };
+        let busy = unsafe { sqlite3_stmt_busy(self.raw) } != 0;
+
+        f.debug_struct("Stmt")
+            .field("sql", &self.sql())
+            .field("state", &self.state)
+            .field("column_count", &self.column_count)
+            .field("parameter_count", &parameter_count)
+            .field("busy", &busy)
+            .finish()
+    }
+}
+
 /// Builds [`Stmt`] from raw pointer of `sqlite3_stmt` .
 ///
+/// `flags` is the flags passed to C function `sqlite3_prepare_v3` to build `raw` .
+///
 /// [`Stmt`]: struct.Stmt.html
 #[inline]
-pub fn from_raw(raw: NonNull<sqlite3_stmt>) -> Stmt {
+pub fn from_raw(raw: NonNull<sqlite3_stmt>, flags: PrepareFlags) -> Stmt {
     let column_count = unsafe { sqlite3_column_count(raw.as_ptr()) };
     Stmt {
         raw: raw.as_ptr(),
+        flags,
         column_count,
-        is_row: false,
+        reprepare: 0,
+        state: StmtState::Ready,
+        last_changes: None,
+        explicit_txn: false,
+        auto_optimize: None,
+        stats: None,
     }
 }
 
 impl Stmt {
+    /// Returns the raw pointer of `sqlite3_stmt` .
+    #[inline]
+    pub(crate) fn raw(&self) -> *mut sqlite3_stmt {
+        self.raw
+    }
+
+    /// Returns the raw pointer of C [`sqlite3_stmt`] to call the C functions that this crate
+    /// does not wrap.
+    ///
+    /// The ownership is not transferred; the pointer is valid while `self` is alive. Note that
+    /// [`step`] may replace the pointer if the database schema has been changed, so the caller
+    /// should not keep the pointer across the call.
+    ///
+    /// # Safety
+    ///
+    /// The caller must not finalize the statement; `self` does it on drop. The caller must not
+    /// call C functions `sqlite3_step` nor `sqlite3_reset` either, because `self` tracks whether
+    /// the statement points to a row or not. Calling the other C functions like
+    /// `sqlite3_bind_*` , `sqlite3_column_*` , or `sqlite3_stmt_status` is tolerated.
+    ///
+    /// [`sqlite3_stmt`]: https://www.sqlite.org/c3ref/stmt.html
+    /// [`step`]: #method.step
+    #[inline]
+    pub unsafe fn as_ptr(&self) -> *mut sqlite3_stmt {
+        self.raw
+    }
+
     /// Calls C function [`sqlite3_reset`] to clear the previous result.
     ///
     /// This method is called automatically if necessary, so the user will rarely call this method.
@@ -100,7 +281,47 @@ impl Stmt {
     #[inline]
     pub fn reset(&mut self) {
         unsafe { sqlite3_reset(self.raw) };
-        self.is_row = false;
+        self.state = StmtState::Ready;
+        self.last_changes = None;
+        if let Some(stats) = self.stats.as_mut() {
+            stats.finish();
+        }
+    }
+
+    /// Calls C function [`sqlite3_reset`] and returns the error if any.
+    ///
+    /// This method is same to [`reset`] except for returning the result of [`sqlite3_reset`] ,
+    /// which is the error of the most recent [`sqlite3_step`] if it failed. (The statement is
+    /// reset even if `Err` is returned.)
+    ///
+    /// [`reset`]: #method.reset
+    /// [`sqlite3_reset`]: https://www.sqlite.org/c3ref/reset.html
+    /// [`sqlite3_step`]: https://www.sqlite.org/c3ref/step.html
+    #[inline]
+    pub fn try_reset(&mut self) -> Result<(), Error> {
+        let ret = self.reset_impl();
+        if let Some(stats) = self.stats.as_mut() {
+            stats.finish();
+        }
+        ret
+    }
+
+    /// Same to [`try_reset`] except for leaving the statistics of the current execution
+    /// unfinished; [`step_stats`] finishes it after taking the time of the last step.
+    ///
+    /// [`try_reset`]: #method.try_reset
+    /// [`step_stats`]: #method.step_stats
+    fn reset_impl(&mut self) -> Result<(), Error> {
+        let code = unsafe { sqlite3_reset(self.raw) };
+        self.state = StmtState::Ready;
+        self.last_changes = None;
+        match Error::new(code) {
+            e if e == Error::OK => Ok(()),
+            e => {
+                self.state = StmtState::Errored(e.code());
+                Err(e)
+            }
+        }
     }
 
     /// Calls C function [`sqlite3_reset`] and [`sqlite3_clear_bindings`] to reset all the
@@ -109,19 +330,34 @@ impl Stmt {
     /// Because the document of [`sqlite3_clear_bindings`] is ambiguous, this method calls
     /// [`sqlite3_reset`] at the same time.
     ///
-    /// # Panics
-    ///
-    /// Panics if [`sqlite3_clear_bindings`] failed.
+    /// Returns `Err` if [`sqlite3_clear_bindings`] failed.
     ///
     /// [`sqlite3_reset`]: https://www.sqlite.org/c3ref/reset.html
     /// [`sqlite3_clear_bindings`]: https://www.sqlite.org/c3ref/clear_bindings.html
     #[inline]
-    pub fn clear(&mut self) {
+    pub fn clear(&mut self) -> Result<(), Error> {
         self.reset();
+        self.clear_bindings()
+    }
+
+    /// Wrapper of C function [`sqlite3_clear_bindings`] to set all the parameters NULL.
+    ///
+    /// Calls method [`reset`] if the privious [`step`] returns `true` , and calls
+    /// [`sqlite3_clear_bindings`] ; otherwise, the execution state is not changed.
+    ///
+    /// [`reset`]: #method.reset
+    /// [`step`]: #method.step
+    /// [`sqlite3_clear_bindings`]: https://www.sqlite.org/c3ref/clear_bindings.html
+    #[inline]
+    pub fn clear_bindings(&mut self) -> Result<(), Error> {
+        if self.is_row() {
+            self.reset();
+        }
+
         let code = unsafe { sqlite3_clear_bindings(self.raw) };
-        let e = Error::new(code);
-        if e != Error::OK {
-            panic!("{}", e);
+        match Error::new(code) {
+            e if e == Error::OK => Ok(()),
+            e => Err(e),
         }
     }
 
@@ -140,21 +376,275 @@ impl Stmt {
     /// [`sqlite3_step`]: https://www.sqlite.org/c3ref/step.html
     #[inline]
     pub fn step(&mut self) -> Result<bool, Error> {
-        let code = unsafe { sqlite3_step(self.raw) };
+        self.step2().map(|r| r == StepResult::Row)
+    }
+
+    /// Wrapper of C function [`sqlite3_step`] .
+    ///
+    /// Returns [`StepResult::Row`] if the SQL statement being executed returns any data (i.e.
+    /// [`sqlite3_step`] returned `SQLITE_ROW`.)
+    ///
+    /// Calls [`reset`] and returns [`StepResult::Done`] if the SQL statement has finished (i.e.
+    /// [`sqlite3_step`] returned `SQLITE_DONE` .) It holds the number of the rows the statement
+    /// changed.
+    ///
+    /// Otherwise, i.e. [`sqlite3_step`] failed, calls [`reset`] and returns `Err` .
+    ///
+    /// If the database schema has been changed since the statement was compiled, the statement is
+    /// compiled again from the SQL text with the bound parameters kept. This method returns `Err`
+    /// only if the SQL text no longer compiles.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse_sqlite3::{Connection, StepResult};
+    ///
+    /// let mut con = Connection::open_memory_db().unwrap();
+    /// con.execute_batch(r#"CREATE TABLE "foo" ("value" INTEGER)"#).unwrap();
+    ///
+    /// let stmt = con.stmt(r#"INSERT INTO "foo" VALUES (1), (2)"#).unwrap();
+    /// assert_eq!(Ok(StepResult::Done { changes: 2 }), stmt.step2());
+    ///
+    /// let stmt = con.stmt(r#"SELECT "value" FROM "foo""#).unwrap();
+    /// let mut sum = 0;
+    /// while let StepResult::Row = stmt.step2().unwrap() {
+    ///     sum += stmt.get::<i64>(0).unwrap();
+    /// }
+    /// assert_eq!(3, sum);
+    /// ```
+    ///
+    /// [`reset`]: #method.reset
+    /// [`sqlite3_step`]: https://www.sqlite.org/c3ref/step.html
+    /// [`StepResult::Row`]: enum.StepResult.html#variant.Row
+    /// [`StepResult::Done`]: enum.StepResult.html#variant.Done
+    pub fn step2(&mut self) -> Result<StepResult, Error> {
+        #[cfg(feature = "tracing")]
+        {
+            if crate::trace::step_enabled() {
+                let span = crate::trace::step_span(self.sql());
+                let _enter = span.enter();
+                let start = std::time::Instant::now();
+                let ret = self.step_stats();
+                crate::trace::record_step(&span, start, &ret);
+                return ret;
+            }
+        }
+        self.step_stats()
+    }
+
+    /// Calls [`step_impl`] recording the statistics if enabled, and then runs the scheduled
+    /// "PRAGMA optimize" if the statement has finished. (The statistics of `self` exclude it.)
+    ///
+    /// [`step_impl`]: #method.step_impl
+    fn step_stats(&mut self) -> Result<StepResult, Error> {
+        let ret = match self.stats.as_mut() {
+            None => self.step_impl(),
+            Some(stats) => {
+                let start = stats.start_step();
+                let ret = self.step_impl();
+                if let Some(stats) = self.stats.as_mut() {
+                    let result = match &ret {
+                        Ok(StepResult::Row) => Ok(true),
+                        Ok(StepResult::Done { .. }) => Ok(false),
+                        Err(e) => Err(e),
+                    };
+                    stats.end_step(start, result);
+                }
+                ret
+            }
+        };
+
+        if let (Ok(StepResult::Done { .. }), Some(auto_optimize)) = (&ret, &self.auto_optimize) {
+            auto_optimize.after_done(unsafe { sqlite3_db_handle(self.raw) });
+        }
+        ret
+    }
+
+    /// Implementation of [`step2`] .
+    ///
+    /// [`step2`]: #method.step2
+    fn step_impl(&mut self) -> Result<StepResult, Error> {
+        if self.explicit_txn && !self.is_row() {
+            let db = unsafe { sqlite3_db_handle(self.raw) };
+            if !self.is_readonly() && unsafe { sqlite3_get_autocommit(db) } != 0 {
+                return Err(Error::AUTOCOMMIT_WRITE);
+            }
+        }
+
+        let mut code = unsafe { sqlite3_step(self.raw) };
+        if code == SQLITE_SCHEMA && !self.is_row() {
+            // libsqlite3 re-prepares the statement automatically on schema change, however, it
+            // gives up after retrying several times. Then, prepare it again from the SQL text.
+            if let Err(e) = self.reprepare() {
+                return Err(self.step_failed(e));
+            }
+            code = unsafe { sqlite3_step(self.raw) };
+        }
+
+        #[cfg(feature = "unlock-notify")]
+        {
+            use crate::unlock_notify::{is_locked_sharedcache, wait, Wait};
+
+            let db = unsafe { sqlite3_db_handle(self.raw) };
+            let mut deadline = None;
+            while !self.is_row() && is_locked_sharedcache(db, code) {
+                match wait(db, &mut deadline) {
+                    Ok(Wait::Unlocked) => unsafe {
+                        sqlite3_reset(self.raw);
+                        code = sqlite3_step(self.raw);
+                    },
+                    Ok(Wait::TimedOut) => break,
+                    Err(e) => return Err(self.step_failed(e)),
+                }
+            }
+        }
+
+        if code == SQLITE_ROW || code == SQLITE_DONE {
+            self.sync_column_count();
+        }
+
         match Error::new(code) {
-            Error::DONE => {
-                self.reset();
-                Ok(false)
+            e if e == Error::DONE => {
+                // Take the number before another statement changes it.
+                let changes = unsafe {
+                    if sqlite3_stmt_readonly(self.raw) == 0 {
+                        crate::compat::changes64(sqlite3_db_handle(self.raw)) as u64
+                    } else {
+                        0
+                    }
+                };
+                self.reset_impl()?;
+                self.state = StmtState::Done;
+                self.last_changes = Some(changes);
+                Ok(StepResult::Done { changes })
             }
-            Error::ROW => {
-                self.is_row = true;
-                Ok(true)
+            e if e == Error::ROW => {
+                self.state = StmtState::Row;
+                Ok(StepResult::Row)
             }
-            e => {
-                self.reset();
-                Err(e)
+            e => Err(self.step_failed(e)),
+        }
+    }
+
+    /// Resets the statement after [`step`] failed with `e` , and returns `e` attaching the OS
+    /// error number if any.
+    ///
+    /// [`step`]: #method.step
+    fn step_failed(&mut self, e: Error) -> Error {
+        let db = unsafe { sqlite3_db_handle(self.raw) };
+        let e = crate::stmt_timeout::check_timed_out(db, e.with_system_errno(db));
+        let _ = self.reset_impl();
+        self.state = StmtState::Errored(e.code());
+        e
+    }
+
+    /// Returns the execution state.
+    #[inline]
+    pub fn state(&self) -> StmtState {
+        self.state
+    }
+
+    /// Returns the number of the rows that the last execution of the statement changed, which
+    /// is taken from C function [`sqlite3_changes64`] as soon as [`step`] finished the
+    /// statement; i.e. the same to [`StepResult::Done`] .
+    ///
+    /// Unlike calling [`sqlite3_changes64`] afterwards, the other statements of the connection do
+    /// not affect the result. It is 0 for a read only statement.
+    ///
+    /// Returns `None` if the statement has not finished since it was prepared or reset.
+    ///
+    /// [`sqlite3_changes64`]: https://www.sqlite.org/c3ref/changes.html
+    /// [`step`]: #method.step
+    /// [`StepResult::Done`]: enum.StepResult.html#variant.Done
+    #[inline]
+    pub fn last_changes(&self) -> Option<u64> {
+        self.last_changes
+    }
+
+    /// Returns whether the statement points to a row or not.
+    #[inline]
+    fn is_row(&self) -> bool {
+        self.state == StmtState::Row
+    }
+
+    /// Returns `SQLITE_MISUSE` unless the statement points to a row.
+    fn check_row(&self) -> Result<(), Error> {
+        if self.is_row() {
+            Ok(())
+        } else {
+            Err(Error::misuse(StmtState::Row, self.state))
+        }
+    }
+
+    /// Makes [`step`] refuse to start writing the database in autocommit mode if `required` is
+    /// `true` .
+    ///
+    /// [`step`]: #method.step
+    #[inline]
+    pub(crate) fn require_explicit_transaction(&mut self, required: bool) {
+        self.explicit_txn = required;
+    }
+
+    /// Makes [`step`] run "PRAGMA optimize" by `auto_optimize` whenever the statement finished.
+    ///
+    /// [`step`]: #method.step
+    #[inline]
+    pub(crate) fn set_auto_optimize(&mut self, auto_optimize: Option<Arc<AutoOptimize>>) {
+        self.auto_optimize = auto_optimize;
+    }
+
+    /// Starts collecting the statistics if `enabled` is `true` , or stops and discards them if
+    /// `false` .
+    pub(crate) fn enable_stats(&mut self, enabled: bool) {
+        match (enabled, self.stats.is_some()) {
+            (true, false) => self.stats = Some(Box::default()),
+            (false, true) => self.stats = None,
+            _ => (),
+        }
+    }
+
+    /// Returns the statistics collected so far, or `None` if disabled or never executed.
+    pub(crate) fn stats(&self) -> Option<crate::StmtStats> {
+        self.stats.as_ref()?.to_stmt_stats(self.sql())
+    }
+
+    /// Updates `column_count` if libsqlite3 has re-prepared the statement, because the columns
+    /// may be changed. (e.g. "SELECT *" after "ALTER TABLE ADD COLUMN" .)
+    fn sync_column_count(&mut self) {
+        let reprepare = self.status(StmtStatus::Reprepare, false);
+        if reprepare != self.reprepare {
+            self.reprepare = reprepare;
+            self.column_count = unsafe { sqlite3_column_count(self.raw) };
+        }
+    }
+
+    /// Compiles the SQL text again and replaces `self.raw` with the new statement keeping the
+    /// bound parameters.
+    ///
+    /// Returns the error if the SQL text no longer compiles.
+    fn reprepare(&mut self) -> Result<(), Error> {
+        self.reset();
+
+        unsafe {
+            let db = sqlite3_db_handle(self.raw);
+            let sql = sqlite3_sql(self.raw);
+            let mut raw = core::ptr::null_mut();
+            let pztail = core::ptr::null_mut();
+
+            let code = crate::compat::prepare(db, sql, -1, self.flags, &mut raw, pztail);
+            match Error::new(code) {
+                e if e == Error::OK && !raw.is_null() => (),
+                e if e == Error::OK => return Err(Error::new(SQLITE_MISUSE)),
+                e => return Err(e),
             }
+
+            sqlite3_transfer_bindings(self.raw, raw);
+            sqlite3_finalize(self.raw);
+            self.raw = raw;
+            self.column_count = sqlite3_column_count(raw);
+            self.reprepare = 0;
         }
+        Ok(())
     }
 
     /// Wrapper of C function [`sqlite3_bind_int64`] .
@@ -173,16 +663,13 @@ impl Stmt {
     /// [`sqlite3_step`]: https://www.sqlite.org/c3ref/step.html
     #[inline]
     pub fn bind_int(&mut self, index: usize, val: i64) -> Result<(), Error> {
-        if self.is_row {
+        if self.is_row() {
             self.reset();
         }
 
         let index = c_int::try_from(index).map_err(|_| Error::new(SQLITE_RANGE))?;
         let code = unsafe { sqlite3_bind_int64(self.raw, index, val) };
-        match Error::new(code) {
-            Error::OK => Ok(()),
-            e => Err(e),
-        }
+        self.bind_result(index, code)
     }
 
     /// Wrapper of C function [`sqlite3_bind_blob`] .
@@ -192,6 +679,9 @@ impl Stmt {
     /// (It is necesarry to call [`sqlite3_reset`] after [`sqlite3_step`] , however, [`step`]
     /// did not call [`sqlite3_reset`] when it returned `true` .)
     ///
+    /// An empty `val` is bound as a zero-length BLOB (not NULL) calling
+    /// [`sqlite3_bind_zeroblob`] instead.
+    ///
     /// Note that `index` starts at 1, not 0.
     ///
     /// [`reset`]: #method.reset
@@ -199,25 +689,72 @@ impl Stmt {
     /// [`sqlite3_bind_blob`]: https://www.sqlite.org/c3ref/bind_blob.html
     /// [`sqlite3_reset`]: https://www.sqlite.org/c3ref/reset.html
     /// [`sqlite3_step`]: https://www.sqlite.org/c3ref/step.html
+    /// [`sqlite3_bind_zeroblob`]: https://www.sqlite.org/c3ref/bind_blob.html
     #[inline]
     pub fn bind_blob<'a, 'b>(&'a mut self, index: usize, val: &'b [u8]) -> Result<(), Error>
     where
         'b: 'a,
     {
-        if self.is_row {
+        if self.is_row() {
             self.reset();
         }
 
         let index = c_int::try_from(index).map_err(|_| Error::new(SQLITE_RANGE))?;
+        if val.is_empty() {
+            return self.bind_empty_blob(index);
+        }
+
         let ptr = val.as_ptr() as *const c_void;
         let len = c_int::try_from(val.len()).map_err(|_| Error::new(SQLITE_TOOBIG))?;
         const DESTRUCTOR: *const c_void = core::ptr::null();
 
         let code = unsafe { sqlite3_bind_blob(self.raw, index, ptr, len, DESTRUCTOR) };
-        match Error::new(code) {
-            Error::OK => Ok(()),
-            e => Err(e),
+        self.bind_result(index, code)
+    }
+
+    /// Binds a zero-length BLOB to the parameter `index` .
+    ///
+    /// [`sqlite3_bind_blob`] binds NULL if the pointer is null even if the length is 0. The
+    /// pointer of an empty slice is not null today, however, [`sqlite3_bind_zeroblob`] does not
+    /// depend on it.
+    ///
+    /// [`sqlite3_bind_blob`]: https://www.sqlite.org/c3ref/bind_blob.html
+    /// [`sqlite3_bind_zeroblob`]: https://www.sqlite.org/c3ref/bind_blob.html
+    fn bind_empty_blob(&self, index: c_int) -> Result<(), Error> {
+        let code = unsafe { sqlite3_bind_zeroblob(self.raw, index, 0) };
+        self.bind_result(index, code)
+    }
+
+    /// Wrapper of C function [`sqlite3_bind_text`] .
+    ///
+    /// Calls method [`reset`] if the privious [`step`] returns `true` , and calls
+    /// [`sqlite3_bind_text`] .
+    /// (It is necesarry to call [`sqlite3_reset`] after [`sqlite3_step`] , however, [`step`]
+    /// did not call [`sqlite3_reset`] when it returned `true` .)
+    ///
+    /// Note that `index` starts at 1, not 0.
+    ///
+    /// [`reset`]: #method.reset
+    /// [`step`]: #method.step
+    /// [`sqlite3_bind_text`]: https://www.sqlite.org/c3ref/bind_blob.html
+    /// [`sqlite3_reset`]: https://www.sqlite.org/c3ref/reset.html
+    /// [`sqlite3_step`]: https://www.sqlite.org/c3ref/step.html
+    #[inline]
+    pub fn bind_text<'a, 'b>(&'a mut self, index: usize, val: &'b str) -> Result<(), Error>
+    where
+        'b: 'a,
+    {
+        if self.is_row() {
+            self.reset();
         }
+
+        let index = c_int::try_from(index).map_err(|_| Error::new(SQLITE_RANGE))?;
+        let ptr = val.as_ptr() as *const c_char;
+        let len = c_int::try_from(val.len()).map_err(|_| Error::new(SQLITE_TOOBIG))?;
+        const DESTRUCTOR: *const c_void = core::ptr::null();
+
+        let code = unsafe { sqlite3_bind_text(self.raw, index, ptr, len, DESTRUCTOR) };
+        self.bind_result(index, code)
     }
 
     /// Wrapper of C function [`sqlite3_bind_null`] .
@@ -236,91 +773,2007 @@ impl Stmt {
     /// [`sqlite3_step`]: https://www.sqlite.org/c3ref/step.html
     #[inline]
     pub fn bind_null(&mut self, index: usize) -> Result<(), Error> {
-        if self.is_row {
+        if self.is_row() {
             self.reset();
         }
 
         let index = c_int::try_from(index).map_err(|_| Error::new(SQLITE_RANGE))?;
         let code = unsafe { sqlite3_bind_null(self.raw, index) };
-        match Error::new(code) {
-            Error::OK => Ok(()),
-            e => Err(e),
-        }
+        self.bind_result(index, code)
     }
 
-    /// Wrapper of C function [`sqlite3_column_type`] and [`sqlite3_column_int64`] .
-    ///
-    /// This method calls [`sqlite3_column_type`] first.
+    /// Wrapper of C function [`sqlite3_bind_double`] .
     ///
-    /// If the value type is Null, returns `None` , or if the value type is Integer, calls
-    /// [`sqlite3_column_int64`] and returns the result.
+    /// Calls method [`reset`] if the privious [`step`] returns `true` , and calls
+    /// [`sqlite3_bind_double`] .
+    /// (It is necesarry to call [`sqlite3_reset`] after [`sqlite3_step`] , however, [`step`]
+    /// did not call [`sqlite3_reset`] when it returned `true` .)
     ///
-    /// Note that `index` starts at 0, not 1.
+    /// Note that `index` starts at 1, not 0.
     ///
-    /// # Panics
+    /// [`reset`]: #method.reset
+    /// [`step`]: #method.step
+    /// [`sqlite3_bind_double`]: https://www.sqlite.org/c3ref/bind_blob.html
+    /// [`sqlite3_reset`]: https://www.sqlite.org/c3ref/reset.html
+    /// [`sqlite3_step`]: https://www.sqlite.org/c3ref/step.html
+    #[inline]
+    pub fn bind_double(&mut self, index: usize, val: f64) -> Result<(), Error> {
+        if self.is_row() {
+            self.reset();
+        }
+
+        let index = c_int::try_from(index).map_err(|_| Error::new(SQLITE_RANGE))?;
+        let code = unsafe { sqlite3_bind_double(self.raw, index, val) };
+        self.bind_result(index, code)
+    }
+
+    /// Wrapper of C function [`sqlite3_bind_pointer`] .
     ///
-    /// Panics if the previous [`step`] did not returns `true` or [`step`] did not called.
+    /// Passes the ownership of `value` to libsqlite3; the box is dropped by the destructor callback
+    /// when the binding is cleared, replaced, or the statement is finalized.
+    /// The bound value is visible to SQL as NULL, and only application-defined functions or
+    /// virtual tables that call `sqlite3_value_pointer` with the same `type_name` can read it.
     ///
-    /// Panics if `index` is out of range.
+    /// Calls method [`reset`] if the privious [`step`] returns `true` .
     ///
-    /// Panics if the column value type is neither Null nor Integer.
+    /// Note that `index` starts at 1, not 0.
     ///
+    /// [`reset`]: #method.reset
     /// [`step`]: #method.step
-    /// [`sqlite3_column_type`]: https://www.sqlite.org/c3ref/column_blob.html
-    /// [`sqlite3_column_int64`]: https://www.sqlite.org/c3ref/column_blob.html
+    /// [`sqlite3_bind_pointer`]: https://www.sqlite.org/c3ref/bind_blob.html
     #[inline]
-    pub fn column_int(&mut self, index: usize) -> Option<i64> {
-        assert_eq!(true, self.is_row);
-        assert!(index < (self.column_count as usize));
-
-        let index = index as c_int;
-        unsafe {
-            match sqlite3_column_type(self.raw, index) {
-                SQLITE_NULL => None,
-                SQLITE_INTEGER => Some(sqlite3_column_int64(self.raw, index)),
-                _ => panic!("Bad column type"),
-            }
+    pub fn bind_pointer<T>(
+        &mut self,
+        index: usize,
+        value: Box<T>,
+        type_name: &'static CStr,
+    ) -> Result<(), Error>
+    where
+        T: 'static,
+    {
+        unsafe extern "C" fn drop_box<T>(ptr: *mut c_void) {
+            drop(Box::from_raw(ptr as *mut T));
         }
+
+        let index = c_int::try_from(index).map_err(|_| Error::new(SQLITE_RANGE))?;
+        let ptr = Box::into_raw(value) as *mut c_void;
+        // libsqlite3 calls the destructor even if it fails to bind.
+        unsafe { self.raw_bind_pointer(index, ptr, type_name, Some(drop_box::<T>)) }
     }
 
-    /// Wrapper of C function [`sqlite3_column_type`] , [`sqlite3_column_blob`] , and
-    /// [`sqlite3_column_bytes`] .
-    ///
-    /// This method calls [`sqlite3_column_type`] first.
+    /// Wrapper of C function [`sqlite3_bind_pointer`] for a pointer the caller owns.
     ///
-    /// If the value type is Null, returns `None` , or if the value type is Blob, calls
-    /// [`sqlite3_column_blob`] and [`sqlite3_column_bytes`] and returns the result.
-    ///
-    /// Note that `index` starts at 0, not 1.
+    /// `destructor` is called with `ptr` when the binding is cleared, replaced, or the statement
+    /// is finalized, or immediately if this method fails.
+    /// Pass `None` to borrow `ptr` .
     ///
-    /// # Panics
+    /// Calls method [`reset`] if the privious [`step`] returns `true` .
     ///
-    /// Panics if the previous [`step`] did not returns `true` or [`step`] did not called.
+    /// Note that `index` starts at 1, not 0.
     ///
-    /// Panics if `index` is out of range.
+    /// # Safety
     ///
-    /// Panics if the column value type is neither Null nor Blob.
+    /// `ptr` must stay valid as long as it is bound to `self` .
     ///
+    /// [`reset`]: #method.reset
     /// [`step`]: #method.step
-    /// [`sqlite3_column_type`]: https://www.sqlite.org/c3ref/column_blob.html
-    /// [`sqlite3_column_blob`]: https://www.sqlite.org/c3ref/column_blob.html
+    /// [`sqlite3_bind_pointer`]: https://www.sqlite.org/c3ref/bind_blob.html
+    #[inline]
+    pub unsafe fn bind_pointer_raw(
+        &mut self,
+        index: usize,
+        ptr: *mut c_void,
+        type_name: &'static CStr,
+        destructor: Option<unsafe extern "C" fn(*mut c_void)>,
+    ) -> Result<(), Error> {
+        match c_int::try_from(index) {
+            Ok(index) => self.raw_bind_pointer(index, ptr, type_name, destructor),
+            Err(_) => {
+                if let Some(destructor) = destructor {
+                    destructor(ptr);
+                }
+                Err(Error::new(SQLITE_RANGE))
+            }
+        }
+    }
+
+    unsafe fn raw_bind_pointer(
+        &mut self,
+        index: c_int,
+        ptr: *mut c_void,
+        type_name: &'static CStr,
+        destructor: Option<unsafe extern "C" fn(*mut c_void)>,
+    ) -> Result<(), Error> {
+        if self.is_row() {
+            self.reset();
+        }
+
+        let code = sqlite3_bind_pointer(self.raw, index, ptr, type_name.as_ptr(), destructor);
+        self.bind_result(index, code)
+    }
+
+    /// Binds `val` to the parameter `index` .
+    ///
+    /// Unlike [`bind_blob`] or [`bind_text`] , TEXT and BLOB are copied (i.e. `SQLITE_TRANSIENT`
+    /// is passed to libsqlite3), so `val` does not have to outlive `self` .
+    ///
+    /// Calls method [`reset`] if the privious [`step`] returns `true` .
+    ///
+    /// Note that `index` starts at 1, not 0.
+    ///
+    /// [`bind_blob`]: #method.bind_blob
+    /// [`bind_text`]: #method.bind_text
+    /// [`reset`]: #method.reset
+    /// [`step`]: #method.step
+    #[inline]
+    pub fn bind<T>(&mut self, index: usize, val: &T) -> Result<(), Error>
+    where
+        T: ?Sized + ToSql,
+    {
+        let output = val.to_sql()?;
+        self.bind_value_ref(index, output.as_value_ref())
+    }
+
+    /// Calls [`clear_bindings`] and binds `params` from the parameter 1 in order.
+    ///
+    /// Note that the index of `params` starts at 0 while the parameter index starts at 1; i.e.
+    /// `params[0]` is bound to `?1` .
+    ///
+    /// [`clear_bindings`]: #method.clear_bindings
+    pub fn bind_all(&mut self, params: &[&dyn ToSql]) -> Result<(), Error> {
+        self.clear_bindings()?;
+        for (i, param) in params.iter().enumerate() {
+            self.bind(i + 1, *param)?;
+        }
+        Ok(())
+    }
+
+    /// Binds `params` with [`bind_all`] , executes the statement from the beginning until the end
+    /// discarding the rows, and returns the number of the rows modified, inserted, or deleted.
+    ///
+    /// The statement is reset and the parameters are cleared before returning, even if an error
+    /// occurs.
+    ///
+    /// [`bind_all`]: #method.bind_all
+    pub fn bind_and_execute(&mut self, params: &[&dyn ToSql]) -> Result<usize, Error> {
+        if self.is_row() {
+            self.reset();
+        }
+
+        let ret = self.bind_all(params).and_then(|_| loop {
+            if let StepResult::Done { changes } = self.step2()? {
+                break Ok(changes as usize);
+            }
+        });
+        let cleared = self.clear();
+        let changes = ret?;
+        cleared.map(|_| changes)
+    }
+
+    /// Converts `code` returned from a `sqlite3_bind_*` function into the result.
+    #[inline]
+    fn bind_result(&self, index: c_int, code: c_int) -> Result<(), Error> {
+        #[cfg(feature = "tracing")]
+        crate::trace::bind(self.sql(), index, code);
+        #[cfg(not(feature = "tracing"))]
+        let _ = index;
+
+        match Error::new(code) {
+            e if e == Error::OK => Ok(()),
+            e => Err(e),
+        }
+    }
+
+    /// Binds `val` to the parameter `index` copying TEXT and BLOB.
+    pub(crate) fn bind_value_ref(&mut self, index: usize, val: ValueRef<'_>) -> Result<(), Error> {
+        const DESTRUCTOR: *const c_void = SQLITE_TRANSIENT as *const c_void;
+
+        match val {
+            ValueRef::Null => self.bind_null(index),
+            ValueRef::Integer(i) => self.bind_int(index, i),
+            ValueRef::Real(f) => self.bind_double(index, f),
+            ValueRef::Text(s) => {
+                if self.is_row() {
+                    self.reset();
+                }
+
+                let index = c_int::try_from(index).map_err(|_| Error::new(SQLITE_RANGE))?;
+                let ptr = s.as_ptr() as *const c_char;
+                let len = c_int::try_from(s.len()).map_err(|_| Error::new(SQLITE_TOOBIG))?;
+                let code = unsafe { sqlite3_bind_text(self.raw, index, ptr, len, DESTRUCTOR) };
+                self.bind_result(index, code)
+            }
+            ValueRef::Blob(b) => {
+                if self.is_row() {
+                    self.reset();
+                }
+
+                let index = c_int::try_from(index).map_err(|_| Error::new(SQLITE_RANGE))?;
+                if b.is_empty() {
+                    return self.bind_empty_blob(index);
+                }
+
+                let ptr = b.as_ptr() as *const c_void;
+                let len = c_int::try_from(b.len()).map_err(|_| Error::new(SQLITE_TOOBIG))?;
+                let code = unsafe { sqlite3_bind_blob(self.raw, index, ptr, len, DESTRUCTOR) };
+                self.bind_result(index, code)
+            }
+        }
+    }
+
+    /// Wrapper of C function [`sqlite3_column_type`] and [`sqlite3_column_int64`] .
+    ///
+    /// This method calls [`sqlite3_column_type`] first.
+    ///
+    /// If the value type is Null, returns `None` , or if the value type is Integer, calls
+    /// [`sqlite3_column_int64`] and returns the result.
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the previous [`step`] did not returns `true` or [`step`] did not called.
+    ///
+    /// Panics if `index` is out of range.
+    ///
+    /// Panics if the column value type is neither Null nor Integer.
+    ///
+    /// [`step`]: #method.step
+    /// [`sqlite3_column_type`]: https://www.sqlite.org/c3ref/column_blob.html
+    /// [`sqlite3_column_int64`]: https://www.sqlite.org/c3ref/column_blob.html
+    #[inline]
+    pub fn column_int(&mut self, index: usize) -> Option<i64> {
+        self.try_column_int(index)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Same to [`column_int`] except for returning `Err` instead of panicking.
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// Returns `SQLITE_MISUSE` if the previous [`step`] did not return `true` or [`step`] was not
+    /// called, `SQLITE_RANGE` if `index` is out of range, or `SQLITE_MISMATCH` if the column value
+    /// type is neither Null nor Integer.
+    ///
+    /// [`column_int`]: #method.column_int
+    /// [`step`]: #method.step
+    #[inline]
+    pub fn try_column_int(&mut self, index: usize) -> Result<Option<i64>, Error> {
+        match self.column_value_ref(index)? {
+            ValueRef::Null => Ok(None),
+            ValueRef::Integer(i) => Ok(Some(i)),
+            _ => Err(Self::bad_column_type(index)),
+        }
+    }
+
+    /// Wrapper of C function [`sqlite3_column_type`] , [`sqlite3_column_blob`] , and
+    /// [`sqlite3_column_bytes`] .
+    ///
+    /// This method calls [`sqlite3_column_type`] first.
+    ///
+    /// If the value type is Null, returns `None` , or if the value type is Blob, calls
+    /// [`sqlite3_column_blob`] and [`sqlite3_column_bytes`] and returns the result.
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the previous [`step`] did not returns `true` or [`step`] did not called.
+    ///
+    /// Panics if `index` is out of range.
+    ///
+    /// Panics if the column value type is neither Null nor Blob.
+    ///
+    /// [`step`]: #method.step
+    /// [`sqlite3_column_type`]: https://www.sqlite.org/c3ref/column_blob.html
+    /// [`sqlite3_column_blob`]: https://www.sqlite.org/c3ref/column_blob.html
+    /// [`sqlite3_column_bytes`]: https://www.sqlite.org/c3ref/column_blob.html
+    #[inline]
+    pub fn column_blob(&mut self, index: usize) -> Option<&[u8]> {
+        self.try_column_blob(index)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Same to [`column_blob`] except for returning `Err` instead of panicking.
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// Returns `SQLITE_MISUSE` if the previous [`step`] did not return `true` or [`step`] was not
+    /// called, `SQLITE_RANGE` if `index` is out of range, or `SQLITE_MISMATCH` if the column value
+    /// type is neither Null nor Blob.
+    ///
+    /// [`column_blob`]: #method.column_blob
+    /// [`step`]: #method.step
+    #[inline]
+    pub fn try_column_blob(&mut self, index: usize) -> Result<Option<&[u8]>, Error> {
+        match self.column_value_ref(index)? {
+            ValueRef::Null => Ok(None),
+            ValueRef::Blob(bytes) => Ok(Some(bytes)),
+            _ => Err(Self::bad_column_type(index)),
+        }
+    }
+
+    /// Wrapper of C function [`sqlite3_column_type`] , [`sqlite3_column_text`] , and
+    /// [`sqlite3_column_bytes`] .
+    ///
+    /// This method calls [`sqlite3_column_type`] first.
+    ///
+    /// If the value type is Null, returns `None` , or if the value type is Text, calls
+    /// [`sqlite3_column_text`] and [`sqlite3_column_bytes`] and returns the result.
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the previous [`step`] did not returns `true` or [`step`] did not called.
+    ///
+    /// Panics if `index` is out of range.
+    ///
+    /// Panics if the column value type is neither Null nor Text.
+    ///
+    /// Panics if the value is not a valid UTF-8 string.
+    ///
+    /// [`step`]: #method.step
+    /// [`sqlite3_column_type`]: https://www.sqlite.org/c3ref/column_blob.html
+    /// [`sqlite3_column_text`]: https://www.sqlite.org/c3ref/column_blob.html
     /// [`sqlite3_column_bytes`]: https://www.sqlite.org/c3ref/column_blob.html
     #[inline]
-    pub fn column_blob(&mut self, index: usize) -> Option<&[u8]> {
-        assert_eq!(true, self.is_row);
-        assert!(index < (self.column_count as usize));
+    pub fn column_text(&mut self, index: usize) -> Option<&str> {
+        self.try_column_text(index)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Same to [`column_text`] except for returning `Err` instead of panicking.
+    ///
+    /// libsqlite3 does not validate the encoding of TEXT, so a database written by other
+    /// applications may include TEXT that is not a valid UTF-8 string. This method is strict about
+    /// it; use [`column_text_lossy`] to replace the invalid sequences with U+FFFD, or
+    /// [`column_text_bytes`] to get the bytes as they are.
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// Returns `SQLITE_MISUSE` if the previous [`step`] did not return `true` or [`step`] was not
+    /// called, `SQLITE_RANGE` if `index` is out of range, or `SQLITE_MISMATCH` if the column value
+    /// type is neither Null nor Text, or if the value is not a valid UTF-8 string. (The message of
+    /// the error describes the `Utf8Error` in the last case.)
+    ///
+    /// [`column_text`]: #method.column_text
+    /// [`column_text_lossy`]: #method.column_text_lossy
+    /// [`column_text_bytes`]: #method.column_text_bytes
+    /// [`step`]: #method.step
+    #[inline]
+    pub fn try_column_text(&mut self, index: usize) -> Result<Option<&str>, Error> {
+        match self.column_value_ref(index)? {
+            ValueRef::Null => Ok(None),
+            ValueRef::Text(bytes) => match core::str::from_utf8(bytes) {
+                Ok(s) => Ok(Some(s)),
+                Err(e) => {
+                    let message = format!("column {}: {}", index, e);
+                    Err(Error::with_message(SQLITE_MISMATCH, message))
+                }
+            },
+            _ => Err(Self::bad_column_type(index)),
+        }
+    }
+
+    /// Same to [`try_column_text`] except for replacing the invalid UTF-8 sequences with
+    /// U+FFFD (i.e. `String::from_utf8_lossy` .) The value is not copied if it is a valid UTF-8
+    /// string.
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// Returns `SQLITE_MISUSE` if the previous [`step`] did not return `true` or [`step`] was not
+    /// called, `SQLITE_RANGE` if `index` is out of range, or `SQLITE_MISMATCH` if the column value
+    /// type is neither Null nor Text.
+    ///
+    /// [`try_column_text`]: #method.try_column_text
+    /// [`step`]: #method.step
+    #[inline]
+    pub fn column_text_lossy(&mut self, index: usize) -> Result<Option<Cow<'_, str>>, Error> {
+        match self.column_value_ref(index)? {
+            ValueRef::Null => Ok(None),
+            ValueRef::Text(bytes) => Ok(Some(String::from_utf8_lossy(bytes))),
+            _ => Err(Self::bad_column_type(index)),
+        }
+    }
+
+    /// Wrapper of C function [`sqlite3_column_text`] and [`sqlite3_column_bytes`] .
+    ///
+    /// Returns the bytes of the value as TEXT without validating the encoding. The value is
+    /// converted as [`column_text_coerce`] does if the column value type is not Text; e.g. BLOB
+    /// is returned as it is, and 42 is b"42".
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// Returns `SQLITE_MISUSE` if the previous [`step`] did not return `true` or [`step`] was not
+    /// called, or `SQLITE_RANGE` if `index` is out of range.
+    ///
+    /// [`sqlite3_column_text`]: https://www.sqlite.org/c3ref/column_blob.html
+    /// [`sqlite3_column_bytes`]: https://www.sqlite.org/c3ref/column_blob.html
+    /// [`column_text_coerce`]: #method.column_text_coerce
+    /// [`step`]: #method.step
+    pub fn column_text_bytes(&mut self, index: usize) -> Result<Option<&[u8]>, Error> {
+        let index = self.column_index(index)?;
+        unsafe {
+            let ptr = sqlite3_column_text(self.raw, index);
+            if ptr.is_null() {
+                return Ok(None);
+            }
+            let len = sqlite3_column_bytes(self.raw, index) as usize;
+            if len == 0 {
+                Ok(Some(&[]))
+            } else {
+                Ok(Some(core::slice::from_raw_parts(ptr, len)))
+            }
+        }
+    }
+
+    /// Wrapper of C function [`sqlite3_column_int64`] .
+    ///
+    /// Unlike [`column_int`] , this method does not check the column value type but lets
+    /// libsqlite3 convert the value as follows:
+    ///
+    /// - NULL is 0.
+    /// - REAL is truncated toward 0 (and clamped to the range of i64.)
+    /// - TEXT and BLOB are parsed as the longest prefix that looks like a number, or 0 if there is
+    ///   no such prefix; e.g. '42' is 42, and '12abc' is 12.
+    ///
+    /// See [`Result Values From A Query`] for details.
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// Returns `SQLITE_MISUSE` if the previous [`step`] did not return `true` or [`step`] was not
+    /// called, or `SQLITE_RANGE` if `index` is out of range.
+    ///
+    /// [`sqlite3_column_int64`]: https://www.sqlite.org/c3ref/column_blob.html
+    /// [`column_int`]: #method.column_int
+    /// [`step`]: #method.step
+    /// [`Result Values From A Query`]: https://www.sqlite.org/c3ref/column_blob.html
+    #[inline]
+    pub fn column_int_coerce(&mut self, index: usize) -> Result<i64, Error> {
+        let index = self.column_index(index)?;
+        Ok(unsafe { sqlite3_column_int64(self.raw, index) })
+    }
+
+    /// Wrapper of C function [`sqlite3_column_double`] .
+    ///
+    /// This method does not check the column value type but lets libsqlite3 convert the value as
+    /// follows:
+    ///
+    /// - NULL is 0.0.
+    /// - INTEGER is converted to the nearest REAL.
+    /// - TEXT and BLOB are parsed as the longest prefix that looks like a number, or 0.0 if there
+    ///   is no such prefix.
+    ///
+    /// See [`Result Values From A Query`] for details.
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// Returns `SQLITE_MISUSE` if the previous [`step`] did not return `true` or [`step`] was not
+    /// called, or `SQLITE_RANGE` if `index` is out of range.
+    ///
+    /// [`sqlite3_column_double`]: https://www.sqlite.org/c3ref/column_blob.html
+    /// [`step`]: #method.step
+    /// [`Result Values From A Query`]: https://www.sqlite.org/c3ref/column_blob.html
+    #[inline]
+    pub fn column_double_coerce(&mut self, index: usize) -> Result<f64, Error> {
+        let index = self.column_index(index)?;
+        Ok(unsafe { sqlite3_column_double(self.raw, index) })
+    }
+
+    /// Wrapper of C function [`sqlite3_column_text`] and [`sqlite3_column_bytes`] .
+    ///
+    /// Unlike [`column_text`] , this method does not check the column value type but lets
+    /// libsqlite3 convert the value as follows:
+    ///
+    /// - NULL is `None` .
+    /// - INTEGER and REAL are rendered as decimal; e.g. 42 is '42', and 1.5 is '1.5'.
+    /// - BLOB is interpreted as TEXT as it is.
+    ///
+    /// See [`Result Values From A Query`] for details.
+    ///
+    /// Note that the conversion is done in place; after calling this method for an INTEGER,
+    /// REAL, or BLOB column, the strict accessors like [`column_int`] may see the converted TEXT
+    /// value until the next [`step`] .
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// Returns `SQLITE_MISUSE` if the previous [`step`] did not return `true` or [`step`] was not
+    /// called, `SQLITE_RANGE` if `index` is out of range, or `SQLITE_MISMATCH` if the value is
+    /// not a valid UTF-8 string.
+    ///
+    /// [`sqlite3_column_text`]: https://www.sqlite.org/c3ref/column_blob.html
+    /// [`sqlite3_column_bytes`]: https://www.sqlite.org/c3ref/column_blob.html
+    /// [`column_text`]: #method.column_text
+    /// [`column_int`]: #method.column_int
+    /// [`step`]: #method.step
+    /// [`Result Values From A Query`]: https://www.sqlite.org/c3ref/column_blob.html
+    pub fn column_text_coerce(&mut self, index: usize) -> Result<Option<&str>, Error> {
+        let bytes = match self.column_text_bytes(index)? {
+            None => return Ok(None),
+            Some(bytes) => bytes,
+        };
+        match core::str::from_utf8(bytes) {
+            Ok(s) => Ok(Some(s)),
+            Err(e) => {
+                let message = format!("column {}: {}", index, e);
+                Err(Error::with_message(SQLITE_MISMATCH, message))
+            }
+        }
+    }
+
+    /// Returns the value of the column `index` of the current row without copying.
+    ///
+    /// The returned value borrows `self` , so it must be dropped before the next [`step`] or
+    /// binding.
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// Returns `SQLITE_MISUSE` if the previous [`step`] did not return `true` or [`step`] was not
+    /// called, or `SQLITE_RANGE` if `index` is out of range.
+    ///
+    /// [`step`]: #method.step
+    #[inline]
+    pub fn column_ref(&mut self, index: usize) -> Result<ValueRef<'_>, Error> {
+        self.column_value_ref(index)
+    }
+
+    /// Wrapper of C function [`sqlite3_column_type`] and [`sqlite3_column_bytes`] .
+    ///
+    /// This method calls [`sqlite3_column_type`] first.
+    ///
+    /// If the value type is Null, returns `None` , or if the value type is Blob or Text, calls
+    /// [`sqlite3_column_bytes`] and returns the number of the bytes of the value.
+    ///
+    /// This method does not copy the value, however, libsqlite3 may still load the value from the
+    /// disk. To get the size without loading the value, select `length("column")` instead; for
+    /// a BLOB, libsqlite3 calculates it from the record header.
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the previous [`step`] did not returns `true` or [`step`] did not called.
+    ///
+    /// Panics if `index` is out of range.
+    ///
+    /// Panics if the column value type is neither Null, Blob, nor Text.
+    ///
+    /// [`step`]: #method.step
+    /// [`sqlite3_column_type`]: https://www.sqlite.org/c3ref/column_blob.html
+    /// [`sqlite3_column_bytes`]: https://www.sqlite.org/c3ref/column_blob.html
+    #[inline]
+    pub fn column_len(&mut self, index: usize) -> Option<usize> {
+        self.try_column_len(index)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Same to [`column_len`] except for returning `Err` instead of panicking.
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// Returns `SQLITE_MISUSE` if the previous [`step`] did not return `true` or [`step`] was not
+    /// called, `SQLITE_RANGE` if `index` is out of range, or `SQLITE_MISMATCH` if the column value
+    /// type is neither Null, Blob, nor Text.
+    ///
+    /// [`column_len`]: #method.column_len
+    /// [`step`]: #method.step
+    #[inline]
+    pub fn try_column_len(&mut self, index: usize) -> Result<Option<usize>, Error> {
+        let index = self.column_index(index)?;
+        unsafe {
+            match sqlite3_column_type(self.raw, index) {
+                SQLITE_NULL => Ok(None),
+                SQLITE_BLOB | SQLITE_TEXT => {
+                    Ok(Some(sqlite3_column_bytes(self.raw, index) as usize))
+                }
+                _ => Err(Self::bad_column_type(index as usize)),
+            }
+        }
+    }
+
+    /// Returns the BLOB value of the column `index` as a fixed-size array, or `None` if the value
+    /// is NULL.
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// Returns `SQLITE_MISUSE` if the previous [`step`] did not return `true` or [`step`] was not
+    /// called, `SQLITE_RANGE` if `index` is out of range, or `SQLITE_MISMATCH` if the column value
+    /// type is neither Null nor Blob, or if the length of the BLOB is not `N` .
+    ///
+    /// [`step`]: #method.step
+    #[inline]
+    pub fn column_array<const N: usize>(&mut self, index: usize) -> Result<Option<[u8; N]>, Error> {
+        self.column_value(index)
+    }
+
+    /// Returns the value of the column `index` as `bool` accepting the following values, or
+    /// `None` if the value is NULL.
+    ///
+    /// - INTEGER; 0 is `false` , and any other number is `true` .
+    /// - TEXT 't' , 'true' , or 'yes' as `true` , and 'f' , 'false' , or 'no' as `false` . They
+    ///   are case insensitive.
+    ///
+    /// Unlike this method, [`get`] accepts only INTEGER 0 and 1 for `bool` .
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// Returns `SQLITE_MISUSE` if the previous [`step`] did not return `true` or [`step`] was not
+    /// called, `SQLITE_RANGE` if `index` is out of range, or `SQLITE_MISMATCH` if the value is
+    /// not acceptable.
+    ///
+    /// [`get`]: #method.get
+    /// [`step`]: #method.step
+    pub fn column_bool_lenient(&mut self, index: usize) -> Result<Option<bool>, Error> {
+        const TRUE: [&[u8]; 3] = [b"t", b"true", b"yes"];
+        const FALSE: [&[u8]; 3] = [b"f", b"false", b"no"];
+
+        match self.column_value_ref(index)? {
+            ValueRef::Null => Ok(None),
+            ValueRef::Integer(i) => Ok(Some(i != 0)),
+            ValueRef::Text(s) if TRUE.iter().any(|t| t.eq_ignore_ascii_case(s)) => Ok(Some(true)),
+            ValueRef::Text(s) if FALSE.iter().any(|f| f.eq_ignore_ascii_case(s)) => Ok(Some(false)),
+            ValueRef::Text(s) => {
+                let message = format!(
+                    "column {}: {:?} is not a boolean",
+                    index,
+                    String::from_utf8_lossy(s)
+                );
+                Err(Error::with_message(SQLITE_MISMATCH, message))
+            }
+            _ => Err(Self::bad_column_type(index)),
+        }
+    }
+
+    fn bad_column_type(index: usize) -> Error {
+        let message = format!("column {}: bad column type", index);
+        Error::with_message(SQLITE_MISMATCH, message)
+    }
+
+    /// Returns the number of columns in the result set.
+    #[inline]
+    pub fn column_count(&self) -> usize {
+        self.column_count as usize
+    }
+
+    /// Wrapper of C function [`sqlite3_bind_parameter_count`] .
+    ///
+    /// Returns the largest index of the parameters.
+    ///
+    /// [`sqlite3_bind_parameter_count`]: https://www.sqlite.org/c3ref/bind_parameter_count.html
+    #[inline]
+    pub fn parameter_count(&self) -> usize {
+        unsafe { sqlite3_bind_parameter_count(self.raw) as usize }
+    }
+
+    /// Wrapper of C function [`sqlite3_bind_parameter_index`] .
+    ///
+    /// Returns the index of the parameter `name` including the prefix (e.g. ":id" ), or `None`
+    /// if no such parameter is found.
+    ///
+    /// Note that the index starts at 1, not 0.
+    ///
+    /// [`sqlite3_bind_parameter_index`]: https://www.sqlite.org/c3ref/bind_parameter_index.html
+    pub fn parameter_index(&self, name: &str) -> Option<usize> {
+        let name = CString::new(name).ok()?;
+        match unsafe { sqlite3_bind_parameter_index(self.raw, name.as_ptr()) } {
+            0 => None,
+            index => Some(index as usize),
+        }
+    }
+
+    /// Wrapper of C function [`sqlite3_bind_parameter_name`] .
+    ///
+    /// Returns the name of the parameter `index` including the prefix (e.g. ":id" ), or `None`
+    /// if the parameter is nameless (i.e. "?") or `index` is out of range.
+    ///
+    /// Note that `index` starts at 1, not 0.
+    ///
+    /// [`sqlite3_bind_parameter_name`]: https://www.sqlite.org/c3ref/bind_parameter_name.html
+    pub fn parameter_name(&self, index: usize) -> Option<&str> {
+        let index = c_int::try_from(index).ok()?;
+        let ptr = unsafe { sqlite3_bind_parameter_name(self.raw, index) };
+        if ptr.is_null() {
+            None
+        } else {
+            unsafe { CStr::from_ptr(ptr) }.to_str().ok()
+        }
+    }
+
+    /// Wrapper of C function [`sqlite3_column_name`] .
+    ///
+    /// Returns the name of the column `index` in the result set, or `None` if `index` is out of
+    /// range.
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// [`sqlite3_column_name`]: https://www.sqlite.org/c3ref/column_name.html
+    #[inline]
+    pub fn column_name(&self, index: usize) -> Option<&str> {
+        if self.column_count() <= index {
+            return None;
+        }
+
+        let ptr = unsafe { sqlite3_column_name(self.raw, index as c_int) };
+        if ptr.is_null() {
+            None
+        } else {
+            unsafe { CStr::from_ptr(ptr) }.to_str().ok()
+        }
+    }
+
+    /// Wrapper of C function [`sqlite3_stmt_readonly`] .
+    ///
+    /// Returns `true` if the statement makes no direct change to the database file.
+    ///
+    /// [`sqlite3_stmt_readonly`]: https://www.sqlite.org/c3ref/stmt_readonly.html
+    #[inline]
+    pub fn is_readonly(&self) -> bool {
+        unsafe { sqlite3_stmt_readonly(self.raw) != 0 }
+    }
+
+    /// Wrapper of C function [`sqlite3_stmt_busy`] .
+    ///
+    /// Returns `true` if the previous [`step`] returned `true` and the statement has not been
+    /// reset yet; i.e. the statement is in the middle of the iteration.
+    ///
+    /// [`step`]: #method.step
+    /// [`sqlite3_stmt_busy`]: https://www.sqlite.org/c3ref/stmt_busy.html
+    #[inline]
+    pub fn is_busy(&self) -> bool {
+        let busy = unsafe { sqlite3_stmt_busy(self.raw) != 0 };
+        debug_assert_eq!(self.is_row(), busy);
+        busy
+    }
+
+    /// Wrapper of C function [`sqlite3_sql`] .
+    ///
+    /// Returns the SQL text used to create the statement.
+    ///
+    /// [`sqlite3_sql`]: https://www.sqlite.org/c3ref/expanded_sql.html
+    #[inline]
+    pub fn sql(&self) -> &str {
+        let ptr = unsafe { sqlite3_sql(self.raw) };
+        if ptr.is_null() {
+            ""
+        } else {
+            // The SQL was passed as `&str` .
+            unsafe { CStr::from_ptr(ptr) }.to_str().unwrap_or_default()
+        }
+    }
+
+    /// Wrapper of C function [`sqlite3_expanded_sql`] .
+    ///
+    /// Returns the SQL text with the bound parameters substituted; e.g. TEXT as a quoted string
+    /// literal and BLOB as a hex literal like `x'0102'` .
+    ///
+    /// Returns `None` if libsqlite3 fails to build the text because of out of memory or because
+    /// the text exceeds `SQLITE_LIMIT_LENGTH` (that may happen with a large BLOB, whose hex
+    /// literal is twice as large as the BLOB.) If libsqlite3 is built with option
+    /// `SQLITE_TRACE_SIZE_LIMIT` , TEXT and BLOB longer than the limit are truncated.
+    ///
+    /// Invalid UTF-8 sequences in the bound TEXT are replaced with `U+FFFD` .
+    ///
+    /// [`sqlite3_expanded_sql`]: https://www.sqlite.org/c3ref/expanded_sql.html
+    pub fn expanded_sql(&self) -> Option<String> {
+        unsafe {
+            let ptr = sqlite3_expanded_sql(self.raw);
+            if ptr.is_null() {
+                return None;
+            }
+
+            let ret = CStr::from_ptr(ptr).to_string_lossy().into_owned();
+            sqlite3_free(ptr as *mut c_void);
+            Some(ret)
+        }
+    }
+
+    /// Wrapper of C function [`sqlite3_normalized_sql`] .
+    ///
+    /// Returns the SQL text with the literals replaced with `?` and the keywords and the
+    /// whitespaces normalized, or `None` if libsqlite3 fails to build it.
+    ///
+    /// This method is enabled by feature "normalized-sql" . Returns `None` without calling the C
+    /// function unless libsqlite3 is built with option `SQLITE_ENABLE_NORMALIZE` (i.e.
+    /// [`compile_option_used`] returns `true` for "ENABLE_NORMALIZE".)
+    ///
+    /// [`sqlite3_normalized_sql`]: https://www.sqlite.org/c3ref/expanded_sql.html
+    /// [`compile_option_used`]: fn.compile_option_used.html
+    #[cfg(feature = "normalized-sql")]
+    pub fn normalized_sql(&self) -> Option<&str> {
+        #[link(name = "sqlite3")]
+        extern "C" {
+            fn sqlite3_normalized_sql(pstmt: *mut sqlite3_stmt) -> *const c_char;
+        }
+
+        if !crate::compile_option_used("ENABLE_NORMALIZE") {
+            return None;
+        }
+
+        // The returned text is owned by the statement.
+        let ptr = unsafe { sqlite3_normalized_sql(self.raw) };
+        if ptr.is_null() {
+            None
+        } else {
+            unsafe { CStr::from_ptr(ptr) }.to_str().ok()
+        }
+    }
+
+    /// Returns the current row.
+    ///
+    /// Returns `SQLITE_MISUSE` if the previous [`step`] did not return `true` or [`step`] was not
+    /// called.
+    ///
+    /// [`step`]: #method.step
+    #[inline]
+    pub fn row(&mut self) -> Result<Row<'_>, Error> {
+        self.check_row()?;
+        Ok(crate::row_from_stmt(self))
+    }
+
+    /// Copies all the column values of the current row into `Vec` , so that they outlive the next
+    /// [`step`] . See [`Row::to_values`] for details.
+    ///
+    /// Returns `SQLITE_MISUSE` if the previous [`step`] did not return `true` or [`step`] was not
+    /// called.
+    ///
+    /// [`step`]: #method.step
+    /// [`Row::to_values`]: struct.Row.html#method.to_values
+    #[inline]
+    pub fn current_row_values(&mut self) -> Result<Vec<Value>, Error> {
+        self.row().map(|row| row.to_values())
+    }
+
+    /// Executes the statement from the beginning and returns all the rows converted into `T` .
+    ///
+    /// If the statement is in the middle of the iteration, it is reset at first.
+    /// The bound parameters are kept.
+    ///
+    /// See also [`query_as_iter`] , the streaming variant.
+    ///
+    /// [`query_as_iter`]: #method.query_as_iter
+    #[inline]
+    pub fn query_as<T>(&mut self) -> Result<Vec<T>, Error>
+    where
+        T: FromRow,
+    {
+        self.query_as_iter().collect()
+    }
+
+    /// Executes the statement with "RETURNING" clause (e.g. `INSERT ... RETURNING "id"`) from
+    /// the beginning, and returns all the returned rows converted into `T` .
+    ///
+    /// Unlike [`query_as`] , the statement is always finished (i.e. reset) before returning, even
+    /// if an error occurs; libsqlite3 makes all the changes at the first step, and the changes are
+    /// committed when the statement is reset in autocommit mode.
+    /// The bound parameters are kept.
+    ///
+    /// "RETURNING" clause is available since SQLite 3.35.0. Returns `SQLITE_ERROR` with the
+    /// message if the runtime SQLite is older than that.
+    ///
+    /// [`query_as`]: #method.query_as
+    pub fn execute_returning<T>(&mut self) -> Result<Vec<T>, Error>
+    where
+        T: FromRow,
+    {
+        check_returning(crate::version_number())?;
+
+        if self.is_row() {
+            self.reset();
+        }
+
+        let mut ret = Vec::new();
+        loop {
+            let row = match self.step2() {
+                Ok(StepResult::Row) => self.row().and_then(|row| T::from_row(&row)),
+                Ok(StepResult::Done { .. }) => return Ok(ret),
+                Err(e) => return Err(e),
+            };
+            match row {
+                Ok(row) => ret.push(row),
+                Err(e) => {
+                    self.reset();
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// Executes the statement from the beginning and returns an iterator of the rows converted
+    /// into `T` .
+    ///
+    /// If the statement is in the middle of the iteration, it is reset at first.
+    /// The bound parameters are kept.
+    #[inline]
+    pub fn query_as_iter<T>(&mut self) -> QueryAs<'_, T>
+    where
+        T: FromRow,
+    {
+        if self.is_row() {
+            self.reset();
+        }
+        crate::row_query_as(self)
+    }
+
+    /// Executes the statement from the beginning and returns all the rows as maps from the column
+    /// name to the value.
+    ///
+    /// If more than one column has the same name, the map holds the value of the last one; give
+    /// the columns distinct aliases to keep all of them.
+    /// Invalid UTF-8 sequences in TEXT are replaced with `U+FFFD` .
+    ///
+    /// If the statement is in the middle of the iteration, it is reset at first.
+    /// The bound parameters are kept.
+    pub fn rows_as_maps(&mut self) -> Result<Vec<HashMap<String, Value>>, Error> {
+        if self.is_row() {
+            self.reset();
+        }
+
+        let mut rows = Vec::new();
+        while self.step()? {
+            let mut row = HashMap::with_capacity(self.column_count());
+            for i in 0..self.column_count() {
+                let name = self.column_name(i).unwrap_or_default().to_string();
+                row.insert(name, self.column_value_ref(i)?.to_owned());
+            }
+            rows.push(row);
+        }
+        Ok(rows)
+    }
+
+    /// Returns the value of the column `index` of the current row converted into `T` .
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// Returns `SQLITE_MISUSE` if the previous [`step`] did not return `true` or [`step`] was not
+    /// called, `SQLITE_RANGE` if `index` is out of range, or `SQLITE_MISMATCH` if the value cannot
+    /// be converted into `T` .
+    ///
+    /// [`step`]: #method.step
+    #[inline]
+    pub fn get<'a, T>(&'a mut self, index: usize) -> Result<T, Error>
+    where
+        T: FromSql<'a>,
+    {
+        self.column_value(index)
+    }
+
+    /// Implementation of [`get`] .
+    ///
+    /// [`get`]: #method.get
+    pub(crate) fn column_value<'a, T>(&'a self, index: usize) -> Result<T, Error>
+    where
+        T: FromSql<'a>,
+    {
+        let value = self.column_value_ref(index)?;
+        T::column_result(value).map_err(|e| {
+            let message = format!("column {}: {}", index, e);
+            Error::with_message(SQLITE_MISMATCH, message)
+        })
+    }
+
+    /// Checks that the current row is available and `index` is in range, and converts `index`
+    /// into `c_int` .
+    fn column_index(&self, index: usize) -> Result<c_int, Error> {
+        self.check_row()?;
+        match c_int::try_from(index) {
+            Ok(i) if i < self.column_count => Ok(i),
+            _ => {
+                let message = format!("column index {} is out of range", index);
+                Err(Error::with_message(SQLITE_RANGE, message))
+            }
+        }
+    }
 
-        let index = index as c_int;
+    /// Returns the value of the column `index` of the current row without copying.
+    pub(crate) fn column_value_ref(&self, index: usize) -> Result<ValueRef<'_>, Error> {
+        let index = self.column_index(index)?;
         unsafe {
             match sqlite3_column_type(self.raw, index) {
-                SQLITE_NULL => None,
+                SQLITE_INTEGER => Ok(ValueRef::Integer(sqlite3_column_int64(self.raw, index))),
+                SQLITE_FLOAT => Ok(ValueRef::Real(sqlite3_column_double(self.raw, index))),
+                SQLITE_TEXT => {
+                    let ptr = sqlite3_column_text(self.raw, index);
+                    let len = sqlite3_column_bytes(self.raw, index) as usize;
+                    if len == 0 {
+                        Ok(ValueRef::Text(&[]))
+                    } else {
+                        Ok(ValueRef::Text(core::slice::from_raw_parts(ptr, len)))
+                    }
+                }
                 SQLITE_BLOB => {
                     let ptr = sqlite3_column_blob(self.raw, index) as *const u8;
                     let len = sqlite3_column_bytes(self.raw, index) as usize;
-                    Some(core::slice::from_raw_parts(ptr, len))
+                    if len == 0 {
+                        Ok(ValueRef::Blob(&[]))
+                    } else {
+                        Ok(ValueRef::Blob(core::slice::from_raw_parts(ptr, len)))
+                    }
                 }
-                _ => panic!("Bad column type"),
+                _ => Ok(ValueRef::Null),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Connection, Value, ValueRef, SQLITE_ERROR, SQLITE_MISMATCH, SQLITE_MISUSE, SQLITE_RANGE,
+    };
+
+    const SQLITE_CONSTRAINT: i32 = 19;
+
+    #[test]
+    fn get() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let mut stmt = con
+            .stmt_once("SELECT 1, 300, -1, 1.5, 'foo', x'0102', NULL")
+            .unwrap();
+        assert_eq!(Err(SQLITE_MISUSE), stmt.get::<i64>(0).map_err(|e| e.code()));
+
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(1), stmt.get::<i64>(0));
+        assert_eq!(Ok(true), stmt.get::<bool>(0));
+        assert_eq!(Ok(300), stmt.get::<u16>(1));
+        assert_eq!(Ok(-1), stmt.get::<i32>(2));
+        assert_eq!(Ok(1.5), stmt.get::<f64>(3));
+        assert_eq!(Ok(1.0), stmt.get::<f64>(0));
+        assert_eq!(Ok("foo"), stmt.get::<&str>(4));
+        assert_eq!(Ok("foo".to_string()), stmt.get::<String>(4));
+        assert_eq!(Ok(vec![1, 2]), stmt.get::<Vec<u8>>(5));
+        assert_eq!(Ok(None), stmt.get::<Option<Vec<u8>>>(6));
+        assert_eq!(Ok(Some(1)), stmt.get::<Option<i64>>(0));
+
+        // Errors
+        let e = stmt.get::<i8>(1).unwrap_err();
+        assert_eq!(SQLITE_MISMATCH, e.code());
+        assert!(e.message().unwrap().contains("300"));
+        assert_eq!(
+            Err(SQLITE_MISMATCH),
+            stmt.get::<u64>(2).map_err(|e| e.code())
+        );
+        assert_eq!(
+            Err(SQLITE_MISMATCH),
+            stmt.get::<bool>(1).map_err(|e| e.code())
+        );
+        assert_eq!(
+            Err(SQLITE_MISMATCH),
+            stmt.get::<i64>(4).map_err(|e| e.code())
+        );
+        assert_eq!(
+            Err(SQLITE_MISMATCH),
+            stmt.get::<i64>(6).map_err(|e| e.code())
+        );
+        assert_eq!(Err(SQLITE_RANGE), stmt.get::<i64>(7).map_err(|e| e.code()));
+    }
+
+    #[test]
+    fn step2() {
+        use crate::StepResult;
+
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("value" INTEGER)"#)
+            .unwrap();
+
+        let mut insert = con
+            .stmt_once(r#"INSERT INTO "foo" VALUES (1), (2), (3)"#)
+            .unwrap();
+        let mut delete = con
+            .stmt_once(r#"DELETE FROM "foo" WHERE "value" = 1"#)
+            .unwrap();
+        let mut select = con.stmt_once(r#"SELECT "value" FROM "foo""#).unwrap();
+
+        let inserted = insert.step2().unwrap();
+        assert_eq!(Ok(StepResult::Done { changes: 1 }), delete.step2());
+        // Not clobbered by the DELETE.
+        assert_eq!(StepResult::Done { changes: 3 }, inserted);
+
+        assert_eq!(Ok(StepResult::Row), select.step2());
+        assert_eq!(Ok(StepResult::Row), select.step2());
+        assert_eq!(Ok(StepResult::Done { changes: 0 }), select.step2());
+        // Reset on done
+        assert_eq!(Ok(StepResult::Row), select.step2());
+    }
+
+    #[test]
+    fn readonly_busy() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(
+            r#"CREATE TABLE "foo" ("value" INTEGER);
+               INSERT INTO "foo" VALUES (1), (2);"#,
+        )
+        .unwrap();
+
+        let mut insert = con.stmt_once(r#"INSERT INTO "foo" VALUES (3)"#).unwrap();
+        assert!(!insert.is_readonly());
+        assert_eq!(Ok(false), insert.step());
+        assert!(!insert.is_busy());
+
+        let mut select = con.stmt_once(r#"SELECT * FROM "foo""#).unwrap();
+        assert!(select.is_readonly());
+        assert!(!select.is_busy());
+        assert_eq!(Ok(true), select.step());
+        assert!(select.is_busy());
+        select.reset();
+        assert!(!select.is_busy());
+
+        while select.step().unwrap() {
+            assert!(select.is_busy());
+        }
+        assert!(!select.is_busy());
+    }
+
+    #[test]
+    fn send() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("value" INTEGER)"#)
+            .unwrap();
+
+        let mut stmt = con.stmt_once(r#"INSERT INTO "foo" VALUES (1)"#).unwrap();
+        // `stmt` is stepped in another thread while `con` is used in this one.
+        let handle = std::thread::spawn(move || {
+            for _ in 0..100 {
+                assert_eq!(Ok(false), stmt.step());
+            }
+            stmt
+        });
+        for _ in 0..100 {
+            con.execute_batch(r#"INSERT INTO "foo" VALUES (2)"#)
+                .unwrap();
+        }
+        let mut stmt = handle.join().unwrap();
+        assert_eq!(Ok(false), stmt.step());
+
+        let sum = con.query_one(r#"SELECT sum("value") FROM "foo""#, &[]);
+        assert_eq!(Ok(Some((101 + 200,))), sum);
+    }
+
+    #[test]
+    fn sql() {
+        let mut con = Connection::open_memory_db().unwrap();
+        const SQL: &str = "SELECT ?1, ?2, ?3, ?4, ?5";
+        let mut stmt = con.stmt_once(SQL).unwrap();
+        assert_eq!(SQL, stmt.sql());
+        assert_eq!(
+            Some("SELECT NULL, NULL, NULL, NULL, NULL".to_string()),
+            stmt.expanded_sql()
+        );
+
+        stmt.bind(1, &42).unwrap();
+        stmt.bind(2, "it's").unwrap();
+        stmt.bind(3, &[1_u8, 0xab][..]).unwrap();
+        stmt.bind(4, &1.5).unwrap();
+        let expanded = stmt.expanded_sql().unwrap();
+        assert_eq!("SELECT 42, 'it''s', x'01ab', 1.5, NULL", expanded);
+        assert_eq!(SQL, stmt.sql());
+
+        // Only the first statement
+        let stmt = con.stmt_once("SELECT 1; SELECT 2").unwrap();
+        assert_eq!("SELECT 1;", stmt.sql());
+    }
+
+    #[cfg(feature = "normalized-sql")]
+    #[test]
+    fn normalized_sql() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let stmt = con.stmt_once("SELECT 42, 'foo'").unwrap();
+        let normalized = stmt.normalized_sql();
+
+        if crate::compile_option_used("ENABLE_NORMALIZE") {
+            let normalized = normalized.unwrap();
+            assert!(!normalized.contains("42"), "{}", normalized);
+            assert!(!normalized.contains("foo"), "{}", normalized);
+        } else {
+            assert_eq!(None, normalized);
+        }
+    }
+
+    #[test]
+    fn row() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let mut stmt = con.stmt_once("SELECT 1, 'foo', x''").unwrap();
+        assert!(stmt.row().is_err());
+
+        assert_eq!(Ok(true), stmt.step());
+        let row = stmt.row().unwrap();
+        assert_eq!(3, row.column_count());
+        assert_eq!(Ok(ValueRef::Integer(1)), row.get_ref(0));
+        assert_eq!(Ok(ValueRef::Blob(&[])), row.get_ref(2));
+
+        let (a, b): (i64, &str) = (row.get(0).unwrap(), row.get(1).unwrap());
+        assert_eq!((1, "foo"), (a, b));
+    }
+
+    #[test]
+    fn query_as() {
+        use crate::{Error, FromRow, Row, SQLITE_MISMATCH};
+
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(
+            r#"CREATE TABLE "foo" ("_id" INTEGER PRIMARY KEY, "name" TEXT, "value" BLOB);
+               INSERT INTO "foo" ("name", "value") VALUES ('a', x'01'), ('b', NULL);"#,
+        )
+        .unwrap();
+
+        const SELECT: &str = r#"SELECT "_id", "name", "value" FROM "foo" ORDER BY "_id""#;
+
+        let rows: Vec<(i64, String, Option<Vec<u8>>)> =
+            con.stmt(SELECT).unwrap().query_as().unwrap();
+        let expected = vec![
+            (1, "a".to_string(), Some(vec![1])),
+            (2, "b".to_string(), None),
+        ];
+        assert_eq!(expected, rows);
+
+        #[derive(Debug, PartialEq)]
+        struct Foo {
+            id: i64,
+            name: String,
+            value: Option<Vec<u8>>,
+        }
+
+        impl FromRow for Foo {
+            fn from_row(row: &Row<'_>) -> Result<Self, Error> {
+                Ok(Self {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    value: row.get(2)?,
+                })
+            }
+        }
+
+        let mut iter = con.stmt(SELECT).unwrap().query_as_iter::<Foo>();
+        let first = iter.next().unwrap().unwrap();
+        assert_eq!(1, first.id);
+        assert_eq!("a", first.name);
+        assert_eq!(Some(vec![1]), first.value);
+        assert_eq!(2, iter.next().unwrap().unwrap().id);
+        assert!(iter.next().is_none());
+        assert!(iter.next().is_none());
+
+        // Mismatch of the column count.
+        let e = con
+            .stmt(SELECT)
+            .unwrap()
+            .query_as::<(i64, String)>()
+            .unwrap_err();
+        assert_eq!(SQLITE_MISMATCH, e.code());
+        assert_eq!(Some("expected 2 columns, but the row has 3"), e.message());
+    }
+
+    #[test]
+    fn bind() {
+        use crate::Value;
+
+        let mut con = Connection::open_memory_db().unwrap();
+        let mut stmt = con.stmt_once("SELECT ?1, ?2, ?3, ?4, ?5, ?6").unwrap();
+
+        stmt.bind(1, &42_u32).unwrap();
+        stmt.bind(2, &1.5).unwrap();
+        stmt.bind(3, &"foo".to_string()).unwrap();
+        stmt.bind(4, &vec![1_u8, 2]).unwrap();
+        stmt.bind(5, &Option::<i64>::None).unwrap();
+        stmt.bind(6, &Value::Text("bar".to_string())).unwrap();
+        assert!(stmt.bind(1, &u64::MAX).is_err());
+        assert!(stmt.bind(7, &1).is_err());
+
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(42), stmt.get::<i64>(0));
+        assert_eq!(Ok(1.5), stmt.get::<f64>(1));
+        assert_eq!(Ok("foo"), stmt.get::<&str>(2));
+        assert_eq!(Ok(&[1_u8, 2][..]), stmt.get::<&[u8]>(3));
+        assert_eq!(Ok(None), stmt.get::<Option<i64>>(4));
+        assert_eq!(Ok("bar"), stmt.get::<&str>(5));
+    }
+
+    #[test]
+    fn bind_pointer() {
+        use crate::{
+            sqlite3, sqlite3_context as Context, sqlite3_result_int64, sqlite3_result_null,
+            sqlite3_value as Value,
+        };
+        use std::ffi::CStr;
+        use std::os::raw::{c_char, c_int, c_void};
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        extern "C" {
+            fn sqlite3_create_function_v2(
+                pdb: *mut sqlite3,
+                zname: *const c_char,
+                narg: c_int,
+                etextrep: c_int,
+                papp: *mut c_void,
+                xfunc: Option<unsafe extern "C" fn(*mut Context, c_int, *mut *mut Value)>,
+                xstep: Option<unsafe extern "C" fn(*mut Context, c_int, *mut *mut Value)>,
+                xfinal: Option<unsafe extern "C" fn(*mut Context)>,
+                xdestroy: Option<unsafe extern "C" fn(*mut c_void)>,
+            ) -> c_int;
+            fn sqlite3_value_pointer(pval: *mut Value, ztype: *const c_char) -> *mut c_void;
+        }
+
+        struct Payload {
+            values: Vec<i64>,
+            dropped: Arc<AtomicBool>,
+        }
+
+        impl Drop for Payload {
+            fn drop(&mut self) {
+                self.dropped.store(true, Ordering::SeqCst);
             }
         }
+
+        const TYPE_NAME: &[u8] = b"mouse-sqlite3-test-payload\0";
+        fn type_name() -> &'static CStr {
+            CStr::from_bytes_with_nul(TYPE_NAME).unwrap()
+        }
+
+        unsafe extern "C" fn payload_sum(ctx: *mut Context, _: c_int, argv: *mut *mut Value) {
+            let ptr = sqlite3_value_pointer(*argv, type_name().as_ptr()) as *const Payload;
+            match ptr.as_ref() {
+                Some(payload) => sqlite3_result_int64(ctx, payload.values.iter().sum()),
+                None => sqlite3_result_null(ctx),
+            }
+        }
+
+        let mut con = Connection::open_memory_db().unwrap();
+        let code = unsafe {
+            sqlite3_create_function_v2(
+                con.raw(),
+                b"payload_sum\0".as_ptr() as *const c_char,
+                1,
+                1, // SQLITE_UTF8
+                core::ptr::null_mut(),
+                Some(payload_sum),
+                None,
+                None,
+                None,
+            )
+        };
+        assert_eq!(0, code);
+
+        let dropped = Arc::new(AtomicBool::new(false));
+        let payload = Payload {
+            values: vec![1, 2, 3],
+            dropped: dropped.clone(),
+        };
+
+        let mut stmt = con.stmt_once("SELECT payload_sum(?1), ?1 IS NULL").unwrap();
+        stmt.bind_pointer(1, Box::new(payload), type_name())
+            .unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(6), stmt.get::<i64>(0));
+        assert_eq!(Ok(true), stmt.get::<bool>(1));
+        assert!(!dropped.load(Ordering::SeqCst));
+
+        // Another type name does not expose the pointer.
+        let other = CStr::from_bytes_with_nul(b"mouse-sqlite3-test-other\0").unwrap();
+        stmt.bind_pointer(1, Box::new(0_i64), other).unwrap();
+        assert!(dropped.load(Ordering::SeqCst));
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(None), stmt.get::<Option<i64>>(0));
+
+        // Borrowed pointer
+        let mut borrowed = Payload {
+            values: vec![10, 20],
+            dropped: Arc::new(AtomicBool::new(false)),
+        };
+        let ptr = &mut borrowed as *mut Payload as *mut c_void;
+        unsafe { stmt.bind_pointer_raw(1, ptr, type_name(), None).unwrap() };
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(30), stmt.get::<i64>(0));
+        drop(stmt);
+        assert!(!borrowed.dropped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn reprepare() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(
+            r#"CREATE TABLE "foo" ("value" INTEGER);
+               INSERT INTO "foo" VALUES (1), (2), (3)"#,
+        )
+        .unwrap();
+
+        let mut stmt = con
+            .stmt_once(r#"SELECT "value" FROM "foo" WHERE "value" > ?1"#)
+            .unwrap();
+        stmt.bind_int(1, 1).unwrap();
+
+        // The bound parameters are kept.
+        assert_eq!(Ok(()), stmt.reprepare());
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(2), stmt.get::<i64>(0));
+
+        // Fails if the SQL no longer compiles.
+        stmt.reset();
+        con.execute_batch(r#"DROP TABLE "foo""#).unwrap();
+        assert!(stmt.reprepare().is_err());
+    }
+
+    #[test]
+    fn state() {
+        use crate::{Error, StmtState};
+
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("value" INTEGER NOT NULL)"#)
+            .unwrap();
+
+        let assert_misuse = |e: Error, actual: &str| {
+            assert_eq!(SQLITE_MISUSE, e.code());
+            let expected = format!("misuse: expected Row, but the statement is {}", actual);
+            assert_eq!(Some(expected.as_str()), e.message());
+        };
+
+        // Before step
+        let mut stmt = con.stmt_once(r#"SELECT "value" FROM "foo""#).unwrap();
+        assert_eq!(StmtState::Ready, stmt.state());
+        assert_misuse(stmt.try_column_int(0).unwrap_err(), "Ready");
+        assert_misuse(stmt.row().map(|_| ()).unwrap_err(), "Ready");
+
+        // After the last row
+        con.execute_batch(r#"INSERT INTO "foo" VALUES (1)"#)
+            .unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(StmtState::Row, stmt.state());
+        assert_eq!(Some(1), stmt.column_int(0));
+        assert_eq!(Ok(false), stmt.step());
+        assert_eq!(StmtState::Done, stmt.state());
+        assert_misuse(stmt.try_column_int(0).unwrap_err(), "Done");
+        assert_misuse(stmt.current_row_values().unwrap_err(), "Done");
+
+        // After reset
+        assert_eq!(Ok(true), stmt.step());
+        stmt.reset();
+        assert_eq!(StmtState::Ready, stmt.state());
+        assert_misuse(stmt.get::<i64>(0).unwrap_err(), "Ready");
+
+        // After an error
+        let mut stmt = con.stmt_once(r#"INSERT INTO "foo" VALUES (?1)"#).unwrap();
+        let e = stmt.step().unwrap_err();
+        let state = StmtState::Errored(e.code());
+        assert_eq!(state, stmt.state());
+        assert_misuse(stmt.try_column_text(0).unwrap_err(), &state.to_string());
+        assert!(state.to_string().starts_with("Errored("));
+
+        // Binding resets the statement running the previous execution.
+        let mut stmt = con.stmt_once(r#"SELECT "value" + ?1 FROM "foo""#).unwrap();
+        stmt.bind(1, &1).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        stmt.bind(1, &2).unwrap();
+        assert_eq!(StmtState::Ready, stmt.state());
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Some(3), stmt.column_int(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "misuse: expected Row, but the statement is Done")]
+    fn column_after_done() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let mut stmt = con.stmt_once("SELECT 1 WHERE 0").unwrap();
+        assert_eq!(Ok(false), stmt.step());
+        stmt.column_int(0);
+    }
+
+    #[test]
+    fn debug() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("value" INTEGER NOT NULL)"#)
+            .unwrap();
+
+        let mut stmt = con.stmt_once("SELECT 1, ?1").unwrap();
+        let s = format!("{:?}", stmt);
+        assert!(s.contains("SELECT 1, ?1"));
+        assert!(s.contains("state: Ready"));
+        assert!(s.contains("column_count: 2"));
+        assert!(s.contains("parameter_count: 1"));
+        assert!(s.contains("busy: false"));
+
+        assert_eq!(Ok(true), stmt.step());
+        let s = format!("{:?}", stmt);
+        assert!(s.contains("state: Row"));
+        assert!(s.contains("busy: true"));
+
+        // Right after an error
+        let mut stmt = con.stmt_once(r#"INSERT INTO "foo" VALUES (NULL)"#).unwrap();
+        assert!(stmt.step().is_err());
+        assert!(format!("{:?}", stmt).contains("INSERT"));
+    }
+
+    #[test]
+    fn clear() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let mut stmt = con.stmt_once("SELECT ?1").unwrap();
+
+        stmt.bind_int(1, 5).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(5), stmt.get::<i64>(0));
+
+        assert_eq!(Ok(()), stmt.clear());
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(None), stmt.get::<Option<i64>>(0));
+    }
+
+    #[test]
+    fn clear_bindings() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let mut stmt = con.stmt_once("SELECT ?1, ?2").unwrap();
+        stmt.bind_int(1, 5).unwrap();
+        stmt.bind_text(2, "foo").unwrap();
+
+        // The bindings survive `reset()` .
+        assert_eq!(Ok(true), stmt.step());
+        stmt.reset();
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(5), stmt.get::<i64>(0));
+        assert_eq!(Ok("foo"), stmt.get::<&str>(1));
+
+        // `clear_bindings()` sets them NULL.
+        assert_eq!(Ok(()), stmt.clear_bindings());
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(None), stmt.get::<Option<i64>>(0));
+        assert_eq!(Ok(None), stmt.get::<Option<&str>>(1));
+    }
+
+    #[test]
+    fn last_changes() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(
+            r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "value" INTEGER);
+               INSERT INTO "foo" VALUES (1, 0), (2, 0), (3, 0)"#,
+        )
+        .unwrap();
+
+        let mut select = con.stmt_once(r#"SELECT "id" FROM "foo""#).unwrap();
+        let mut update = con
+            .stmt_once(r#"UPDATE "foo" SET "value" = "value" + 1 WHERE "id" <= ?"#)
+            .unwrap();
+        let mut delete = con
+            .stmt_once(r#"DELETE FROM "foo" WHERE "id" = ?"#)
+            .unwrap();
+        assert_eq!(None, update.last_changes());
+
+        // Interleave the statements while `select` is running.
+        assert_eq!(Ok(true), select.step());
+        update.bind(1, &2).unwrap();
+        assert_eq!(Ok(false), update.step());
+        delete.bind(1, &3).unwrap();
+        assert_eq!(Ok(false), delete.step());
+        assert_eq!(Some(2), update.last_changes());
+        assert_eq!(Some(1), delete.last_changes());
+        assert_eq!(None, select.last_changes());
+
+        while select.step().unwrap() {}
+        assert_eq!(Some(0), select.last_changes());
+        assert_eq!(Some(2), update.last_changes());
+
+        // Cleared on reset.
+        update.reset();
+        assert_eq!(None, update.last_changes());
+        update.bind(1, &1).unwrap();
+        assert_eq!(Ok(false), update.step());
+        assert_eq!(Some(1), update.last_changes());
+        assert_eq!(Some(1), delete.last_changes());
+    }
+
+    #[test]
+    fn try_reset() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(
+            r#"PRAGMA foreign_keys = ON;
+               CREATE TABLE "parent" ("id" INTEGER PRIMARY KEY);
+               CREATE TABLE "child" (
+                   "parent_id" INTEGER REFERENCES "parent" ("id") DEFERRABLE INITIALLY DEFERRED
+               );
+               BEGIN;
+               INSERT INTO "child" VALUES (1)"#,
+        )
+        .unwrap();
+
+        // The foreign key violation is detected at commit time.
+        let mut stmt = con.stmt_once("COMMIT").unwrap();
+        let code = unsafe { crate::sqlite3_step(stmt.raw) };
+        assert_eq!(SQLITE_CONSTRAINT, code);
+        assert_eq!(
+            Err(SQLITE_CONSTRAINT),
+            stmt.try_reset().map_err(|e| e.code())
+        );
+        assert_eq!(Ok(()), stmt.try_reset());
+
+        // `step()` reports the error as well.
+        assert_eq!(Err(SQLITE_CONSTRAINT), stmt.step().map_err(|e| e.code()));
+        assert_eq!(Ok(()), stmt.try_reset());
+
+        con.execute_batch(r#"INSERT INTO "parent" VALUES (1)"#)
+            .unwrap();
+        assert_eq!(Ok(false), stmt.step());
+    }
+
+    #[test]
+    fn try_column() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let mut stmt = con
+            .stmt_once("SELECT 1, x'0102', 'foo', NULL, CAST(x'ff' AS TEXT)")
+            .unwrap();
+
+        // Before step()
+        assert_eq!(SQLITE_MISUSE, stmt.try_column_int(0).unwrap_err().code());
+        assert_eq!(SQLITE_MISUSE, stmt.try_column_blob(1).unwrap_err().code());
+        assert_eq!(SQLITE_MISUSE, stmt.try_column_text(2).unwrap_err().code());
+
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(Some(1)), stmt.try_column_int(0));
+        assert_eq!(Ok(Some(&[1_u8, 2][..])), stmt.try_column_blob(1));
+        assert_eq!(Ok(Some("foo")), stmt.try_column_text(2));
+        assert_eq!(Ok(None), stmt.try_column_int(3));
+        assert_eq!(Ok(None), stmt.try_column_blob(3));
+        assert_eq!(Ok(None), stmt.try_column_text(3));
+
+        // Out of range
+        let max = usize::MAX;
+        assert_eq!(SQLITE_RANGE, stmt.try_column_int(max).unwrap_err().code());
+        assert_eq!(SQLITE_RANGE, stmt.try_column_blob(max).unwrap_err().code());
+        assert_eq!(SQLITE_RANGE, stmt.try_column_text(5).unwrap_err().code());
+        assert_eq!(SQLITE_RANGE, stmt.get::<i64>(max).unwrap_err().code());
+
+        // Bad type or invalid UTF-8
+        assert_eq!(SQLITE_MISMATCH, stmt.try_column_int(2).unwrap_err().code());
+        assert_eq!(SQLITE_MISMATCH, stmt.try_column_blob(0).unwrap_err().code());
+        assert_eq!(SQLITE_MISMATCH, stmt.try_column_text(1).unwrap_err().code());
+        assert_eq!(SQLITE_MISMATCH, stmt.try_column_text(4).unwrap_err().code());
+    }
+
+    #[test]
+    fn column_len() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "value" BLOB)"#)
+            .unwrap();
+
+        let sizes = [0, 1, 100, 4096, 100_000];
+        for &size in sizes.iter() {
+            let mut stmt = con
+                .stmt_once(r#"INSERT INTO "foo" ("value") VALUES (?1)"#)
+                .unwrap();
+            stmt.bind_blob(1, &vec![0xab; size]).unwrap();
+            assert!(!stmt.step().unwrap());
+        }
+        con.execute_batch(r#"INSERT INTO "foo" ("value") VALUES (NULL), ('foo'), (1)"#)
+            .unwrap();
+
+        let mut stmt = con
+            .stmt_once(r#"SELECT "value" FROM "foo" ORDER BY "id""#)
+            .unwrap();
+        assert_eq!(SQLITE_MISUSE, stmt.try_column_len(0).unwrap_err().code());
+        for &size in sizes.iter() {
+            assert_eq!(Ok(true), stmt.step());
+            assert_eq!(Some(size), stmt.column_len(0));
+            assert_eq!(size, stmt.column_blob(0).unwrap().len());
+            assert_eq!(Some(size), stmt.column_len(0));
+        }
+
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(None, stmt.column_len(0));
+
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Some(3), stmt.column_len(0));
+
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(SQLITE_MISMATCH, stmt.try_column_len(0).unwrap_err().code());
+        assert_eq!(SQLITE_RANGE, stmt.try_column_len(1).unwrap_err().code());
+    }
+
+    #[test]
+    fn empty_blob() {
+        use crate::Value;
+
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "value" BLOB)"#)
+            .unwrap();
+
+        const INSERT: &str = r#"INSERT INTO "foo" ("value") VALUES (?1)"#;
+        let mut stmt = con.stmt_once(INSERT).unwrap();
+        stmt.bind_blob(1, &[]).unwrap();
+        assert_eq!(Ok(false), stmt.step());
+        stmt.bind(1, &Vec::<u8>::new()).unwrap();
+        assert_eq!(Ok(false), stmt.step());
+        stmt.bind(1, &Value::Blob(Vec::new())).unwrap();
+        assert_eq!(Ok(false), stmt.step());
+        stmt.bind_null(1).unwrap();
+        assert_eq!(Ok(false), stmt.step());
+        drop(stmt);
+
+        let mut stmt = con
+            .stmt_once(r#"SELECT "value", typeof("value"), length("value") FROM "foo""#)
+            .unwrap();
+        for _ in 0..3 {
+            assert_eq!(Ok(true), stmt.step());
+            assert_eq!(Ok("blob"), stmt.get::<&str>(1));
+            assert_eq!(Some(0), stmt.column_int(2));
+            assert_eq!(Some(&[][..]), stmt.column_blob(0));
+            assert_eq!(Ok(Some(&[][..])), stmt.column_text_bytes(0));
+        }
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok("null"), stmt.get::<&str>(1));
+        assert_eq!(None, stmt.column_blob(0));
+        assert_eq!(Ok(false), stmt.step());
+    }
+
+    #[test]
+    fn column_array() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "hash" BLOB)"#)
+            .unwrap();
+
+        let hash: [u8; 32] = core::array::from_fn(|i| i as u8);
+        let mut stmt = con
+            .stmt_once(r#"INSERT INTO "foo" ("hash") VALUES (?1), (?2), (NULL)"#)
+            .unwrap();
+        stmt.bind(1, &hash).unwrap();
+        stmt.bind(2, &&hash[..31]).unwrap();
+        assert_eq!(Ok(false), stmt.step());
+
+        let mut stmt = con
+            .stmt_once(r#"SELECT "hash" FROM "foo" ORDER BY "id""#)
+            .unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(Some(hash)), stmt.column_array::<32>(0));
+        assert_eq!(Ok(hash), stmt.get::<[u8; 32]>(0));
+
+        assert_eq!(Ok(true), stmt.step());
+        let e = stmt.column_array::<32>(0).unwrap_err();
+        assert_eq!(SQLITE_MISMATCH, e.code());
+        assert_eq!(
+            Some("column 0: expected 32 bytes, but 31 bytes"),
+            e.message()
+        );
+
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(None), stmt.column_array::<32>(0));
+        assert_eq!(SQLITE_RANGE, stmt.column_array::<32>(1).unwrap_err().code());
+    }
+
+    #[test]
+    fn column_bool_lenient() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let mut stmt = con
+            .stmt_once(
+                r#"SELECT 1, 0, -5, 't', 'f', 'true', 'FALSE', 'Yes', 'no', NULL,
+                          'maybe', 1.0, X'01'"#,
+            )
+            .unwrap();
+        assert_eq!(Ok(true), stmt.step());
+
+        let expected = [
+            Some(true),
+            Some(false),
+            Some(true),
+            Some(true),
+            Some(false),
+            Some(true),
+            Some(false),
+            Some(true),
+            Some(false),
+            None,
+        ];
+        for (i, &e) in expected.iter().enumerate() {
+            assert_eq!(Ok(e), stmt.column_bool_lenient(i));
+        }
+        for i in 10..13 {
+            let e = stmt.column_bool_lenient(i).unwrap_err();
+            assert_eq!(SQLITE_MISMATCH, e.code());
+        }
+
+        // Strict mode
+        assert_eq!(Ok(true), stmt.get::<bool>(0));
+        assert_eq!(SQLITE_MISMATCH, stmt.get::<bool>(2).unwrap_err().code());
+        assert_eq!(SQLITE_MISMATCH, stmt.get::<bool>(3).unwrap_err().code());
+        assert_eq!(SQLITE_MISMATCH, stmt.get::<bool>(11).unwrap_err().code());
+    }
+
+    #[test]
+    fn invalid_utf8_text() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let mut stmt = con
+            .stmt_once(r#"SELECT CAST(x'41ff42' AS TEXT), 'abc', NULL, x'ff', 42"#)
+            .unwrap();
+        assert_eq!(Ok(true), stmt.step());
+
+        // Strict
+        let e = stmt.try_column_text(0).unwrap_err();
+        assert_eq!(SQLITE_MISMATCH, e.code());
+        assert!(e.message().unwrap().contains("invalid utf-8"));
+        assert!(stmt.get::<String>(0).is_err());
+        assert_eq!(Ok(Some("abc")), stmt.try_column_text(1));
+
+        // Lossy
+        let lossy = stmt.column_text_lossy(0).unwrap().unwrap();
+        assert_eq!("A\u{FFFD}B", lossy);
+        assert!(matches!(
+            stmt.column_text_lossy(1),
+            Ok(Some(std::borrow::Cow::Borrowed("abc")))
+        ));
+        assert_eq!(Ok(None), stmt.column_text_lossy(2));
+        let e = stmt.column_text_lossy(3).unwrap_err();
+        assert_eq!(SQLITE_MISMATCH, e.code());
+
+        // Raw
+        assert_eq!(Ok(Some(&b"A\xffB"[..])), stmt.column_text_bytes(0));
+        assert_eq!(Ok(Some(&b"abc"[..])), stmt.column_text_bytes(1));
+        assert_eq!(Ok(None), stmt.column_text_bytes(2));
+        assert_eq!(Ok(Some(&b"\xff"[..])), stmt.column_text_bytes(3));
+        assert_eq!(Ok(Some(&b"42"[..])), stmt.column_text_bytes(4));
+        assert_eq!(SQLITE_RANGE, stmt.column_text_bytes(5).unwrap_err().code());
+    }
+
+    #[test]
+    fn column_coerce() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(
+            r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "value");
+               INSERT INTO "foo" ("value") VALUES ('42'), (42.9), (NULL), ('12abc'), (7)"#,
+        )
+        .unwrap();
+
+        let mut stmt = con
+            .stmt_once(r#"SELECT "value" FROM "foo" ORDER BY "id""#)
+            .unwrap();
+        assert_eq!(SQLITE_MISUSE, stmt.column_int_coerce(0).unwrap_err().code());
+
+        // '42'
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(SQLITE_MISMATCH, stmt.try_column_int(0).unwrap_err().code());
+        assert_eq!(Ok(42), stmt.column_int_coerce(0));
+        assert_eq!(Ok(42.0), stmt.column_double_coerce(0));
+        assert_eq!(Ok(Some("42")), stmt.column_text_coerce(0));
+        assert_eq!(SQLITE_RANGE, stmt.column_int_coerce(1).unwrap_err().code());
+
+        // 42.9
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(SQLITE_MISMATCH, stmt.try_column_int(0).unwrap_err().code());
+        assert_eq!(Ok(42), stmt.column_int_coerce(0));
+        assert_eq!(Ok(42.9), stmt.column_double_coerce(0));
+
+        // NULL
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(0), stmt.column_int_coerce(0));
+        assert_eq!(Ok(0.0), stmt.column_double_coerce(0));
+        assert_eq!(Ok(None), stmt.column_text_coerce(0));
+
+        // '12abc'
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(12), stmt.column_int_coerce(0));
+
+        // 7
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(Some("7")), stmt.column_text_coerce(0));
+    }
+
+    #[test]
+    fn current_row_values() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(
+            r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "a", "b");
+               INSERT INTO "foo" ("a", "b") VALUES
+                   (1, 'x'), (1, 'x'), (1.5, 'x'), (1.5, X'00'), (NULL, X'00'), (NULL, X'00')"#,
+        )
+        .unwrap();
+
+        let mut stmt = con
+            .stmt_once(r#"SELECT "a", "b" FROM "foo" ORDER BY "id""#)
+            .unwrap();
+        assert_eq!(SQLITE_MISUSE, stmt.current_row_values().unwrap_err().code());
+
+        let mut prev: Option<Vec<Value>> = None;
+        let mut changed = Vec::new();
+        while stmt.step().unwrap() {
+            let values = stmt.current_row_values().unwrap();
+            assert_eq!(values, stmt.row().unwrap().to_values());
+            changed.push(prev.as_ref() != Some(&values));
+            prev = Some(values);
+        }
+        assert_eq!(vec![true, false, true, true, true, false], changed);
+        assert_eq!(Some(vec![Value::Null, Value::Blob(vec![0])]), prev);
+    }
+
+    #[test]
+    fn execute_returning() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "name" TEXT)"#)
+            .unwrap();
+
+        let sql = r#"INSERT INTO "foo" ("name") VALUES (?1), (?2), (?3) RETURNING "id", "name""#;
+        let mut stmt = con.stmt_once(sql).unwrap();
+        stmt.bind_all(&[&"a", &"b", &"c"]).unwrap();
+        let rows: Vec<(i64, String)> = stmt.execute_returning().unwrap();
+        assert_eq!(
+            vec![
+                (1, "a".to_string()),
+                (2, "b".to_string()),
+                (3, "c".to_string())
+            ],
+            rows
+        );
+
+        // Parameters are kept.
+        let rows: Vec<(i64, String)> = stmt.execute_returning().unwrap();
+        let ids: Vec<_> = rows.into_iter().map(|(id, _)| id).collect();
+        assert_eq!(vec![4, 5, 6], ids);
+
+        // Conversion error finishes the statement.
+        let e = stmt.execute_returning::<(i64,)>().unwrap_err();
+        assert_eq!(SQLITE_MISMATCH, e.code());
+        drop(stmt);
+        assert!(con.is_autocommit());
+        let count = con
+            .query_one::<(i64,)>(r#"SELECT COUNT(*) FROM "foo""#, &[])
+            .unwrap();
+        assert_eq!(Some((9,)), count);
+    }
+
+    #[test]
+    fn check_returning() {
+        assert_eq!(Ok(()), super::check_returning(3_035_000));
+        let e = super::check_returning(3_034_001).unwrap_err();
+        assert_eq!(SQLITE_ERROR, e.code());
+        let expected =
+            "RETURNING clause requires SQLite 3.35.0 or later, but the runtime SQLite is 3.34.1";
+        assert_eq!(Some(expected), e.message());
+    }
+
+    #[test]
+    fn rows_as_maps() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let sql = r#"SELECT 1 AS "id", 'foo' AS "name", NULL AS "none", x'0102' AS "data",
+                            1.5 AS "dup", 'last' AS "dup"
+                     UNION ALL SELECT 2, NULL, NULL, x'', 2.5, NULL"#;
+        let mut stmt = con.stmt_once(sql).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+
+        // Starts from the beginning.
+        let rows = stmt.rows_as_maps().unwrap();
+        assert_eq!(2, rows.len());
+
+        assert_eq!(5, rows[0].len());
+        assert_eq!(Value::Integer(1), rows[0]["id"]);
+        assert_eq!(Value::Text("foo".to_string()), rows[0]["name"]);
+        assert_eq!(Value::Null, rows[0]["none"]);
+        assert_eq!(Value::Blob(vec![1, 2]), rows[0]["data"]);
+        assert_eq!(Value::Text("last".to_string()), rows[0]["dup"]);
+
+        assert_eq!(Value::Integer(2), rows[1]["id"]);
+        assert_eq!(Value::Null, rows[1]["name"]);
+        assert_eq!(Value::Blob(Vec::new()), rows[1]["data"]);
+        assert_eq!(Value::Null, rows[1]["dup"]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn column_int_out_of_range() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let mut stmt = con.stmt_once("SELECT 1").unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        stmt.column_int(usize::MAX);
+    }
+
+    #[test]
+    fn bind_and_execute() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("id" INTEGER NOT NULL, "name" TEXT)"#)
+            .unwrap();
+
+        let mut stmt = con
+            .stmt_once(r#"INSERT INTO "foo" VALUES (?1, ?2), (?1 + 1, ?2)"#)
+            .unwrap();
+        assert_eq!(Ok(2), stmt.bind_and_execute(&[&1, &"bar"]));
+
+        // The parameters are cleared even on error.
+        assert!(stmt
+            .bind_and_execute(&[&3, &Some(&[0_u8][..]), &4])
+            .is_err());
+        assert!(stmt.bind_and_execute(&[]).is_err());
+
+        let mut stmt = con.stmt_once(r#"SELECT * FROM "foo""#).unwrap();
+        assert_eq!(Ok(0), stmt.bind_and_execute(&[]));
     }
 }
@@ -0,0 +1,462 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{Error, Stmt, StmtStatus, SQLITE_MISUSE};
+use core::hash::{Hash, Hasher};
+use std::collections::HashMap;
+
+/// New type of `&'static str` , which is compared by the address.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Sql(pub(crate) &'static str);
+
+impl PartialEq for Sql {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        core::ptr::eq(self.0.as_ptr(), other.0.as_ptr())
+    }
+}
+
+impl Eq for Sql {}
+
+impl Hash for Sql {
+    #[inline]
+    fn hash<H>(&self, hasher: &mut H)
+    where
+        H: Hasher,
+    {
+        (self.0.as_ptr() as usize).hash(hasher)
+    }
+}
+
+/// Key of the statement cache.
+///
+/// `arity` is the number of the placeholders that [`Connection::stmt_with_arity`] substituted,
+/// or `None` for [`Connection::stmt`] .
+///
+/// [`Connection::stmt_with_arity`]: struct.Connection.html#method.stmt_with_arity
+/// [`Connection::stmt`]: struct.Connection.html#method.stmt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct CacheKey {
+    pub(crate) sql: Sql,
+    pub(crate) arity: Option<usize>,
+}
+
+/// Statistics of the statement cache of [`Connection`] , which
+/// [`Connection::stmt_cache_stats`] returns.
+///
+/// [`Connection`]: struct.Connection.html
+/// [`Connection::stmt_cache_stats`]: struct.Connection.html#method.stmt_cache_stats
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct StmtCacheStats {
+    /// The max number of the cached statements, or `None` if unbounded.
+    pub capacity: Option<usize>,
+    /// The max bytes of the heap memory that the cached statements use, or `None` if unbounded.
+    pub budget_bytes: Option<usize>,
+    /// The bytes of the heap memory that the cached statements use, including the pinned ones.
+    pub mem_used: usize,
+    /// The number of the cached statements, including the pinned ones.
+    pub len: usize,
+    /// The SQL text of the pinned statements.
+    pub pinned: Vec<String>,
+    /// The number of the times a cached statement was reused.
+    pub hits: u64,
+    /// The number of the times a statement was prepared to be cached.
+    pub misses: u64,
+    /// The number of the statements evicted to keep the capacity.
+    pub evictions: u64,
+}
+
+/// Returns `SQLITE_STMTSTATUS_MEMUSED` of `stmt` .
+#[inline]
+fn mem_used_of(stmt: &Stmt) -> usize {
+    stmt.status(StmtStatus::MemUsed, false).max(0) as usize
+}
+
+struct CacheEntry {
+    stmt: Stmt,
+    pinned: bool,
+    /// Value of `StmtCache::clock` when the entry was used last time.
+    last_used: u64,
+}
+
+/// LRU cache of [`Stmt`] , whose entries can be pinned not to be evicted.
+///
+/// [`Stmt`]: struct.Stmt.html
+#[derive(Default)]
+pub(crate) struct StmtCache {
+    entries: HashMap<CacheKey, CacheEntry>,
+    capacity: Option<usize>,
+    budget_bytes: Option<usize>,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl StmtCache {
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Finalizes all the statements, including the pinned ones.
+    #[inline]
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &Stmt> {
+        self.entries.values().map(|e| &e.stmt)
+    }
+
+    pub(crate) fn values_mut(&mut self) -> impl Iterator<Item = &mut Stmt> {
+        self.entries.values_mut().map(|e| &mut e.stmt)
+    }
+
+    fn pinned_count(&self) -> usize {
+        self.entries.values().filter(|e| e.pinned).count()
+    }
+
+    /// Returns the bytes of the heap memory that all the cached statements use.
+    fn mem_used(&self) -> usize {
+        self.entries.values().map(|e| mem_used_of(&e.stmt)).sum()
+    }
+
+    /// Returns the cached statement of `key` and `true` , or caches the statement that `prepare`
+    /// returns and returns it and `false` .
+    ///
+    /// The least recently used statement that is not pinned is evicted before caching a new one
+    /// if the cache is full. The new statement is cached even if all the others are pinned.
+    ///
+    /// After caching a new one, the least recently used statements except for the new one are
+    /// evicted until the memory they use fits the budget.
+    pub(crate) fn get_or_insert_with<F>(
+        &mut self,
+        key: CacheKey,
+        prepare: F,
+    ) -> Result<(&mut Stmt, bool), Error>
+    where
+        F: FnOnce() -> Result<Stmt, Error>,
+    {
+        self.clock += 1;
+        let clock = self.clock;
+
+        if self.entries.contains_key(&key) {
+            self.hits += 1;
+            let entry = self.entries.get_mut(&key).unwrap();
+            entry.last_used = clock;
+            return Ok((&mut entry.stmt, true));
+        }
+
+        let stmt = prepare()?;
+        self.misses += 1;
+        if let Some(capacity) = self.capacity {
+            self.evict(capacity.saturating_sub(1));
+        }
+        let entry = CacheEntry {
+            stmt,
+            pinned: false,
+            last_used: clock,
+        };
+        self.entries.insert(key, entry);
+        if let Some(budget) = self.budget_bytes {
+            self.evict_bytes(budget, Some(&key));
+        }
+        Ok((&mut self.entries.get_mut(&key).unwrap().stmt, false))
+    }
+
+    /// Evicts the least recently used statements that are not pinned until the number of the
+    /// cached statements is `len` or less.
+    fn evict(&mut self, len: usize) {
+        while len < self.entries.len() {
+            if self.evict_lru(None).is_none() {
+                break;
+            }
+        }
+    }
+
+    /// Evicts the least recently used statements that are neither pinned nor `keep` until the
+    /// memory that the cached statements use is `budget` bytes or less.
+    fn evict_bytes(&mut self, budget: usize, keep: Option<&CacheKey>) {
+        let mut mem_used = self.mem_used();
+        while budget < mem_used {
+            match self.evict_lru(keep) {
+                None => break,
+                Some(stmt) => mem_used -= mem_used_of(&stmt).min(mem_used),
+            }
+        }
+    }
+
+    /// Evicts and returns the least recently used statement that is neither pinned nor `keep` ,
+    /// or returns `None` if there is no such statement.
+    fn evict_lru(&mut self, keep: Option<&CacheKey>) -> Option<Stmt> {
+        let lru = self
+            .entries
+            .iter()
+            .filter(|(k, e)| !e.pinned && Some(*k) != keep)
+            .min_by_key(|(_, e)| e.last_used)
+            .map(|(k, _)| *k)?;
+        self.evictions += 1;
+        self.entries.remove(&lru).map(|e| e.stmt)
+    }
+
+    /// Sets the max number of the cached statements, evicting the statements if necessary.
+    ///
+    /// Returns `SQLITE_MISUSE` if `capacity` is less than the number of the pinned statements.
+    pub(crate) fn set_capacity(&mut self, capacity: Option<usize>) -> Result<(), Error> {
+        if let Some(capacity) = capacity {
+            let pinned = self.pinned_count();
+            if capacity < pinned {
+                let message = format!(
+                    "capacity {} is less than the number of the pinned statements {}",
+                    capacity, pinned
+                );
+                return Err(Error::with_message(SQLITE_MISUSE, message));
+            }
+            self.evict(capacity);
+        }
+        self.capacity = capacity;
+        Ok(())
+    }
+
+    /// Sets the max bytes of the heap memory that the cached statements use, evicting the
+    /// statements if necessary.
+    pub(crate) fn set_budget_bytes(&mut self, budget: Option<usize>) {
+        if let Some(budget) = budget {
+            self.evict_bytes(budget, None);
+        }
+        self.budget_bytes = budget;
+    }
+
+    /// Returns `SQLITE_MISUSE` if pinning one more statement exceeds the capacity.
+    pub(crate) fn check_pin(&self, key: &CacheKey) -> Result<(), Error> {
+        if self.entries.get(key).is_some_and(|e| e.pinned) {
+            return Ok(());
+        }
+        match self.capacity {
+            Some(capacity) if capacity <= self.pinned_count() => {
+                let message = format!("all the capacity {} is pinned", capacity);
+                Err(Error::with_message(SQLITE_MISUSE, message))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Sets whether the cached statement of `key` is pinned or not, and returns `false` if
+    /// `key` is not cached.
+    pub(crate) fn set_pinned(&mut self, key: &CacheKey, pinned: bool) -> bool {
+        match self.entries.get_mut(key) {
+            None => false,
+            Some(e) => {
+                e.pinned = pinned;
+                true
+            }
+        }
+    }
+
+    /// Returns the SQL passed to [`Connection::pin_stmt`] of the pinned statements.
+    ///
+    /// [`Connection::pin_stmt`]: struct.Connection.html#method.pin_stmt
+    pub(crate) fn pinned_sqls(&self) -> Vec<&'static str> {
+        self.entries
+            .iter()
+            .filter(|(_, e)| e.pinned)
+            .map(|(k, _)| k.sql.0)
+            .collect()
+    }
+
+    pub(crate) fn stats(&self) -> StmtCacheStats {
+        StmtCacheStats {
+            capacity: self.capacity,
+            budget_bytes: self.budget_bytes,
+            mem_used: self.mem_used(),
+            len: self.entries.len(),
+            pinned: self
+                .entries
+                .values()
+                .filter(|e| e.pinned)
+                .map(|e| e.stmt.sql().to_string())
+                .collect(),
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Connection, SQLITE_MISUSE};
+
+    const PINNED: &str = "SELECT 'pinned'";
+    const OTHERS: [&str; 10] = [
+        "SELECT 0", "SELECT 1", "SELECT 2", "SELECT 3", "SELECT 4", "SELECT 5", "SELECT 6",
+        "SELECT 7", "SELECT 8", "SELECT 9",
+    ];
+
+    #[test]
+    fn pin() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.set_stmt_cache_capacity(Some(2)).unwrap();
+        con.pin_stmt(PINNED).unwrap();
+
+        for _ in 0..2 {
+            for sql in OTHERS.iter() {
+                let stmt = con.stmt(sql).unwrap();
+                assert_eq!(Ok(true), stmt.step());
+                assert_eq!(2, con.stmt_cache_stats().len);
+            }
+        }
+
+        let stats = con.stmt_cache_stats();
+        assert_eq!(Some(2), stats.capacity);
+        assert_eq!(vec![PINNED.to_string()], stats.pinned);
+        assert_eq!(21, stats.misses);
+        assert_eq!(19, stats.evictions);
+
+        // The pinned statement is still cached.
+        con.stmt(PINNED).unwrap();
+        let stats = con.stmt_cache_stats();
+        assert_eq!(1, stats.hits);
+        assert_eq!(21, stats.misses);
+
+        // Pinning a statement again is not an error.
+        con.pin_stmt(PINNED).unwrap();
+        assert_eq!(1, con.stmt_cache_stats().pinned.len());
+    }
+
+    #[test]
+    fn pin_exceeding_capacity() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.set_stmt_cache_capacity(Some(1)).unwrap();
+        con.pin_stmt(PINNED).unwrap();
+
+        let e = con.pin_stmt(OTHERS[0]).unwrap_err();
+        assert_eq!(SQLITE_MISUSE, e.code());
+        let e = con.set_stmt_cache_capacity(Some(0)).unwrap_err();
+        assert_eq!(SQLITE_MISUSE, e.code());
+
+        // A new statement is cached even if all the capacity is pinned.
+        con.stmt(OTHERS[0]).unwrap();
+        con.stmt(OTHERS[1]).unwrap();
+        assert_eq!(2, con.stmt_cache_stats().len);
+
+        assert!(con.unpin_stmt(PINNED));
+        assert!(!con.unpin_stmt(OTHERS[0]));
+        assert!(con.stmt_cache_stats().pinned.is_empty());
+        con.set_stmt_cache_capacity(Some(0)).unwrap();
+        assert_eq!(0, con.stmt_cache_stats().len);
+
+        con.set_stmt_cache_capacity(None).unwrap();
+        for sql in OTHERS.iter() {
+            con.stmt(sql).unwrap();
+        }
+        assert_eq!(10, con.stmt_cache_stats().len);
+    }
+
+    #[test]
+    fn budget_bytes() {
+        let values: Vec<String> = (0..1000).map(|i| i.to_string()).collect();
+        let sql = format!("SELECT 1 WHERE 1 IN ({})", values.join(", "));
+        let large: &'static str = Box::leak(sql.into_boxed_str());
+
+        // Measure the memory of each statement.
+        let mut con = Connection::open_memory_db().unwrap();
+        con.stmt(large).unwrap();
+        let large_bytes = con.stmt_cache_stats().mem_used;
+        let mut small_bytes = 0;
+        for sql in OTHERS.iter() {
+            let before = con.stmt_cache_stats().mem_used;
+            con.stmt(sql).unwrap();
+            small_bytes = small_bytes.max(con.stmt_cache_stats().mem_used - before);
+        }
+        assert!(10 * small_bytes < large_bytes);
+        con.clear_stmt_cache();
+        assert_eq!(0, con.stmt_cache_stats().mem_used);
+
+        // Room for the large one and 2 small ones.
+        let budget = large_bytes + 2 * small_bytes + small_bytes / 2;
+        con.set_stmt_cache_budget_bytes(Some(budget));
+        con.stmt(large).unwrap();
+        con.stmt(OTHERS[0]).unwrap();
+        con.stmt(OTHERS[1]).unwrap();
+        assert_eq!(3, con.stmt_cache_stats().len);
+        assert_eq!(0, con.stmt_cache_stats().evictions);
+
+        // The large one is the least recently used.
+        con.stmt(OTHERS[2]).unwrap();
+        let stats = con.stmt_cache_stats();
+        assert_eq!(Some(budget), stats.budget_bytes);
+        assert_eq!(3, stats.len);
+        assert_eq!(1, stats.evictions);
+        assert!(stats.mem_used <= budget);
+        for sql in OTHERS[..3].iter() {
+            con.stmt(sql).unwrap();
+        }
+        assert_eq!(stats.misses, con.stmt_cache_stats().misses);
+
+        // The new statement is kept, evicting the small ones.
+        con.stmt(large).unwrap();
+        let stats = con.stmt_cache_stats();
+        assert_eq!(3, stats.len);
+        assert_eq!(2, stats.evictions);
+        assert!(stats.mem_used <= budget);
+
+        // Pinned statements are not evicted.
+        con.pin_stmt(OTHERS[2]).unwrap();
+        con.set_stmt_cache_budget_bytes(Some(0));
+        let stats = con.stmt_cache_stats();
+        assert_eq!(vec![OTHERS[2].to_string()], stats.pinned);
+        assert_eq!(1, stats.len);
+    }
+}
@@ -0,0 +1,143 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::Error;
+use core::time::Duration;
+use std::time::Instant;
+
+/// Aggregate execution metrics of a cached statement, which [`Connection::stats`] returns.
+///
+/// [`Connection::stats`]: struct.Connection.html#method.stats
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StmtStats {
+    /// The SQL text of the statement.
+    pub sql: String,
+    /// The number of the times the statement was executed.
+    pub count: u64,
+    /// The cumulative wall time spent in the executions.
+    pub total_time: Duration,
+    /// The longest wall time of an execution.
+    pub max_time: Duration,
+    /// The number of the rows returned.
+    pub rows: u64,
+    /// The error of the last failed execution if any.
+    pub last_error: Option<Error>,
+}
+
+/// Metrics that `Stmt` collects while the statistics are enabled.
+#[derive(Debug, Default)]
+pub(crate) struct Stats {
+    count: u64,
+    total_time: Duration,
+    max_time: Duration,
+    rows: u64,
+    last_error: Option<Error>,
+    /// The time spent in the current execution, or `None` if not executing.
+    current: Option<Duration>,
+}
+
+impl Stats {
+    /// Returns the time when the step started.
+    #[inline]
+    pub fn start_step(&mut self) -> Instant {
+        if self.current.is_none() {
+            self.count += 1;
+            self.current = Some(Duration::default());
+        }
+        Instant::now()
+    }
+
+    /// Records the result of the step that started at `start` .
+    ///
+    /// `result` is `Ok(true)` if the step returned a row, or `Ok(false)` if the execution
+    /// finished.
+    pub fn end_step(&mut self, start: Instant, result: Result<bool, &Error>) {
+        let elapsed = start.elapsed();
+        if let Some(current) = self.current.as_mut() {
+            *current += elapsed;
+        }
+
+        match result {
+            Ok(true) => self.rows += 1,
+            Ok(false) => self.finish(),
+            Err(e) => {
+                self.last_error = Some(e.clone());
+                self.finish();
+            }
+        }
+    }
+
+    /// Finishes the current execution if any.
+    #[inline]
+    pub fn finish(&mut self) {
+        if let Some(current) = self.current.take() {
+            self.total_time += current;
+            self.max_time = self.max_time.max(current);
+        }
+    }
+
+    /// Builds `StmtStats` unless the statement has never been executed.
+    pub fn to_stmt_stats(&self, sql: &str) -> Option<StmtStats> {
+        if self.count == 0 {
+            return None;
+        }
+        Some(StmtStats {
+            sql: sql.to_string(),
+            count: self.count,
+            total_time: self.total_time,
+            max_time: self.max_time,
+            rows: self.rows,
+            last_error: self.last_error.clone(),
+        })
+    }
+}
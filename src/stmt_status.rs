@@ -0,0 +1,202 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{sqlite3_stmt_status, Stmt};
+use std::os::raw::c_int;
+
+/// Counter of [`Stmt::status`] .
+///
+/// See [`Status Parameters for prepared statements`] for details.
+///
+/// [`Stmt::status`]: struct.Stmt.html#method.status
+/// [`Status Parameters for prepared statements`]:
+/// https://www.sqlite.org/c3ref/c_stmtstatus_counter.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StmtStatus {
+    /// "SQLITE_STMTSTATUS_FULLSCAN_STEP"; the number of the forward steps in a full table scan.
+    FullscanStep,
+    /// "SQLITE_STMTSTATUS_SORT"; the number of the sort operations.
+    Sort,
+    /// "SQLITE_STMTSTATUS_AUTOINDEX"; the number of the rows inserted into transient indices
+    /// created automatically.
+    AutoIndex,
+    /// "SQLITE_STMTSTATUS_VM_STEP"; the number of the virtual machine operations.
+    VmStep,
+    /// "SQLITE_STMTSTATUS_REPREPARE"; the number of the times the statement was re-prepared
+    /// because of a schema change.
+    Reprepare,
+    /// "SQLITE_STMTSTATUS_RUN"; the number of the times the statement has run.
+    Run,
+    /// "SQLITE_STMTSTATUS_MEMUSED"; the bytes of the heap memory the statement uses.
+    MemUsed,
+}
+
+impl StmtStatus {
+    const fn as_c_int(self) -> c_int {
+        match self {
+            Self::FullscanStep => 1,
+            Self::Sort => 2,
+            Self::AutoIndex => 3,
+            Self::VmStep => 4,
+            Self::Reprepare => 5,
+            Self::Run => 6,
+            Self::MemUsed => 99,
+        }
+    }
+}
+
+/// All the counters of [`StmtStatus`] , which [`Stmt::status_snapshot`] returns.
+///
+/// [`StmtStatus`]: enum.StmtStatus.html
+/// [`Stmt::status_snapshot`]: struct.Stmt.html#method.status_snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct StmtStatusSnapshot {
+    /// Value of [`StmtStatus::FullscanStep`] .
+    ///
+    /// [`StmtStatus::FullscanStep`]: enum.StmtStatus.html#variant.FullscanStep
+    pub fullscan_step: i32,
+    /// Value of [`StmtStatus::Sort`] .
+    ///
+    /// [`StmtStatus::Sort`]: enum.StmtStatus.html#variant.Sort
+    pub sort: i32,
+    /// Value of [`StmtStatus::AutoIndex`] .
+    ///
+    /// [`StmtStatus::AutoIndex`]: enum.StmtStatus.html#variant.AutoIndex
+    pub auto_index: i32,
+    /// Value of [`StmtStatus::VmStep`] .
+    ///
+    /// [`StmtStatus::VmStep`]: enum.StmtStatus.html#variant.VmStep
+    pub vm_step: i32,
+    /// Value of [`StmtStatus::Reprepare`] .
+    ///
+    /// [`StmtStatus::Reprepare`]: enum.StmtStatus.html#variant.Reprepare
+    pub reprepare: i32,
+    /// Value of [`StmtStatus::Run`] .
+    ///
+    /// [`StmtStatus::Run`]: enum.StmtStatus.html#variant.Run
+    pub run: i32,
+    /// Value of [`StmtStatus::MemUsed`] .
+    ///
+    /// [`StmtStatus::MemUsed`]: enum.StmtStatus.html#variant.MemUsed
+    pub mem_used: i32,
+}
+
+impl Stmt {
+    /// Wrapper of C function [`sqlite3_stmt_status`] .
+    ///
+    /// Returns the current value of `counter` , and resets it to 0 if `reset` is `true` .
+    /// ([`StmtStatus::MemUsed`] is never reset.)
+    ///
+    /// [`sqlite3_stmt_status`]: https://www.sqlite.org/c3ref/stmt_status.html
+    /// [`StmtStatus::MemUsed`]: enum.StmtStatus.html#variant.MemUsed
+    #[inline]
+    pub fn status(&self, counter: StmtStatus, reset: bool) -> i32 {
+        unsafe { sqlite3_stmt_status(self.raw(), counter.as_c_int(), reset as c_int) }
+    }
+
+    /// Returns the current values of all the counters without resetting them.
+    pub fn status_snapshot(&self) -> StmtStatusSnapshot {
+        StmtStatusSnapshot {
+            fullscan_step: self.status(StmtStatus::FullscanStep, false),
+            sort: self.status(StmtStatus::Sort, false),
+            auto_index: self.status(StmtStatus::AutoIndex, false),
+            vm_step: self.status(StmtStatus::VmStep, false),
+            reprepare: self.status(StmtStatus::Reprepare, false),
+            run: self.status(StmtStatus::Run, false),
+            mem_used: self.status(StmtStatus::MemUsed, false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestDb;
+    use crate::Value;
+
+    #[test]
+    fn status() {
+        let rows = ["a", "b", "c", "d"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| vec![Value::Integer(i as i64 + 1), Value::Text(name.to_string())]);
+        let mut con = TestDb::new()
+            .with_schema(r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "name" TEXT)"#)
+            .seed("foo", rows);
+
+        const SQL: &str = r#"SELECT "id" FROM "foo" WHERE "name" = 'c' ORDER BY "name""#;
+
+        let mut stmt = con.stmt_once(SQL).unwrap();
+        assert_eq!(0, stmt.status(StmtStatus::Run, false));
+        while stmt.step().unwrap() {}
+        let snapshot = stmt.status_snapshot();
+        assert!(0 < snapshot.fullscan_step);
+        assert!(0 < snapshot.vm_step);
+        assert!(0 < snapshot.mem_used);
+        assert_eq!(1, snapshot.run);
+        assert_eq!(0, snapshot.reprepare);
+
+        assert_eq!(snapshot.vm_step, stmt.status(StmtStatus::VmStep, true));
+        assert_eq!(0, stmt.status(StmtStatus::VmStep, false));
+        drop(stmt);
+
+        con.execute_batch(r#"CREATE INDEX "foo_name" ON "foo" ("name")"#)
+            .unwrap();
+        let mut stmt = con.stmt_once(SQL).unwrap();
+        while stmt.step().unwrap() {}
+        let snapshot = stmt.status_snapshot();
+        assert_eq!(0, snapshot.fullscan_step);
+        assert_eq!(0, snapshot.sort);
+        assert_eq!(1, snapshot.run);
+    }
+}
@@ -0,0 +1,295 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{sqlite3, Connection, Error, SQLITE_INTERRUPT};
+use core::cell::Cell;
+use std::collections::BTreeSet;
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+extern "C" {
+    fn sqlite3_progress_handler(
+        db: *mut sqlite3,
+        n_ops: c_int,
+        xprogress: Option<unsafe extern "C" fn(*mut c_void) -> c_int>,
+        parg: *mut c_void,
+    );
+    fn sqlite3_trace_v2(
+        db: *mut sqlite3,
+        umask: c_uint,
+        xcallback: Option<
+            unsafe extern "C" fn(c_uint, *mut c_void, *mut c_void, *mut c_void) -> c_int,
+        >,
+        pctx: *mut c_void,
+    ) -> c_int;
+}
+
+// https://www.sqlite.org/c3ref/c_trace.html
+const SQLITE_TRACE_STMT: c_uint = 0x01;
+
+/// The number of the virtual machine instructions between the checks of the elapsed time.
+const PROGRESS_OPS: c_int = 1000;
+
+/// The connections whose statement was interrupted by the timeout, keyed by the address of
+/// `sqlite3` .
+///
+/// This is looked up only when a step fails with `SQLITE_INTERRUPT` , so that the statements do
+/// not have to hold the setting.
+static TIMED_OUT: Mutex<BTreeSet<usize>> = Mutex::new(BTreeSet::new());
+
+/// State of [`Connection::set_statement_timeout`] shared with the callbacks.
+///
+/// [`Connection::set_statement_timeout`]: struct.Connection.html#method.set_statement_timeout
+pub(crate) struct StmtTimeout {
+    db: *mut sqlite3,
+    limit: Duration,
+    /// When the last statement started.
+    start: Cell<Instant>,
+    /// Whether `db` may be in `TIMED_OUT` .
+    fired: Cell<bool>,
+}
+
+// Safety:
+// The callbacks may be called in another thread than the one owning the connection (and so
+// `self` ), because a `Stmt` returned by `Connection::stmt_once` can be stepped in any thread.
+// However, they are called only inside `sqlite3_*` calls holding the mutex of the connection,
+// which is always opened in the "serialized" mode. `start` and `fired` are accessed only there,
+// or after `set_statement_timeout` has replaced the callbacks (which also holds the mutex) and
+// no callback can refer to `self` any longer, so the `Cell` fields are never raced. `limit` and
+// `db` are not changed after construction.
+unsafe impl Send for StmtTimeout {}
+
+impl StmtTimeout {
+    fn clear_fired(&self) {
+        if self.fired.replace(false) {
+            forget(self.db);
+        }
+    }
+}
+
+unsafe extern "C" fn on_trace(
+    _event: c_uint,
+    ctx: *mut c_void,
+    _stmt: *mut c_void,
+    sql: *mut c_void,
+) -> c_int {
+    let timeout = &*(ctx as *const StmtTimeout);
+
+    // The event is also fired at the start of each trigger with the comment naming the trigger.
+    let sql = sql as *const c_char;
+    if !sql.is_null() && *sql == b'-' as c_char && *sql.add(1) == b'-' as c_char {
+        return 0;
+    }
+
+    timeout.clear_fired();
+    timeout.start.set(Instant::now());
+    0
+}
+
+unsafe extern "C" fn on_progress(arg: *mut c_void) -> c_int {
+    let timeout = &*(arg as *const StmtTimeout);
+    if timeout.start.get().elapsed() < timeout.limit {
+        return 0;
+    }
+
+    if !timeout.fired.replace(true) {
+        let mut timed_out = TIMED_OUT.lock().unwrap_or_else(PoisonError::into_inner);
+        timed_out.insert(timeout.db as usize);
+    }
+    1
+}
+
+/// Forgets that a statement of `db` has timed out.
+pub(crate) fn forget(db: *mut sqlite3) {
+    let mut timed_out = TIMED_OUT.lock().unwrap_or_else(PoisonError::into_inner);
+    timed_out.remove(&(db as usize));
+}
+
+/// Replaces `e` with [`Error::TIMEOUT`] if `e` is `SQLITE_INTERRUPT` caused by the statement
+/// timeout of `db` .
+///
+/// [`Error::TIMEOUT`]: struct.Error.html#associatedconstant.TIMEOUT
+pub(crate) fn check_timed_out(db: *mut sqlite3, e: Error) -> Error {
+    if e.code() != SQLITE_INTERRUPT {
+        return e;
+    }
+
+    let mut timed_out = TIMED_OUT.lock().unwrap_or_else(PoisonError::into_inner);
+    if timed_out.remove(&(db as usize)) {
+        Error::TIMEOUT
+    } else {
+        e
+    }
+}
+
+impl Connection {
+    /// Limits the time that each statement of `self` may run to `timeout` , or removes the limit
+    /// if `timeout` is `None` .
+    ///
+    /// A statement running longer than `timeout` fails with [`Error::TIMEOUT`] , which is
+    /// distinguishable from `SQLITE_INTERRUPT` by [`InterruptHandle::interrupt`] .
+    ///
+    /// The time is measured from the first [`Stmt::step`] after the statement is prepared or
+    /// reset, including the time between the steps. The timer restarts whenever any statement of
+    /// `self` starts (not when a trigger starts), so a statement started while another one is
+    /// running extends the time of the running one. The elapsed time is checked every
+    /// 1000 virtual machine instructions.
+    ///
+    /// This method uses C functions [`sqlite3_progress_handler`] and [`sqlite3_trace_v2`] ;
+    /// they must not be set by other means. It costs nothing if `timeout` is `None` .
+    ///
+    /// [`Error::TIMEOUT`]: struct.Error.html#associatedconstant.TIMEOUT
+    /// [`InterruptHandle::interrupt`]: struct.InterruptHandle.html#method.interrupt
+    /// [`Stmt::step`]: struct.Stmt.html#method.step
+    /// [`sqlite3_progress_handler`]: https://www.sqlite.org/c3ref/progress_handler.html
+    /// [`sqlite3_trace_v2`]: https://www.sqlite.org/c3ref/trace_v2.html
+    pub fn set_statement_timeout(&mut self, timeout: Option<Duration>) {
+        let db = self.raw();
+        let timeout = timeout.map(|limit| {
+            Box::new(StmtTimeout {
+                db,
+                limit,
+                start: Cell::new(Instant::now()),
+                fired: Cell::new(false),
+            })
+        });
+
+        unsafe {
+            match timeout.as_ref() {
+                None => {
+                    sqlite3_progress_handler(db, 0, None, core::ptr::null_mut());
+                    sqlite3_trace_v2(db, 0, None, core::ptr::null_mut());
+                }
+                Some(t) => {
+                    let arg = t.as_ref() as *const StmtTimeout as *mut c_void;
+                    sqlite3_progress_handler(db, PROGRESS_OPS, Some(on_progress), arg);
+                    sqlite3_trace_v2(db, SQLITE_TRACE_STMT, Some(on_trace), arg);
+                }
+            }
+        }
+
+        // Drop the old state after the callbacks are replaced. The callbacks may be running in
+        // another thread until then; do not touch `start` nor `fired` of the state in use outside
+        // the callbacks.
+        if let Some(old) = core::mem::replace(&mut self.stmt_timeout, timeout) {
+            old.clear_fired();
+        }
+    }
+
+    /// Returns the limit set by [`set_statement_timeout`] .
+    ///
+    /// [`set_statement_timeout`]: #method.set_statement_timeout
+    #[inline]
+    pub fn statement_timeout(&self) -> Option<Duration> {
+        self.stmt_timeout.as_ref().map(|t| t.limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Takes long enough, and returns a row only at the end.
+    const SLOW_QUERY: &str = r#"
+        WITH RECURSIVE "s"("i") AS (SELECT 1 UNION ALL SELECT "i" + 1 FROM "s" LIMIT 10000)
+        SELECT count(*) FROM "s" AS "a", "s" AS "b""#;
+
+    #[test]
+    fn timeout() {
+        let mut con = Connection::open_memory_db().unwrap();
+        assert_eq!(None, con.statement_timeout());
+
+        let limit = Duration::from_millis(50);
+        con.set_statement_timeout(Some(limit));
+        assert_eq!(Some(limit), con.statement_timeout());
+
+        let start = Instant::now();
+        let e = con.execute_batch(SLOW_QUERY).unwrap_err();
+        assert_eq!(Error::TIMEOUT, e);
+        assert_eq!(SQLITE_INTERRUPT, e.code() & 0xff);
+        assert_eq!("statement timed out", e.to_string());
+        assert_eq!(std::io::ErrorKind::TimedOut, e.io_kind());
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        // The timer restarts for each statement.
+        for _ in 0..10 {
+            std::thread::sleep(Duration::from_millis(10));
+            assert!(con.execute_batch("SELECT 1").is_ok());
+        }
+
+        // The limit is removed.
+        con.set_statement_timeout(None);
+        assert_eq!(None, con.statement_timeout());
+        let mut stmt = con.stmt_once("SELECT 1 UNION ALL SELECT 2").unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(Ok(true), stmt.step());
+    }
+
+    #[test]
+    fn interrupt_is_not_timeout() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.set_statement_timeout(Some(Duration::from_secs(60)));
+        let handle = con.interrupt_handle();
+
+        let thread = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            handle.interrupt();
+        });
+        let e = con.execute_batch(SLOW_QUERY).unwrap_err();
+        assert_eq!(SQLITE_INTERRUPT, e.code());
+        assert_ne!(Error::TIMEOUT, e);
+        thread.join().unwrap();
+    }
+}
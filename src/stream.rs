@@ -0,0 +1,231 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{Error, FromRow, Stmt};
+use std::sync::mpsc::{sync_channel, IntoIter, Receiver, SyncSender};
+
+/// Receiving side of [`Stmt::stream_rows`] .
+///
+/// `RowReceiver` is an iterator of the rows that [`StreamDriver`] sends. It can be moved to
+/// another thread. If the driver fails, the last item is the error, and then the iteration
+/// ends.
+///
+/// [`Stmt::stream_rows`]: struct.Stmt.html#method.stream_rows
+/// [`StreamDriver`]: struct.StreamDriver.html
+pub struct RowReceiver<T> {
+    receiver: Receiver<Result<T, Error>>,
+}
+
+impl<T> RowReceiver<T> {
+    /// Blocks until the next row is available, and returns it, or returns `None` if the driver
+    /// has finished.
+    #[inline]
+    pub fn recv(&self) -> Option<Result<T, Error>> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl<T> IntoIterator for RowReceiver<T> {
+    type Item = Result<T, Error>;
+    type IntoIter = IntoIter<Result<T, Error>>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.receiver.into_iter()
+    }
+}
+
+/// Sending side of [`Stmt::stream_rows`] , which executes the statement.
+///
+/// [`Stmt::stream_rows`]: struct.Stmt.html#method.stream_rows
+pub struct StreamDriver<'a, T> {
+    stmt: &'a mut Stmt,
+    sender: SyncSender<Result<T, Error>>,
+}
+
+impl<T> StreamDriver<'_, T>
+where
+    T: FromRow,
+{
+    /// Executes the statement from the beginning, and sends each row converted into `T` to
+    /// [`RowReceiver`] , and returns the number of the rows sent.
+    ///
+    /// Blocks while the buffer of the channel is full; i.e. a slow receiver slows the driver
+    /// down.
+    ///
+    /// If [`Stmt::step`] or the conversion fails, sends the error to [`RowReceiver`] and
+    /// returns it. If [`RowReceiver`] is dropped, stops executing the statement and returns the
+    /// number of the rows sent so far.
+    ///
+    /// The statement is reset before returning. The bound parameters are kept.
+    ///
+    /// [`RowReceiver`]: struct.RowReceiver.html
+    /// [`Stmt::step`]: struct.Stmt.html#method.step
+    pub fn run(self) -> Result<u64, Error> {
+        let mut sent = 0;
+        for row in self.stmt.query_as_iter::<T>() {
+            let error = row.as_ref().err().cloned();
+            if self.sender.send(row).is_err() {
+                break;
+            }
+            if let Some(e) = error {
+                return Err(e);
+            }
+            sent += 1;
+        }
+
+        self.stmt.reset();
+        Ok(sent)
+    }
+}
+
+impl Stmt {
+    /// Creates a bounded channel of the rows of the statement converted into `T` , which holds
+    /// up to `buffer` rows, and returns the receiver and the driver.
+    ///
+    /// Nothing is executed until [`StreamDriver::run`] is called. The rows are converted into
+    /// owned `T` in the driver, so [`RowReceiver`] can be sent to another thread to process the
+    /// rows while the driver is running.
+    ///
+    /// If `buffer` is 0, each row is handed over directly when the receiver takes it.
+    ///
+    /// [`StreamDriver::run`]: struct.StreamDriver.html#method.run
+    /// [`RowReceiver`]: struct.RowReceiver.html
+    pub fn stream_rows<T>(&mut self, buffer: usize) -> (RowReceiver<T>, StreamDriver<'_, T>)
+    where
+        T: FromRow + Send,
+    {
+        let (sender, receiver) = sync_channel(buffer);
+        let receiver = RowReceiver { receiver };
+        let driver = StreamDriver { stmt: self, sender };
+        (receiver, driver)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Connection, Row, SQLITE_ERROR};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    static PRODUCED: AtomicUsize = AtomicUsize::new(0);
+
+    /// Counts the rows converted.
+    struct Counted(i64);
+
+    impl FromRow for Counted {
+        fn from_row(row: &Row<'_>) -> Result<Self, Error> {
+            PRODUCED.fetch_add(1, Ordering::SeqCst);
+            row.get(0).map(Counted)
+        }
+    }
+
+    const SQL: &str = r#"WITH RECURSIVE "s"("i") AS (SELECT 1 UNION ALL SELECT "i" + 1 FROM "s")
+                         SELECT "i" FROM "s" LIMIT 200"#;
+
+    #[test]
+    fn backpressure() {
+        const BUFFER: usize = 4;
+
+        let mut con = Connection::open_memory_db().unwrap();
+        let mut stmt = con.stmt_once(SQL).unwrap();
+        let (receiver, driver) = stmt.stream_rows::<Counted>(BUFFER);
+
+        let consumer = thread::spawn(move || {
+            let mut consumed = 0;
+            let mut max_ahead = 0;
+            for row in receiver {
+                consumed += 1;
+                assert_eq!(consumed, row.unwrap().0);
+                let ahead = PRODUCED.load(Ordering::SeqCst) - consumed as usize;
+                max_ahead = max_ahead.max(ahead);
+                if consumed % 20 == 0 {
+                    thread::sleep(Duration::from_millis(5));
+                }
+            }
+            (consumed, max_ahead)
+        });
+
+        assert_eq!(Ok(200), driver.run());
+        let (consumed, max_ahead) = consumer.join().unwrap();
+        assert_eq!(200, consumed);
+        // The buffer, the row being sent, and the row being converted.
+        assert!(max_ahead <= BUFFER + 2);
+    }
+
+    #[test]
+    fn error() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let sql = r#"WITH RECURSIVE "s"("i") AS (SELECT 1 UNION ALL SELECT "i" + 1 FROM "s")
+                     SELECT CASE "i" WHEN 50 THEN abs(-9223372036854775808) ELSE "i" END
+                     FROM "s" LIMIT 100"#;
+        let mut stmt = con.stmt_once(sql).unwrap();
+        let (receiver, driver) = stmt.stream_rows::<(i64,)>(8);
+
+        let consumer = thread::spawn(move || receiver.into_iter().collect::<Vec<_>>());
+        let e = driver.run().unwrap_err();
+        assert_eq!(SQLITE_ERROR, e.code());
+
+        let rows = consumer.join().unwrap();
+        assert_eq!(50, rows.len());
+        assert!(rows[..49].iter().all(|r| r.is_ok()));
+        assert_eq!(Err(e), rows[49]);
+
+        // Receiver is dropped.
+        let (receiver, driver) = stmt.stream_rows::<(i64,)>(0);
+        drop(receiver);
+        assert_eq!(Ok(0), driver.run());
+    }
+}
@@ -0,0 +1,373 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Fixtures for the tests of the crates depending on mouse-sqlite3.
+//!
+//! This module is available if feature "test-util" is enabled.
+//!
+//! ```ignore
+//! use mouse_sqlite3::{testing::TestDb, Value};
+//!
+//! let mut db = TestDb::new()
+//!     .with_schema(r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "name" TEXT)"#)
+//!     .seed("foo", vec![vec![Value::Integer(1), Value::Text("a".to_string())]]);
+//! db.assert_table_eq("foo", vec![vec![Value::Integer(1), Value::Text("a".to_string())]]);
+//! ```
+
+use crate::quote::quote_identifier;
+use crate::{BindRow, Connection, Error, OpenOptions, Value, ValueRef, SQLITE_RANGE};
+use core::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// Database for tests, which derefs to [`Connection`] .
+///
+/// The methods of `TestDb` panic on error rather than returning `Result` , so that a test can
+/// set up a populated database in a few lines.
+///
+/// [`Connection`]: ../struct.Connection.html
+pub struct TestDb {
+    // `con` must be dropped before `dir` .
+    con: Connection,
+    dir: Option<(TempDir, PathBuf)>,
+}
+
+impl Deref for TestDb {
+    type Target = Connection;
+
+    #[inline]
+    fn deref(&self) -> &Connection {
+        &self.con
+    }
+}
+
+impl DerefMut for TestDb {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Connection {
+        &mut self.con
+    }
+}
+
+impl TestDb {
+    /// Opens an empty in-memory database.
+    ///
+    /// # Panics
+    ///
+    /// Panics if failed to open the database.
+    pub fn new() -> Self {
+        let con = Connection::open_memory_db().expect("failed to open an in-memory database");
+        Self { con, dir: None }
+    }
+
+    /// Creates a temporary directory and opens an empty database file in it.
+    ///
+    /// The path of the file is available via [`path`] , so that the test can open other
+    /// connections to the same database. The directory is removed when `self` is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if failed to create the directory or to open the database.
+    ///
+    /// [`path`]: #method.path
+    pub fn file() -> Self {
+        let dir = tempfile::tempdir().expect("failed to create a temporary directory");
+        let path = dir.path().join("test.sqlite3");
+        let con = OpenOptions::default()
+            .open(&path)
+            .unwrap_or_else(|e| panic!("failed to open {}: {}", path.display(), e));
+        Self {
+            con,
+            dir: Some((dir, path)),
+        }
+    }
+
+    /// Returns the path to the database file if `self` is created by [`file`] , or `None` if
+    /// `self` is an in-memory database.
+    ///
+    /// [`file`]: #method.file
+    #[inline]
+    pub fn path(&self) -> Option<&Path> {
+        self.dir.as_ref().map(|(_, path)| path.as_path())
+    }
+
+    /// Executes `sql` , which consists of any number of SQL statements, by
+    /// [`Connection::execute_batch`] .
+    ///
+    /// # Panics
+    ///
+    /// Panics if failed to execute `sql` .
+    ///
+    /// [`Connection::execute_batch`]: ../struct.Connection.html#method.execute_batch
+    pub fn with_schema(mut self, sql: &str) -> Self {
+        if let Err(e) = self.con.execute_batch(sql) {
+            panic!("failed to execute the schema: {}", e);
+        }
+        self
+    }
+
+    /// Inserts `rows` into `table` in a transaction.
+    ///
+    /// Each row is a list of the values of all the columns in the declared order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rows differ in the number of the values, or if failed to insert any row.
+    pub fn seed<I>(mut self, table: &str, rows: I) -> Self
+    where
+        I: IntoIterator<Item = Vec<Value>>,
+    {
+        if let Err(e) = self.try_seed(table, rows) {
+            panic!("failed to seed {:?}: {}", table, e);
+        }
+        self
+    }
+
+    fn try_seed<I>(&mut self, table: &str, rows: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = Vec<Value>>,
+    {
+        let mut rows = rows.into_iter().peekable();
+        let width = match rows.peek() {
+            None => return Ok(()),
+            Some(row) => row.len(),
+        };
+
+        let placeholders = vec!["?"; width].join(", ");
+        let sql = format!(
+            "INSERT INTO {} VALUES ({})",
+            quote_identifier(table)?,
+            placeholders
+        );
+
+        let mut txn = self.con.transaction()?;
+        let mut stmt = txn.stmt_once(&sql)?;
+        for (i, row) in rows.enumerate() {
+            if row.len() != width {
+                let message = format!("row {} has {} values, but {} expected", i, row.len(), width);
+                return Err(Error::with_message(SQLITE_RANGE, message));
+            }
+            stmt.clear()?;
+            row.bind_row(&mut stmt)?;
+            while stmt.step()? {}
+        }
+        drop(stmt);
+        txn.commit()
+    }
+
+    /// Asserts that `table` consists of `expected` rows.
+    ///
+    /// The rows are compared in the order of "SELECT * FROM `table` ", i.e. in the order of the
+    /// rowid, or the primary key for a "WITHOUT ROWID" table.
+    ///
+    /// # Panics
+    ///
+    /// Panics with the rows of both sides marked where they differ if the table does not match,
+    /// or if failed to read the table.
+    pub fn assert_table_eq<I>(&mut self, table: &str, expected: I)
+    where
+        I: IntoIterator<Item = Vec<Value>>,
+    {
+        let expected: Vec<Vec<Value>> = expected.into_iter().collect();
+        let actual = match self.table_rows(table) {
+            Ok(rows) => rows,
+            Err(e) => panic!("failed to read {:?}: {}", table, e),
+        };
+
+        if expected != actual {
+            panic!(
+                "table {:?} does not match (-expected +actual):\n{}",
+                table,
+                diff_rows(&expected, &actual)
+            );
+        }
+    }
+
+    fn table_rows(&mut self, table: &str) -> Result<Vec<Vec<Value>>, Error> {
+        let sql = format!("SELECT * FROM {}", quote_identifier(table)?);
+        let mut stmt = self.con.stmt_once(&sql)?;
+        let mut ret = Vec::new();
+        while stmt.step()? {
+            ret.push(stmt.current_row_values()?);
+        }
+        Ok(ret)
+    }
+}
+
+impl Default for TestDb {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Renders `expected` and `actual` line by line; the equal rows start with ' ', and the others
+/// start with '-' (expected) or '+' (actual.)
+fn diff_rows(expected: &[Vec<Value>], actual: &[Vec<Value>]) -> String {
+    let mut ret = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => write_row(&mut ret, ' ', i, e),
+            (e, a) => {
+                if let Some(e) = e {
+                    write_row(&mut ret, '-', i, e);
+                }
+                if let Some(a) = a {
+                    write_row(&mut ret, '+', i, a);
+                }
+            }
+        }
+    }
+    ret
+}
+
+/// Appends `row` to `out` as a line like "- 1: (1, 'foo', NULL)" .
+fn write_row(out: &mut String, mark: char, index: usize, row: &[Value]) {
+    out.push(mark);
+    out.push(' ');
+    out.push_str(&index.to_string());
+    out.push_str(": (");
+    for (i, val) in row.iter().enumerate() {
+        if i != 0 {
+            out.push_str(", ");
+        }
+        // Writing into String never fails.
+        ValueRef::from(val).write_sql_literal(out).unwrap();
+    }
+    out.push_str(")\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows() -> Vec<Vec<Value>> {
+        vec![
+            vec![Value::Integer(1), Value::Text("foo".to_string())],
+            vec![Value::Integer(2), Value::Null],
+        ]
+    }
+
+    #[test]
+    fn seed_assert() {
+        let mut db = TestDb::new()
+            .with_schema(r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "name" TEXT)"#)
+            .seed("foo", rows());
+        assert_eq!(None, db.path());
+        db.assert_table_eq("foo", rows());
+
+        // Seeding nothing is allowed.
+        let mut db = db.seed("foo", Vec::new());
+        db.assert_table_eq("foo", rows());
+    }
+
+    #[test]
+    fn file() {
+        let db = TestDb::file().with_schema(r#"CREATE TABLE "foo" ("value" INTEGER)"#);
+        let path = db.path().unwrap().to_path_buf();
+        assert!(path.exists());
+
+        let mut other = OpenOptions::default().open(&path).unwrap();
+        other
+            .execute(r#"INSERT INTO "foo" VALUES (1)"#, &[])
+            .unwrap();
+        let mut db = db;
+        db.assert_table_eq("foo", vec![vec![Value::Integer(1)]]);
+
+        drop(other);
+        drop(db);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    #[should_panic(expected = "row 1 has 1 values, but 2 expected")]
+    fn seed_width_mismatch() {
+        let rows = vec![
+            vec![Value::Integer(1), Value::Null],
+            vec![Value::Integer(2)],
+        ];
+        TestDb::new()
+            .with_schema(r#"CREATE TABLE "foo" ("id" INTEGER, "name" TEXT)"#)
+            .seed("foo", rows);
+    }
+
+    #[test]
+    fn seed_rolls_back() {
+        let mut db = TestDb::new().with_schema(r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY)"#);
+        let rows = vec![vec![Value::Integer(1)], vec![Value::Integer(1)]];
+        assert!(db.try_seed("foo", rows).is_err());
+        db.assert_table_eq("foo", Vec::new());
+    }
+
+    #[test]
+    fn diff() {
+        let expected = rows();
+        let mut actual = rows();
+        actual[1][1] = Value::Blob(vec![0xab]);
+        actual.push(vec![Value::Integer(3), Value::Real(1.5)]);
+
+        let diff = diff_rows(&expected, &actual);
+        assert_eq!(
+            "  0: (1, 'foo')\n- 1: (2, NULL)\n+ 1: (2, X'ab')\n+ 2: (3, 1.5)\n",
+            diff
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "does not match")]
+    fn assert_table_ne() {
+        let mut db = TestDb::new()
+            .with_schema(r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "name" TEXT)"#)
+            .seed("foo", rows());
+        db.assert_table_eq("foo", rows().into_iter().take(1));
+    }
+}
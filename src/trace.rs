@@ -0,0 +1,245 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Instrumentation with crate `tracing` .
+//!
+//! Every function here checks whether the subscriber is interested before building the fields,
+//! so nothing is formatted or allocated otherwise.
+
+use crate::{Error, StepResult};
+use core::hash::{Hash, Hasher};
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::os::raw::c_int;
+use std::time::Instant;
+use tracing::field::Empty;
+use tracing::{Level, Span};
+
+/// SQL longer than this is recorded as a hash instead of the text.
+const MAX_SQL_LEN: usize = 1024;
+
+/// Returns `sql` itself, or the hash of `sql` if it is too long to record.
+fn statement(sql: &str) -> Cow<'_, str> {
+    if sql.len() <= MAX_SQL_LEN {
+        Cow::Borrowed(sql)
+    } else {
+        let mut hasher = DefaultHasher::new();
+        sql.hash(&mut hasher);
+        Cow::Owned(format!("hash:{:016x}", hasher.finish()))
+    }
+}
+
+fn error_code<T>(result: &Result<T, Error>) -> c_int {
+    match result {
+        Ok(_) => Error::OK.code(),
+        Err(e) => e.code(),
+    }
+}
+
+/// Returns `true` if the subscriber is interested in the span of [`step_span`] .
+///
+/// [`step_span`]: fn.step_span.html
+#[inline]
+pub(crate) fn step_enabled() -> bool {
+    tracing::enabled!(Level::DEBUG)
+}
+
+/// Creates span "step" for `sql` ; call [`step_enabled`] first.
+///
+/// The other fields are recorded by [`record_step`] .
+///
+/// [`step_enabled`]: fn.step_enabled.html
+/// [`record_step`]: fn.record_step.html
+pub(crate) fn step_span(sql: &str) -> Span {
+    tracing::debug_span!(
+        "step",
+        db.statement = &*statement(sql),
+        rows = Empty,
+        changes = Empty,
+        duration_us = Empty,
+        error.code = Empty,
+    )
+}
+
+/// Records the result of the step started at `start` to `span` .
+pub(crate) fn record_step(span: &Span, start: Instant, result: &Result<StepResult, Error>) {
+    span.record("duration_us", start.elapsed().as_micros() as u64);
+    span.record("error.code", error_code(result));
+    match result {
+        Ok(StepResult::Row) => {
+            span.record("rows", 1);
+        }
+        Ok(StepResult::Done { changes }) => {
+            span.record("rows", 0);
+            span.record("changes", changes);
+        }
+        Err(_) => (),
+    }
+}
+
+/// Emits event "prepare" if the subscriber is interested.
+///
+/// `start` is the time when the preparation started.
+#[inline]
+pub(crate) fn prepare<T>(sql: &str, start: Instant, result: &Result<T, Error>) {
+    if tracing::enabled!(Level::DEBUG) {
+        tracing::debug!(
+            db.statement = &*statement(sql),
+            cached = false,
+            duration_us = start.elapsed().as_micros() as u64,
+            error.code = error_code(result),
+            "prepare"
+        );
+    }
+}
+
+/// Emits event "prepare" for the statement found in the statement cache if the subscriber is
+/// interested.
+#[inline]
+pub(crate) fn cache_hit(sql: &str) {
+    if tracing::enabled!(Level::DEBUG) {
+        tracing::debug!(db.statement = &*statement(sql), cached = true, "prepare");
+    }
+}
+
+/// Emits event "bind" if the subscriber is interested.
+#[inline]
+pub(crate) fn bind(sql: &str, index: c_int, code: c_int) {
+    if tracing::enabled!(Level::TRACE) {
+        tracing::trace!(
+            db.statement = &*statement(sql),
+            index,
+            error.code = code,
+            "bind"
+        );
+    }
+}
+
+/// Emits event "close" for the error that occurred while a connection was being dropped.
+#[inline]
+pub(crate) fn drop_error(e: &Error) {
+    tracing::warn!(error.code = e.code(), error.message = %e, "close");
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Connection;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id};
+    use tracing::Subscriber;
+    use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+    use tracing_subscriber::registry::Registry;
+
+    /// Name and "db.statement" of a span.
+    type SpanRecord = (&'static str, Option<String>);
+
+    /// Collects [`SpanRecord`] of each new span.
+    #[derive(Default, Clone)]
+    struct Spans(Arc<Mutex<Vec<SpanRecord>>>);
+
+    struct Statement(Option<String>);
+
+    impl Visit for Statement {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            if field.name() == "db.statement" {
+                self.0 = Some(value.to_string());
+            }
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn core::fmt::Debug) {
+            if field.name() == "db.statement" {
+                self.0 = Some(format!("{:?}", value));
+            }
+        }
+    }
+
+    impl<S: Subscriber> Layer<S> for Spans {
+        fn on_new_span(&self, attrs: &Attributes<'_>, _id: &Id, _ctx: Context<'_, S>) {
+            let mut statement = Statement(None);
+            attrs.record(&mut statement);
+            let name = attrs.metadata().name();
+            self.0.lock().unwrap().push((name, statement.0));
+        }
+    }
+
+    #[test]
+    fn step_span() {
+        let spans = Spans::default();
+        let subscriber = Registry::default().with(spans.clone());
+
+        const SQL: &str = "SELECT 1";
+        tracing::subscriber::with_default(subscriber, || {
+            let mut con = Connection::open_memory_db().unwrap();
+            let mut stmt = con.stmt_once(SQL).unwrap();
+            assert_eq!(Ok(true), stmt.step());
+            assert_eq!(Ok(false), stmt.step());
+        });
+
+        let spans = spans.0.lock().unwrap();
+        let steps: Vec<_> = spans.iter().filter(|(name, _)| *name == "step").collect();
+        assert_eq!(2, steps.len());
+        for (_, statement) in steps {
+            assert_eq!(Some(SQL), statement.as_deref());
+        }
+    }
+
+    #[test]
+    fn long_statement() {
+        let sql = format!("SELECT '{}'", "a".repeat(super::MAX_SQL_LEN));
+        let statement = super::statement(&sql);
+        assert!(statement.starts_with("hash:"));
+        assert_eq!(statement, super::statement(&sql));
+        assert_eq!("SELECT 1", super::statement("SELECT 1"));
+    }
+}
@@ -0,0 +1,681 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    sqlite3_get_autocommit, Connection, Error, JournalMode, SQLITE_BUSY, SQLITE_MISUSE,
+    SQLITE_READONLY,
+};
+use core::ops::{Deref, DerefMut};
+use core::time::Duration;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+use std::time::SystemTime;
+
+// Constants for sqlite3_txn_state()
+// https://www.sqlite.org/c3ref/c_txn_none.html
+const SQLITE_TXN_NONE: c_int = 0;
+const SQLITE_TXN_READ: c_int = 1;
+const SQLITE_TXN_WRITE: c_int = 2;
+
+/// The longest sleep of [`Connection::run_txn`] before retrying.
+///
+/// [`Connection::run_txn`]: struct.Connection.html#method.run_txn
+const MAX_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Behavior of "BEGIN" statement.
+///
+/// See [`BEGIN TRANSACTION`] for details.
+///
+/// [`BEGIN TRANSACTION`]: https://www.sqlite.org/lang_transaction.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TransactionBehavior {
+    /// "BEGIN DEFERRED"
+    Deferred,
+    /// "BEGIN IMMEDIATE"
+    Immediate,
+    /// "BEGIN EXCLUSIVE"
+    Exclusive,
+}
+
+impl Default for TransactionBehavior {
+    #[inline]
+    fn default() -> Self {
+        Self::Deferred
+    }
+}
+
+/// Transaction state of a schema, which C function [`sqlite3_txn_state`] returns.
+///
+/// [`sqlite3_txn_state`]: https://www.sqlite.org/c3ref/txn_state.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TxnState {
+    /// No transaction is currently pending.
+    None,
+    /// A read transaction is started but not yet committed.
+    Read,
+    /// A write transaction is started but not yet committed.
+    Write,
+}
+
+/// RAII guard of a transaction.
+///
+/// `Transaction` dereferences to [`Connection`] , and the transaction is rolled back on drop
+/// unless [`commit`] is called.
+///
+/// [`Connection`]: struct.Connection.html
+/// [`commit`]: #method.commit
+pub struct Transaction<'a> {
+    con: &'a mut Connection,
+    is_finished: bool,
+}
+
+impl Drop for Transaction<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        if !self.is_finished {
+            // Nothing can be done if "ROLLBACK" failed.
+            let _ = self.con.execute_batch("ROLLBACK");
+        }
+    }
+}
+
+impl Deref for Transaction<'_> {
+    type Target = Connection;
+
+    #[inline]
+    fn deref(&self) -> &Connection {
+        self.con
+    }
+}
+
+impl DerefMut for Transaction<'_> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.con
+    }
+}
+
+impl Transaction<'_> {
+    /// Executes "COMMIT" .
+    ///
+    /// If "COMMIT" failed, the transaction is rolled back and the error is returned.
+    #[inline]
+    pub fn commit(mut self) -> Result<(), Error> {
+        self.con.execute_batch("COMMIT")?;
+        self.is_finished = true;
+        Ok(())
+    }
+
+    /// Executes "ROLLBACK" .
+    #[inline]
+    pub fn rollback(mut self) -> Result<(), Error> {
+        self.is_finished = true;
+        self.con.execute_batch("ROLLBACK")
+    }
+}
+
+impl Connection {
+    /// Executes "BEGIN DEFERRED" and returns a new [`Transaction`] .
+    ///
+    /// [`Transaction`]: struct.Transaction.html
+    #[inline]
+    pub fn transaction(&mut self) -> Result<Transaction<'_>, Error> {
+        self.transaction_with_behavior(TransactionBehavior::Deferred)
+    }
+
+    /// Executes "BEGIN" with `behavior` and returns a new [`Transaction`] .
+    ///
+    /// [`Transaction`]: struct.Transaction.html
+    pub fn transaction_with_behavior(
+        &mut self,
+        behavior: TransactionBehavior,
+    ) -> Result<Transaction<'_>, Error> {
+        let sql = match behavior {
+            TransactionBehavior::Deferred => "BEGIN DEFERRED",
+            TransactionBehavior::Immediate => "BEGIN IMMEDIATE",
+            TransactionBehavior::Exclusive => "BEGIN EXCLUSIVE",
+        };
+        self.execute_batch(sql)?;
+
+        Ok(Transaction {
+            con: self,
+            is_finished: false,
+        })
+    }
+
+    /// Executes "BEGIN DEFERRED" as [`transaction`] does after checking that the databases of
+    /// `schemas` (i.e. "main" or the names of the attached databases) can be written in the
+    /// transaction atomically as a set.
+    ///
+    /// libsqlite3 commits a transaction writing two or more database files atomically by a
+    /// super-journal, but only if the "main" database is a file, and each of the written
+    /// databases uses a rollback journal on the disk (`journal_mode` is "DELETE" , "TRUNCATE" ,
+    /// or "PERSIST" , not "WAL" , "MEMORY" , or "OFF" ) and `synchronous` is not "OFF" .
+    /// Otherwise, a crash during the commit may leave some of the files committed and the others
+    /// not. In-memory and temporary databases in `schemas` are not checked because they are lost
+    /// by a crash anyway.
+    ///
+    /// Returns `SQLITE_MISUSE` if any of `schemas` is not attached or if the above requirements
+    /// are not met, or `SQLITE_READONLY` if any of `schemas` is read-only.
+    ///
+    /// [`transaction`]: #method.transaction
+    pub fn attached_transaction(&mut self, schemas: &[&str]) -> Result<Transaction<'_>, Error> {
+        let mut files = Vec::with_capacity(schemas.len());
+        for &schema in schemas {
+            match self.is_readonly(schema) {
+                Ok(false) => (),
+                Ok(true) => {
+                    let message = format!("database {} is read-only", schema);
+                    return Err(Error::with_message(SQLITE_READONLY, message));
+                }
+                Err(_) => {
+                    let message = format!("no such database: {}", schema);
+                    return Err(Error::with_message(SQLITE_MISUSE, message));
+                }
+            }
+            if self.db_filename(schema).is_some() && !files.contains(&schema) {
+                files.push(schema);
+            }
+        }
+
+        if 1 < files.len() {
+            if self.db_filename("main").is_none() {
+                let message = "the commit is not atomic across the databases \
+                               because the main database is not a file";
+                return Err(Error::with_message(SQLITE_MISUSE, message));
+            }
+            for schema in files {
+                self.check_super_journal(schema)?;
+            }
+        }
+
+        self.transaction()
+    }
+
+    /// Returns `SQLITE_MISUSE` unless the database of `schema` takes part in the super-journal.
+    fn check_super_journal(&mut self, schema: &str) -> Result<(), Error> {
+        let mode = self
+            .pragma_text(Some(schema), "journal_mode")?
+            .unwrap_or_default();
+        match JournalMode::parse(&mode) {
+            Some(JournalMode::Delete)
+            | Some(JournalMode::Truncate)
+            | Some(JournalMode::Persist) => {}
+            _ => {
+                let message = format!(
+                    "the commit is not atomic across the databases \
+                     because journal_mode of {} is {}",
+                    schema, mode
+                );
+                return Err(Error::with_message(SQLITE_MISUSE, message));
+            }
+        }
+
+        if self.pragma_int(Some(schema), "synchronous")? == Some(0) {
+            let message = format!(
+                "the commit is not atomic across the databases \
+                 because synchronous of {} is OFF",
+                schema
+            );
+            return Err(Error::with_message(SQLITE_MISUSE, message));
+        }
+        Ok(())
+    }
+
+    /// Runs `f` in a new transaction of `behavior` and commits it, retrying the whole
+    /// transaction up to `max_retries` times if it fails with `SQLITE_BUSY` (including the
+    /// extended codes like `SQLITE_BUSY_SNAPSHOT` .)
+    ///
+    /// In WAL mode, a deferred transaction that has read the database fails with
+    /// `SQLITE_BUSY_SNAPSHOT` when it tries to write after another connection has written. The
+    /// busy timeout does not help; the only way to recover is to roll back and start over, which
+    /// this method does. It sleeps for a random duration before each retry, which grows
+    /// exponentially up to 100 milliseconds.
+    ///
+    /// The transaction is rolled back if `f` returns `Err` or the commit fails. The other errors
+    /// than `SQLITE_BUSY` are returned immediately.
+    ///
+    /// `f` may be called more than once, so it should have side effects only through the
+    /// transaction; the changes made outside of it (e.g. pushing to a `Vec` captured) are not
+    /// rolled back.
+    pub fn run_txn<T, F>(
+        &mut self,
+        behavior: TransactionBehavior,
+        max_retries: u32,
+        mut f: F,
+    ) -> Result<T, Error>
+    where
+        F: FnMut(&mut Transaction<'_>) -> Result<T, Error>,
+    {
+        let mut backoff = Backoff::new();
+        let mut retries = 0;
+        loop {
+            let ret = self
+                .transaction_with_behavior(behavior)
+                .and_then(|mut txn| {
+                    let ret = f(&mut txn)?;
+                    txn.commit()?;
+                    Ok(ret)
+                });
+            match ret {
+                Err(e) if e.code() & 0xff == SQLITE_BUSY && retries < max_retries => {
+                    retries += 1;
+                    std::thread::sleep(backoff.next());
+                }
+                ret => return ret,
+            }
+        }
+    }
+
+    /// Wrapper of C function [`sqlite3_get_autocommit`] .
+    ///
+    /// Returns `false` if a transaction is open, or `true` if not.
+    ///
+    /// [`sqlite3_get_autocommit`]: https://www.sqlite.org/c3ref/get_autocommit.html
+    #[inline]
+    pub fn is_autocommit(&self) -> bool {
+        unsafe { sqlite3_get_autocommit(self.raw()) != 0 }
+    }
+
+    /// Wrapper of C function [`sqlite3_txn_state`] .
+    ///
+    /// Returns the transaction state of `schema` , or the most advanced state of all the schemas
+    /// if `schema` is `None` .
+    ///
+    /// Returns `SQLITE_MISUSE` if `schema` is not the name of any schema, or
+    /// [`Error::UNSUPPORTED`] if SQLite is older than 3.34.0, which lacks
+    /// [`sqlite3_txn_state`] ; see [`HAS_TXN_STATE`] .
+    ///
+    /// [`sqlite3_txn_state`]: https://www.sqlite.org/c3ref/txn_state.html
+    /// [`Error::UNSUPPORTED`]: struct.Error.html#associatedconstant.UNSUPPORTED
+    /// [`HAS_TXN_STATE`]: constant.HAS_TXN_STATE.html
+    pub fn txn_state(&self, schema: Option<&str>) -> Result<TxnState, Error> {
+        let schema = match schema {
+            None => None,
+            Some(s) => Some(CString::new(s).map_err(|_| Error::new(SQLITE_MISUSE))?),
+        };
+        let zschema = schema
+            .as_ref()
+            .map_or(core::ptr::null(), |s| s.as_ptr() as *const c_char);
+
+        match unsafe { crate::compat::txn_state(self.raw(), zschema)? } {
+            SQLITE_TXN_NONE => Ok(TxnState::None),
+            SQLITE_TXN_READ => Ok(TxnState::Read),
+            SQLITE_TXN_WRITE => Ok(TxnState::Write),
+            _ => Err(Error::new(SQLITE_MISUSE)),
+        }
+    }
+}
+
+/// Jittered exponential backoff of [`Connection::run_txn`] .
+///
+/// [`Connection::run_txn`]: struct.Connection.html#method.run_txn
+struct Backoff {
+    /// The upper bound of the next sleep.
+    limit: Duration,
+    /// State of xorshift64.
+    seed: u64,
+}
+
+impl Backoff {
+    fn new() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos());
+        // xorshift must not start with 0.
+        let seed = (u64::from(nanos) << 1) | 1;
+        Self {
+            limit: Duration::from_millis(1),
+            seed,
+        }
+    }
+
+    /// Returns a random duration up to the limit, and doubles the limit.
+    fn next(&mut self) -> Duration {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+
+        let limit = self.limit.as_micros() as u64;
+        let ret = Duration::from_micros(self.seed % (limit + 1));
+        self.limit = (self.limit * 2).min(MAX_BACKOFF);
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestDb;
+    use crate::SQLITE_ERROR;
+
+    fn count(con: &mut Connection) -> i64 {
+        let mut stmt = con.stmt_once(r#"SELECT COUNT(*) FROM "foo""#).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        stmt.column_int(0).unwrap()
+    }
+
+    #[test]
+    fn commit_rollback() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("value" INTEGER)"#)
+            .unwrap();
+
+        const INSERT: &str = r#"INSERT INTO "foo" VALUES (1)"#;
+        {
+            let mut tx = con.transaction().unwrap();
+            tx.execute_batch(INSERT).unwrap();
+            tx.commit().unwrap();
+        }
+        assert_eq!(1, count(&mut con));
+
+        {
+            let mut tx = con.transaction().unwrap();
+            tx.execute_batch(INSERT).unwrap();
+            tx.rollback().unwrap();
+        }
+        assert_eq!(1, count(&mut con));
+
+        {
+            let mut tx = con
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .unwrap();
+            tx.execute_batch(INSERT).unwrap();
+            // Drop without commit.
+        }
+        assert_eq!(1, count(&mut con));
+
+        // The connection can start a new transaction.
+        assert!(con.transaction().is_ok());
+    }
+
+    #[test]
+    fn require_explicit_transactions() {
+        let mut con = TestDb::new().with_schema(r#"CREATE TABLE "foo" ("value" INTEGER)"#);
+        const INSERT: &str = r#"INSERT INTO "foo" VALUES (1)"#;
+        con.stmt(INSERT).unwrap();
+
+        assert!(!con.requires_explicit_transactions());
+        con.require_explicit_transactions(true);
+        assert!(con.requires_explicit_transactions());
+
+        // The cached statement, a new one, and execute_batch() refuse to write.
+        assert_eq!(Err(Error::AUTOCOMMIT_WRITE), con.execute(INSERT, &[]));
+        let mut stmt = con.stmt_once(INSERT).unwrap();
+        assert_eq!(Err(Error::AUTOCOMMIT_WRITE), stmt.step());
+        drop(stmt);
+        assert_eq!(Err(Error::AUTOCOMMIT_WRITE), con.execute_batch(INSERT));
+        assert_eq!(0, count(&mut con));
+
+        // Reading is allowed.
+        assert_eq!(Ok(Some((0,))), con.query_one::<(i64,)>("SELECT 0", &[]));
+
+        // Writing in a transaction is allowed.
+        {
+            let mut tx = con.transaction().unwrap();
+            assert_eq!(Ok(1), tx.execute(INSERT, &[]));
+            tx.execute_batch(INSERT).unwrap();
+            tx.commit().unwrap();
+        }
+        assert_eq!(2, count(&mut con));
+
+        con.require_explicit_transactions(false);
+        assert_eq!(Ok(1), con.execute(INSERT, &[]));
+        assert_eq!(3, count(&mut con));
+    }
+
+    #[test]
+    fn attached_transaction() {
+        let tmp = tempfile::tempdir().unwrap();
+        let main_path = tmp.path().join("main.sqlite3");
+        let aux_path = tmp.path().join("aux.sqlite3");
+        let mut con = crate::OpenOptions::default()
+            .uri(true)
+            .open(&main_path)
+            .unwrap();
+        let attach = format!(
+            r#"ATTACH '{}' AS "aux";
+               ATTACH 'file:{}?mode=ro' AS "ro";
+               ATTACH ':memory:' AS "mem";
+               CREATE TABLE "main"."foo" ("value" INTEGER);
+               CREATE TABLE "aux"."bar" ("value" INTEGER)"#,
+            aux_path.display(),
+            aux_path.display(),
+        );
+        con.execute_batch(&attach).unwrap();
+
+        fn counts(con: &mut Connection) -> (Option<(i64,)>, Option<(i64,)>) {
+            let foo = con.query_one(r#"SELECT count(*) FROM "main"."foo""#, &[]);
+            let bar = con.query_one(r#"SELECT count(*) FROM "aux"."bar""#, &[]);
+            (foo.unwrap(), bar.unwrap())
+        }
+
+        const INSERT_FOO: &str = r#"INSERT INTO "main"."foo" VALUES (1)"#;
+        const INSERT_BAR: &str = r#"INSERT INTO "aux"."bar" VALUES (1)"#;
+
+        // Dropped without commit.
+        {
+            let mut tx = con.attached_transaction(&["main", "aux", "mem"]).unwrap();
+            tx.execute(INSERT_FOO, &[]).unwrap();
+            tx.execute(INSERT_BAR, &[]).unwrap();
+        }
+        assert_eq!((Some((0,)), Some((0,))), counts(&mut con));
+
+        {
+            let mut tx = con.attached_transaction(&["main", "aux"]).unwrap();
+            tx.execute(INSERT_FOO, &[]).unwrap();
+            tx.execute(INSERT_BAR, &[]).unwrap();
+            tx.commit().unwrap();
+        }
+        assert_eq!((Some((1,)), Some((1,))), counts(&mut con));
+
+        let e = con
+            .attached_transaction(&["main", "nothing"])
+            .err()
+            .unwrap();
+        assert_eq!(SQLITE_MISUSE, e.code());
+        let e = con.attached_transaction(&["main", "ro"]).err().unwrap();
+        assert_eq!(SQLITE_READONLY, e.code());
+
+        // Not atomic in WAL mode.
+        con.execute_batch(r#"PRAGMA "aux".journal_mode = WAL"#)
+            .unwrap();
+        let e = con.attached_transaction(&["main", "aux"]).err().unwrap();
+        assert_eq!(SQLITE_MISUSE, e.code());
+        assert!(
+            e.to_string().contains("journal_mode of aux is wal"),
+            "{}",
+            e
+        );
+        assert!(con.attached_transaction(&["aux", "mem"]).is_ok());
+
+        con.execute_batch(
+            r#"PRAGMA "aux".journal_mode = DELETE;
+               PRAGMA "aux".synchronous = OFF"#,
+        )
+        .unwrap();
+        let e = con.attached_transaction(&["main", "aux"]).err().unwrap();
+        assert_eq!(SQLITE_MISUSE, e.code());
+        assert!(con.is_autocommit());
+    }
+
+    #[test]
+    fn autocommit_txn_state() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("value" INTEGER)"#)
+            .unwrap();
+
+        if !crate::HAS_TXN_STATE {
+            assert_eq!(Err(Error::UNSUPPORTED), con.txn_state(None));
+            return;
+        }
+
+        assert!(con.is_autocommit());
+        assert_eq!(Ok(TxnState::None), con.txn_state(None));
+
+        let mut tx = con.transaction().unwrap();
+        assert!(!tx.is_autocommit());
+        // "BEGIN DEFERRED" does not start any transaction until the database is accessed.
+        assert_eq!(Ok(TxnState::None), tx.txn_state(Some("main")));
+
+        tx.execute_batch(r#"SELECT * FROM "foo""#).unwrap();
+        assert_eq!(Ok(TxnState::Read), tx.txn_state(Some("main")));
+
+        tx.execute_batch(r#"INSERT INTO "foo" VALUES (1)"#).unwrap();
+        assert_eq!(Ok(TxnState::Write), tx.txn_state(Some("main")));
+        assert_eq!(Ok(TxnState::Write), tx.txn_state(None));
+        assert!(tx.txn_state(Some("no_such_schema")).is_err());
+
+        tx.commit().unwrap();
+        assert!(con.is_autocommit());
+        assert_eq!(Ok(TxnState::None), con.txn_state(None));
+    }
+
+    #[test]
+    fn run_txn_busy_snapshot() {
+        let mut con1 = TestDb::file().with_schema(
+            r#"PRAGMA journal_mode = WAL;
+               CREATE TABLE "foo" ("value" INTEGER)"#,
+        );
+        let mut con2 = crate::OpenOptions::default()
+            .open(con1.path().unwrap())
+            .unwrap();
+
+        const INSERT: &str = r#"INSERT INTO "foo" VALUES (1)"#;
+
+        // Another connection writes between the read and the write.
+        let attempts = core::cell::Cell::new(0);
+        let mut f = |txn: &mut Transaction<'_>| {
+            attempts.set(attempts.get() + 1);
+            let n = count(txn);
+            if attempts.get() == 1 {
+                con2.execute_batch(INSERT).unwrap();
+            }
+            txn.execute_batch(INSERT)?;
+            Ok(n)
+        };
+
+        let e = con1
+            .run_txn(TransactionBehavior::Deferred, 0, &mut f)
+            .unwrap_err();
+        assert_eq!(SQLITE_BUSY, e.code() & 0xff);
+        assert_eq!(1, count(&mut con1));
+
+        attempts.set(0);
+        assert_eq!(
+            Ok(2),
+            con1.run_txn(TransactionBehavior::Deferred, 3, &mut f)
+        );
+        assert_eq!(2, attempts.get());
+        assert_eq!(3, count(&mut con1));
+        assert!(con1.is_autocommit());
+    }
+
+    #[test]
+    fn run_txn_immediate() {
+        use std::sync::mpsc;
+
+        let mut con1 = TestDb::file().with_schema(
+            r#"PRAGMA journal_mode = WAL;
+               CREATE TABLE "foo" ("value" INTEGER)"#,
+        );
+        let mut con2 = crate::OpenOptions::default()
+            .open(con1.path().unwrap())
+            .unwrap();
+
+        // Another thread holds the write lock for a while.
+        let (tx, rx) = mpsc::channel();
+        let handle = std::thread::spawn(move || {
+            let txn = con2
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .unwrap();
+            tx.send(()).unwrap();
+            std::thread::sleep(Duration::from_millis(200));
+            txn.commit().unwrap();
+        });
+        rx.recv().unwrap();
+
+        let mut f = |txn: &mut Transaction<'_>| txn.execute(r#"INSERT INTO "foo" VALUES (1)"#, &[]);
+        let e = con1
+            .run_txn(TransactionBehavior::Immediate, 0, &mut f)
+            .unwrap_err();
+        assert_eq!(SQLITE_BUSY, e.code() & 0xff);
+
+        assert_eq!(
+            Ok(1),
+            con1.run_txn(TransactionBehavior::Immediate, 100, &mut f)
+        );
+        handle.join().unwrap();
+        assert_eq!(1, count(&mut con1));
+
+        // The other errors are not retried.
+        let mut attempts = 0;
+        let e = con1
+            .run_txn(TransactionBehavior::Immediate, 100, |txn| {
+                attempts += 1;
+                txn.execute_batch("INVALID SQL")
+            })
+            .unwrap_err();
+        assert_eq!(SQLITE_ERROR, e.code());
+        assert_eq!(1, attempts);
+    }
+
+    #[test]
+    fn backoff() {
+        let mut backoff = Backoff::new();
+        for i in 0..16 {
+            let limit = Duration::from_millis(1 << i).min(MAX_BACKOFF);
+            assert!(backoff.next() <= limit);
+        }
+    }
+}
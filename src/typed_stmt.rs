@@ -0,0 +1,324 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{Connection, Error, FromRow, StepResult, Stmt, SQLITE_MISUSE};
+use core::marker::PhantomData;
+
+/// The prefixes of the named parameters that [`BindParams`] fields are bound to, in the order of
+/// the priority.
+///
+/// [`BindParams`]: trait.BindParams.html
+const PREFIXES: [&str; 3] = [":", "@", "$"];
+
+/// Types that bind the fields to the named parameters of a statement, which [`TypedStmt`] uses.
+///
+/// Feature "derive" enables `#[derive(BindParams)]` for structs with named fields; it binds each
+/// field to the parameter of the same name (e.g. field `id` to ":id" .)
+///
+/// # Examples
+///
+/// ```
+/// use mouse_sqlite3::{BindParams, Connection, Error, Stmt};
+///
+/// struct Foo {
+///     id: i64,
+///     name: Option<String>,
+/// }
+///
+/// // Same as `#[derive(BindParams)]`
+/// impl BindParams for Foo {
+///     fn param_names() -> &'static [&'static str] {
+///         &["id", "name"]
+///     }
+///
+///     fn bind_params(&self, stmt: &mut Stmt, indices: &[usize]) -> Result<(), Error> {
+///         stmt.bind(indices[0], &self.id)?;
+///         stmt.bind(indices[1], &self.name)?;
+///         Ok(())
+///     }
+/// }
+///
+/// let mut con = Connection::open_memory_db().unwrap();
+/// con.execute_batch(r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "name" TEXT)"#)
+///     .unwrap();
+///
+/// let mut insert = con
+///     .typed_stmt::<Foo, ()>(r#"INSERT INTO "foo" VALUES (:id, :name)"#)
+///     .unwrap();
+/// let foo = Foo { id: 1, name: None };
+/// assert_eq!(Ok(1), insert.execute(&foo));
+/// ```
+///
+/// [`TypedStmt`]: struct.TypedStmt.html
+pub trait BindParams {
+    /// Returns the names of the parameters without the prefix (e.g. "id" for ":id" .)
+    fn param_names() -> &'static [&'static str];
+
+    /// Binds the fields to `stmt` ; `indices[i]` is the index of the parameter named
+    /// `param_names()[i]` .
+    fn bind_params(&self, stmt: &mut Stmt, indices: &[usize]) -> Result<(), Error>;
+}
+
+/// Prepared statement that binds `P` and returns the rows as `R` .
+///
+/// The parameters are matched to the fields of `P` at the first execution, which returns
+/// `SQLITE_MISUSE` listing the unmatched names if any field or parameter is left. The parameter
+/// of field `id` is ":id" , "@id" , or "$id" .
+///
+/// See [`Connection::typed_stmt`] .
+///
+/// [`Connection::typed_stmt`]: struct.Connection.html#method.typed_stmt
+pub struct TypedStmt<P, R> {
+    stmt: Stmt,
+    /// The parameter index of each `P::param_names()` , which is resolved at the first use.
+    indices: Option<Vec<usize>>,
+    _phantom: PhantomData<fn(&P) -> R>,
+}
+
+impl<P, R> TypedStmt<P, R>
+where
+    P: BindParams,
+{
+    /// Returns the statement.
+    #[inline]
+    pub fn stmt(&mut self) -> &mut Stmt {
+        &mut self.stmt
+    }
+
+    /// Binds `params` , executes the statement until the end discarding the rows, and returns
+    /// the number of the rows modified, inserted, or deleted.
+    ///
+    /// The statement is reset and the parameters are cleared before returning, even if an error
+    /// occurs.
+    pub fn execute(&mut self, params: &P) -> Result<usize, Error> {
+        let ret = self.bind(params).and_then(|stmt| loop {
+            if let StepResult::Done { changes } = stmt.step2()? {
+                break Ok(changes as usize);
+            }
+        });
+        let cleared = self.stmt.clear();
+        let changes = ret?;
+        cleared.map(|_| changes)
+    }
+
+    /// Resets the statement and binds `params` .
+    fn bind(&mut self, params: &P) -> Result<&mut Stmt, Error> {
+        self.stmt.reset();
+        if self.indices.is_none() {
+            self.indices = Some(resolve_indices(&self.stmt, P::param_names())?);
+        }
+
+        let indices = self.indices.as_deref().unwrap_or_default();
+        params.bind_params(&mut self.stmt, indices)?;
+        Ok(&mut self.stmt)
+    }
+}
+
+impl<P, R> TypedStmt<P, R>
+where
+    P: BindParams,
+    R: FromRow,
+{
+    /// Binds `params` , executes the statement, and returns all the rows converted into `R` .
+    ///
+    /// The statement is reset and the parameters are cleared before returning, even if an error
+    /// occurs.
+    pub fn query(&mut self, params: &P) -> Result<Vec<R>, Error> {
+        let ret = self.bind(params).and_then(|stmt| stmt.query_as());
+        let cleared = self.stmt.clear();
+        let rows = ret?;
+        cleared.map(|_| rows)
+    }
+}
+
+/// Returns the parameter index of each of `names` , or an error listing the unmatched fields and
+/// parameters.
+fn resolve_indices(stmt: &Stmt, names: &[&str]) -> Result<Vec<usize>, Error> {
+    let mut indices = Vec::with_capacity(names.len());
+    let mut unmatched_fields = Vec::new();
+    for &name in names {
+        let index = PREFIXES
+            .iter()
+            .find_map(|prefix| stmt.parameter_index(&format!("{}{}", prefix, name)));
+        match index {
+            Some(index) => indices.push(index),
+            None => unmatched_fields.push(name.to_string()),
+        }
+    }
+
+    let unmatched_params: Vec<String> = (1..=stmt.parameter_count())
+        .filter(|index| !indices.contains(index))
+        .map(|index| match stmt.parameter_name(index) {
+            Some(name) => name.to_string(),
+            None => format!("?{}", index),
+        })
+        .collect();
+
+    if unmatched_fields.is_empty() && unmatched_params.is_empty() {
+        return Ok(indices);
+    }
+
+    let mut messages = Vec::new();
+    if !unmatched_fields.is_empty() {
+        let fields = unmatched_fields.join(", ");
+        messages.push(format!("no parameter for field(s) {}", fields));
+    }
+    if !unmatched_params.is_empty() {
+        let params = unmatched_params.join(", ");
+        messages.push(format!("no field for parameter(s) {}", params));
+    }
+    Err(Error::with_message(SQLITE_MISUSE, messages.join("; ")))
+}
+
+impl Connection {
+    /// Prepares `sql` as [`TypedStmt`] , which binds `P` and returns the rows as `R` .
+    ///
+    /// Unlike [`stmt`] , the statement is not cached; `TypedStmt` owns it. `R` is required only
+    /// by [`TypedStmt::query`] ; e.g. `()` will do for "INSERT" statements.
+    ///
+    /// [`TypedStmt`]: struct.TypedStmt.html
+    /// [`stmt`]: #method.stmt
+    /// [`TypedStmt::query`]: struct.TypedStmt.html#method.query
+    pub fn typed_stmt<P, R>(&mut self, sql: &str) -> Result<TypedStmt<P, R>, Error>
+    where
+        P: BindParams,
+    {
+        Ok(TypedStmt {
+            stmt: self.stmt_once(sql)?,
+            indices: None,
+            _phantom: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Foo {
+        id: i64,
+        name: Option<String>,
+    }
+
+    impl BindParams for Foo {
+        fn param_names() -> &'static [&'static str] {
+            &["id", "name"]
+        }
+
+        fn bind_params(&self, stmt: &mut Stmt, indices: &[usize]) -> Result<(), Error> {
+            stmt.bind(indices[0], &self.id)?;
+            stmt.bind(indices[1], &self.name)?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn typed_stmt() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "name" TEXT)"#)
+            .unwrap();
+
+        let mut insert = con
+            .typed_stmt::<Foo, ()>(r#"INSERT INTO "foo" VALUES (@id, :name)"#)
+            .unwrap();
+        for (id, name) in [(1, Some("a")), (2, None), (3, Some("c"))].iter() {
+            let foo = Foo {
+                id: *id,
+                name: name.map(String::from),
+            };
+            assert_eq!(Ok(1), insert.execute(&foo));
+        }
+
+        let sql = r#"SELECT "id", "name" FROM "foo" WHERE "id" >= $id OR "name" = :name"#;
+        let mut select = con.typed_stmt::<Foo, (i64, Option<String>)>(sql).unwrap();
+        let params = Foo {
+            id: 2,
+            name: Some("a".to_string()),
+        };
+        let expected = vec![
+            (1, Some("a".to_string())),
+            (2, None),
+            (3, Some("c".to_string())),
+        ];
+        assert_eq!(Ok(expected), select.query(&params));
+
+        let params = Foo { id: 3, name: None };
+        assert_eq!(Ok(vec![(3, Some("c".to_string()))]), select.query(&params));
+
+        let e = con
+            .query_one::<(i64,)>(r#"SELECT count(*) FROM "foo" WHERE "name" IS NULL"#, &[])
+            .unwrap();
+        assert_eq!(Some((1,)), e);
+    }
+
+    #[test]
+    fn unmatched() {
+        let mut con = Connection::open_memory_db().unwrap();
+
+        let mut stmt = con
+            .typed_stmt::<Foo, (i64,)>("SELECT :id, :nam, ?")
+            .unwrap();
+        let params = Foo { id: 1, name: None };
+        let e = stmt.execute(&params).unwrap_err();
+        assert_eq!(SQLITE_MISUSE, e.code());
+        let message = "no parameter for field(s) name; no field for parameter(s) :nam, ?3";
+        assert_eq!(Some(message), e.message());
+
+        // Every use fails.
+        assert_eq!(Err(e), stmt.query(&params).map(|_| ()));
+
+        let mut stmt = con.typed_stmt::<Foo, ()>("SELECT :id").unwrap();
+        let e = stmt.execute(&params).unwrap_err();
+        assert_eq!(Some("no parameter for field(s) name"), e.message());
+    }
+}
@@ -0,0 +1,586 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{Error, Value, ValueRef, SQLITE_TOOBIG};
+use core::convert::TryFrom;
+use core::num::{
+    NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroIsize, NonZeroU16, NonZeroU32,
+    NonZeroU64, NonZeroU8, NonZeroUsize,
+};
+use core::str::Utf8Error;
+use std::fmt;
+
+/// Error of [`FromSql`] .
+///
+/// [`FromSql`]: trait.FromSql.html
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum FromSqlError {
+    /// The datatype of the value is not acceptable.
+    InvalidType,
+    /// The integer value is out of the range of the type.
+    OutOfRange(i64),
+    /// The length of the TEXT or BLOB is not acceptable.
+    InvalidLength {
+        /// The acceptable length in bytes.
+        expected: usize,
+        /// The actual length in bytes.
+        actual: usize,
+    },
+    /// The text is not a valid UTF-8.
+    Utf8(Utf8Error),
+    /// Any other error.
+    Other(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl fmt::Display for FromSqlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FromSqlError::InvalidType => f.write_str("invalid datatype"),
+            FromSqlError::OutOfRange(i) => write!(f, "value {} is out of range", i),
+            FromSqlError::InvalidLength { expected, actual } => {
+                write!(f, "expected {} bytes, but {} bytes", expected, actual)
+            }
+            FromSqlError::Utf8(e) => e.fmt(f),
+            FromSqlError::Other(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for FromSqlError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FromSqlError::Utf8(e) => Some(e),
+            FromSqlError::Other(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// Types that can be built from a column value.
+///
+/// Lifetime `'a` is that of the column value; types like `&'a str` and `&'a [u8]` borrow the
+/// column data without copying.
+pub trait FromSql<'a>: Sized {
+    /// Converts `value` into `Self` .
+    fn column_result(value: ValueRef<'a>) -> Result<Self, FromSqlError>;
+}
+
+macro_rules! impl_from_sql_for_integer {
+    ($($t:ty),*) => {
+        $(
+            impl FromSql<'_> for $t {
+                #[inline]
+                fn column_result(value: ValueRef<'_>) -> Result<Self, FromSqlError> {
+                    match value {
+                        ValueRef::Integer(i) => {
+                            <$t>::try_from(i).map_err(|_| FromSqlError::OutOfRange(i))
+                        }
+                        _ => Err(FromSqlError::InvalidType),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_from_sql_for_integer!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+macro_rules! impl_from_sql_for_non_zero {
+    ($($t:ty => $inner:ty),*) => {
+        $(
+            impl FromSql<'_> for $t {
+                /// Accepts INTEGER except for 0.
+                #[inline]
+                fn column_result(value: ValueRef<'_>) -> Result<Self, FromSqlError> {
+                    let n = <$inner>::column_result(value)?;
+                    <$t>::new(n).ok_or(FromSqlError::OutOfRange(0))
+                }
+            }
+        )*
+    };
+}
+
+impl_from_sql_for_non_zero!(
+    NonZeroI8 => i8, NonZeroI16 => i16, NonZeroI32 => i32, NonZeroI64 => i64,
+    NonZeroIsize => isize, NonZeroU8 => u8, NonZeroU16 => u16, NonZeroU32 => u32,
+    NonZeroU64 => u64, NonZeroUsize => usize
+);
+
+impl FromSql<'_> for f64 {
+    /// Accepts both REAL and INTEGER.
+    #[inline]
+    fn column_result(value: ValueRef<'_>) -> Result<Self, FromSqlError> {
+        match value {
+            ValueRef::Real(f) => Ok(f),
+            ValueRef::Integer(i) => Ok(i as f64),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl FromSql<'_> for bool {
+    /// Accepts INTEGER 0 as `false` and 1 as `true` .
+    ///
+    /// This strict mode is what the generic accessors like `Stmt::get::<bool>` use. Use
+    /// `Stmt::column_bool_lenient` to accept other spellings, e.g. TEXT 'true' .
+    #[inline]
+    fn column_result(value: ValueRef<'_>) -> Result<Self, FromSqlError> {
+        match value {
+            ValueRef::Integer(0) => Ok(false),
+            ValueRef::Integer(1) => Ok(true),
+            ValueRef::Integer(i) => Err(FromSqlError::OutOfRange(i)),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl<'a> FromSql<'a> for &'a str {
+    /// Accepts TEXT, which must be a valid UTF-8; otherwise fails with [`FromSqlError::Utf8`] .
+    ///
+    /// Use [`Stmt::column_text_lossy`] or [`Stmt::column_text_bytes`] to read TEXT in another
+    /// encoding.
+    ///
+    /// [`FromSqlError::Utf8`]: enum.FromSqlError.html#variant.Utf8
+    /// [`Stmt::column_text_lossy`]: struct.Stmt.html#method.column_text_lossy
+    /// [`Stmt::column_text_bytes`]: struct.Stmt.html#method.column_text_bytes
+    #[inline]
+    fn column_result(value: ValueRef<'a>) -> Result<Self, FromSqlError> {
+        match value {
+            ValueRef::Text(s) => core::str::from_utf8(s).map_err(FromSqlError::Utf8),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl FromSql<'_> for String {
+    /// Accepts TEXT, which must be a valid UTF-8 as `&str` does.
+    #[inline]
+    fn column_result(value: ValueRef<'_>) -> Result<Self, FromSqlError> {
+        <&str>::column_result(value).map(ToString::to_string)
+    }
+}
+
+impl<'a> FromSql<'a> for &'a [u8] {
+    /// Accepts BLOB.
+    #[inline]
+    fn column_result(value: ValueRef<'a>) -> Result<Self, FromSqlError> {
+        match value {
+            ValueRef::Blob(b) => Ok(b),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl FromSql<'_> for Vec<u8> {
+    /// Accepts BLOB.
+    #[inline]
+    fn column_result(value: ValueRef<'_>) -> Result<Self, FromSqlError> {
+        <&[u8]>::column_result(value).map(<[u8]>::to_vec)
+    }
+}
+
+impl<const N: usize> FromSql<'_> for [u8; N] {
+    /// Accepts BLOB of exactly `N` bytes.
+    #[inline]
+    fn column_result(value: ValueRef<'_>) -> Result<Self, FromSqlError> {
+        let bytes = <&[u8]>::column_result(value)?;
+        <[u8; N]>::try_from(bytes).map_err(|_| FromSqlError::InvalidLength {
+            expected: N,
+            actual: bytes.len(),
+        })
+    }
+}
+
+impl<'a, T> FromSql<'a> for Option<T>
+where
+    T: FromSql<'a>,
+{
+    /// Returns `None` for NULL, or delegates to `T` .
+    #[inline]
+    fn column_result(value: ValueRef<'a>) -> Result<Self, FromSqlError> {
+        match value {
+            ValueRef::Null => Ok(None),
+            v => T::column_result(v).map(Some),
+        }
+    }
+}
+
+/// The output of [`ToSql`] .
+///
+/// [`ToSql`]: trait.ToSql.html
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToSqlOutput<'a> {
+    /// Borrowed value.
+    Borrowed(ValueRef<'a>),
+    /// Owned value.
+    Owned(Value),
+}
+
+impl ToSqlOutput<'_> {
+    /// Returns the value as `ValueRef` .
+    #[inline]
+    pub fn as_value_ref(&self) -> ValueRef<'_> {
+        match self {
+            ToSqlOutput::Borrowed(v) => *v,
+            ToSqlOutput::Owned(v) => ValueRef::from(v),
+        }
+    }
+}
+
+impl<'a> From<ValueRef<'a>> for ToSqlOutput<'a> {
+    #[inline]
+    fn from(v: ValueRef<'a>) -> Self {
+        ToSqlOutput::Borrowed(v)
+    }
+}
+
+impl From<Value> for ToSqlOutput<'_> {
+    #[inline]
+    fn from(v: Value) -> Self {
+        ToSqlOutput::Owned(v)
+    }
+}
+
+/// Types that can be bound to a parameter.
+pub trait ToSql {
+    /// Converts `self` into a value to be bound.
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error>;
+}
+
+impl<T> ToSql for &T
+where
+    T: ?Sized + ToSql,
+{
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        (**self).to_sql()
+    }
+}
+
+macro_rules! impl_to_sql_for_integer {
+    ($($t:ty),*) => {
+        $(
+            impl ToSql for $t {
+                /// Binds as INTEGER.
+                #[inline]
+                fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+                    match i64::try_from(*self) {
+                        Ok(i) => Ok(ToSqlOutput::Borrowed(ValueRef::Integer(i))),
+                        Err(_) => {
+                            let message = format!("{} is out of range of i64", self);
+                            Err(Error::with_message(SQLITE_TOOBIG, message))
+                        }
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_to_sql_for_integer!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+macro_rules! impl_to_sql_for_non_zero {
+    ($($t:ty),*) => {
+        $(
+            impl ToSql for $t {
+                /// Binds as INTEGER.
+                #[inline]
+                fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+                    match i64::try_from(self.get()) {
+                        Ok(i) => Ok(ToSqlOutput::Borrowed(ValueRef::Integer(i))),
+                        Err(_) => {
+                            let message = format!("{} is out of range of i64", self);
+                            Err(Error::with_message(SQLITE_TOOBIG, message))
+                        }
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_to_sql_for_non_zero!(
+    NonZeroI8,
+    NonZeroI16,
+    NonZeroI32,
+    NonZeroI64,
+    NonZeroIsize,
+    NonZeroU8,
+    NonZeroU16,
+    NonZeroU32,
+    NonZeroU64,
+    NonZeroUsize
+);
+
+impl ToSql for f64 {
+    /// Binds as REAL.
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        Ok(ToSqlOutput::Borrowed(ValueRef::Real(*self)))
+    }
+}
+
+impl ToSql for bool {
+    /// Binds as INTEGER 0 or 1.
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        Ok(ToSqlOutput::Borrowed(ValueRef::Integer(*self as i64)))
+    }
+}
+
+impl ToSql for str {
+    /// Binds as TEXT.
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        Ok(ToSqlOutput::Borrowed(ValueRef::Text(self.as_bytes())))
+    }
+}
+
+impl ToSql for String {
+    /// Binds as TEXT.
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        self.as_str().to_sql()
+    }
+}
+
+impl ToSql for [u8] {
+    /// Binds as BLOB.
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        Ok(ToSqlOutput::Borrowed(ValueRef::Blob(self)))
+    }
+}
+
+impl ToSql for Vec<u8> {
+    /// Binds as BLOB.
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        self.as_slice().to_sql()
+    }
+}
+
+impl<const N: usize> ToSql for [u8; N] {
+    /// Binds as BLOB.
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        self[..].to_sql()
+    }
+}
+
+impl<T> ToSql for Option<T>
+where
+    T: ToSql,
+{
+    /// Binds NULL for `None` , or delegates to `T` .
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        match self {
+            None => Ok(ToSqlOutput::Borrowed(ValueRef::Null)),
+            Some(t) => t.to_sql(),
+        }
+    }
+}
+
+impl ToSql for Value {
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        Ok(ToSqlOutput::Borrowed(ValueRef::from(self)))
+    }
+}
+
+impl ToSql for ValueRef<'_> {
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        Ok(ToSqlOutput::Borrowed(*self))
+    }
+}
+
+/// Implements [`ToSql`] and [`FromSql`] for tuple structs with one field by delegating to the
+/// field.
+///
+/// `impl_sql_newtype!(Foo => T)` requires `Foo` to be declared as `struct Foo(T)` , where `T`
+/// implements [`ToSql`] and `for<'a> FromSql<'a>` . The field must be visible from where the
+/// macro is invoked.
+///
+/// # Example
+///
+/// ```
+/// use mouse_sqlite3::{impl_sql_newtype, Connection};
+///
+/// #[derive(Debug, PartialEq)]
+/// struct UserId(i64);
+/// #[derive(Debug, PartialEq)]
+/// struct UserName(String);
+///
+/// impl_sql_newtype!(UserId => i64, UserName => String);
+///
+/// let mut con = Connection::open_memory_db().unwrap();
+/// let mut stmt = con.stmt_once("SELECT ?1, ?2").unwrap();
+/// stmt.bind(1, &UserId(42)).unwrap();
+/// stmt.bind(2, &UserName("alice".to_string())).unwrap();
+/// assert_eq!(Ok(true), stmt.step());
+/// assert_eq!(Ok(UserId(42)), stmt.get(0));
+/// assert_eq!(Ok(UserName("alice".to_string())), stmt.get(1));
+/// ```
+///
+/// [`ToSql`]: trait.ToSql.html
+/// [`FromSql`]: trait.FromSql.html
+#[macro_export]
+macro_rules! impl_sql_newtype {
+    ($($name:ident => $inner:ty),* $(,)?) => {
+        $(
+            impl $crate::ToSql for $name {
+                #[inline]
+                fn to_sql(&self) -> Result<$crate::ToSqlOutput<'_>, $crate::Error> {
+                    $crate::ToSql::to_sql(&self.0)
+                }
+            }
+
+            impl $crate::FromSql<'_> for $name {
+                #[inline]
+                fn column_result(
+                    value: $crate::ValueRef<'_>,
+                ) -> Result<Self, $crate::FromSqlError> {
+                    <$inner as $crate::FromSql<'_>>::column_result(value).map($name)
+                }
+            }
+        )*
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integer() {
+        assert_eq!(127, i8::column_result(ValueRef::Integer(127)).unwrap());
+        assert!(matches!(
+            i8::column_result(ValueRef::Integer(128)),
+            Err(FromSqlError::OutOfRange(128))
+        ));
+        assert!(matches!(
+            u64::column_result(ValueRef::Integer(-1)),
+            Err(FromSqlError::OutOfRange(-1))
+        ));
+        assert!(matches!(
+            i64::column_result(ValueRef::Real(1.0)),
+            Err(FromSqlError::InvalidType)
+        ));
+    }
+
+    #[test]
+    fn option() {
+        assert_eq!(None, Option::<i64>::column_result(ValueRef::Null).unwrap());
+        assert_eq!(
+            Some("foo"),
+            Option::<&str>::column_result(ValueRef::Text(b"foo")).unwrap()
+        );
+        assert!(i64::column_result(ValueRef::Null).is_err());
+    }
+
+    #[test]
+    fn non_zero() {
+        assert_eq!(
+            NonZeroI64::new(-3),
+            Some(NonZeroI64::column_result(ValueRef::Integer(-3)).unwrap())
+        );
+        assert!(matches!(
+            NonZeroI64::column_result(ValueRef::Integer(0)),
+            Err(FromSqlError::OutOfRange(0))
+        ));
+        assert!(matches!(
+            NonZeroU8::column_result(ValueRef::Integer(256)),
+            Err(FromSqlError::OutOfRange(256))
+        ));
+        let n = NonZeroU32::new(7).unwrap();
+        assert_eq!(ValueRef::Integer(7), n.to_sql().unwrap().as_value_ref());
+        let n = NonZeroU64::new(u64::MAX).unwrap();
+        assert_eq!(SQLITE_TOOBIG, n.to_sql().unwrap_err().code());
+    }
+
+    #[test]
+    fn newtype() {
+        use crate::Connection;
+
+        #[derive(Debug, PartialEq)]
+        struct UserId(NonZeroI64);
+        impl_sql_newtype!(UserId => NonZeroI64);
+
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(
+            r#"CREATE TABLE "users" ("id" INTEGER PRIMARY KEY, "name" TEXT);
+               INSERT INTO "users" VALUES (0, 'root'), (1000, 'alice')"#,
+        )
+        .unwrap();
+
+        let id = UserId(NonZeroI64::new(1000).unwrap());
+        let mut stmt = con
+            .stmt_once(r#"SELECT "id", "name" FROM "users" WHERE "id" = ?1"#)
+            .unwrap();
+        stmt.bind(1, &id).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(id), stmt.get(0));
+        assert_eq!(Ok("alice"), stmt.get(1));
+
+        let mut stmt = con
+            .stmt_once(r#"SELECT "id" FROM "users" ORDER BY "id""#)
+            .unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        let e = stmt.get::<UserId>(0).unwrap_err();
+        assert_eq!(crate::SQLITE_MISMATCH, e.code());
+        assert_eq!(Some("column 0: value 0 is out of range"), e.message());
+    }
+}
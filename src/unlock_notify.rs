@@ -0,0 +1,304 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{sqlite3, Connection, Error, SQLITE_LOCKED, SQLITE_OK};
+use core::ptr::null_mut;
+use std::collections::BTreeMap;
+use std::os::raw::{c_int, c_void};
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+#[link(name = "sqlite3")]
+extern "C" {
+    fn sqlite3_unlock_notify(
+        pblocked: *mut sqlite3,
+        xnotify: Option<unsafe extern "C" fn(*mut *mut c_void, c_int)>,
+        pnotifyarg: *mut c_void,
+    ) -> c_int;
+    fn sqlite3_extended_errcode(db: *mut sqlite3) -> c_int;
+}
+
+// https://www.sqlite.org/rescode.html
+const SQLITE_LOCKED_SHAREDCACHE: c_int = SQLITE_LOCKED | (1 << 8);
+
+/// The timeout of each connection that enables waiting, keyed by the address of `sqlite3` .
+///
+/// This is looked up only when a step fails with `SQLITE_LOCKED` , so that the statements do not
+/// have to hold the setting.
+static TIMEOUTS: Mutex<BTreeMap<usize, Duration>> = Mutex::new(BTreeMap::new());
+
+fn timeout_of(db: *mut sqlite3) -> Option<Duration> {
+    let timeouts = TIMEOUTS.lock().unwrap_or_else(PoisonError::into_inner);
+    timeouts.get(&(db as usize)).copied()
+}
+
+/// Forgets the timeout of `db` , which is being closed.
+pub(crate) fn unregister(db: *mut sqlite3) {
+    let mut timeouts = TIMEOUTS.lock().unwrap_or_else(PoisonError::into_inner);
+    timeouts.remove(&(db as usize));
+}
+
+/// Whether `code` returned from `sqlite3_step` is `SQLITE_LOCKED_SHAREDCACHE` .
+#[inline]
+pub(crate) fn is_locked_sharedcache(db: *mut sqlite3, code: c_int) -> bool {
+    code & 0xff == SQLITE_LOCKED
+        && unsafe { sqlite3_extended_errcode(db) } == SQLITE_LOCKED_SHAREDCACHE
+}
+
+struct Notification {
+    unlocked: Mutex<bool>,
+    cond: Condvar,
+}
+
+unsafe extern "C" fn notify(args: *mut *mut c_void, n: c_int) {
+    for i in 0..n as usize {
+        let notification = &*(*args.add(i) as *const Notification);
+        *notification
+            .unlocked
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = true;
+        notification.cond.notify_one();
+    }
+}
+
+/// Result of [`wait`] .
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Wait {
+    /// The blocking connection has finished its transaction.
+    Unlocked,
+    /// Waiting is disabled for the connection, or the deadline has passed.
+    TimedOut,
+}
+
+/// Waits until the connection blocking `db` finishes its transaction, or until `deadline` .
+///
+/// `deadline` is `None` at the first call of each step, and set to the deadline calculated from
+/// the timeout of `db` .
+///
+/// Returns `SQLITE_LOCKED_SHAREDCACHE` if waiting would deadlock.
+pub(crate) fn wait(db: *mut sqlite3, deadline: &mut Option<Instant>) -> Result<Wait, Error> {
+    let deadline = match deadline {
+        Some(d) => *d,
+        None => match timeout_of(db) {
+            None => return Ok(Wait::TimedOut),
+            Some(timeout) => *deadline.insert(Instant::now() + timeout),
+        },
+    };
+
+    let notification = Notification {
+        unlocked: Mutex::new(false),
+        cond: Condvar::new(),
+    };
+    let arg = &notification as *const Notification as *mut c_void;
+
+    // The callback may be invoked in this call if the blocking connection has already finished.
+    let code = unsafe { sqlite3_unlock_notify(db, Some(notify), arg) };
+    if code != SQLITE_OK {
+        let message = "waiting for the unlock notification would deadlock";
+        return Err(Error::with_message(SQLITE_LOCKED_SHAREDCACHE, message));
+    }
+
+    let unlocked = notification
+        .unlocked
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let timeout = deadline.saturating_duration_since(Instant::now());
+    let (unlocked, _) = notification
+        .cond
+        .wait_timeout_while(unlocked, timeout, |unlocked| !*unlocked)
+        .unwrap_or_else(PoisonError::into_inner);
+    if *unlocked {
+        return Ok(Wait::Unlocked);
+    }
+    drop(unlocked);
+
+    // Cancel the callback. libsqlite3 invokes the callback and cancels it under the same mutex,
+    // so `notification` is never accessed after this call.
+    unsafe { sqlite3_unlock_notify(db, None, null_mut()) };
+    if *notification
+        .unlocked
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+    {
+        Ok(Wait::Unlocked)
+    } else {
+        Ok(Wait::TimedOut)
+    }
+}
+
+impl Connection {
+    /// Makes [`Stmt::step`] wait with [`sqlite3_unlock_notify`] up to `timeout` and retry if it
+    /// fails with `SQLITE_LOCKED_SHAREDCACHE` , or disables waiting if `timeout` is `None` .
+    ///
+    /// `SQLITE_LOCKED_SHAREDCACHE` occurs when connections sharing the cache (e.g. opened with
+    /// URI parameter `cache=shared` ) conflict on a table lock, where the busy timeout does not
+    /// help. The setting applies to all the statements of `self` including those already
+    /// prepared. Waiting is disabled by default.
+    ///
+    /// If waiting would deadlock (i.e. the blocking connection is waiting for `self` ), the step
+    /// returns `SQLITE_LOCKED_SHAREDCACHE` (262) immediately. If the lock is not released before
+    /// the deadline, the step returns `SQLITE_LOCKED` (6) as it does without waiting.
+    ///
+    /// A statement is retried only if it is not in the middle of the iteration.
+    ///
+    /// This method is enabled by feature "unlock-notify" , which requires libsqlite3 built with
+    /// option `SQLITE_ENABLE_UNLOCK_NOTIFY` .
+    ///
+    /// [`Stmt::step`]: struct.Stmt.html#method.step
+    /// [`sqlite3_unlock_notify`]: https://www.sqlite.org/c3ref/unlock_notify.html
+    pub fn set_unlock_notify_timeout(&mut self, timeout: Option<Duration>) {
+        let mut timeouts = TIMEOUTS.lock().unwrap_or_else(PoisonError::into_inner);
+        match timeout {
+            Some(timeout) => timeouts.insert(self.raw() as usize, timeout),
+            None => timeouts.remove(&(self.raw() as usize)),
+        };
+    }
+
+    /// Returns the timeout that [`set_unlock_notify_timeout`] set.
+    ///
+    /// [`set_unlock_notify_timeout`]: #method.set_unlock_notify_timeout
+    #[inline]
+    pub fn unlock_notify_timeout(&self) -> Option<Duration> {
+        timeout_of(self.raw())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OpenOptions;
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    fn open(name: &str) -> Connection {
+        let uri = format!("file:{}?mode=memory&cache=shared", name);
+        OpenOptions::new().uri(true).open(uri).unwrap()
+    }
+
+    #[test]
+    fn wait_for_writer() {
+        let mut writer = open("unlock_notify_wait");
+        let mut reader = open("unlock_notify_wait");
+        writer
+            .execute_batch(
+                r#"CREATE TABLE "foo" ("value" INTEGER);
+                   BEGIN;
+                   INSERT INTO "foo" VALUES (1);"#,
+            )
+            .unwrap();
+
+        // Disabled by default
+        let mut stmt = reader.stmt_once(r#"SELECT count(*) FROM "foo""#).unwrap();
+        assert_eq!(SQLITE_LOCKED, stmt.step().unwrap_err().code());
+        drop(stmt);
+
+        reader.set_unlock_notify_timeout(Some(Duration::from_millis(100)));
+        assert_eq!(
+            Some(Duration::from_millis(100)),
+            reader.unlock_notify_timeout()
+        );
+        let mut stmt = reader.stmt_once(r#"SELECT count(*) FROM "foo""#).unwrap();
+        assert_eq!(SQLITE_LOCKED, stmt.step().unwrap_err().code());
+        drop(stmt);
+
+        reader.set_unlock_notify_timeout(Some(Duration::from_secs(10)));
+        let (tx, rx) = channel();
+        let handle = thread::spawn(move || {
+            let mut stmt = reader.stmt_once(r#"SELECT count(*) FROM "foo""#).unwrap();
+            tx.send(()).unwrap();
+            assert_eq!(Ok(true), stmt.step());
+            stmt.get::<i64>(0)
+        });
+
+        rx.recv().unwrap();
+        thread::sleep(Duration::from_millis(100));
+        writer.execute_batch("COMMIT").unwrap();
+        assert_eq!(Ok(1), handle.join().unwrap());
+    }
+
+    #[test]
+    fn deadlock() {
+        let mut con1 = open("unlock_notify_deadlock");
+        let mut con2 = open("unlock_notify_deadlock");
+        con1.execute_batch(r#"CREATE TABLE "foo" ("v"); CREATE TABLE "bar" ("v")"#)
+            .unwrap();
+        con1.execute_batch(r#"BEGIN; INSERT INTO "foo" VALUES (1)"#)
+            .unwrap();
+        // con2 holds the read lock on "bar" until the end of the transaction.
+        con2.execute_batch(r#"BEGIN; SELECT * FROM "bar""#).unwrap();
+        con1.set_unlock_notify_timeout(Some(Duration::from_secs(10)));
+        con2.set_unlock_notify_timeout(Some(Duration::from_secs(10)));
+
+        // con2 waits for con1.
+        let (tx, rx) = channel();
+        let handle = thread::spawn(move || {
+            let mut stmt = con2.stmt_once(r#"SELECT count(*) FROM "foo""#).unwrap();
+            tx.send(()).unwrap();
+            let ret = stmt.step().and_then(|_| stmt.get::<i64>(0));
+            drop(stmt);
+            con2.execute_batch("COMMIT").unwrap();
+            ret
+        });
+        rx.recv().unwrap();
+        thread::sleep(Duration::from_millis(100));
+
+        // con1 waiting for con2 would deadlock.
+        let mut stmt = con1.stmt_once(r#"INSERT INTO "bar" VALUES (1)"#).unwrap();
+        let e = stmt.step().unwrap_err();
+        assert_eq!(SQLITE_LOCKED_SHAREDCACHE, e.code());
+        drop(stmt);
+
+        con1.execute_batch("ROLLBACK").unwrap();
+        assert_eq!(Ok(0), handle.join().unwrap());
+    }
+}
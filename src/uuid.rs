@@ -0,0 +1,148 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{Error, FromSql, FromSqlError, Stmt, ToSql, ToSqlOutput, ValueRef};
+use uuid::Uuid;
+
+/// Length of the hyphenated text format like "67e55044-10b1-426f-9247-bb680e5fe0c8" .
+const HYPHENATED_LEN: usize = 36;
+
+impl ToSql for Uuid {
+    /// Binds as BLOB of the 16 raw bytes.
+    #[inline]
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        Ok(ToSqlOutput::Borrowed(ValueRef::Blob(self.as_bytes())))
+    }
+}
+
+impl FromSql<'_> for Uuid {
+    /// Accepts BLOB of 16 bytes and TEXT of 36 characters in hyphenated format.
+    fn column_result(value: ValueRef<'_>) -> Result<Self, FromSqlError> {
+        match value {
+            ValueRef::Blob(b) => Uuid::from_slice(b).map_err(|_| FromSqlError::InvalidLength {
+                expected: 16,
+                actual: b.len(),
+            }),
+            ValueRef::Text(s) => {
+                if s.len() != HYPHENATED_LEN {
+                    return Err(FromSqlError::InvalidLength {
+                        expected: HYPHENATED_LEN,
+                        actual: s.len(),
+                    });
+                }
+                let s = core::str::from_utf8(s).map_err(FromSqlError::Utf8)?;
+                Uuid::parse_str(s).map_err(|e| FromSqlError::Other(Box::new(e)))
+            }
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl Stmt {
+    /// Returns the value of the column `index` of the current row as `Uuid` , or `None` if the
+    /// value is NULL.
+    ///
+    /// This method is the same as `get::<Option<Uuid>>(index)` .
+    /// It is enabled by feature "uuid".
+    ///
+    /// Note that `index` starts at 0, not 1.
+    #[inline]
+    pub fn column_uuid(&mut self, index: usize) -> Result<Option<Uuid>, Error> {
+        self.get(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Connection;
+
+    #[test]
+    fn round_trip() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(r#"CREATE TABLE "foo" ("id" BLOB PRIMARY KEY)"#)
+            .unwrap();
+
+        let id = Uuid::from_u128(0x67e5_5044_10b1_426f_9247_bb68_0e5f_e0c8);
+        {
+            let mut stmt = con.stmt_once(r#"INSERT INTO "foo" VALUES (?1)"#).unwrap();
+            stmt.bind(1, &id).unwrap();
+            assert_eq!(Ok(false), stmt.step());
+        }
+
+        let mut stmt = con
+            .stmt_once(r#"SELECT "id", typeof("id"), length("id") FROM "foo""#)
+            .unwrap();
+        assert_eq!(Ok(true), stmt.step());
+        assert_eq!(Ok(Some(id)), stmt.column_uuid(0));
+        assert_eq!(Ok("blob"), stmt.get::<&str>(1));
+        assert_eq!(Ok(16), stmt.get::<i64>(2));
+    }
+
+    #[test]
+    fn text_and_errors() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let sql = "SELECT '67e55044-10b1-426f-9247-bb680e5fe0c8', \
+                   x'000102030405060708090a0b0c0d0e', NULL, 'foo'";
+        let mut stmt = con.stmt_once(sql).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+
+        let expected = Uuid::from_u128(0x67e5_5044_10b1_426f_9247_bb68_0e5f_e0c8);
+        assert_eq!(Ok(Some(expected)), stmt.column_uuid(0));
+        assert_eq!(Ok(None), stmt.column_uuid(2));
+
+        let e = stmt.column_uuid(1).unwrap_err();
+        assert!(e.message().unwrap().contains("expected 16 bytes, but 15"));
+        assert!(stmt.column_uuid(3).is_err());
+    }
+}
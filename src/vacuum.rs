@@ -0,0 +1,186 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{Connection, Error, StepResult, SQLITE_MISUSE};
+use std::path::Path;
+
+impl Connection {
+    /// Executes "VACUUM" to rebuild the main database file.
+    ///
+    /// Returns `SQLITE_MISUSE` if a transaction is open, in which "VACUUM" fails.
+    pub fn vacuum(&mut self) -> Result<(), Error> {
+        self.check_no_transaction("VACUUM")?;
+        self.execute_batch("VACUUM")
+    }
+
+    /// Executes "VACUUM INTO" to write a defragmented copy of the main database to `dest` .
+    ///
+    /// `dest` is bound as a parameter, so it does not have to be quoted. `dest` must not exist
+    /// or must be an empty file.
+    ///
+    /// Returns `SQLITE_MISUSE` if a transaction is open, in which "VACUUM" fails, or if `dest` is
+    /// not a valid UTF-8 string.
+    pub fn vacuum_into(&mut self, dest: &Path) -> Result<(), Error> {
+        self.check_no_transaction("VACUUM INTO")?;
+        let dest = dest.to_str().ok_or_else(|| {
+            let message = format!("VACUUM INTO: invalid UTF-8 path: {:?}", dest);
+            Error::with_message(SQLITE_MISUSE, message)
+        })?;
+
+        let mut stmt = self.stmt_once("VACUUM INTO ?1")?;
+        stmt.bind_text(1, dest)?;
+        while let StepResult::Row = stmt.step2()? {}
+        Ok(())
+    }
+
+    /// Executes `PRAGMA incremental_vacuum(pages)` to remove up to `pages` pages from the freelist,
+    /// or all the free pages if `pages` is `None` .
+    ///
+    /// It does nothing unless `PRAGMA auto_vacuum` is "INCREMENTAL".
+    pub fn incremental_vacuum(&mut self, pages: Option<u64>) -> Result<(), Error> {
+        let sql = match pages {
+            None => "PRAGMA incremental_vacuum".to_string(),
+            Some(pages) => format!("PRAGMA incremental_vacuum({})", pages.min(i64::MAX as u64)),
+        };
+        let mut stmt = self.stmt_once(&sql)?;
+        while let StepResult::Row = stmt.step2()? {}
+        Ok(())
+    }
+
+    /// Returns `SQLITE_MISUSE` if a transaction is open.
+    pub(crate) fn check_no_transaction(&self, sql: &str) -> Result<(), Error> {
+        if self.is_autocommit() {
+            Ok(())
+        } else {
+            let message = format!("{} cannot be executed in a transaction", sql);
+            Err(Error::with_message(SQLITE_MISUSE, message))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::TryFrom;
+    use tempfile::tempdir;
+
+    fn count(con: &mut Connection) -> Option<i64> {
+        con.query_one(r#"SELECT COUNT(*) FROM "foo""#, &[])
+            .unwrap()
+            .map(|(n,)| n)
+    }
+
+    #[test]
+    fn vacuum_into() {
+        let tmp = tempdir().unwrap();
+        let path = tmp.path().join("test_sqlite");
+        let dest = tmp.path().join("it's a copy");
+
+        let mut con = Connection::try_from(path.as_path()).unwrap();
+        con.execute_batch(
+            r#"CREATE TABLE "foo" ("value" INTEGER);
+               WITH RECURSIVE "s"("i") AS (SELECT 1 UNION ALL SELECT "i" + 1 FROM "s" LIMIT 1000)
+               INSERT INTO "foo" SELECT "i" FROM "s";
+               DELETE FROM "foo" WHERE "value" % 2 = 0"#,
+        )
+        .unwrap();
+        assert_eq!(Ok(()), con.vacuum());
+        assert_eq!(Ok(()), con.vacuum_into(&dest));
+
+        let mut copy = Connection::try_from(dest.as_path()).unwrap();
+        assert_eq!(Some(500), count(&mut copy));
+        assert_eq!(count(&mut con), count(&mut copy));
+
+        // Fails because `dest` is not empty.
+        assert!(con.vacuum_into(&dest).is_err());
+    }
+
+    #[test]
+    fn vacuum_in_transaction() {
+        let tmp = tempdir().unwrap();
+        let dest = tmp.path().join("copy");
+
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch("BEGIN").unwrap();
+        let e = con.vacuum().unwrap_err();
+        assert_eq!(SQLITE_MISUSE, e.code());
+        assert!(e.message().is_some());
+        assert_eq!(SQLITE_MISUSE, con.vacuum_into(&dest).unwrap_err().code());
+        assert!(!dest.exists());
+
+        con.execute_batch("COMMIT").unwrap();
+        assert_eq!(Ok(()), con.vacuum());
+    }
+
+    #[test]
+    fn incremental_vacuum() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.execute_batch(
+            r#"PRAGMA auto_vacuum = INCREMENTAL;
+               CREATE TABLE "foo" ("value" BLOB);
+               WITH RECURSIVE "s"("i") AS (SELECT 1 UNION ALL SELECT "i" + 1 FROM "s" LIMIT 100)
+               INSERT INTO "foo" SELECT zeroblob(4096) FROM "s";
+               DELETE FROM "foo""#,
+        )
+        .unwrap();
+
+        let freelist = |con: &mut Connection| con.pragma_int(None, "freelist_count").unwrap();
+        let before = freelist(&mut con).unwrap();
+        assert!(10 < before);
+
+        con.incremental_vacuum(Some(10)).unwrap();
+        assert_eq!(Some(before - 10), freelist(&mut con));
+        con.incremental_vacuum(None).unwrap();
+        assert_eq!(Some(0), freelist(&mut con));
+    }
+}
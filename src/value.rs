@@ -0,0 +1,324 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::encoding::write_hex;
+use crate::{
+    sqlite3_context, sqlite3_result_blob, sqlite3_result_double, sqlite3_result_int64,
+    sqlite3_result_null, sqlite3_result_text, sqlite3_value, sqlite3_value_blob,
+    sqlite3_value_bytes, sqlite3_value_double, sqlite3_value_int64, sqlite3_value_text,
+    sqlite3_value_type, DisplayBase64, DisplayHex, Error, FromSqlError, SQLITE_BLOB, SQLITE_FLOAT,
+    SQLITE_INTEGER, SQLITE_TEXT, SQLITE_TOOBIG, SQLITE_TRANSIENT,
+};
+use core::convert::TryFrom;
+use core::fmt;
+use std::os::raw::{c_char, c_int, c_void};
+
+/// Owned value of a column or a parameter.
+///
+/// Each variant corresponds to a [`datatype`] of SQLite.
+///
+/// [`datatype`]: https://www.sqlite.org/datatype3.html
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// NULL value.
+    Null,
+    /// A signed integer.
+    Integer(i64),
+    /// A floating point value.
+    Real(f64),
+    /// A text string.
+    Text(String),
+    /// A blob of data.
+    Blob(Vec<u8>),
+}
+
+/// Borrowed value of a column or a parameter.
+///
+/// The difference from [`Value`] is that `ValueRef` does not copy TEXT or BLOB.
+///
+/// Note that `Text` is not checked whether it is a valid UTF-8 or not, because SQLite does not
+/// enforce it.
+///
+/// [`Value`]: enum.Value.html
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueRef<'a> {
+    /// NULL value.
+    Null,
+    /// A signed integer.
+    Integer(i64),
+    /// A floating point value.
+    Real(f64),
+    /// A text string, which is not checked whether it is a valid UTF-8 or not.
+    Text(&'a [u8]),
+    /// A blob of data.
+    Blob(&'a [u8]),
+}
+
+impl Value {
+    /// Returns `self` as an SQL literal, which libsqlite3 parses into the same value.
+    ///
+    /// TEXT is enclosed in single quotes doubling the quotes in it, and BLOB is written as
+    /// `X'...'` . TEXT including NUL is written as `CAST(X'...' AS TEXT)` because libsqlite3
+    /// stops parsing SQL at NUL. Infinite REAL is written as `9.0e999` or `-9.0e999` , and NaN as
+    /// `NULL` because SQLite stores NaN as NULL.
+    pub fn to_sql_literal(&self) -> String {
+        let mut ret = String::new();
+        // Writing into String never fails.
+        ValueRef::from(self).write_sql_literal(&mut ret).unwrap();
+        ret
+    }
+}
+
+impl<'a> From<&'a Value> for ValueRef<'a> {
+    #[inline]
+    fn from(v: &'a Value) -> Self {
+        match v {
+            Value::Null => ValueRef::Null,
+            Value::Integer(i) => ValueRef::Integer(*i),
+            Value::Real(f) => ValueRef::Real(*f),
+            Value::Text(s) => ValueRef::Text(s.as_bytes()),
+            Value::Blob(b) => ValueRef::Blob(b),
+        }
+    }
+}
+
+impl<'a> ValueRef<'a> {
+    /// Returns the TEXT as `&str` without copying.
+    ///
+    /// Returns [`FromSqlError::InvalidType`] if `self` is not `Text` , or
+    /// [`FromSqlError::Utf8`] if it is not a valid UTF-8 string.
+    ///
+    /// [`FromSqlError::InvalidType`]: enum.FromSqlError.html#variant.InvalidType
+    /// [`FromSqlError::Utf8`]: enum.FromSqlError.html#variant.Utf8
+    #[inline]
+    pub fn as_str(&self) -> Result<&'a str, FromSqlError> {
+        match *self {
+            ValueRef::Text(s) => core::str::from_utf8(s).map_err(FromSqlError::Utf8),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+
+    /// Copies the data and returns a new [`Value`] .
+    ///
+    /// Invalid UTF-8 sequences in `Text` are replaced with `U+FFFD` .
+    ///
+    /// [`Value`]: enum.Value.html
+    #[inline]
+    pub fn to_owned(&self) -> Value {
+        match *self {
+            ValueRef::Null => Value::Null,
+            ValueRef::Integer(i) => Value::Integer(i),
+            ValueRef::Real(f) => Value::Real(f),
+            ValueRef::Text(s) => Value::Text(String::from_utf8_lossy(s).into_owned()),
+            ValueRef::Blob(b) => Value::Blob(b.to_vec()),
+        }
+    }
+
+    /// Returns an adapter to display TEXT or BLOB in hexadecimal without allocating memory.
+    ///
+    /// The other types are displayed as SQL literals.
+    #[inline]
+    pub fn display_hex(&self) -> DisplayHex<'a> {
+        DisplayHex(*self)
+    }
+
+    /// Returns an adapter to display TEXT or BLOB in base64.
+    ///
+    /// The other types are displayed as SQL literals.
+    #[inline]
+    pub fn display_base64(&self) -> DisplayBase64<'a> {
+        DisplayBase64(*self)
+    }
+
+    /// Writes `self` into `out` as an SQL literal. See [`Value::to_sql_literal`] for details.
+    ///
+    /// [`Value::to_sql_literal`]: enum.Value.html#method.to_sql_literal
+    pub(crate) fn write_sql_literal<W>(&self, out: &mut W) -> fmt::Result
+    where
+        W: ?Sized + fmt::Write,
+    {
+        match *self {
+            ValueRef::Null => out.write_str("NULL"),
+            ValueRef::Integer(i) => write!(out, "{}", i),
+            // `Debug` writes the shortest representation to parse the same value, which includes
+            // '.' or 'e' so that libsqlite3 parses it as REAL.
+            ValueRef::Real(f) if f.is_finite() => write!(out, "{:?}", f),
+            ValueRef::Real(f) if f.is_nan() => out.write_str("NULL"),
+            ValueRef::Real(f) if f.is_sign_positive() => out.write_str("9.0e999"),
+            ValueRef::Real(_) => out.write_str("-9.0e999"),
+            ValueRef::Text(bytes) => match core::str::from_utf8(bytes) {
+                Ok(s) if !s.contains('\0') => {
+                    out.write_char('\'')?;
+                    for (i, part) in s.split('\'').enumerate() {
+                        if i != 0 {
+                            out.write_str("''")?;
+                        }
+                        out.write_str(part)?;
+                    }
+                    out.write_char('\'')
+                }
+                _ => {
+                    out.write_str("CAST(X'")?;
+                    write_hex(out, bytes)?;
+                    out.write_str("' AS TEXT)")
+                }
+            },
+            ValueRef::Blob(bytes) => {
+                out.write_str("X'")?;
+                write_hex(out, bytes)?;
+                out.write_char('\'')
+            }
+        }
+    }
+}
+
+/// Builds [`ValueRef`] from a protected or unprotected `sqlite3_value` .
+///
+/// # Safety
+///
+/// `raw` must be valid while the returned value is alive.
+///
+/// [`ValueRef`]: enum.ValueRef.html
+pub unsafe fn from_raw<'a>(raw: *mut sqlite3_value) -> ValueRef<'a> {
+    match sqlite3_value_type(raw) {
+        SQLITE_INTEGER => ValueRef::Integer(sqlite3_value_int64(raw)),
+        SQLITE_FLOAT => ValueRef::Real(sqlite3_value_double(raw)),
+        SQLITE_TEXT => {
+            // sqlite3_value_bytes() must be called after sqlite3_value_text().
+            let ptr = sqlite3_value_text(raw);
+            let len = sqlite3_value_bytes(raw) as usize;
+            if len == 0 {
+                ValueRef::Text(&[])
+            } else {
+                ValueRef::Text(core::slice::from_raw_parts(ptr, len))
+            }
+        }
+        SQLITE_BLOB => {
+            // sqlite3_value_bytes() must be called after sqlite3_value_blob().
+            let ptr = sqlite3_value_blob(raw) as *const u8;
+            let len = sqlite3_value_bytes(raw) as usize;
+            if len == 0 {
+                ValueRef::Blob(&[])
+            } else {
+                ValueRef::Blob(core::slice::from_raw_parts(ptr, len))
+            }
+        }
+        _ => ValueRef::Null,
+    }
+}
+
+/// Sets `val` as the result of `ctx` copying TEXT and BLOB.
+///
+/// # Safety
+///
+/// `ctx` must be a valid `sqlite3_context` .
+pub unsafe fn set_result(ctx: *mut sqlite3_context, val: ValueRef<'_>) -> Result<(), Error> {
+    const DESTRUCTOR: *const c_void = SQLITE_TRANSIENT as *const c_void;
+
+    match val {
+        ValueRef::Null => sqlite3_result_null(ctx),
+        ValueRef::Integer(i) => sqlite3_result_int64(ctx, i),
+        ValueRef::Real(f) => sqlite3_result_double(ctx, f),
+        ValueRef::Text(s) => {
+            let ptr = s.as_ptr() as *const c_char;
+            let len = c_int::try_from(s.len()).map_err(|_| Error::new(SQLITE_TOOBIG))?;
+            sqlite3_result_text(ctx, ptr, len, DESTRUCTOR);
+        }
+        ValueRef::Blob(b) => {
+            let ptr = b.as_ptr() as *const c_void;
+            let len = c_int::try_from(b.len()).map_err(|_| Error::new(SQLITE_TOOBIG))?;
+            sqlite3_result_blob(ctx, ptr, len, DESTRUCTOR);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Connection;
+
+    #[test]
+    fn to_sql_literal() {
+        let values = [
+            Value::Null,
+            Value::Integer(i64::MIN),
+            Value::Integer(0),
+            Value::Real(1.0),
+            Value::Real(-0.125e-300),
+            Value::Real(f64::INFINITY),
+            Value::Real(f64::NEG_INFINITY),
+            Value::Text(String::new()),
+            Value::Text("it's a 'quoted' text".to_string()),
+            Value::Text("nul\0inside".to_string()),
+            Value::Blob(Vec::new()),
+            Value::Blob(vec![0xde, 0xad, 0xbe, 0xef]),
+        ];
+
+        let mut con = Connection::open_memory_db().unwrap();
+        for value in values.iter() {
+            let literal = value.to_sql_literal();
+            let mut stmt = con.stmt_once(&format!("SELECT {}", literal)).unwrap();
+            assert_eq!(Ok(true), stmt.step());
+            let actual = stmt.column_value_ref(0).unwrap().to_owned();
+            assert_eq!(*value, actual, "{}", literal);
+        }
+
+        assert_eq!("'it''s'", Value::Text("it's".to_string()).to_sql_literal());
+        assert_eq!(
+            "X'deadbeef'",
+            Value::Blob(vec![0xde, 0xad, 0xbe, 0xef]).to_sql_literal()
+        );
+        assert_eq!("NULL", Value::Real(f64::NAN).to_sql_literal());
+    }
+}
@@ -0,0 +1,218 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    sqlite3_compileoption_get, sqlite3_compileoption_used, sqlite3_libversion,
+    sqlite3_libversion_number, Connection,
+};
+use std::ffi::{CStr, CString};
+use std::os::raw::c_int;
+
+/// Wrapper of C function [`sqlite3_libversion`] .
+///
+/// Returns the version of the runtime SQLite like "3.40.1".
+///
+/// [`sqlite3_libversion`]: https://www.sqlite.org/c3ref/libversion.html
+#[inline]
+pub fn version() -> &'static str {
+    let ptr = unsafe { sqlite3_libversion() };
+    unsafe { CStr::from_ptr(ptr) }.to_str().unwrap_or_default()
+}
+
+/// Wrapper of C function [`sqlite3_libversion_number`] .
+///
+/// Returns the version of the runtime SQLite as a number like 3040001 for "3.40.1".
+///
+/// [`sqlite3_libversion_number`]: https://www.sqlite.org/c3ref/libversion.html
+#[inline]
+pub fn version_number() -> u32 {
+    unsafe { sqlite3_libversion_number() as u32 }
+}
+
+/// Returns the compile-time options of the runtime SQLite iterating C function
+/// [`sqlite3_compileoption_get`] .
+///
+/// The "SQLITE_" prefix is omitted; e.g. "ENABLE_FTS5" or "THREADSAFE=1".
+///
+/// [`sqlite3_compileoption_get`]: https://www.sqlite.org/c3ref/compileoption_get.html
+pub fn compile_options() -> Vec<&'static str> {
+    let mut ret = Vec::new();
+    for n in 0.. {
+        let ptr = unsafe { sqlite3_compileoption_get(n as c_int) };
+        if ptr.is_null() {
+            break;
+        }
+        if let Ok(option) = unsafe { CStr::from_ptr(ptr) }.to_str() {
+            ret.push(option);
+        }
+    }
+    ret
+}
+
+/// Wrapper of C function [`sqlite3_compileoption_used`] .
+///
+/// Returns whether the runtime SQLite was compiled with option `name` or not. The "SQLITE_"
+/// prefix of `name` is optional.
+///
+/// [`sqlite3_compileoption_used`]: https://www.sqlite.org/c3ref/compileoption_get.html
+pub fn compile_option_used(name: &str) -> bool {
+    match CString::new(name) {
+        Ok(name) => unsafe { sqlite3_compileoption_used(name.as_ptr()) != 0 },
+        Err(_) => false,
+    }
+}
+
+/// SQL features that the runtime SQLite may or may not support.
+///
+/// See [`Connection::supports`] .
+///
+/// [`Connection::supports`]: struct.Connection.html#method.supports
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Feature {
+    /// "UPSERT" ("ON CONFLICT DO UPDATE"); since SQLite 3.24.0.
+    Upsert,
+    /// Window functions; since SQLite 3.25.0 unless omitted at compile time.
+    WindowFunctions,
+    /// "RETURNING" clause; since SQLite 3.35.0.
+    Returning,
+    /// "STRICT" tables; since SQLite 3.37.0.
+    StrictTables,
+    /// JSON functions; built-in since SQLite 3.38.0 unless omitted, or extension JSON1 before
+    /// that.
+    Json,
+    /// Full text search version 5.
+    Fts5,
+    /// R*Tree index.
+    RTree,
+    /// [`DBSTAT virtual table`] .
+    ///
+    /// [`DBSTAT virtual table`]: https://www.sqlite.org/dbstat.html
+    Dbstat,
+}
+
+impl Connection {
+    /// Returns whether the runtime SQLite supports `feature` or not, judging from
+    /// [`version_number`] and [`compile_option_used`] .
+    ///
+    /// Note that the features loaded as a run-time extension are not detected.
+    ///
+    /// [`version_number`]: fn.version_number.html
+    /// [`compile_option_used`]: fn.compile_option_used.html
+    pub fn supports(&self, feature: Feature) -> bool {
+        let version = version_number();
+        match feature {
+            Feature::Upsert => 3_024_000 <= version,
+            Feature::WindowFunctions => {
+                3_025_000 <= version && !compile_option_used("OMIT_WINDOWFUNC")
+            }
+            Feature::Returning => 3_035_000 <= version,
+            Feature::StrictTables => 3_037_000 <= version,
+            Feature::Json => {
+                if 3_038_000 <= version {
+                    !compile_option_used("OMIT_JSON")
+                } else {
+                    compile_option_used("ENABLE_JSON1")
+                }
+            }
+            Feature::Fts5 => compile_option_used("ENABLE_FTS5"),
+            Feature::RTree => compile_option_used("ENABLE_RTREE"),
+            Feature::Dbstat => compile_option_used("ENABLE_DBSTAT_VTAB"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version() {
+        assert!(3_000_000 <= version_number());
+        let expected = format!(
+            "{}.{}.{}",
+            version_number() / 1_000_000,
+            version_number() / 1_000 % 1_000,
+            version_number() % 1_000
+        );
+        assert!(super::version().starts_with(&expected));
+    }
+
+    #[test]
+    fn compile_options() {
+        let options = super::compile_options();
+        assert!(!options.is_empty());
+        assert!(options.iter().all(|o| !o.starts_with("SQLITE_")));
+        assert!(options.iter().any(|o| o.starts_with("THREADSAFE=")));
+
+        let name = options.iter().find(|o| !o.contains('=')).unwrap();
+        assert!(compile_option_used(name));
+        assert!(compile_option_used(&format!("SQLITE_{}", name)));
+        assert!(!compile_option_used("NO_SUCH_OPTION"));
+        assert!(!compile_option_used("NUL\0"));
+    }
+
+    #[test]
+    fn supports() {
+        let mut con = Connection::open_memory_db().unwrap();
+        if con.supports(Feature::Returning) {
+            con.execute_batch(r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY)"#)
+                .unwrap();
+            let row = con.query_one(r#"INSERT INTO "foo" VALUES (3) RETURNING "id""#, &[]);
+            assert_eq!(Ok(Some((3,))), row);
+        }
+        if con.supports(Feature::Json) {
+            assert!(con.execute_batch("SELECT json('[1]')").is_ok());
+        }
+    }
+}
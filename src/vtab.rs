@@ -0,0 +1,1020 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    sqlite3, sqlite3_context, sqlite3_create_module_v2, sqlite3_declare_vtab, sqlite3_free,
+    sqlite3_mprintf, sqlite3_value, value_from_raw, value_set_result, Connection, Error,
+    ToSqlOutput, ValueRef, SQLITE_ERROR, SQLITE_MISUSE, SQLITE_OK,
+};
+use core::convert::TryFrom;
+use core::ptr::null_mut;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_void};
+use std::panic::AssertUnwindSafe;
+
+/// Virtual table implementation of an eponymous-only module.
+///
+/// An eponymous-only virtual table is available as a table of the module name without
+/// `CREATE VIRTUAL TABLE` statement, and as a table-valued function if the [`schema`] declares
+/// HIDDEN columns; e.g. `SELECT * FROM my_module(1, 2)` passes 1 and 2 to the first and the
+/// second HIDDEN columns as constraints of `=` .
+///
+/// The instance is owned by the connection, and is shared among all the cursors.
+///
+/// See [`The Virtual Table Mechanism Of SQLite`] for details.
+///
+/// [`schema`]: #tymethod.schema
+/// [`The Virtual Table Mechanism Of SQLite`]: https://www.sqlite.org/vtab.html
+pub trait VTab: Sized + Send + 'static {
+    /// Cursor type to scan the table.
+    type Cursor: VTabCursor;
+
+    /// Returns `CREATE TABLE` statement to declare the columns of the table.
+    ///
+    /// The table name in the statement is ignored.
+    fn schema(&self) -> &str;
+
+    /// Corresponds to `xBestIndex` ; investigates the constraints and the order in `info` and
+    /// tells the query plan of the table to SQLite.
+    fn best_index(&self, info: &mut BestIndexInfo<'_>) -> Result<(), Error>;
+
+    /// Corresponds to `xOpen` ; creates a new cursor.
+    fn open(&self) -> Result<Self::Cursor, Error>;
+}
+
+/// Cursor of [`VTab`] .
+///
+/// [`VTab`]: trait.VTab.html
+pub trait VTabCursor: Sized + Send + 'static {
+    /// Corresponds to `xFilter` ; starts a new scan.
+    ///
+    /// `idx_num` is the value set by [`BestIndexInfo::set_idx_num`] , and `args` is the values of
+    /// the constraints which were given positive `argv_index` by
+    /// [`BestIndexInfo::set_argv_index`] in the order of the `argv_index` .
+    ///
+    /// [`BestIndexInfo::set_idx_num`]: struct.BestIndexInfo.html#method.set_idx_num
+    /// [`BestIndexInfo::set_argv_index`]: struct.BestIndexInfo.html#method.set_argv_index
+    fn filter(&mut self, idx_num: i32, args: &[ValueRef<'_>]) -> Result<(), Error>;
+
+    /// Corresponds to `xNext` ; advances the cursor to the next row.
+    fn next(&mut self) -> Result<(), Error>;
+
+    /// Corresponds to `xEof` ; returns `true` if the cursor does not point any row.
+    fn eof(&self) -> bool;
+
+    /// Corresponds to `xColumn` ; returns the value of the column `index` of the current row.
+    ///
+    /// Note that `index` starts at 0, not 1.
+    fn column(&self, index: usize) -> Result<ToSqlOutput<'_>, Error>;
+
+    /// Corresponds to `xRowid` ; returns the rowid of the current row.
+    fn rowid(&self) -> Result<i64, Error>;
+}
+
+/// Operator of [`IndexConstraint`] .
+///
+/// [`IndexConstraint`]: struct.IndexConstraint.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ConstraintOp {
+    /// `=`
+    Eq,
+    /// `>`
+    Gt,
+    /// `<=`
+    Le,
+    /// `<`
+    Lt,
+    /// `>=`
+    Ge,
+    /// `MATCH`
+    Match,
+    /// `LIKE`
+    Like,
+    /// `GLOB`
+    Glob,
+    /// `REGEXP`
+    Regexp,
+    /// `!=` or `<>`
+    Ne,
+    /// `IS NOT`
+    IsNot,
+    /// `IS NOT NULL`
+    IsNotNull,
+    /// `IS NULL`
+    IsNull,
+    /// `IS`
+    Is,
+    /// `LIMIT`
+    Limit,
+    /// `OFFSET`
+    Offset,
+    /// Other operators, including the ones overloaded by `xFindFunction` .
+    Other(u8),
+}
+
+impl From<u8> for ConstraintOp {
+    #[inline]
+    fn from(op: u8) -> Self {
+        // https://www.sqlite.org/c3ref/c_index_constraint_eq.html
+        match op {
+            2 => Self::Eq,
+            4 => Self::Gt,
+            8 => Self::Le,
+            16 => Self::Lt,
+            32 => Self::Ge,
+            64 => Self::Match,
+            65 => Self::Like,
+            66 => Self::Glob,
+            67 => Self::Regexp,
+            68 => Self::Ne,
+            69 => Self::IsNot,
+            70 => Self::IsNotNull,
+            71 => Self::IsNull,
+            72 => Self::Is,
+            73 => Self::Limit,
+            74 => Self::Offset,
+            _ => Self::Other(op),
+        }
+    }
+}
+
+/// A constraint of WHERE clause passed to [`VTab::best_index`] .
+///
+/// [`VTab::best_index`]: trait.VTab.html#tymethod.best_index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IndexConstraint {
+    /// Column index starting at 0, or `None` for the rowid.
+    pub column: Option<usize>,
+    /// Operator of the constraint.
+    pub op: ConstraintOp,
+    /// Whether the constraint can be used in this query plan or not.
+    pub usable: bool,
+}
+
+/// A term of ORDER BY clause passed to [`VTab::best_index`] .
+///
+/// [`VTab::best_index`]: trait.VTab.html#tymethod.best_index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IndexOrderBy {
+    /// Column index starting at 0, or `None` for the rowid.
+    pub column: Option<usize>,
+    /// Whether descending or not.
+    pub desc: bool,
+}
+
+/// Wrapper of C [`sqlite3_index_info`] .
+///
+/// [`sqlite3_index_info`]: https://www.sqlite.org/c3ref/index_info.html
+pub struct BestIndexInfo<'a> {
+    raw: &'a mut sqlite3_index_info,
+}
+
+impl BestIndexInfo<'_> {
+    /// Returns the constraints of WHERE clause.
+    pub fn constraints(&self) -> Vec<IndexConstraint> {
+        let n = self.raw.n_constraint as usize;
+        let raws = unsafe { core::slice::from_raw_parts(self.raw.a_constraint, n) };
+        raws.iter()
+            .map(|c| IndexConstraint {
+                column: usize::try_from(c.i_column).ok(),
+                op: ConstraintOp::from(c.op),
+                usable: c.usable != 0,
+            })
+            .collect()
+    }
+
+    /// Returns the terms of ORDER BY clause.
+    pub fn order_bys(&self) -> Vec<IndexOrderBy> {
+        let n = self.raw.n_order_by as usize;
+        let raws = unsafe { core::slice::from_raw_parts(self.raw.a_order_by, n) };
+        raws.iter()
+            .map(|o| IndexOrderBy {
+                column: usize::try_from(o.i_column).ok(),
+                desc: o.desc != 0,
+            })
+            .collect()
+    }
+
+    /// Passes the value of the constraint `constraint` to [`VTabCursor::filter`] as
+    /// `args[argv_index - 1]` .
+    ///
+    /// If `omit` is `true` , SQLite does not double-check the constraint.
+    ///
+    /// Note that `constraint` starts at 0 and `argv_index` starts at 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `constraint` is out of range.
+    ///
+    /// [`VTabCursor::filter`]: trait.VTabCursor.html#tymethod.filter
+    pub fn set_argv_index(&mut self, constraint: usize, argv_index: usize, omit: bool) {
+        let n = self.raw.n_constraint as usize;
+        assert!(constraint < n);
+
+        let usages = unsafe { core::slice::from_raw_parts_mut(self.raw.a_constraint_usage, n) };
+        usages[constraint].argv_index = c_int::try_from(argv_index).unwrap_or(c_int::MAX);
+        usages[constraint].omit = omit as u8;
+    }
+
+    /// Sets the number passed to [`VTabCursor::filter`] as `idx_num` .
+    ///
+    /// [`VTabCursor::filter`]: trait.VTabCursor.html#tymethod.filter
+    #[inline]
+    pub fn set_idx_num(&mut self, idx_num: i32) {
+        self.raw.idx_num = idx_num;
+    }
+
+    /// Tells SQLite that the cursor returns the rows in the order of [`order_bys`] .
+    ///
+    /// [`order_bys`]: #method.order_bys
+    #[inline]
+    pub fn set_order_by_consumed(&mut self, consumed: bool) {
+        self.raw.order_by_consumed = consumed as c_int;
+    }
+
+    /// Sets the estimated cost of the query plan.
+    #[inline]
+    pub fn set_estimated_cost(&mut self, cost: f64) {
+        self.raw.estimated_cost = cost;
+    }
+
+    /// Sets the estimated number of rows the query plan returns.
+    #[inline]
+    pub fn set_estimated_rows(&mut self, rows: i64) {
+        self.raw.estimated_rows = rows;
+    }
+}
+
+impl Connection {
+    /// Wrapper of C function [`sqlite3_create_module_v2`] to register `vtab` as an eponymous-only
+    /// virtual table `name` .
+    ///
+    /// If a module of the same name has already been registered, the old one is dropped.
+    ///
+    /// [`sqlite3_create_module_v2`]: https://www.sqlite.org/c3ref/create_module.html
+    pub fn create_module<T>(&mut self, name: &str, vtab: T) -> Result<(), Error>
+    where
+        T: VTab,
+    {
+        let name = CString::new(name).map_err(|_| Error::new(SQLITE_MISUSE))?;
+        let data = Box::new(ClientData {
+            module: sqlite3_module::new::<T>(),
+            vtab,
+        });
+        let module = &data.module as *const sqlite3_module as *const c_void;
+        let data = Box::into_raw(data) as *mut c_void;
+
+        // libsqlite3 calls the destructor even if it fails.
+        let code = unsafe {
+            sqlite3_create_module_v2(
+                self.raw(),
+                name.as_ptr(),
+                module,
+                data,
+                Some(drop_client_data::<T>),
+            )
+        };
+        match Error::new(code) {
+            e if e == Error::OK => Ok(()),
+            e => Err(e),
+        }
+    }
+}
+
+// https://www.sqlite.org/c3ref/module.html
+#[allow(non_camel_case_types)]
+type x_vtab = unsafe extern "C" fn(*mut sqlite3_vtab) -> c_int;
+#[allow(non_camel_case_types)]
+type x_cursor = unsafe extern "C" fn(*mut sqlite3_vtab_cursor) -> c_int;
+#[allow(non_camel_case_types)]
+type x_unused = unsafe extern "C" fn();
+
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct sqlite3_module {
+    i_version: c_int,
+    x_create: Option<x_unused>,
+    x_connect: Option<
+        unsafe extern "C" fn(
+            *mut sqlite3,
+            *mut c_void,
+            c_int,
+            *const *const c_char,
+            *mut *mut sqlite3_vtab,
+            *mut *mut c_char,
+        ) -> c_int,
+    >,
+    x_best_index: Option<unsafe extern "C" fn(*mut sqlite3_vtab, *mut sqlite3_index_info) -> c_int>,
+    x_disconnect: Option<x_vtab>,
+    x_destroy: Option<x_vtab>,
+    x_open: Option<unsafe extern "C" fn(*mut sqlite3_vtab, *mut *mut sqlite3_vtab_cursor) -> c_int>,
+    x_close: Option<x_cursor>,
+    x_filter: Option<
+        unsafe extern "C" fn(
+            *mut sqlite3_vtab_cursor,
+            c_int,
+            *const c_char,
+            c_int,
+            *mut *mut sqlite3_value,
+        ) -> c_int,
+    >,
+    x_next: Option<x_cursor>,
+    x_eof: Option<x_cursor>,
+    x_column: Option<
+        unsafe extern "C" fn(*mut sqlite3_vtab_cursor, *mut sqlite3_context, c_int) -> c_int,
+    >,
+    x_rowid: Option<unsafe extern "C" fn(*mut sqlite3_vtab_cursor, *mut i64) -> c_int>,
+    x_update: Option<x_unused>,
+    x_begin: Option<x_unused>,
+    x_sync: Option<x_unused>,
+    x_commit: Option<x_unused>,
+    x_rollback: Option<x_unused>,
+    x_find_function: Option<x_unused>,
+    x_rename: Option<x_unused>,
+}
+
+impl sqlite3_module {
+    fn new<T: VTab>() -> Self {
+        Self {
+            i_version: 1,
+            // xCreate is NULL for eponymous-only virtual table.
+            x_create: None,
+            x_connect: Some(x_connect::<T>),
+            x_best_index: Some(x_best_index::<T>),
+            x_disconnect: Some(x_disconnect::<T>),
+            x_destroy: None,
+            x_open: Some(x_open::<T>),
+            x_close: Some(x_close::<T>),
+            x_filter: Some(x_filter::<T>),
+            x_next: Some(x_next::<T>),
+            x_eof: Some(x_eof::<T>),
+            x_column: Some(x_column::<T>),
+            x_rowid: Some(x_rowid::<T>),
+            x_update: None,
+            x_begin: None,
+            x_sync: None,
+            x_commit: None,
+            x_rollback: None,
+            x_find_function: None,
+            x_rename: None,
+        }
+    }
+}
+
+// https://www.sqlite.org/c3ref/vtab.html
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct sqlite3_vtab {
+    p_module: *const sqlite3_module,
+    n_ref: c_int,
+    z_err_msg: *mut c_char,
+}
+
+// https://www.sqlite.org/c3ref/vtab_cursor.html
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct sqlite3_vtab_cursor {
+    p_vtab: *mut sqlite3_vtab,
+}
+
+// https://www.sqlite.org/c3ref/index_info.html
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct sqlite3_index_info {
+    n_constraint: c_int,
+    a_constraint: *const sqlite3_index_constraint,
+    n_order_by: c_int,
+    a_order_by: *const sqlite3_index_orderby,
+    a_constraint_usage: *mut sqlite3_index_constraint_usage,
+    idx_num: c_int,
+    idx_str: *mut c_char,
+    need_to_free_idx_str: c_int,
+    order_by_consumed: c_int,
+    estimated_cost: f64,
+    estimated_rows: i64,
+    idx_flags: c_int,
+    col_used: u64,
+}
+
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct sqlite3_index_constraint {
+    i_column: c_int,
+    op: u8,
+    usable: u8,
+    i_term_offset: c_int,
+}
+
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct sqlite3_index_orderby {
+    i_column: c_int,
+    desc: u8,
+}
+
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct sqlite3_index_constraint_usage {
+    argv_index: c_int,
+    omit: u8,
+}
+
+/// Client data of the module, which owns the module struct because libsqlite3 does not copy it.
+struct ClientData<T> {
+    module: sqlite3_module,
+    vtab: T,
+}
+
+#[repr(C)]
+struct RawVTab<T> {
+    base: sqlite3_vtab,
+    vtab: *const T,
+}
+
+#[repr(C)]
+struct RawCursor<C> {
+    base: sqlite3_vtab_cursor,
+    cursor: C,
+}
+
+/// Sets the message of `e` to `*dest` and returns the error code.
+unsafe fn set_error(dest: *mut *mut c_char, e: &Error) -> c_int {
+    if let Some(message) = e.message().and_then(|m| CString::new(m).ok()) {
+        sqlite3_free(*dest as *mut c_void);
+        *dest = sqlite3_mprintf(b"%s\0".as_ptr() as *const c_char, message.as_ptr());
+    }
+    e.code()
+}
+
+unsafe fn vtab_of<'a, T>(vtab: *mut sqlite3_vtab) -> &'a T {
+    &*(*(vtab as *mut RawVTab<T>)).vtab
+}
+
+unsafe fn cursor_of<'a, T: VTab>(cursor: *mut sqlite3_vtab_cursor) -> &'a mut T::Cursor {
+    &mut (*(cursor as *mut RawCursor<T::Cursor>)).cursor
+}
+
+unsafe fn cursor_error(cursor: *mut sqlite3_vtab_cursor, e: &Error) -> c_int {
+    set_error(cursor_errmsg(cursor), e)
+}
+
+unsafe fn cursor_errmsg(cursor: *mut sqlite3_vtab_cursor) -> *mut *mut c_char {
+    &mut (*(*cursor).p_vtab).z_err_msg
+}
+
+/// Calls `f` and returns the result, or returns `SQLITE_ERROR` with the message set to `errmsg` if
+/// `f` panics. (Unwinding across the FFI boundary is not allowed.)
+unsafe fn catch_panic<F>(errmsg: *mut *mut c_char, f: F) -> c_int
+where
+    F: FnOnce() -> c_int,
+{
+    match std::panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(code) => code,
+        Err(_) => {
+            let e = Error::with_message(SQLITE_ERROR, "the virtual table panicked");
+            set_error(errmsg, &e)
+        }
+    }
+}
+
+unsafe extern "C" fn drop_client_data<T>(data: *mut c_void) {
+    let _ = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        drop(Box::from_raw(data as *mut ClientData<T>))
+    }));
+}
+
+unsafe extern "C" fn x_connect<T: VTab>(
+    db: *mut sqlite3,
+    paux: *mut c_void,
+    _argc: c_int,
+    _argv: *const *const c_char,
+    ppvtab: *mut *mut sqlite3_vtab,
+    pzerr: *mut *mut c_char,
+) -> c_int {
+    catch_panic(pzerr, || {
+        let vtab = &(*(paux as *const ClientData<T>)).vtab;
+
+        let schema = match CString::new(vtab.schema()) {
+            Ok(s) => s,
+            Err(_) => {
+                let e = Error::with_message(SQLITE_MISUSE, "the schema contains NUL");
+                return set_error(pzerr, &e);
+            }
+        };
+        let code = sqlite3_declare_vtab(db, schema.as_ptr());
+        if code != SQLITE_OK {
+            return code;
+        }
+
+        let raw = Box::new(RawVTab {
+            base: sqlite3_vtab {
+                p_module: core::ptr::null(),
+                n_ref: 0,
+                z_err_msg: null_mut(),
+            },
+            vtab: vtab as *const T,
+        });
+        *ppvtab = Box::into_raw(raw) as *mut sqlite3_vtab;
+        SQLITE_OK
+    })
+}
+
+unsafe extern "C" fn x_disconnect<T: VTab>(vtab: *mut sqlite3_vtab) -> c_int {
+    // `RawVTab` does not own `T` , so dropping it never panics.
+    sqlite3_free((*vtab).z_err_msg as *mut c_void);
+    drop(Box::from_raw(vtab as *mut RawVTab<T>));
+    SQLITE_OK
+}
+
+unsafe extern "C" fn x_best_index<T: VTab>(
+    vtab: *mut sqlite3_vtab,
+    info: *mut sqlite3_index_info,
+) -> c_int {
+    catch_panic(&mut (*vtab).z_err_msg, || {
+        let mut info = BestIndexInfo { raw: &mut *info };
+        match vtab_of::<T>(vtab).best_index(&mut info) {
+            Ok(()) => SQLITE_OK,
+            Err(e) => set_error(&mut (*vtab).z_err_msg, &e),
+        }
+    })
+}
+
+unsafe extern "C" fn x_open<T: VTab>(
+    vtab: *mut sqlite3_vtab,
+    ppcursor: *mut *mut sqlite3_vtab_cursor,
+) -> c_int {
+    catch_panic(&mut (*vtab).z_err_msg, || match vtab_of::<T>(vtab).open() {
+        Ok(cursor) => {
+            let raw = Box::new(RawCursor {
+                base: sqlite3_vtab_cursor { p_vtab: null_mut() },
+                cursor,
+            });
+            *ppcursor = Box::into_raw(raw) as *mut sqlite3_vtab_cursor;
+            SQLITE_OK
+        }
+        Err(e) => set_error(&mut (*vtab).z_err_msg, &e),
+    })
+}
+
+unsafe extern "C" fn x_close<T: VTab>(cursor: *mut sqlite3_vtab_cursor) -> c_int {
+    catch_panic(cursor_errmsg(cursor), || {
+        drop(Box::from_raw(cursor as *mut RawCursor<T::Cursor>));
+        SQLITE_OK
+    })
+}
+
+unsafe extern "C" fn x_filter<T: VTab>(
+    cursor: *mut sqlite3_vtab_cursor,
+    idx_num: c_int,
+    _idx_str: *const c_char,
+    argc: c_int,
+    argv: *mut *mut sqlite3_value,
+) -> c_int {
+    catch_panic(cursor_errmsg(cursor), || {
+        let args: Vec<ValueRef<'_>> = (0..argc as usize)
+            .map(|i| value_from_raw(*argv.add(i)))
+            .collect();
+        match cursor_of::<T>(cursor).filter(idx_num, &args) {
+            Ok(()) => SQLITE_OK,
+            Err(e) => cursor_error(cursor, &e),
+        }
+    })
+}
+
+unsafe extern "C" fn x_next<T: VTab>(cursor: *mut sqlite3_vtab_cursor) -> c_int {
+    catch_panic(cursor_errmsg(cursor), || {
+        match cursor_of::<T>(cursor).next() {
+            Ok(()) => SQLITE_OK,
+            Err(e) => cursor_error(cursor, &e),
+        }
+    })
+}
+
+unsafe extern "C" fn x_eof<T: VTab>(cursor: *mut sqlite3_vtab_cursor) -> c_int {
+    // `SQLITE_ERROR` is true; i.e. the scan finishes if `eof` panics.
+    catch_panic(cursor_errmsg(cursor), || {
+        cursor_of::<T>(cursor).eof() as c_int
+    })
+}
+
+unsafe extern "C" fn x_column<T: VTab>(
+    cursor: *mut sqlite3_vtab_cursor,
+    ctx: *mut sqlite3_context,
+    index: c_int,
+) -> c_int {
+    catch_panic(cursor_errmsg(cursor), || {
+        let result = cursor_of::<T>(cursor)
+            .column(index as usize)
+            .and_then(|output| value_set_result(ctx, output.as_value_ref()));
+        match result {
+            Ok(()) => SQLITE_OK,
+            Err(e) => cursor_error(cursor, &e),
+        }
+    })
+}
+
+unsafe extern "C" fn x_rowid<T: VTab>(cursor: *mut sqlite3_vtab_cursor, prowid: *mut i64) -> c_int {
+    catch_panic(cursor_errmsg(cursor), || {
+        match cursor_of::<T>(cursor).rowid() {
+            Ok(rowid) => {
+                *prowid = rowid;
+                SQLITE_OK
+            }
+            Err(e) => cursor_error(cursor, &e),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Value, SQLITE_ERROR};
+    use std::sync::Arc;
+
+    /// Simplified generate_series(start, stop).
+    struct Series;
+
+    const START: i32 = 1;
+    const STOP: i32 = 2;
+
+    struct SeriesCursor {
+        value: i64,
+        stop: i64,
+    }
+
+    impl VTab for Series {
+        type Cursor = SeriesCursor;
+
+        fn schema(&self) -> &str {
+            "CREATE TABLE x(value INTEGER, start HIDDEN, stop HIDDEN)"
+        }
+
+        fn best_index(&self, info: &mut BestIndexInfo<'_>) -> Result<(), Error> {
+            let mut idx_num = 0;
+            let mut argv_index = 1;
+            for (i, c) in info.constraints().iter().enumerate() {
+                if !c.usable || c.op != ConstraintOp::Eq {
+                    continue;
+                }
+                let flag = match c.column {
+                    Some(1) => START,
+                    Some(2) => STOP,
+                    _ => continue,
+                };
+                if idx_num & flag == 0 {
+                    idx_num |= flag;
+                    info.set_argv_index(i, argv_index, true);
+                    argv_index += 1;
+                }
+            }
+
+            info.set_idx_num(idx_num);
+            if idx_num & STOP == 0 {
+                info.set_estimated_cost(1e20);
+            } else {
+                info.set_estimated_cost(10.0);
+                info.set_estimated_rows(10);
+            }
+
+            let order_bys = info.order_bys();
+            if order_bys.len() == 1 && order_bys[0].column == Some(0) && !order_bys[0].desc {
+                info.set_order_by_consumed(true);
+            }
+            Ok(())
+        }
+
+        fn open(&self) -> Result<SeriesCursor, Error> {
+            Ok(SeriesCursor { value: 0, stop: -1 })
+        }
+    }
+
+    impl VTabCursor for SeriesCursor {
+        fn filter(&mut self, idx_num: i32, args: &[ValueRef<'_>]) -> Result<(), Error> {
+            if idx_num & STOP == 0 {
+                return Err(Error::with_message(SQLITE_ERROR, "stop is required"));
+            }
+
+            let mut args = args.iter();
+            let start = if idx_num & START == 0 {
+                Some(&ValueRef::Integer(0))
+            } else {
+                args.next()
+            };
+            self.value = match start {
+                Some(ValueRef::Integer(i)) => *i,
+                _ => {
+                    return Err(Error::with_message(
+                        SQLITE_ERROR,
+                        "start must be an integer",
+                    ))
+                }
+            };
+            self.stop = match args.next() {
+                Some(ValueRef::Integer(i)) => *i,
+                _ => return Err(Error::with_message(SQLITE_ERROR, "stop must be an integer")),
+            };
+            Ok(())
+        }
+
+        fn next(&mut self) -> Result<(), Error> {
+            self.value += 1;
+            Ok(())
+        }
+
+        fn eof(&self) -> bool {
+            self.value > self.stop
+        }
+
+        fn column(&self, index: usize) -> Result<ToSqlOutput<'_>, Error> {
+            let v = match index {
+                0 => self.value,
+                2 => self.stop,
+                _ => return Ok(ToSqlOutput::Owned(Value::Null)),
+            };
+            Ok(ToSqlOutput::Owned(Value::Integer(v)))
+        }
+
+        fn rowid(&self) -> Result<i64, Error> {
+            Ok(self.value)
+        }
+    }
+
+    struct Metric {
+        ts: i64,
+        name: &'static str,
+        value: f64,
+    }
+
+    /// Exposes `Vec<Metric>` as a table.
+    struct Metrics(Arc<Vec<Metric>>);
+
+    struct MetricsCursor {
+        metrics: Arc<Vec<Metric>>,
+        pos: usize,
+    }
+
+    impl VTab for Metrics {
+        type Cursor = MetricsCursor;
+
+        fn schema(&self) -> &str {
+            "CREATE TABLE x(ts INTEGER, name TEXT, value REAL)"
+        }
+
+        fn best_index(&self, info: &mut BestIndexInfo<'_>) -> Result<(), Error> {
+            info.set_estimated_cost(self.0.len() as f64);
+            Ok(())
+        }
+
+        fn open(&self) -> Result<MetricsCursor, Error> {
+            Ok(MetricsCursor {
+                metrics: self.0.clone(),
+                pos: 0,
+            })
+        }
+    }
+
+    impl VTabCursor for MetricsCursor {
+        fn filter(&mut self, _: i32, _: &[ValueRef<'_>]) -> Result<(), Error> {
+            self.pos = 0;
+            Ok(())
+        }
+
+        fn next(&mut self) -> Result<(), Error> {
+            self.pos += 1;
+            Ok(())
+        }
+
+        fn eof(&self) -> bool {
+            self.metrics.len() <= self.pos
+        }
+
+        fn column(&self, index: usize) -> Result<ToSqlOutput<'_>, Error> {
+            let metric = &self.metrics[self.pos];
+            match index {
+                0 => Ok(ToSqlOutput::Owned(Value::Integer(metric.ts))),
+                1 => Ok(ToSqlOutput::Borrowed(ValueRef::Text(
+                    metric.name.as_bytes(),
+                ))),
+                _ => Ok(ToSqlOutput::Owned(Value::Real(metric.value))),
+            }
+        }
+
+        fn rowid(&self) -> Result<i64, Error> {
+            Ok(self.pos as i64)
+        }
+    }
+
+    #[test]
+    fn series() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.create_module("series", Series).unwrap();
+        con.execute_batch(
+            r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "name" TEXT);
+               INSERT INTO "foo" VALUES (2, 'b'), (4, 'd'), (8, 'h');"#,
+        )
+        .unwrap();
+
+        let values: Vec<(i64,)> = con
+            .stmt_once("SELECT value FROM series(3, 6)")
+            .unwrap()
+            .query_as()
+            .unwrap();
+        assert_eq!(vec![(3,), (4,), (5,), (6,)], values);
+
+        let values: Vec<(i64,)> = con
+            .stmt_once("SELECT value FROM series WHERE stop = 2 ORDER BY value")
+            .unwrap()
+            .query_as()
+            .unwrap();
+        assert_eq!(vec![(0,), (1,), (2,)], values);
+
+        // Join
+        let rows: Vec<(i64, String)> = con
+            .stmt_once(
+                r#"SELECT s.value, "foo"."name" FROM series(1, 5) AS s
+                   JOIN "foo" ON "foo"."id" = s.value ORDER BY s.value"#,
+            )
+            .unwrap()
+            .query_as()
+            .unwrap();
+        assert_eq!(vec![(2, "b".to_string()), (4, "d".to_string())], rows);
+
+        // Errors
+        let e = con
+            .stmt_once("SELECT value FROM series")
+            .unwrap()
+            .query_as::<(i64,)>()
+            .unwrap_err();
+        assert_eq!(SQLITE_ERROR, e.code());
+        let e = con
+            .stmt_once("SELECT value FROM series('a', 2)")
+            .unwrap()
+            .query_as::<(i64,)>()
+            .unwrap_err();
+        assert_eq!(SQLITE_ERROR, e.code());
+    }
+
+    /// A table with a row, and whose `column` panics.
+    struct Panic;
+
+    impl VTab for Panic {
+        type Cursor = PanicCursor;
+
+        fn schema(&self) -> &str {
+            "CREATE TABLE x(value INTEGER)"
+        }
+
+        fn best_index(&self, _info: &mut BestIndexInfo<'_>) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn open(&self) -> Result<PanicCursor, Error> {
+            Ok(PanicCursor(false))
+        }
+    }
+
+    struct PanicCursor(bool);
+
+    impl VTabCursor for PanicCursor {
+        fn filter(&mut self, _idx_num: i32, _args: &[ValueRef<'_>]) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn next(&mut self) -> Result<(), Error> {
+            self.0 = true;
+            Ok(())
+        }
+
+        fn eof(&self) -> bool {
+            self.0
+        }
+
+        fn column(&self, _index: usize) -> Result<ToSqlOutput<'_>, Error> {
+            panic!("column")
+        }
+
+        fn rowid(&self) -> Result<i64, Error> {
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn panic() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.create_module("panic", Panic).unwrap();
+
+        let e = con
+            .stmt_once("SELECT value FROM panic")
+            .unwrap()
+            .query_as::<(i64,)>()
+            .unwrap_err();
+        assert_eq!(SQLITE_ERROR, e.code());
+        let (_, message) = con.last_error().unwrap();
+        assert!(
+            message.contains("the virtual table panicked"),
+            "{}",
+            message
+        );
+
+        // The rowid does not panic.
+        assert_eq!(
+            Ok(Some((1,))),
+            con.query_one::<(i64,)>("SELECT rowid FROM panic", &[])
+        );
+    }
+
+    #[test]
+    fn metrics() {
+        let metrics = vec![
+            Metric {
+                ts: 10,
+                name: "cpu",
+                value: 0.5,
+            },
+            Metric {
+                ts: 20,
+                name: "mem",
+                value: 128.0,
+            },
+            Metric {
+                ts: 30,
+                name: "cpu",
+                value: 0.75,
+            },
+        ];
+
+        let mut con = Connection::open_memory_db().unwrap();
+        con.create_module("my_metrics", Metrics(Arc::new(metrics)))
+            .unwrap();
+        con.execute_batch(
+            r#"CREATE TABLE "unit" ("name" TEXT PRIMARY KEY, "unit" TEXT);
+               INSERT INTO "unit" VALUES ('cpu', 'ratio'), ('mem', 'MiB');"#,
+        )
+        .unwrap();
+
+        let mut stmt = con
+            .stmt_once(
+                r#"SELECT m.ts, m.value, u.unit FROM my_metrics() AS m
+                   JOIN "unit" AS u ON u.name = m.name WHERE m.ts > ?1 ORDER BY m.ts"#,
+            )
+            .unwrap();
+        stmt.bind(1, &15).unwrap();
+        let rows: Vec<(i64, f64, String)> = stmt.query_as().unwrap();
+        let expected = vec![
+            (20, 128.0, "MiB".to_string()),
+            (30, 0.75, "ratio".to_string()),
+        ];
+        assert_eq!(expected, rows);
+    }
+}
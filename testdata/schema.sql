@@ -0,0 +1 @@
+CREATE TABLE "country" ("code" TEXT PRIMARY KEY, "name" TEXT NOT NULL, "population" INTEGER);
@@ -0,0 +1,106 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! The test is in its own process because libsqlite3 must not be initialized yet.
+
+use mouse_sqlite3::{config_log, initialize, Connection};
+use std::sync::Mutex;
+
+static LOGS: Mutex<Vec<(i32, String)>> = Mutex::new(Vec::new());
+
+fn log(code: i32, msg: &str) {
+    LOGS.lock().unwrap().push((code, msg.to_string()));
+}
+
+fn panic_log(_: i32, _: &str) {
+    panic!("The panic must not unwind into libsqlite3.");
+}
+
+#[test]
+fn config_log_automatic_index() {
+    const SQLITE_WARNING_AUTOINDEX: i32 = 284;
+
+    config_log(Some(log)).unwrap();
+    initialize().unwrap();
+    // Fails after initialized.
+    let e = config_log(None).unwrap_err();
+    assert!(e.message().is_some());
+
+    let mut con = Connection::open_memory_db().unwrap();
+    con.execute_batch(
+        r#"CREATE TABLE "a" ("x" INTEGER);
+           CREATE TABLE "b" ("x" INTEGER);
+           INSERT INTO "a" VALUES (1), (2), (3);
+           INSERT INTO "b" VALUES (1), (2), (3);
+           SELECT * FROM "a" JOIN "b" ON "a"."x" = "b"."x";"#,
+    )
+    .unwrap();
+
+    let logs = LOGS.lock().unwrap();
+    assert!(logs
+        .iter()
+        .any(|(code, msg)| *code == SQLITE_WARNING_AUTOINDEX && msg.contains("automatic index")));
+    drop(logs);
+
+    // Reconfigure after shutdown.
+    drop(con);
+    unsafe { mouse_sqlite3::shutdown() }.unwrap();
+    config_log(Some(panic_log)).unwrap();
+    let mut con = Connection::open_memory_db().unwrap();
+    con.execute_batch(
+        r#"CREATE TABLE "a" ("x" INTEGER);
+           CREATE TABLE "b" ("x" INTEGER);
+           SELECT * FROM "a" JOIN "b" ON "a"."x" = "b"."x";"#,
+    )
+    .unwrap();
+}
@@ -0,0 +1,110 @@
+// Copyright 2021 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0 OR BSD-2-Clause"
+//
+// This is part of mouse-sqlite3
+//
+//  mouse-sqlite3 is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sqlite3 is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sqlite3.  If not, see <http://www.gnu.org/licenses/>.
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted
+// provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of
+//    conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright notice, this
+//    list of conditions and the following disclaimer in the documentation and/or other
+//    materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+// ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+// WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED.
+// IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT,
+// INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT
+// NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+#![cfg(feature = "derive")]
+
+use mouse_sqlite3::{BindParams, Connection};
+
+/// C "SQLITE_MISUSE"
+const SQLITE_MISUSE: i32 = 21;
+
+#[derive(BindParams)]
+struct Foo {
+    id: i64,
+    name: Option<String>,
+    r#type: &'static str,
+}
+
+#[test]
+fn derive_bind_params() {
+    assert_eq!(&["id", "name", "type"], Foo::param_names());
+
+    let mut con = Connection::open_memory_db().unwrap();
+    con.execute_batch(
+        r#"CREATE TABLE "foo" ("id" INTEGER PRIMARY KEY, "name" TEXT, "type" TEXT NOT NULL)"#,
+    )
+    .unwrap();
+
+    let mut insert = con
+        .typed_stmt::<Foo, ()>(r#"INSERT INTO "foo" VALUES (:id, :name, :type)"#)
+        .unwrap();
+    let foo = Foo {
+        id: 1,
+        name: Some("a".to_string()),
+        r#type: "x",
+    };
+    assert_eq!(Ok(1), insert.execute(&foo));
+    let foo = Foo {
+        id: 2,
+        name: None,
+        r#type: "y",
+    };
+    assert_eq!(Ok(1), insert.execute(&foo));
+
+    const SQL: &str =
+        r#"SELECT "id", "name", typeof("name") FROM "foo" WHERE "id" = :id OR "type" = :type"#;
+    let mut select = con
+        .typed_stmt::<Foo, (i64, Option<String>, String)>(SQL)
+        .unwrap();
+    let e = select.query(&foo).unwrap_err();
+    assert_eq!(SQLITE_MISUSE, e.code());
+    assert_eq!(Some("no parameter for field(s) name"), e.message());
+
+    const SQL2: &str = r#"SELECT "id", "name", typeof("name") FROM "foo"
+                          WHERE "id" = :id OR "type" = :type OR "name" = :name"#;
+    let mut select = con
+        .typed_stmt::<Foo, (i64, Option<String>, String)>(SQL2)
+        .unwrap();
+    let expected = vec![(2, None, "null".to_string())];
+    assert_eq!(Ok(expected), select.query(&foo));
+}